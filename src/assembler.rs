@@ -0,0 +1,1092 @@
+use crate::elf::{Object, Relocation, Section, SectionKind, Symbol, Target, R_X86_64_32, R_X86_64_64, R_X86_64_PC32, R_X86_64_PLT32};
+use std::collections::{HashMap, HashSet};
+
+/// Sections every object starts with, in the order of `Object::sections`.
+const SECTIONS: [(&str, SectionKind, u64); 5] = [
+    (".text", SectionKind::Code, 16),
+    (".data", SectionKind::Data, 1),
+    (".bss", SectionKind::Zeroed, 1),
+    (".rodata", SectionKind::ReadOnly, 1),
+    (".note.GNU-stack", SectionKind::Info, 1),
+];
+const TEXT: usize = 0;
+
+/// AT&T names of the general purpose registers by number, for 64, 32, 16 and 8 bits.
+const REGISTERS: [[&str; 4]; 16] = [
+    ["rax", "eax", "ax", "al"],
+    ["rcx", "ecx", "cx", "cl"],
+    ["rdx", "edx", "dx", "dl"],
+    ["rbx", "ebx", "bx", "bl"],
+    ["rsp", "esp", "sp", "spl"],
+    ["rbp", "ebp", "bp", "bpl"],
+    ["rsi", "esi", "si", "sil"],
+    ["rdi", "edi", "di", "dil"],
+    ["r8", "r8d", "r8w", "r8b"],
+    ["r9", "r9d", "r9w", "r9b"],
+    ["r10", "r10d", "r10w", "r10b"],
+    ["r11", "r11d", "r11w", "r11b"],
+    ["r12", "r12d", "r12w", "r12b"],
+    ["r13", "r13d", "r13w", "r13b"],
+    ["r14", "r14d", "r14w", "r14b"],
+    ["r15", "r15d", "r15w", "r15b"],
+];
+
+/// Condition code suffixes of `jcc`, `setcc` and `cmovcc`.
+const CONDITIONS: [(&str, u8); 30] = [
+    ("o", 0), ("no", 1), ("b", 2), ("c", 2), ("nae", 2), ("ae", 3), ("nb", 3), ("nc", 3),
+    ("e", 4), ("z", 4), ("ne", 5), ("nz", 5), ("be", 6), ("na", 6), ("a", 7), ("nbe", 7),
+    ("s", 8), ("ns", 9), ("p", 10), ("pe", 10), ("np", 11), ("po", 11), ("l", 12), ("nge", 12),
+    ("ge", 13), ("nl", 13), ("le", 14), ("ng", 14), ("g", 15), ("nle", 15),
+];
+
+/// Directives that put data into the current section.
+const DATA: [&str; 8] = [".ascii", ".asciz", ".string", ".byte", ".short", ".value", ".long", ".quad"];
+
+/// Opcode extension of the `0x80` group, the register forms are `8 * n + 1` and `8 * n + 3`.
+const ARITHMETIC: [(&str, u8); 6] = [("add", 0), ("or", 1), ("and", 4), ("sub", 5), ("xor", 6), ("cmp", 7)];
+
+/// Opcode extension of the `0xF7` group.
+const UNARY: [(&str, u8); 5] = [("not", 2), ("neg", 3), ("mul", 4), ("div", 6), ("idiv", 7)];
+
+/// Opcode extension of the `0xD1` group.
+const SHIFTS: [(&str, u8); 3] = [("shl", 4), ("shr", 5), ("sar", 7)];
+
+/// Sign and zero extensions by the source size, the suffix gives the destination size.
+const EXTENSIONS: [(&str, &[u8]); 5] = [
+    ("movsb", &[0x0F, 0xBE]),
+    ("movsw", &[0x0F, 0xBF]),
+    ("movsl", &[0x63]),
+    ("movzb", &[0x0F, 0xB6]),
+    ("movzw", &[0x0F, 0xB7]),
+];
+
+/// Scalar SSE arithmetic, `ss` takes an `F3` prefix and `sd` an `F2` prefix.
+const SSE_ARITHMETIC: [(&str, u8); 4] = [("add", 0x58), ("mul", 0x59), ("sub", 0x5C), ("div", 0x5E)];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Register {
+    number: u8,
+    // In bytes, 16 for `%xmm` registers
+    size: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Memory {
+    displacement: i64,
+    symbol: Option<String>,
+    base: Option<u8>,
+    // Register and scale
+    index: Option<(u8, u8)>,
+    rip: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Register(Register),
+    Immediate(i64),
+    Memory(Memory),
+    // Jump and call targets
+    Label(String),
+}
+
+/// A 4 or 8 byte field that refers to a label, patched or turned into a relocation once all
+/// labels are known.
+struct Fixup {
+    section: usize,
+    offset: usize,
+    symbol: String,
+    addend: i64,
+    kind: u32,
+}
+
+fn register(name: &str) -> Option<Register> {
+    if let Some(number) = name.strip_prefix("xmm") {
+        return number.parse().ok().filter(|t| *t < 16).map(|number| Register { number, size: 16 });
+    }
+    REGISTERS.iter().enumerate().find_map(|(number, names)| {
+        let size = names.iter().position(|t| *t == name)?;
+        Some(Register { number: number as u8, size: [8, 4, 2, 1][size] })
+    })
+}
+
+/// Decimal or `0x` hexadecimal, optionally negative. Values up to `u64::MAX` wrap.
+fn number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None if digits.starts_with(|t: char| t.is_ascii_digit()) => digits.parse::<u64>().ok()?,
+        None => return None,
+    };
+    Some(if negative { (value as i64).wrapping_neg() } else { value as i64 })
+}
+
+/// Labels that stay in the object, `.L` and numeric labels are private to the assembler.
+fn is_symbol(name: &str) -> bool {
+    !name.starts_with(".L") && !name.starts_with(|t: char| t.is_ascii_digit())
+}
+
+fn is_label(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|t| t.is_ascii_alphanumeric() || "_.$".contains(t))
+}
+
+/// Splits `text` at the commas outside of parentheses and strings.
+fn split(text: &str) -> Vec<&str> {
+    let (mut parts, mut start, mut depth) = (vec![], 0, 0);
+    for (index, char, quoted) in scan(text) {
+        match char {
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                parts.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(text[start..].trim());
+    parts.retain(|t| !t.is_empty());
+    parts
+}
+
+/// Removes a `#` comment, keeping `#` inside strings.
+fn strip_comment(line: &str) -> &str {
+    match scan(line).find(|t| t.1 == '#' && !t.2) {
+        Some((index, ..)) => &line[..index],
+        None => line,
+    }
+}
+
+/// Characters of `text` with their index and whether they are inside a string.
+fn scan(text: &str) -> impl Iterator<Item = (usize, char, bool)> + '_ {
+    let (mut quoted, mut escaped) = (false, false);
+    text.char_indices().map(move |(index, char)| {
+        let inside = quoted;
+        match char {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ => {}
+        }
+        (index, char, inside)
+    })
+}
+
+/// Bytes of a quoted string with C escapes, `\ooo` octal included.
+fn string(text: &str) -> Result<Vec<u8>, String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .ok_or_else(|| format!("Expected a string, found `{}`", text))?;
+    let (mut bytes, mut chars) = (vec![], inner.bytes().peekable());
+    while let Some(byte) = chars.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match chars.next() {
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(digit @ b'0'..=b'7') => {
+                let mut value = (digit - b'0') as u32;
+                for _ in 0..2 {
+                    match chars.peek() {
+                        Some(digit @ b'0'..=b'7') => {
+                            value = value * 8 + (digit - b'0') as u32;
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            Some(byte) => bytes.push(byte),
+            None => return Err("String ends with `\\`".to_string()),
+        }
+    }
+    Ok(bytes)
+}
+
+fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn is_xmm(operand: &Operand) -> bool {
+    matches!(operand, Operand::Register(Register { size: 16, .. }))
+}
+
+fn fits_i8(value: i64) -> bool {
+    i8::try_from(value).is_ok()
+}
+
+/// An immediate of an operand of `size` bytes, which takes at most 4 bytes.
+fn immediate(value: i64, size: u8) -> Result<(i64, usize), String> {
+    let fits = match size {
+        1 => (-128..=255).contains(&value),
+        2 => (-32768..=65535).contains(&value),
+        4 => (i32::MIN as i64..=u32::MAX as i64).contains(&value),
+        _ => i32::try_from(value).is_ok(),
+    };
+    match fits {
+        true => Ok((value, size.min(4) as usize)),
+        false => Err(format!("Immediate {} doesn't fit the operand", value)),
+    }
+}
+
+/// Operand size of a sized mnemonic like `addq`: the base name and the size in bytes.
+fn suffixed<'m>(mnemonic: &'m str, bases: &[&str]) -> Option<(&'m str, u8)> {
+    let size = match mnemonic.chars().last()? {
+        'b' => 1,
+        'w' => 2,
+        'l' => 4,
+        'q' => 8,
+        _ => 0,
+    };
+    if bases.contains(&mnemonic) {
+        return Some((mnemonic, 0));
+    }
+    let base = &mnemonic[..mnemonic.len() - 1];
+    (size != 0 && bases.contains(&base)).then_some((base, size))
+}
+
+struct Assembler {
+    sections: Vec<Section>,
+    current: usize,
+    labels: HashMap<String, (usize, u64)>,
+    // Symbols in the order they first appear, which keeps the symbol table stable
+    names: Vec<String>,
+    globals: HashSet<String>,
+    functions: HashSet<String>,
+    sizes: HashMap<String, u64>,
+    fixups: Vec<Fixup>,
+    // How often each numeric label like `1:` was defined so far
+    numbered: HashMap<String, usize>,
+    // Instructions using `%spl`, `%bpl`, `%sil` or `%dil` need a REX prefix
+    byte_registers: bool,
+    // `.file` names by number, starting at 1
+    files: Vec<String>,
+    // `.loc` rows: offset in `.text`, file, line and column
+    rows: Vec<(u64, u64, u64, u64)>,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Self {
+            sections: SECTIONS.iter().map(|(name, kind, align)| Section::new(name, *kind, *align)).collect(),
+            current: TEXT,
+            labels: HashMap::new(),
+            names: vec![],
+            globals: HashSet::new(),
+            functions: HashSet::new(),
+            sizes: HashMap::new(),
+            fixups: vec![],
+            numbered: HashMap::new(),
+            byte_registers: false,
+            files: vec![],
+            rows: vec![],
+        }
+    }
+
+    fn data(&mut self) -> &mut Vec<u8> {
+        &mut self.sections[self.current].data
+    }
+
+    fn offset(&self) -> u64 {
+        self.sections[self.current].data.len() as u64
+    }
+
+    fn mention(&mut self, name: &str) {
+        if is_symbol(name) && !self.names.iter().any(|t| t == name) {
+            self.names.push(name.to_string());
+        }
+    }
+
+    fn define(&mut self, label: &str) -> Result<(), String> {
+        let name = match label.bytes().all(|t| t.is_ascii_digit()) {
+            true => {
+                let count = self.numbered.entry(label.to_string()).or_insert(0);
+                *count += 1;
+                format!("{}:{}", label, *count - 1)
+            }
+            false => label.to_string(),
+        };
+        if self.labels.insert(name.clone(), (self.current, self.offset())).is_some() {
+            return Err(format!("Symbol `{}` is already defined", name));
+        }
+        self.mention(&name);
+        Ok(())
+    }
+
+    /// Name a jump target refers to, `1f` is the next `1:` and `1b` the previous one.
+    fn target(&mut self, text: &str) -> Result<String, String> {
+        let text = text.strip_suffix("@PLT").unwrap_or(text);
+        let digits = &text[..text.len().saturating_sub(1)];
+        if !digits.is_empty() && digits.bytes().all(|t| t.is_ascii_digit()) {
+            let count = self.numbered.get(digits).copied().unwrap_or(0);
+            return match text.as_bytes()[text.len() - 1] {
+                b'f' => Ok(format!("{}:{}", digits, count)),
+                b'b' if count > 0 => Ok(format!("{}:{}", digits, count - 1)),
+                _ => Err(format!("No label `{}:` before `{}`", digits, text)),
+            };
+        }
+        self.mention(text);
+        Ok(text.to_string())
+    }
+
+    fn fixup(&mut self, symbol: String, addend: i64, kind: u32, size: usize) {
+        self.fixups.push(Fixup { section: self.current, offset: self.offset() as usize, symbol, addend, kind });
+        self.data().extend(vec![0; size]);
+    }
+
+    fn operand(&mut self, text: &str) -> Result<Operand, String> {
+        if let Some(name) = text.strip_prefix('%') {
+            return register(name).map(Operand::Register).ok_or_else(|| format!("Unknown register `{}`", text));
+        }
+        if let Some(value) = text.strip_prefix('$') {
+            return number(value).map(Operand::Immediate).ok_or_else(|| format!("Expected a number, found `{}`", text));
+        }
+        let Some((displacement, address)) = text.split_once('(') else {
+            return match number(text) {
+                Some(displacement) => Ok(Operand::Memory(Memory { displacement, ..Memory::default() })),
+                None if is_label(text.strip_suffix("@PLT").unwrap_or(text)) => Ok(Operand::Label(text.to_string())),
+                None => Err(format!("Unknown operand `{}`", text)),
+            };
+        };
+        let mut memory = Memory::default();
+        // A displacement is a number, a symbol or a symbol with a number added
+        let split = displacement.rfind(['+', '-']).filter(|t| *t > 0).unwrap_or(displacement.len());
+        let (symbol, offset) = displacement.split_at(split);
+        match number(symbol) {
+            Some(value) if offset.is_empty() => memory.displacement = value,
+            _ if symbol.is_empty() => {}
+            _ => {
+                memory.displacement = match offset.strip_prefix('+').unwrap_or(offset) {
+                    "" => 0,
+                    offset => number(offset).ok_or_else(|| format!("Unknown displacement `{}`", displacement))?,
+                };
+                memory.symbol = Some(self.target(symbol)?);
+            }
+        }
+        let address = address.strip_suffix(')').ok_or_else(|| format!("Missing `)` in `{}`", text))?;
+        let parts: Vec<&str> = address.split(',').map(|t| t.trim()).collect();
+        let general = |text: &str| match text.strip_prefix('%').and_then(register) {
+            Some(Register { number, size: 8 }) => Ok(number),
+            _ => Err(format!("Expected a 64-bit register, found `{}`", text)),
+        };
+        match parts[0] {
+            "%rip" => memory.rip = true,
+            "" => {}
+            base => memory.base = Some(general(base)?),
+        }
+        if let Some(index) = parts.get(1) {
+            let scale = match parts.get(2) {
+                Some(scale) => scale.parse().map_err(|_| format!("Unknown scale `{}`", scale))?,
+                None => 1,
+            };
+            memory.index = Some((general(index)?, scale));
+        }
+        Ok(Operand::Memory(memory))
+    }
+
+    /// Encodes an instruction with a ModRM byte: `reg` is the register field, a register or
+    /// an opcode extension, and `rm` the register or memory operand. The immediate follows.
+    fn modrm(&mut self, prefix: &[u8], wide: bool, opcode: &[u8], reg: u8, rm: &Operand, immediate: Option<(i64, usize)>) -> Result<(), String> {
+        let mut rex = (wide as u8) << 3 | (reg >> 3 & 1) << 2;
+        let mut tail = vec![];
+        let mut fixup = None;
+        match rm {
+            Operand::Register(register) => {
+                rex |= register.number >> 3;
+                tail.push(0xC0 | (reg & 7) << 3 | register.number & 7);
+            }
+            Operand::Memory(memory) if memory.rip => {
+                tail.push((reg & 7) << 3 | 5);
+                let size = immediate.map_or(0, |t| t.1) as i64;
+                match &memory.symbol {
+                    Some(symbol) => fixup = Some((symbol.clone(), memory.displacement - 4 - size)),
+                    None => tail.extend((memory.displacement as i32).to_le_bytes()),
+                }
+            }
+            Operand::Memory(memory) => {
+                if memory.symbol.is_some() {
+                    return Err("Symbols are only addressed relative to %rip".to_string());
+                }
+                let displacement = i32::try_from(memory.displacement).map_err(|_| "Displacement out of range".to_string())?;
+                let (index, scale) = memory.index.unwrap_or((4, 1));
+                if memory.index.is_some_and(|t| t.0 == 4) {
+                    return Err("%rsp can't be an index".to_string());
+                }
+                rex |= (index >> 3 & 1) << 1;
+                let scale = match scale {
+                    1 => 0,
+                    2 => 1,
+                    4 => 2,
+                    8 => 3,
+                    _ => return Err(format!("Scale {} isn't 1, 2, 4 or 8", scale)),
+                };
+                match memory.base {
+                    None => {
+                        tail.extend([(reg & 7) << 3 | 4, scale << 6 | (index & 7) << 3 | 5]);
+                        tail.extend(displacement.to_le_bytes());
+                    }
+                    Some(base) => {
+                        rex |= base >> 3;
+                        // `%rbp` and `%r13` without displacement would mean no base
+                        let mode = match displacement {
+                            0 if base & 7 != 5 => 0,
+                            _ if fits_i8(displacement as i64) => 1,
+                            _ => 2,
+                        };
+                        let sib = memory.index.is_some() || base & 7 == 4;
+                        tail.push(mode << 6 | (reg & 7) << 3 | if sib { 4 } else { base & 7 });
+                        if sib {
+                            tail.push(scale << 6 | (index & 7) << 3 | base & 7);
+                        }
+                        match mode {
+                            1 => tail.push(displacement as u8),
+                            2 => tail.extend(displacement.to_le_bytes()),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            _ => return Err("Expected a register or memory operand".to_string()),
+        }
+        let force_rex = self.byte_registers;
+        let data = self.data();
+        data.extend(prefix);
+        if rex != 0 || force_rex {
+            data.push(0x40 | rex);
+        }
+        data.extend(opcode);
+        data.extend(tail);
+        if let Some((symbol, addend)) = fixup {
+            self.fixup(symbol, addend, R_X86_64_PC32, 4);
+        }
+        if let Some((value, size)) = immediate {
+            self.data().extend(&value.to_le_bytes()[..size]);
+        }
+        Ok(())
+    }
+
+    /// Like `modrm` for integer instructions of `size` bytes, which picks the prefix and
+    /// between the byte opcode and the full size one.
+    fn sized(&mut self, size: u8, opcodes: (&[u8], &[u8]), reg: u8, rm: &Operand, immediate: Option<(i64, usize)>) -> Result<(), String> {
+        let prefix: &[u8] = if size == 2 { &[0x66] } else { &[] };
+        let opcode = if size == 1 { opcodes.0 } else { opcodes.1 };
+        self.modrm(prefix, size == 8, opcode, reg, rm, immediate)
+    }
+
+    /// Jumps and calls always take a 32-bit displacement.
+    fn branch(&mut self, opcode: &[u8], target: &str) -> Result<(), String> {
+        let symbol = self.target(target)?;
+        self.data().extend(opcode);
+        self.fixup(symbol, -4, R_X86_64_PLT32, 4);
+        Ok(())
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operands: &[Operand]) -> Result<(), String> {
+        use Operand::{Immediate, Label, Register as Reg};
+        self.byte_registers = operands.iter().any(|t| matches!(t, Reg(Register { number: 4..=7, size: 1 })));
+        let condition = |prefix: &str| {
+            let suffix = mnemonic.strip_prefix(prefix)?;
+            let suffix = CONDITIONS.iter().any(|t| t.0 == suffix).then_some(suffix).or_else(|| suffix.strip_suffix(['l', 'q', 'b']))?;
+            CONDITIONS.iter().find(|t| t.0 == suffix).map(|t| t.1)
+        };
+        // Size of the general purpose registers among the operands
+        let size = operands.iter().find_map(|t| match t {
+            Reg(register) if register.size != 16 => Some(register.size),
+            _ => None,
+        });
+
+        match (mnemonic, operands) {
+            ("ret", []) => self.data().push(0xC3),
+            ("leave", []) => self.data().push(0xC9),
+            ("cqto" | "cqo", []) => self.data().extend([0x48, 0x99]),
+            ("syscall", []) => self.data().extend([0x0F, 0x05]),
+            ("rep", [Label(string)]) if string == "movsb" => self.data().extend([0xF3, 0xA4]),
+            ("call", [Label(target)]) => return self.branch(&[0xE8], target),
+            ("jmp", [Label(target)]) => return self.branch(&[0xE9], target),
+            ("push" | "pushq", [Reg(register)]) | ("pop" | "popq", [Reg(register)]) if register.size == 8 => {
+                if register.number >= 8 {
+                    self.data().push(0x41);
+                }
+                let opcode = if mnemonic.starts_with("push") { 0x50 } else { 0x58 };
+                self.data().push(opcode + (register.number & 7));
+            }
+            ("movabsq" | "movabs", [Immediate(value), Reg(register)]) if register.size == 8 => {
+                let opcode = 0xB8 + (register.number & 7);
+                self.data().extend([0x48 | register.number >> 3, opcode]);
+                self.data().extend(value.to_le_bytes());
+            }
+            ("movq" | "movd", [source, destination]) if is_xmm(source) || is_xmm(destination) => {
+                let wide = mnemonic == "movq";
+                return match (source, destination) {
+                    (Reg(xmm), Reg(general)) if xmm.size == 16 && general.size != 16 => {
+                        self.modrm(&[0x66], wide, &[0x0F, 0x7E], xmm.number, destination, None)
+                    }
+                    (Reg(general), Reg(xmm)) if general.size != 16 => self.modrm(&[0x66], wide, &[0x0F, 0x6E], xmm.number, source, None),
+                    (_, Reg(xmm)) if wide => self.modrm(&[0xF3], false, &[0x0F, 0x7E], xmm.number, source, None),
+                    (_, Reg(xmm)) => self.modrm(&[0x66], false, &[0x0F, 0x6E], xmm.number, source, None),
+                    (Reg(xmm), _) => self.modrm(&[0x66], false, &[0x0F, if wide { 0xD6 } else { 0x7E }], xmm.number, destination, None),
+                    _ => Err(format!("Unsupported operands for `{}`", mnemonic)),
+                };
+            }
+            ("cvtss2sd" | "cvtsd2ss", [source, Reg(destination)]) => {
+                let prefix = if mnemonic == "cvtss2sd" { 0xF3 } else { 0xF2 };
+                return self.modrm(&[prefix], false, &[0x0F, 0x5A], destination.number, source, None);
+            }
+            ("ucomiss" | "ucomisd", [source, Reg(destination)]) => {
+                let prefix: &[u8] = if mnemonic == "ucomisd" { &[0x66] } else { &[] };
+                return self.modrm(prefix, false, &[0x0F, 0x2E], destination.number, source, None);
+            }
+            _ if mnemonic.starts_with("cvtsi2s") => {
+                let [source, Reg(destination)] = operands else {
+                    return Err(format!("Unsupported operands for `{}`", mnemonic));
+                };
+                let prefix = if mnemonic.starts_with("cvtsi2sd") { 0xF2 } else { 0xF3 };
+                let wide = mnemonic.ends_with('q') || size == Some(8);
+                return self.modrm(&[prefix], wide, &[0x0F, 0x2A], destination.number, source, None);
+            }
+            _ if mnemonic.starts_with("cvtts") => {
+                let [source, Reg(destination)] = operands else {
+                    return Err(format!("Unsupported operands for `{}`", mnemonic));
+                };
+                let prefix = if mnemonic.starts_with("cvttsd") { 0xF2 } else { 0xF3 };
+                let wide = mnemonic.ends_with('q') || destination.size == 8;
+                return self.modrm(&[prefix], wide, &[0x0F, 0x2C], destination.number, source, None);
+            }
+            _ if SSE_ARITHMETIC.iter().any(|t| mnemonic == format!("{}ss", t.0) || mnemonic == format!("{}sd", t.0)) => {
+                let [source, Reg(destination)] = operands else {
+                    return Err(format!("Unsupported operands for `{}`", mnemonic));
+                };
+                let opcode = SSE_ARITHMETIC.iter().find(|t| mnemonic.starts_with(t.0)).unwrap().1;
+                let prefix = if mnemonic.ends_with("ss") { 0xF3 } else { 0xF2 };
+                return self.modrm(&[prefix], false, &[0x0F, opcode], destination.number, source, None);
+            }
+            _ if mnemonic.len() == 6 && EXTENSIONS.iter().any(|t| mnemonic.starts_with(t.0)) => {
+                let [source, Reg(destination)] = operands else {
+                    return Err(format!("Unsupported operands for `{}`", mnemonic));
+                };
+                let opcode = EXTENSIONS.iter().find(|t| mnemonic.starts_with(t.0)).unwrap().1;
+                return self.sized(destination.size, (opcode, opcode), destination.number, source, None);
+            }
+            _ if mnemonic.starts_with('j') && condition("j").is_some() => {
+                let [Label(target)] = operands else {
+                    return Err(format!("Expected a label for `{}`", mnemonic));
+                };
+                return self.branch(&[0x0F, 0x80 + condition("j").unwrap()], target);
+            }
+            _ if condition("set").is_some() => {
+                let [destination] = operands else {
+                    return Err(format!("Expected one operand for `{}`", mnemonic));
+                };
+                return self.modrm(&[], false, &[0x0F, 0x90 + condition("set").unwrap()], 0, destination, None);
+            }
+            _ if condition("cmov").is_some() => {
+                let [source, Reg(destination)] = operands else {
+                    return Err(format!("Unsupported operands for `{}`", mnemonic));
+                };
+                let opcode = 0x40 + condition("cmov").unwrap();
+                return self.sized(destination.size, (&[], &[0x0F, opcode]), destination.number, source, None);
+            }
+            _ => return self.integer(mnemonic, operands, size),
+        }
+        Ok(())
+    }
+
+    /// Integer instructions with a size suffix, or with registers implying the size.
+    fn integer(&mut self, mnemonic: &str, operands: &[Operand], register_size: Option<u8>) -> Result<(), String> {
+        use Operand::{Immediate, Register as Reg};
+        let mut bases = vec!["mov", "test", "lea", "imul", "inc", "dec", "btc"];
+        bases.extend(ARITHMETIC.iter().map(|t| t.0));
+        bases.extend(UNARY.iter().map(|t| t.0));
+        bases.extend(SHIFTS.iter().map(|t| t.0));
+        let Some((base, size)) = suffixed(mnemonic, &bases) else {
+            return Err(format!("Unknown instruction `{}`", mnemonic));
+        };
+        let size = match size {
+            0 => register_size.ok_or_else(|| format!("Operand size of `{}` is unknown", mnemonic))?,
+            size => size,
+        };
+        let unsupported = || format!("Unsupported operands for `{}`", mnemonic);
+        let arithmetic = ARITHMETIC.iter().find(|t| t.0 == base).map(|t| t.1);
+        let unary = UNARY.iter().find(|t| t.0 == base).map(|t| t.1);
+        let shift = SHIFTS.iter().find(|t| t.0 == base).map(|t| t.1);
+        match (base, operands) {
+            ("mov", [Immediate(value), Reg(register)]) if size == 4 => {
+                if register.number >= 8 {
+                    self.data().push(0x41);
+                }
+                self.data().push(0xB8 + (register.number & 7));
+                let value = immediate(*value, 4)?;
+                self.data().extend(&value.0.to_le_bytes()[..4]);
+                Ok(())
+            }
+            ("mov", [Immediate(value), destination]) => self.sized(size, (&[0xC6], &[0xC7]), 0, destination, Some(immediate(*value, size)?)),
+            ("mov", [Reg(source), destination]) => self.sized(size, (&[0x88], &[0x89]), source.number, destination, None),
+            ("mov", [source, Reg(destination)]) => self.sized(size, (&[0x8A], &[0x8B]), destination.number, source, None),
+            ("lea", [source, Reg(destination)]) => self.sized(size, (&[], &[0x8D]), destination.number, source, None),
+            ("test", [Reg(source), destination]) => self.sized(size, (&[0x84], &[0x85]), source.number, destination, None),
+            ("test", [Immediate(value), destination]) => self.sized(size, (&[0xF6], &[0xF7]), 0, destination, Some(immediate(*value, size)?)),
+            (_, [Immediate(value), destination]) if arithmetic.is_some() => {
+                let extension = arithmetic.unwrap();
+                match size != 1 && fits_i8(*value) {
+                    true => self.sized(size, (&[], &[0x83]), extension, destination, Some((*value, 1))),
+                    false => self.sized(size, (&[0x80], &[0x81]), extension, destination, Some(immediate(*value, size)?)),
+                }
+            }
+            (_, [Reg(source), destination]) if arithmetic.is_some() => {
+                let opcode = arithmetic.unwrap() * 8;
+                self.sized(size, (&[opcode], &[opcode + 1]), source.number, destination, None)
+            }
+            (_, [source, Reg(destination)]) if arithmetic.is_some() => {
+                let opcode = arithmetic.unwrap() * 8;
+                self.sized(size, (&[opcode + 2], &[opcode + 3]), destination.number, source, None)
+            }
+            ("imul", [source, Reg(destination)]) => self.sized(size, (&[], &[0x0F, 0xAF]), destination.number, source, None),
+            ("imul", [Immediate(value), source, Reg(destination)]) => match fits_i8(*value) {
+                true => self.sized(size, (&[], &[0x6B]), destination.number, source, Some((*value, 1))),
+                false => self.sized(size, (&[], &[0x69]), destination.number, source, Some(immediate(*value, size)?)),
+            },
+            (_, [destination]) if unary.is_some() => self.sized(size, (&[0xF6], &[0xF7]), unary.unwrap(), destination, None),
+            ("inc" | "dec", [destination]) => self.sized(size, (&[0xFE], &[0xFF]), (base == "dec") as u8, destination, None),
+            (_, [destination]) if shift.is_some() => self.sized(size, (&[0xD0], &[0xD1]), shift.unwrap(), destination, None),
+            (_, [Immediate(value), destination]) if shift.is_some() => {
+                self.sized(size, (&[0xC0], &[0xC1]), shift.unwrap(), destination, Some((*value, 1)))
+            }
+            (_, [Reg(Register { number: 1, size: 1 }), destination]) if shift.is_some() => {
+                self.sized(size, (&[0xD2], &[0xD3]), shift.unwrap(), destination, None)
+            }
+            ("btc", [Immediate(value), destination]) => self.sized(size, (&[], &[0x0F, 0xBA]), 7, destination, Some((*value, 1))),
+            _ => Err(unsupported()),
+        }
+    }
+
+    fn directive(&mut self, name: &str, arguments: &[&str]) -> Result<(), String> {
+        let argument = |index: usize| arguments.get(index).copied().ok_or_else(|| format!("Missing argument for `{}`", name));
+        let count = |text: &str| number(text).filter(|t| *t >= 0).ok_or_else(|| format!("Expected a count, found `{}`", text));
+        match name {
+            ".text" => self.current = TEXT,
+            ".data" | ".bss" => self.current = SECTIONS.iter().position(|t| t.0 == name).unwrap(),
+            ".section" => {
+                let section = argument(0)?;
+                self.current = SECTIONS.iter().position(|t| t.0 == section).ok_or_else(|| format!("Unknown section `{}`", section))?;
+            }
+            ".globl" | ".global" => {
+                for symbol in arguments {
+                    self.mention(symbol);
+                    self.globals.insert(symbol.to_string());
+                }
+            }
+            ".type" => {
+                let symbol = argument(0)?;
+                self.mention(symbol);
+                if argument(1)? == "@function" {
+                    self.functions.insert(symbol.to_string());
+                }
+            }
+            ".size" => {
+                let symbol = argument(0)?;
+                let size = match argument(1)?.strip_prefix(".-") {
+                    Some(start) => match self.labels.get(start) {
+                        Some(&(section, value)) if section == self.current => self.offset() - value,
+                        _ => return Err(format!("`{}` isn't defined in this section", start)),
+                    },
+                    None => count(argument(1)?)? as u64,
+                };
+                self.sizes.insert(symbol.to_string(), size);
+            }
+            // `.file "name"` without a number only names the source of the object
+            ".file" if argument(0)?.starts_with('"') => {}
+            ".file" => {
+                let (index, file) = argument(0)?.split_once(' ').ok_or_else(|| "Expected a file number and name".to_string())?;
+                let index = count(index)? as usize;
+                if index == 0 {
+                    return Err("File numbers start at 1".to_string());
+                }
+                if self.files.len() < index {
+                    self.files.resize(index, String::new());
+                }
+                self.files[index - 1] = String::from_utf8_lossy(&string(file.trim())?).to_string();
+            }
+            ".loc" => {
+                let fields: Vec<u64> = argument(0)?.split_whitespace().take(3).map(|t| count(t).map(|t| t as u64)).collect::<Result<_, _>>()?;
+                if self.current == TEXT && fields.len() >= 2 {
+                    self.rows.push((self.offset(), fields[0], fields[1], fields.get(2).copied().unwrap_or(0)));
+                }
+            }
+            ".ascii" | ".asciz" | ".string" => {
+                for text in arguments {
+                    let mut bytes = string(text)?;
+                    if name != ".ascii" {
+                        bytes.push(0);
+                    }
+                    self.data().extend(bytes);
+                }
+            }
+            ".zero" | ".skip" => {
+                let size = count(argument(0)?)? as usize;
+                self.data().extend(vec![0; size]);
+            }
+            ".p2align" | ".balign" | ".align" => {
+                let value = count(argument(0)?)? as u64;
+                let align = if name == ".p2align" { 1 << value } else { value.max(1) };
+                let fill = if self.current == TEXT { 0x90 } else { 0 };
+                let section = &mut self.sections[self.current];
+                section.align = section.align.max(align);
+                let size = section.data.len().next_multiple_of(align as usize);
+                section.data.resize(size, fill);
+            }
+            ".byte" | ".short" | ".value" | ".long" | ".quad" => {
+                let size = match name {
+                    ".byte" => 1,
+                    ".short" | ".value" => 2,
+                    ".long" => 4,
+                    _ => 8,
+                };
+                for value in arguments {
+                    match number(value) {
+                        Some(value) => self.data().extend(&value.to_le_bytes()[..size]),
+                        None if size == 8 && is_label(value) => {
+                            let symbol = self.target(value)?;
+                            self.fixup(symbol, 0, R_X86_64_64, 8);
+                        }
+                        None => return Err(format!("Expected a number, found `{}`", value)),
+                    }
+                }
+            }
+            _ => return Err(format!("Unknown directive `{}`", name)),
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        let mut line = strip_comment(line).trim();
+        while let Some((label, rest)) = line.split_once(':').filter(|t| is_label(t.0)) {
+            self.define(label)?;
+            line = rest.trim_start();
+        }
+        if line.is_empty() {
+            return Ok(());
+        }
+        let (mnemonic, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        if mnemonic.starts_with('.') {
+            let arguments = match mnemonic {
+                ".file" | ".loc" => vec![rest],
+                _ => split(rest),
+            };
+            if self.sections[self.current].kind == SectionKind::Zeroed && DATA.contains(&mnemonic) {
+                return Err("Only zeroes can go into .bss".to_string());
+            }
+            return self.directive(mnemonic, &arguments);
+        }
+        if self.sections[self.current].kind == SectionKind::Zeroed {
+            return Err("Only zeroes can go into .bss".to_string());
+        }
+        let operands = split(rest).into_iter().map(|t| self.operand(t)).collect::<Result<Vec<_>, _>>()?;
+        self.instruction(mnemonic, &operands)
+    }
+
+    /// `.debug_abbrev`, `.debug_info` and `.debug_line` for the `.loc` rows: one compile unit
+    /// spanning `.text`, whose line program maps its addresses to the `.file` sources.
+    fn debug_sections(&mut self) {
+        let first = self.sections.len();
+        let (abbrev, line) = (first, first + 2);
+        let text_size = self.sections[TEXT].data.len() as u64;
+        let mut sections = vec![
+            Section::new(".debug_abbrev", SectionKind::Info, 1),
+            Section::new(".debug_info", SectionKind::Info, 1),
+            Section::new(".debug_line", SectionKind::Info, 1),
+        ];
+
+        // Compile unit without children: stmt_list, low_pc, high_pc, name and producer
+        sections[0].data = vec![1, 0x11, 0, 0x10, 0x17, 0x11, 0x01, 0x12, 0x07, 0x03, 0x08, 0x25, 0x08, 0, 0, 0];
+
+        let mut unit = vec![];
+        unit.extend(4u16.to_le_bytes());
+        let abbrev_offset = unit.len() + 4;
+        unit.extend(0u32.to_le_bytes());
+        unit.push(8);
+        unit.push(1);
+        let line_offset = unit.len() + 4;
+        unit.extend(0u32.to_le_bytes());
+        let low_pc = unit.len() + 4;
+        unit.extend(0u64.to_le_bytes());
+        unit.extend(text_size.to_le_bytes());
+        unit.extend(self.files.first().map_or("", |t| t.as_str()).bytes().chain([0]));
+        unit.extend(b"sila\0");
+        let data = &mut sections[1].data;
+        data.extend((unit.len() as u32).to_le_bytes());
+        data.extend(unit);
+        sections[1].relocations = vec![
+            Relocation { offset: abbrev_offset as u64, target: Target::Section(abbrev), kind: R_X86_64_32, addend: 0 },
+            Relocation { offset: line_offset as u64, target: Target::Section(line), kind: R_X86_64_32, addend: 0 },
+            Relocation { offset: low_pc as u64, target: Target::Section(TEXT), kind: R_X86_64_64, addend: 0 },
+        ];
+
+        // DWARF 4 line program header: minimum instruction length 1, one operation per
+        // instruction, `is_stmt` by default, line base -5, line range 14 and opcode base 13
+        let mut header = vec![1, 1, 1, (-5i8) as u8, 14, 13, 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+        header.push(0);
+        for file in &self.files {
+            header.extend(file.bytes().chain([0, 0, 0, 0]));
+        }
+        header.push(0);
+        let mut program = vec![0, 9, 2];
+        let address = program.len();
+        program.extend(0u64.to_le_bytes());
+        let (mut offset, mut file, mut line_number, mut column) = (0, 1, 1, 0);
+        for &(row_offset, row_file, row_line, row_column) in &self.rows {
+            if row_file != file {
+                program.push(4);
+                unsigned(&mut program, row_file);
+                file = row_file;
+            }
+            if row_line != line_number {
+                program.push(3);
+                signed(&mut program, row_line as i64 - line_number as i64);
+                line_number = row_line;
+            }
+            if row_column != column {
+                program.push(5);
+                unsigned(&mut program, row_column);
+                column = row_column;
+            }
+            if row_offset != offset {
+                program.push(2);
+                unsigned(&mut program, row_offset - offset);
+                offset = row_offset;
+            }
+            program.push(1);
+        }
+        if text_size != offset {
+            program.push(2);
+            unsigned(&mut program, text_size - offset);
+        }
+        program.extend([0, 1, 1]);
+
+        let data = &mut sections[2].data;
+        let length = 2 + 4 + header.len() + program.len();
+        data.extend((length as u32).to_le_bytes());
+        data.extend(4u16.to_le_bytes());
+        data.extend((header.len() as u32).to_le_bytes());
+        data.extend(header);
+        let address = data.len() + address;
+        data.extend(program);
+        sections[2].relocations = vec![Relocation { offset: address as u64, target: Target::Section(TEXT), kind: R_X86_64_64, addend: 0 }];
+        self.sections.extend(sections);
+    }
+
+    /// Resolves the fixups: local labels in the same section are patched in place, everything
+    /// else becomes a relocation. Local labels are addressed through their section.
+    fn finish(mut self) -> Result<Object, String> {
+        for fixup in std::mem::take(&mut self.fixups) {
+            let (target, addend) = match self.labels.get(&fixup.symbol) {
+                Some(&(section, value)) if section == fixup.section && fixup.kind != R_X86_64_64 && !self.globals.contains(&fixup.symbol) => {
+                    let value = value as i64 + fixup.addend - fixup.offset as i64;
+                    let data = &mut self.sections[section].data;
+                    data[fixup.offset..fixup.offset + 4].copy_from_slice(&(value as i32).to_le_bytes());
+                    continue;
+                }
+                Some(&(section, value)) if !self.globals.contains(&fixup.symbol) => (Target::Section(section), fixup.addend + value as i64),
+                None if !is_symbol(&fixup.symbol) => return Err(format!("Label `{}` is never defined", fixup.symbol)),
+                _ => (Target::Symbol(self.names.iter().position(|t| *t == fixup.symbol).unwrap()), fixup.addend),
+            };
+            // Calls through the PLT only make sense for symbols
+            let kind = match (target, fixup.kind) {
+                (Target::Section(_), R_X86_64_PLT32) => R_X86_64_PC32,
+                (_, kind) => kind,
+            };
+            let relocation = Relocation { offset: fixup.offset as u64, target, kind, addend };
+            self.sections[fixup.section].relocations.push(relocation);
+        }
+        if !self.rows.is_empty() {
+            self.debug_sections();
+        }
+        let symbols = self
+            .names
+            .iter()
+            .map(|name| {
+                let defined = self.labels.get(name);
+                Symbol {
+                    name: name.clone(),
+                    section: defined.map(|t| t.0),
+                    value: defined.map_or(0, |t| t.1),
+                    size: self.sizes.get(name).copied().unwrap_or(0),
+                    global: self.globals.contains(name) || defined.is_none(),
+                    function: self.functions.contains(name),
+                }
+            })
+            .collect();
+        Ok(Object { sections: self.sections, symbols })
+    }
+}
+
+fn object(source: &str) -> Result<Object, String> {
+    let mut assembler = Assembler::new();
+    for (number, line) in source.lines().enumerate() {
+        assembler.line(line).map_err(|e| format!("Line {}: {}", number + 1, e))?;
+    }
+    assembler.finish()
+}
+
+/// Assembles x86-64 GNU assembler `source` into an ELF64 relocatable object. Only the
+/// AT&T syntax the compiler and the runtime emit is understood.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    object(source).map(|t| t.write())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::{entry_point, START_SYMBOL};
+    use crate::interpreter::interpret;
+    use crate::linker::{lld_link, LldFlavor};
+    use crate::runtime::{link_args, runtime_asm, start_asm, AllocatorKind};
+    use crate::testing::check_module_of;
+    use std::process::Command;
+
+    fn text(source: &str) -> Vec<u8> {
+        object(source).unwrap_or_else(|e| panic!("{}: {}", source, e)).sections.swap_remove(TEXT).data
+    }
+
+    /// Same bytes as the GNU assembler, except that branches always take 32-bit displacements.
+    #[test]
+    fn encodes_instructions() {
+        let cases: [(&str, &[u8]); 35] = [
+            ("movq $1, %rax", &[0x48, 0xC7, 0xC0, 0x01, 0x00, 0x00, 0x00]),
+            ("movl $4294967295, %eax", &[0xB8, 0xFF, 0xFF, 0xFF, 0xFF]),
+            ("movabsq $81985529216486895, %rcx", &[0x48, 0xB9, 0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01]),
+            ("movb %sil, -1(%rbp)", &[0x40, 0x88, 0x75, 0xFF]),
+            ("movw %ax, (%rdi)", &[0x66, 0x89, 0x07]),
+            ("movl (%rsp), %r10d", &[0x44, 0x8B, 0x14, 0x24]),
+            ("movq -200(%rbp), %rax", &[0x48, 0x8B, 0x85, 0x38, 0xFF, 0xFF, 0xFF]),
+            ("leaq 8(%rbp,%rax), %rax", &[0x48, 0x8D, 0x44, 0x05, 0x08]),
+            ("movzbl %al, %eax", &[0x0F, 0xB6, 0xC0]),
+            ("movsbq %dil, %rax", &[0x48, 0x0F, 0xBE, 0xC7]),
+            ("movslq -8(%r13), %r9", &[0x4D, 0x63, 0x4D, 0xF8]),
+            ("addq $1000, %rsp", &[0x48, 0x81, 0xC4, 0xE8, 0x03, 0x00, 0x00]),
+            ("andl $1, %eax", &[0x83, 0xE0, 0x01]),
+            ("cmpq %rcx, %rax", &[0x48, 0x39, 0xC8]),
+            ("imulq $24, %rax, %rsi", &[0x48, 0x6B, 0xF0, 0x18]),
+            ("idivq %rcx", &[0x48, 0xF7, 0xF9]),
+            ("incq -16(%rbp)", &[0x48, 0xFF, 0x45, 0xF0]),
+            ("shrq %rcx", &[0x48, 0xD1, 0xE9]),
+            ("btcq $63, %rax", &[0x48, 0x0F, 0xBA, 0xF8, 0x3F]),
+            ("sete %al", &[0x0F, 0x94, 0xC0]),
+            ("cmova %rdi, %rsi", &[0x48, 0x0F, 0x47, 0xF7]),
+            ("pushq %r12", &[0x41, 0x54]),
+            ("movq %rax, %xmm0", &[0x66, 0x48, 0x0F, 0x6E, 0xC0]),
+            ("movq %xmm1, %rdx", &[0x66, 0x48, 0x0F, 0x7E, 0xCA]),
+            ("movq 8(%rsi), %xmm0", &[0xF3, 0x0F, 0x7E, 0x46, 0x08]),
+            ("movq %xmm0, -24(%rbp)", &[0x66, 0x0F, 0xD6, 0x45, 0xE8]),
+            ("movd %eax, %xmm0", &[0x66, 0x0F, 0x6E, 0xC0]),
+            ("addsd %xmm1, %xmm0", &[0xF2, 0x0F, 0x58, 0xC1]),
+            ("ucomiss %xmm0, %xmm1", &[0x0F, 0x2E, 0xC8]),
+            ("cvtsi2sdq %rcx, %xmm0", &[0xF2, 0x48, 0x0F, 0x2A, 0xC1]),
+            ("cvttsd2siq %xmm0, %rax", &[0xF2, 0x48, 0x0F, 0x2C, 0xC0]),
+            ("cvtss2sd %xmm0, %xmm0", &[0xF3, 0x0F, 0x5A, 0xC0]),
+            ("rep movsb", &[0xF3, 0xA4]),
+            ("cqto", &[0x48, 0x99]),
+            ("1:  jne 1b # back", &[0x0F, 0x85, 0xFA, 0xFF, 0xFF, 0xFF]),
+        ];
+        for (source, bytes) in cases {
+            assert_eq!(text(source), bytes, "{}", source);
+        }
+    }
+
+    #[test]
+    fn reports_unknown_instructions() {
+        assert_eq!(object("    ret\n    bogus %rax\n").err().unwrap(), "Line 2: Unknown instruction `bogus`");
+        assert!(object("    jmp .Lmissing\n").is_err());
+        assert!(object("    .bss\n    .byte 1\n").is_err());
+    }
+
+    /// Private functions stay local, calls to functions of other objects go through the PLT.
+    #[test]
+    fn keeps_visibility_and_relocates_extern_calls() {
+        let source = "    .text\n    .globl api\n    .type api, @function\napi:\n    call helper\n    leaq .Lstr0(%rip), %rdi\n    \
+            call puts@PLT\n    ret\n    .size api, .-api\n    .type helper, @function\nhelper:\n    ret\n    \
+            .section .rodata\n.Lstr0:\n    .asciz \"hi\\n\"\n    .section .note.GNU-stack,\"\",@progbits\n";
+        let object = object(source).unwrap();
+        let symbol = |name: &str| object.symbols.iter().position(|t| t.name == name).unwrap();
+        let api = &object.symbols[symbol("api")];
+        assert!(api.global && api.function);
+        assert_eq!((api.section, api.value, api.size), (Some(TEXT), 0, 18));
+        let helper = &object.symbols[symbol("helper")];
+        assert!(!helper.global && helper.function);
+        assert!(object.symbols[symbol("puts")].global && object.symbols[symbol("puts")].section.is_none());
+        assert!(!object.symbols.iter().any(|t| t.name.starts_with(".L")));
+
+        // The call to `helper` is resolved in place
+        assert_eq!(object.sections[TEXT].data[..5], [0xE8, 0x0D, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            object.sections[TEXT].relocations,
+            [
+                Relocation { offset: 8, target: Target::Section(3), kind: R_X86_64_PC32, addend: -4 },
+                Relocation { offset: 13, target: Target::Symbol(symbol("puts")), kind: R_X86_64_PLT32, addend: -4 },
+            ]
+        );
+        assert_eq!(object.sections[3].data, b"hi\n\0");
+    }
+
+    /// Assembles the golden program, links it with the libc runtime and runs it like the interpreter.
+    #[test]
+    fn runs_like_the_interpreter() {
+        let source = include_str!("../tests/golden/program.sila");
+        let modules = [check_module_of(source).unwrap()];
+        let entry = entry_point(&modules[0].ast, &modules[0].name, None).unwrap();
+        let dir = std::env::temp_dir().join(format!("sila-assembler-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut objects = vec![];
+        for (file, source) in [
+            ("program.o", crate::compiler::generate(&modules, &entry)),
+            ("start.o", start_asm(&entry, AllocatorKind::Libc)),
+            ("runtime.o", runtime_asm(AllocatorKind::Libc).to_string()),
+        ] {
+            let object = dir.join(file);
+            std::fs::write(&object, assemble(&source).unwrap()).unwrap();
+            objects.push(object.to_str().unwrap().to_string());
+        }
+        objects.extend(link_args(AllocatorKind::Libc));
+        let program = dir.join("program");
+        let linked = lld_link(LldFlavor::Elf, program.to_str().unwrap().to_string(), false, objects, Some(START_SYMBOL.to_string()));
+        if linked.as_ref().is_err_and(|t| t.starts_with("No linker found")) {
+            return;
+        }
+        linked.unwrap();
+        let run = Command::new(&program).output().unwrap();
+
+        let mut output = vec![];
+        let code = interpret(&modules, &entry, "program", &mut output).unwrap();
+        assert_eq!(run.status.code(), Some(code & 0xff));
+        assert_eq!(run.stdout, output);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,490 @@
+use crate::checker::bind_arguments;
+use crate::interpreter::{literal, size_of, Value};
+use crate::lexer::{CodePosition, Token, TokenType};
+use crate::modules::Module;
+use crate::parser::{ASTNode, Builtin, FunctionMode};
+use crate::types::Type;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    // Constant
+    Const(u32),
+    // Slot
+    Load(u32),
+    // Slot
+    Store(u32),
+    Pop,
+    // Operator, Result type
+    Binary(TokenType, u32),
+    // Target type
+    Cast(u32),
+    // Target instruction
+    Jump(u32),
+    // Target instruction, taken if the popped condition is false
+    JumpIfFalse(u32),
+    // Function
+    Call(u32),
+    // Symbol (constant), Argument count
+    CallHost(u32, u32),
+    // Builtin, Element size
+    Builtin(Builtin, u32),
+    // Element count
+    MakeArray(u32),
+    // Field count
+    MakeStruct(u32),
+    // Array, Index -> Element
+    Index,
+    // Pushes the result and then the `&mut` parameters to the caller
+    Return,
+}
+
+/// Code object of a function. Parameters take the first slots.
+pub struct Function {
+    pub symbol: String,
+    pub module: usize,
+    pub params: u32,
+    pub slots: u32,
+    // Slots of the `&mut` parameters, in order
+    pub references: Vec<u32>,
+    pub code: Vec<Instruction>,
+    // Source position of each instruction
+    pub positions: Vec<CodePosition>,
+}
+
+pub struct Program {
+    pub constants: Vec<Value>,
+    pub types: Vec<Type>,
+    pub functions: Vec<Function>,
+}
+
+impl Program {
+    pub fn function(&self, symbol: &str) -> Option<u32> {
+        self.functions.iter().position(|t| t.symbol == symbol).map(|t| t as u32)
+    }
+}
+
+/// Jumps out of the innermost loop, patched once its end is known.
+#[derive(Default)]
+struct Loop {
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+struct Compiler<'a, 'm> {
+    modules: &'m [Module<'a>],
+    program: Program,
+    // Linker symbol -> function, of every function with a body
+    symbols: HashMap<String, u32>,
+    // State of the function being compiled
+    module: usize,
+    slots: HashMap<*const Token, u32>,
+    slot_count: u32,
+    code: Vec<Instruction>,
+    positions: Vec<CodePosition>,
+    loops: Vec<Loop>,
+}
+
+impl<'a, 'm> Compiler<'a, 'm> {
+    fn new(modules: &'m [Module<'a>]) -> Self {
+        let mut symbols = HashMap::new();
+        for module in modules {
+            for item in &module.ast {
+                if let ASTNode::FunctionDef(_, mode, ..) = item {
+                    if *mode != FunctionMode::Extern {
+                        symbols.insert(item.symbol_name(&module.name).unwrap(), symbols.len() as u32);
+                    }
+                }
+            }
+        }
+        Self {
+            modules,
+            program: Program {
+                constants: vec![],
+                types: vec![],
+                functions: vec![],
+            },
+            symbols,
+            module: 0,
+            slots: HashMap::new(),
+            slot_count: 0,
+            code: vec![],
+            positions: vec![],
+            loops: vec![],
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction, position: CodePosition) -> usize {
+        self.code.push(instruction);
+        self.positions.push(position);
+        self.code.len() - 1
+    }
+
+    /// Points the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let target = self.code.len() as u32;
+        match &mut self.code[at] {
+            Instruction::Jump(t) | Instruction::JumpIfFalse(t) => *t = target,
+            _ => unreachable!(),
+        }
+    }
+
+    fn constant(&mut self, value: Value) -> u32 {
+        match self.program.constants.iter().position(|t| *t == value) {
+            Some(index) => index as u32,
+            None => {
+                self.program.constants.push(value);
+                self.program.constants.len() as u32 - 1
+            }
+        }
+    }
+
+    fn type_index(&mut self, ty: Type) -> u32 {
+        match self.program.types.iter().position(|t| *t == ty) {
+            Some(index) => index as u32,
+            None => {
+                self.program.types.push(ty);
+                self.program.types.len() as u32 - 1
+            }
+        }
+    }
+
+    /// Types are keyed by node, default arguments were checked in the module of their function.
+    fn type_of(&self, node: &ASTNode) -> Type {
+        let module = &self.modules[self.module];
+        let ty = module.types.type_of(node);
+        ty.or_else(|| self.modules.iter().find_map(|t| t.types.type_of(node)))
+            .cloned()
+            .unwrap_or(Type::Void)
+    }
+
+    /// Slot of a variable, keyed by its declaration.
+    fn slot(&mut self, usage: &Token) -> u32 {
+        let declaration = self.modules[self.module].key(usage);
+        match self.slots.get(&declaration) {
+            Some(slot) => *slot,
+            None => {
+                let slot = self.new_slot();
+                self.slots.insert(declaration, slot);
+                slot
+            }
+        }
+    }
+
+    fn new_slot(&mut self) -> u32 {
+        self.slot_count += 1;
+        self.slot_count - 1
+    }
+
+    fn compile_function(&mut self, module: usize, function: &ASTNode<'a>) {
+        let ASTNode::FunctionDef(_, _, _, params, body, _) = function else {
+            unreachable!()
+        };
+        self.module = module;
+        self.slots.clear();
+        self.slot_count = 0;
+
+        let mut references = vec![];
+        for (name, ty, _) in params {
+            let slot = self.slot(name);
+            if matches!(**ty, ASTNode::ReferenceType(_, true, _)) {
+                references.push(slot);
+            }
+        }
+        self.compile_block(body);
+
+        // Functions without a return value end here
+        let end = function.position();
+        let void = self.constant(Value::Void);
+        self.emit(Instruction::Const(void), end);
+        self.emit(Instruction::Return, end);
+
+        self.program.functions.push(Function {
+            symbol: function.symbol_name(&self.modules[module].name).unwrap(),
+            module,
+            params: params.len() as u32,
+            slots: self.slot_count,
+            references,
+            code: std::mem::take(&mut self.code),
+            positions: std::mem::take(&mut self.positions),
+        });
+    }
+
+    fn compile_block(&mut self, body: &[Box<ASTNode<'a>>]) {
+        for statement in body {
+            self.compile_statement(statement);
+        }
+    }
+
+    fn compile_loop_body(&mut self, body: &[Box<ASTNode<'a>>]) -> Loop {
+        self.loops.push(Loop::default());
+        self.compile_block(body);
+        self.loops.pop().unwrap()
+    }
+
+    fn compile_statement(&mut self, statement: &ASTNode<'a>) {
+        let position = statement.position();
+        match statement {
+            ASTNode::VariableSet(name, value, ..) => {
+                let slot = self.slot(name);
+                // Variables without a value are assigned before they are read
+                if let Some(value) = value {
+                    self.compile_expr(value);
+                    self.emit(Instruction::Store(slot), position);
+                }
+            }
+            ASTNode::Assignment(name, value) => {
+                self.compile_expr(value);
+                let slot = self.slot(name);
+                self.emit(Instruction::Store(slot), position);
+            }
+            ASTNode::Return(value) => {
+                self.compile_expr(value);
+                self.emit(Instruction::Return, position);
+            }
+            ASTNode::Break(_) => {
+                let jump = self.emit(Instruction::Jump(0), position);
+                self.loops.last_mut().unwrap().breaks.push(jump);
+            }
+            ASTNode::Continue(_) => {
+                let jump = self.emit(Instruction::Jump(0), position);
+                self.loops.last_mut().unwrap().continues.push(jump);
+            }
+            ASTNode::If(condition, then_body, else_body) => {
+                self.compile_expr(condition);
+                let to_else = self.emit(Instruction::JumpIfFalse(0), condition.position());
+                self.compile_block(then_body);
+                let to_end = self.emit(Instruction::Jump(0), position);
+                self.patch(to_else);
+                self.compile_block(else_body);
+                self.patch(to_end);
+            }
+            ASTNode::WhileLoop(condition, body) => {
+                let start = self.code.len() as u32;
+                self.compile_expr(condition);
+                let to_end = self.emit(Instruction::JumpIfFalse(0), condition.position());
+                let jumps = self.compile_loop_body(body);
+                self.emit(Instruction::Jump(start), position);
+                for jump in jumps.continues {
+                    self.code[jump] = Instruction::Jump(start);
+                }
+                self.patch(to_end);
+                jumps.breaks.into_iter().for_each(|t| self.patch(t));
+            }
+            expr => {
+                self.compile_expr(expr);
+                self.emit(Instruction::Pop, position);
+            }
+        }
+    }
+
+    fn compile_expr(&mut self, node: &ASTNode<'a>) {
+        let position = node.position();
+        match node {
+            ASTNode::Literal(t) => {
+                let constant = self.constant(literal(t, &self.type_of(node)));
+                self.emit(Instruction::Const(constant), position);
+            }
+            ASTNode::String(t) => {
+                let constant = self.constant(Value::Str(t.content.as_str().into()));
+                self.emit(Instruction::Const(constant), position);
+            }
+            ASTNode::Identifier(name) => {
+                let slot = self.slot(name);
+                self.emit(Instruction::Load(slot), position);
+            }
+            ASTNode::BinaryOp(lhs, op, rhs) => {
+                self.compile_expr(lhs);
+                self.compile_expr(rhs);
+                let ty = self.type_index(self.type_of(node));
+                // Divisions report division by zero at the divisor
+                let at = if op.token_type == TokenType::Slash { rhs.position() } else { position };
+                self.emit(Instruction::Binary(op.token_type, ty), at);
+            }
+            ASTNode::CastExpr(expr, ty) => {
+                self.compile_expr(expr);
+                let ty = self.type_index(Type::from_node(ty).unwrap());
+                self.emit(Instruction::Cast(ty), expr.position());
+            }
+            ASTNode::FunctionCall(name, args, _) => self.compile_call(node, name, args),
+            ASTNode::NamedArgument(_, value) => self.compile_expr(value),
+            ASTNode::BuiltinCall(builtin, _, type_arg, args, _) => {
+                let element_size = match type_arg {
+                    Some(ty) => size_of(self.modules, &Type::from_node(ty).unwrap()),
+                    None => 0,
+                };
+                args.iter().for_each(|t| self.compile_expr(t));
+                self.emit(Instruction::Builtin(*builtin, element_size as u32), position);
+            }
+            ASTNode::StructLiteral(_, fields) => {
+                fields.iter().for_each(|(_, t)| self.compile_expr(t));
+                self.emit(Instruction::MakeStruct(fields.len() as u32), position);
+            }
+            ASTNode::ArrayLiteral(_, elements) => {
+                elements.iter().for_each(|t| self.compile_expr(t));
+                self.emit(Instruction::MakeArray(elements.len() as u32), position);
+            }
+            ASTNode::Index(array, index) => {
+                self.compile_expr(array);
+                self.compile_expr(index);
+                self.emit(Instruction::Index, position);
+            }
+            _ => {
+                let void = self.constant(Value::Void);
+                self.emit(Instruction::Const(void), position);
+            }
+        }
+    }
+
+    fn compile_call(&mut self, node: &ASTNode<'a>, name: &'a Token, args: &[Box<ASTNode<'a>>]) {
+        let position = node.position();
+        let module = &self.modules[self.module];
+        let function = module.callee(name);
+        let ASTNode::FunctionDef(function_name, mode, _, params, ..) = function else {
+            unreachable!()
+        };
+
+        // Arguments are evaluated where they are written, defaults included
+        let bound = bind_arguments(name, args, function_name, params).unwrap();
+        for value in &bound {
+            self.compile_expr(value);
+        }
+
+        let symbol = function.symbol_name(&module.name).unwrap();
+        let callee = match (mode, self.symbols.get(&symbol)) {
+            (FunctionMode::Extern, None) => {
+                let symbol = self.constant(Value::Str(symbol.into()));
+                self.emit(Instruction::CallHost(symbol, bound.len() as u32), position);
+                return;
+            }
+            (_, callee) => *callee.unwrap(),
+        };
+        self.emit(Instruction::Call(callee), position);
+
+        // `&mut` arguments are variables, they take the final value of the parameter.
+        // The callee returns those values above its result, last one on top
+        for (param, arg) in params.iter().zip(bound).rev() {
+            if let (ASTNode::ReferenceType(_, true, _), ASTNode::Identifier(variable)) = (&*param.1, arg) {
+                let slot = self.slot(variable);
+                self.emit(Instruction::Store(slot), arg.position());
+            }
+        }
+    }
+}
+
+/// Compiles every function of the checked `modules` to bytecode.
+pub fn compile(modules: &[Module]) -> Program {
+    let mut compiler = Compiler::new(modules);
+    for (index, module) in modules.iter().enumerate() {
+        for item in &module.ast {
+            if let ASTNode::FunctionDef(_, mode, ..) = item {
+                if *mode != FunctionMode::Extern {
+                    compiler.compile_function(index, item);
+                }
+            }
+        }
+    }
+    compiler.program
+}
+
+fn show_value(value: &Value) -> String {
+    match value {
+        Value::Int(value) => format!("int {}", value),
+        Value::Float(value) => format!("float {:?}", value),
+        Value::Bool(value) => format!("bool {}", value),
+        Value::Str(text) => format!("str {:?}", text),
+        Value::Void => "void".to_string(),
+        value => format!("{:?}", value),
+    }
+}
+
+fn show_instruction(program: &Program, instruction: &Instruction) -> String {
+    match instruction {
+        Instruction::Const(index) => format!("Const #{} ({})", index, show_value(&program.constants[*index as usize])),
+        Instruction::Binary(op, ty) => format!("Binary {} {}", operator(*op), program.types[*ty as usize]),
+        Instruction::Cast(ty) => format!("Cast {}", program.types[*ty as usize]),
+        Instruction::Call(function) => format!("Call {}", program.functions[*function as usize].symbol),
+        Instruction::CallHost(symbol, count) => {
+            format!("CallHost {} ({} args)", show_value(&program.constants[*symbol as usize]), count)
+        }
+        // `Load(0)` reads as `Load 0`
+        instruction => format!("{:?}", instruction).replacen('(', " ", 1).replace(')', ""),
+    }
+}
+
+fn operator(op: TokenType) -> &'static str {
+    match op {
+        TokenType::Plus => "+",
+        TokenType::Minus => "-",
+        TokenType::Star => "*",
+        TokenType::Slash => "/",
+        TokenType::DoubleEquals => "==",
+        TokenType::NotEquals => "!=",
+        TokenType::Lesser => "<",
+        TokenType::Greater => ">",
+        TokenType::LesserEquals => "<=",
+        TokenType::GreaterEquals => ">=",
+        _ => unreachable!(),
+    }
+}
+
+/// Listing of the constant pool and the code of every function. Each instruction
+/// shows the `line:column` it was compiled from.
+pub fn disassemble(program: &Program, modules: &[Module]) -> String {
+    let mut listing = String::from("constants:\n");
+    for (index, value) in program.constants.iter().enumerate() {
+        writeln!(listing, "    #{:<4} {}", index, show_value(value)).unwrap();
+    }
+    for function in &program.functions {
+        writeln!(
+            listing,
+            "\nfn {} ({}.sila, params: {}, slots: {}, references: {:?})",
+            function.symbol, modules[function.module].name, function.params, function.slots, function.references
+        )
+        .unwrap();
+        for (index, (instruction, position)) in function.code.iter().zip(&function.positions).enumerate() {
+            let location = format!("{}:{}", position.line_start + 1, position.line_idx_start + 1);
+            writeln!(listing, "    {:04}  {:<8} {}", index, location, show_instruction(program, instruction)).unwrap();
+        }
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::check_module_of;
+
+    fn listing(source: &str) -> String {
+        let modules = [check_module_of(source).unwrap()];
+        disassemble(&compile(&modules), &modules)
+    }
+
+    #[test]
+    fn lists_instructions_with_their_positions() {
+        let listing = listing("def main(): i32 {\n    let x = 6;\n    return x / 2;\n}");
+        let main = listing.split("\nfn ").nth(1).unwrap();
+        let lines: Vec<&str> = main.lines().skip(1).map(str::trim).collect();
+        assert_eq!(
+            lines,
+            [
+                "0000  2:13     Const #0 (int 6)",
+                "0001  2:9      Store 0",
+                "0002  3:12     Load 0",
+                "0003  3:16     Const #1 (int 2)",
+                "0004  3:16     Binary / i32",
+                "0005  3:12     Return",
+                "0006  1:5      Const #2 (void)",
+                "0007  1:5      Return",
+            ]
+        );
+    }
+
+    #[test]
+    fn shares_constants_between_functions() {
+        let listing = listing("def one(): i32 {\n    return 1;\n}\n\ndef main(): i32 {\n    return one() + 1;\n}");
+        assert_eq!(listing.matches("int 1\n").count(), 1);
+        assert!(listing.contains("Call test__one\n"));
+    }
+}
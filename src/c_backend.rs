@@ -0,0 +1,667 @@
+use crate::checker::bind_arguments;
+use crate::entry::EntryPoint;
+use crate::lexer::{CodePosition, Token, TokenType};
+use crate::modules::Module;
+use crate::parser::{ASTNode, Builtin, FunctionMode};
+use crate::types::Type;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+const C_KEYWORDS: [&str; 44] = [
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum", "extern",
+    "float", "for", "goto", "if", "inline", "int", "long", "register", "restrict", "return", "short", "signed",
+    "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void", "volatile", "while", "_Bool",
+    "_Complex", "_Imaginary", "bool", "true", "false", "main", "size_t", "NULL", "errno",
+];
+
+/// The C library functions the heap builtins forward to.
+const HEAP_FUNCTIONS: [(&str, &str); 3] = [
+    ("malloc", "void *malloc(size_t size);"),
+    ("free", "void free(void *pointer);"),
+    ("realloc", "void *realloc(void *pointer, size_t size);"),
+];
+
+/// Name that is a valid C identifier and not taken by anything else in scope.
+fn identifier(name: &str, taken: &HashSet<String>) -> String {
+    // The `sila_` prefix is reserved for generated names
+    let mut name = if name.starts_with("sila_") { format!("user_{}", name) } else { name.to_string() };
+    while C_KEYWORDS.contains(&name.as_str()) || taken.contains(&name) {
+        name.push('_');
+    }
+    name
+}
+
+/// Part of a type in the names of generated types, `[*u8; 4]` is `array_ptr_u8_4`.
+fn mangle(ty: &Type) -> String {
+    match ty {
+        Type::Pointer(pointee) => format!("ptr_{}", mangle(pointee)),
+        Type::Array(element, length) => format!("array_{}_{}", mangle(element), length),
+        ty => ty.to_string(),
+    }
+}
+
+/// C string literal with the same bytes as `text`.
+pub(crate) fn string_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' | b'\\' => write!(literal, "\\{}", byte as char).unwrap(),
+            // `?` would start trigraphs
+            b' '..=b'~' if byte != b'?' => literal.push(byte as char),
+            _ => write!(literal, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+struct CBackend<'a, 'm> {
+    modules: &'m [Module<'a>],
+    // Linker symbols of the functions with a body
+    defined: HashSet<String>,
+    // Struct name -> fields
+    structs: HashMap<String, Vec<(String, Type)>>,
+    // Type definitions in the order they depend on each other
+    types: String,
+    defined_types: HashSet<String>,
+    // Helpers for float to integer casts, by name
+    helpers: HashMap<String, String>,
+    // State of the function being generated
+    module: usize,
+    locals: HashMap<*const Token, String>,
+    // Parameters passed by reference, accessed through a pointer
+    references: HashSet<*const Token>,
+    taken: HashSet<String>,
+    return_type: Type,
+}
+
+impl<'a, 'm> CBackend<'a, 'm> {
+    fn new(modules: &'m [Module<'a>]) -> Self {
+        let mut defined = HashSet::new();
+        let mut structs = HashMap::new();
+        for module in modules {
+            for item in &module.ast {
+                match item {
+                    ASTNode::FunctionDef(_, mode, ..) if *mode != FunctionMode::Extern => {
+                        defined.insert(item.symbol_name(&module.name).unwrap());
+                    }
+                    ASTNode::StructDef(name, fields) => {
+                        let fields = fields
+                            .iter()
+                            .map(|(name, ty)| (name.content.clone(), Type::from_node(ty).unwrap()))
+                            .collect();
+                        structs.insert(name.content.clone(), fields);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Self {
+            modules,
+            defined,
+            structs,
+            types: String::new(),
+            defined_types: HashSet::new(),
+            helpers: HashMap::new(),
+            module: 0,
+            locals: HashMap::new(),
+            references: HashSet::new(),
+            taken: HashSet::new(),
+            return_type: Type::Void,
+        }
+    }
+
+    /// C name of a linker symbol. C programs start at `main`, so a Sila function named like that is renamed.
+    fn function_name(symbol: &str) -> String {
+        match symbol {
+            "main" => "sila_main".to_string(),
+            symbol => symbol.to_string(),
+        }
+    }
+
+    /// C spelling of `ty`. Structs and arrays it uses get defined first.
+    fn c_type(&mut self, ty: &Type) -> String {
+        match ty {
+            Type::Int(bits, true) => format!("int{}_t", bits),
+            Type::Int(bits, false) => format!("uint{}_t", bits),
+            Type::Float(32) => "float".to_string(),
+            Type::Float(_) => "double".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::Str => "const char *".to_string(),
+            Type::Void | Type::Infer(..) => "void".to_string(),
+            Type::Pointer(pointee) => {
+                let pointee = self.c_type(pointee);
+                match pointee.ends_with('*') {
+                    true => format!("{}*", pointee),
+                    false => format!("{} *", pointee),
+                }
+            }
+            Type::Array(..) | Type::Struct(_) => {
+                self.define_type(ty);
+                match ty {
+                    Type::Struct(name) => format!("struct {}", name),
+                    ty => format!("sila_{}", mangle(ty)),
+                }
+            }
+        }
+    }
+
+    /// `c_type` followed by a name, without a space after pointers.
+    fn declaration(&mut self, ty: &Type, name: &str) -> String {
+        let ty = self.c_type(ty);
+        match ty.ends_with('*') {
+            true => format!("{}{}", ty, name),
+            false => format!("{} {}", ty, name),
+        }
+    }
+
+    /// Arrays are wrapped in structs, so they are values like in Sila.
+    fn define_type(&mut self, ty: &Type) {
+        let name = mangle(ty);
+        if !self.defined_types.insert(name.clone()) {
+            return;
+        }
+        let definition = match ty {
+            Type::Array(element, length) => {
+                let element = self.declaration(element, &format!("items[{}]", length));
+                format!("typedef struct {{\n    {};\n}} sila_{};\n\n", element, name)
+            }
+            Type::Struct(name) => {
+                let mut definition = format!("struct {} {{\n", name);
+                for (field, ty) in self.structs[name].clone() {
+                    let field = self.declaration(&ty, &field);
+                    writeln!(definition, "    {};", field).unwrap();
+                }
+                definition + "};\n\n"
+            }
+            _ => unreachable!(),
+        };
+        self.types.push_str(&definition);
+    }
+
+    fn type_of(&self, node: &ASTNode) -> Type {
+        let module = &self.modules[self.module];
+        let ty = module.types.type_of(node);
+        ty.or_else(|| self.modules.iter().find_map(|t| t.types.type_of(node)))
+            .cloned()
+            .unwrap_or(Type::Void)
+    }
+
+    fn type_of_declaration(&self, name: &Token) -> Type {
+        self.modules[self.module].types.type_of_declaration(name).cloned().unwrap_or(Type::Void)
+    }
+
+    /// Gives the variable declared by `name` a C name.
+    fn declare(&mut self, name: &Token) -> String {
+        let local = identifier(&name.content, &self.taken);
+        self.taken.insert(local.clone());
+        self.locals.insert(name as *const Token, local.clone());
+        local
+    }
+
+    fn line_directive(&self, position: CodePosition) -> String {
+        let file = &self.modules[self.module].file_manager.input_file;
+        format!("#line {} {}\n", position.line_start + 1, string_literal(file))
+    }
+
+    fn signature(&mut self, function: &ASTNode<'a>, module: usize) -> String {
+        let ASTNode::FunctionDef(_, mode, ret, params, ..) = function else {
+            unreachable!()
+        };
+        let symbol = function.symbol_name(&self.modules[module].name).unwrap();
+        let storage = match mode {
+            FunctionMode::Export | FunctionMode::Extern => "",
+            FunctionMode::Private | FunctionMode::Default => "static ",
+        };
+        let mut list = vec![];
+        for (name, ty, _) in params {
+            let param = match &**ty {
+                ASTNode::ReferenceType(_, mutable, referenced) => {
+                    let referenced = self.c_type(&Type::from_node(referenced).unwrap());
+                    let constness = if *mutable { "" } else { "const " };
+                    format!("{}{} *{}", constness, referenced, self.declare(name))
+                }
+                ty => {
+                    let name = self.declare(name);
+                    self.declaration(&Type::from_node(ty).unwrap(), &name)
+                }
+            };
+            list.push(param);
+        }
+        if list.is_empty() {
+            list.push("void".to_string());
+        }
+        let name = format!("{}({})", Self::function_name(&symbol), list.join(", "));
+        format!("{}{}", storage, self.declaration(&Type::from_node(ret).unwrap(), &name))
+    }
+
+    fn function(&mut self, module: usize, function: &ASTNode<'a>) -> (String, String) {
+        let ASTNode::FunctionDef(_, _, ret, params, body, _) = function else {
+            unreachable!()
+        };
+        self.module = module;
+        self.locals.clear();
+        self.references.clear();
+        self.taken = self.defined.iter().map(|t| Self::function_name(t)).collect();
+        self.return_type = Type::from_node(ret).unwrap();
+
+        let signature = self.signature(function, module);
+        for (name, ty, _) in params {
+            if matches!(**ty, ASTNode::ReferenceType(..)) {
+                self.references.insert(*name as *const Token);
+            }
+        }
+        let mut code = self.line_directive(function.position());
+        writeln!(code, "{} {{", signature).unwrap();
+        self.block(&mut code, body, 1);
+        code.push_str("}\n");
+        (signature, code)
+    }
+
+    fn block(&mut self, code: &mut String, body: &[Box<ASTNode<'a>>], depth: usize) {
+        for statement in body {
+            self.statement(code, statement, depth);
+        }
+    }
+
+    fn statement(&mut self, code: &mut String, statement: &ASTNode<'a>, depth: usize) {
+        let indent = "    ".repeat(depth);
+        code.push_str(&self.line_directive(statement.position()));
+        match statement {
+            ASTNode::VariableSet(name, value, ..) => {
+                let ty = self.type_of_declaration(name);
+                let local = self.declare(name);
+                match (ty, value) {
+                    // Calls of functions without a value are kept for their effects
+                    (Type::Void, Some(value)) => writeln!(code, "{}{};", indent, self.expr(value)).unwrap(),
+                    (Type::Void, None) => {}
+                    (ty, Some(value)) => {
+                        let declaration = self.declaration(&ty, &local);
+                        writeln!(code, "{}{} = {};", indent, declaration, self.expr(value)).unwrap()
+                    }
+                    (ty, None) => writeln!(code, "{}{};", indent, self.declaration(&ty, &local)).unwrap(),
+                }
+            }
+            ASTNode::Assignment(name, value) => {
+                let value = self.expr(value);
+                writeln!(code, "{}{} = {};", indent, self.variable(name), value).unwrap();
+            }
+            ASTNode::Return(value) => match (&self.return_type, &**value) {
+                (Type::Void, ASTNode::Identifier(_)) => writeln!(code, "{}return;", indent).unwrap(),
+                (Type::Void, value) => writeln!(code, "{}{};\n{}return;", indent, self.expr(value), indent).unwrap(),
+                (_, value) => writeln!(code, "{}return {};", indent, self.expr(value)).unwrap(),
+            },
+            ASTNode::Break(_) => writeln!(code, "{}break;", indent).unwrap(),
+            ASTNode::Continue(_) => writeln!(code, "{}continue;", indent).unwrap(),
+            ASTNode::If(condition, then_body, else_body) => {
+                writeln!(code, "{}if ({}) {{", indent, self.condition(condition)).unwrap();
+                self.block(code, then_body, depth + 1);
+                if !else_body.is_empty() {
+                    writeln!(code, "{}}} else {{", indent).unwrap();
+                    self.block(code, else_body, depth + 1);
+                }
+                writeln!(code, "{}}}", indent).unwrap();
+            }
+            ASTNode::WhileLoop(condition, body) => {
+                writeln!(code, "{}while ({}) {{", indent, self.condition(condition)).unwrap();
+                self.block(code, body, depth + 1);
+                writeln!(code, "{}}}", indent).unwrap();
+            }
+            expr => writeln!(code, "{}{};", indent, self.expr(expr)).unwrap(),
+        }
+    }
+
+    /// Expression in the parentheses of `if` and `while`, without another pair around it.
+    fn condition(&mut self, node: &ASTNode<'a>) -> String {
+        let condition = self.expr(node);
+        match node {
+            ASTNode::BinaryOp(..) if condition.starts_with('(') && condition.ends_with(')') => {
+                condition[1..condition.len() - 1].to_string()
+            }
+            _ => condition,
+        }
+    }
+
+    /// C expression reading a variable, parameters passed by reference are dereferenced.
+    fn variable(&self, name: &Token) -> String {
+        let key = self.modules[self.module].key(name);
+        let local = &self.locals[&key];
+        match self.references.contains(&key) {
+            true => format!("(*{})", local),
+            false => local.clone(),
+        }
+    }
+
+    fn literal(&self, token: &Token, ty: &Type) -> String {
+        match (token.token_type, ty) {
+            (TokenType::Boolean, _) => token.content.clone(),
+            (_, Type::Float(bits)) => {
+                let mut literal = token.content.clone();
+                if !literal.contains(['.', 'e', 'E']) {
+                    literal.push_str(".0");
+                }
+                if *bits == 32 {
+                    literal.push('f');
+                }
+                literal
+            }
+            // Larger values do not fit C's `int`
+            _ => match token.content.parse::<i128>().is_ok_and(|t| t <= i32::MAX as i128) {
+                true => token.content.clone(),
+                false => format!("UINT64_C({})", token.content),
+            },
+        }
+    }
+
+    fn expr(&mut self, node: &ASTNode<'a>) -> String {
+        match node {
+            ASTNode::Literal(t) => self.literal(t, &self.type_of(node)),
+            ASTNode::String(t) => string_literal(&t.content),
+            ASTNode::Identifier(name) => self.variable(name),
+            ASTNode::BinaryOp(lhs, op, rhs) => self.binary_op(node, lhs, op, rhs),
+            ASTNode::CastExpr(expr, ty) => {
+                let (from, to) = (self.type_of(expr), Type::from_node(ty).unwrap());
+                let value = self.expr(expr);
+                self.cast(value, &from, &to)
+            }
+            ASTNode::FunctionCall(name, args, _) => self.call(name, args),
+            ASTNode::NamedArgument(_, value) => self.expr(value),
+            ASTNode::BuiltinCall(builtin, _, type_arg, args, _) => {
+                let args: Vec<String> = args.iter().map(|t| self.expr(t)).collect();
+                let element = type_arg.as_ref().map(|t| Type::from_node(t).unwrap());
+                let element = element.map(|t| self.c_type(&t));
+                match (builtin, element) {
+                    (Builtin::Alloc, Some(element)) => {
+                        format!("(({0} *)sila_alloc((size_t)({1}) * sizeof({0})))", element, args[0])
+                    }
+                    (Builtin::Realloc, Some(element)) => format!(
+                        "(({0} *)sila_realloc({1}, (size_t)({2}) * sizeof({0})))",
+                        element, args[0], args[1]
+                    ),
+                    _ => format!("sila_free({})", args[0]),
+                }
+            }
+            ASTNode::StructLiteral(name, fields) => {
+                let ty = self.c_type(&Type::Struct(name.content.clone()));
+                let fields: Vec<String> =
+                    fields.iter().map(|(field, value)| format!(".{} = {}", field.content, self.expr(value))).collect();
+                format!("(({}){{{}}})", ty, fields.join(", "))
+            }
+            ASTNode::ArrayLiteral(_, elements) => {
+                let ty = self.c_type(&self.type_of(node));
+                let elements: Vec<String> = elements.iter().map(|t| self.expr(t)).collect();
+                format!("(({}){{{{{}}}}})", ty, elements.join(", "))
+            }
+            ASTNode::Index(array, index) => format!("{}.items[{}]", self.expr(array), self.expr(index)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Integers wrap around like in the other backends, so they are computed unsigned.
+    fn binary_op(&mut self, node: &ASTNode<'a>, lhs: &ASTNode<'a>, op: &Token, rhs: &ASTNode<'a>) -> String {
+        let (lhs, rhs) = (self.expr(lhs), self.expr(rhs));
+        let ty = self.type_of(node);
+        // Two-character tokens only keep their last character as content
+        let c_op = op.token_type.visualize();
+        match (op.token_type, &ty) {
+            (TokenType::Plus | TokenType::Minus | TokenType::Star, Type::Int(bits, _)) => {
+                let unsigned = if *bits == 64 { "uint64_t" } else { "uint32_t" };
+                let ty = self.c_type(&ty);
+                format!("(({0})(({1}){2} {3} ({1}){4}))", ty, unsigned, lhs, c_op, rhs)
+            }
+            (TokenType::Slash, Type::Int(..)) => format!("(({})({} / {}))", self.c_type(&ty), lhs, rhs),
+            _ => format!("({} {} {})", lhs, c_op, rhs),
+        }
+    }
+
+    fn cast(&mut self, value: String, from: &Type, to: &Type) -> String {
+        match (from, to) {
+            (Type::Float(bits), Type::Int(..)) => {
+                let helper = format!("sila_{}_to_{}", mangle(from), mangle(to));
+                if !self.helpers.contains_key(&helper) {
+                    let definition = self.float_to_int(&helper, *bits, to);
+                    self.helpers.insert(helper.clone(), definition);
+                }
+                format!("{}({})", helper, value)
+            }
+            (Type::Pointer(_), Type::Int(..)) => format!("(({})(uintptr_t){})", self.c_type(to), value),
+            (Type::Int(..), Type::Pointer(_)) => format!("(({})(uintptr_t){})", self.c_type(to), value),
+            (from, to) if from == to => value,
+            (_, to) => format!("(({}){})", self.c_type(to), value),
+        }
+    }
+
+    /// Float to integer conversions truncate and saturate, out of range values are undefined in C.
+    fn float_to_int(&mut self, name: &str, bits: u8, to: &Type) -> String {
+        let Type::Int(to_bits, signed) = to else {
+            unreachable!()
+        };
+        let (min, max) = match signed {
+            true => (format!("INT{}_MIN", to_bits), format!("INT{}_MAX", to_bits)),
+            false => ("0".to_string(), format!("UINT{}_MAX", to_bits)),
+        };
+        let (float, int) = (self.c_type(&Type::Float(bits)), self.c_type(to));
+        format!(
+            "static {int} {name}({float} value) {{\n    \
+                if (value != value) return 0;\n    \
+                if (value <= ({float}){min}) return {min};\n    \
+                if (value >= ({float}){max}) return {max};\n    \
+                return ({int})value;\n}}\n\n",
+        )
+    }
+
+    fn call(&mut self, name: &'a Token, args: &[Box<ASTNode<'a>>]) -> String {
+        let module = &self.modules[self.module];
+        let function = module.callee(name);
+        let ASTNode::FunctionDef(function_name, _, _, params, ..) = function else {
+            unreachable!()
+        };
+        let symbol = function.symbol_name(&module.name).unwrap();
+
+        let bound = bind_arguments(name, args, function_name, params).unwrap();
+        let mut list = vec![];
+        for ((_, ty, _), arg) in params.iter().zip(bound) {
+            let value = self.expr(arg);
+            list.push(match (&**ty, arg) {
+                // A reference parameter passed on keeps its pointer
+                (ASTNode::ReferenceType(..), ASTNode::Identifier(variable))
+                    if self.references.contains(&self.modules[self.module].key(variable)) =>
+                {
+                    self.locals[&self.modules[self.module].key(variable)].clone()
+                }
+                (ASTNode::ReferenceType(..), ASTNode::Identifier(_)) => format!("&{}", value),
+                (ASTNode::ReferenceType(_, _, referenced), _) => {
+                    let referenced = self.c_type(&Type::from_node(referenced).unwrap());
+                    format!("&({}){{{}}}", referenced, value)
+                }
+                _ => value,
+            });
+        }
+        format!("{}({})", Self::function_name(&symbol), list.join(", "))
+    }
+}
+
+/// C99 source of the checked `modules` as one translation unit. `#line` directives map
+/// every statement back to its `.sila` file, so C compiler errors and debuggers point there.
+pub fn generate(modules: &[Module], entry: &EntryPoint) -> String {
+    let mut backend = CBackend::new(modules);
+    let mut prototypes = String::new();
+    let mut bodies = String::new();
+    let mut externs = HashSet::new();
+
+    for (index, module) in modules.iter().enumerate() {
+        for item in &module.ast {
+            let ASTNode::FunctionDef(_, mode, ..) = item else {
+                continue;
+            };
+            let symbol = item.symbol_name(&module.name).unwrap();
+            // Externs defined in another module are already declared
+            if *mode == FunctionMode::Extern {
+                if !backend.defined.contains(&symbol) && externs.insert(symbol) {
+                    let signature = backend.signature(item, index);
+                    writeln!(prototypes, "{};", signature).unwrap();
+                }
+                continue;
+            }
+            let (signature, body) = backend.function(index, item);
+            writeln!(prototypes, "{};", signature).unwrap();
+            write!(bodies, "\n{}", body).unwrap();
+        }
+    }
+
+    let mut source = String::from(
+        "/* Generated by the Sila compiler */\n#include <stdbool.h>\n#include <stddef.h>\n#include <stdint.h>\n\n",
+    );
+    let mut names: Vec<String> = backend.structs.keys().cloned().collect();
+    names.sort();
+    for name in names {
+        writeln!(source, "struct {};", name).unwrap();
+        backend.define_type(&Type::Struct(name));
+    }
+    if !backend.structs.is_empty() {
+        source.push('\n');
+    }
+    source.push_str(&backend.types);
+
+    // Heap builtins, a program may declare the C library functions itself
+    for (name, prototype) in HEAP_FUNCTIONS {
+        if !externs.contains(name) && !backend.defined.contains(name) {
+            writeln!(source, "{}", prototype).unwrap();
+        }
+    }
+    source.push_str(
+        "static inline void *sila_alloc(size_t size) { return (void *)malloc(size); }\n\
+         static inline void sila_free(void *pointer) { free(pointer); }\n\
+         static inline void *sila_realloc(void *pointer, size_t size) { return (void *)realloc(pointer, size); }\n\n",
+    );
+    let mut helpers: Vec<&String> = backend.helpers.values().collect();
+    helpers.sort();
+    helpers.into_iter().for_each(|t| source.push_str(t));
+    source.push_str(&prototypes);
+    source.push_str(&bodies);
+
+    let args = if entry.takes_args { "argc, (uint8_t **)argv" } else { "" };
+    writeln!(
+        source,
+        "\nint main(int argc, char **argv) {{\n    (void)argc;\n    (void)argv;\n    return {}({});\n}}",
+        CBackend::function_name(&entry.symbol),
+        args
+    )
+    .unwrap();
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::entry_point;
+    use crate::interpreter::interpret;
+    use crate::testing::check_module_of;
+    use std::process::Command;
+
+    fn generate_c(source: &str) -> String {
+        let modules = [check_module_of(source).unwrap_or_else(|e| panic!("{}: {}", e.title, e.footer))];
+        let entry = entry_point(&modules[0].ast, &modules[0].name, None).unwrap();
+        generate(&modules, &entry)
+    }
+
+    #[test]
+    fn declares_functions_by_mode() {
+        let source = generate_c(
+            "def extern puts(s: str): i32 {\n}\n\ndef private helper(): i32 {\n    return 1;\n}\n\n\
+             def export api(x: &mut i32): void {\n    x = helper();\n}\n\ndef main(): i32 {\n    return 0;\n}",
+        );
+        assert!(source.contains("\nint32_t puts(const char *s);\n"));
+        assert!(source.contains("\nstatic int32_t test__helper(void);\n"));
+        assert!(source.contains("\nvoid api(int32_t *x);\n"));
+        assert!(source.contains("    (*x) = test__helper();\n"));
+        assert!(source.contains("    return test__main();\n"));
+    }
+
+    #[test]
+    fn maps_statements_to_sila_lines() {
+        let source = generate_c("def main(): i32 {\n    let x = 1;\n\n    return x;\n}");
+        let body = &source[source.find("#line 1").unwrap()..];
+        assert!(body.starts_with(
+            "#line 1 \"test.sila\"\nstatic int32_t test__main(void) {\n#line 2 \"test.sila\"\n    int32_t x = 1;\n\
+             #line 4 \"test.sila\"\n    return x;\n}\n"
+        ));
+    }
+
+    #[test]
+    fn keeps_integer_semantics() {
+        let source = generate_c("def main(): i32 {\n    let b: u8 = 200;\n    let f = 2.5 -> i32;\n    return ((b + b) -> i32) + f;\n}");
+        assert!(source.contains("uint8_t b = 200;"));
+        assert!(source.contains("((uint8_t)((uint32_t)b + (uint32_t)b))"));
+        assert!(source.contains("int32_t f = sila_f64_to_i32(2.5);"));
+        assert!(source.contains("static int32_t sila_f64_to_i32(double value) {"));
+    }
+
+    #[test]
+    fn renames_clashing_names() {
+        assert_eq!(identifier("int", &HashSet::new()), "int_");
+        assert_eq!(identifier("sila_end_1", &HashSet::new()), "user_sila_end_1");
+        assert_eq!(identifier("puts", &HashSet::from(["puts".to_string()])), "puts_");
+        assert_eq!(string_literal("a\"b\\??="), "\"a\\\"b\\\\\\077\\077=\"");
+    }
+
+    /// Builds the generated C with the system compiler, the program has to behave like in the interpreter.
+    fn run_like_the_interpreter(source: &str) {
+        if Command::new("cc").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("sila-c-{}-{:x}", std::process::id(), source.len()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("program.c"), generate_c(source)).unwrap();
+        let built = Command::new("cc")
+            .args(["-std=c99", "-Werror", "-Wno-unused", "-o", "program", "program.c"])
+            .current_dir(&dir)
+            .status()
+            .unwrap();
+        assert!(built.success());
+        let run = Command::new(dir.join("program")).output().unwrap();
+
+        let modules = [check_module_of(source).unwrap()];
+        let entry = entry_point(&modules[0].ast, &modules[0].name, None).unwrap();
+        let mut output = vec![];
+        let code = interpret(&modules, &entry, "program", &mut output).unwrap();
+        assert_eq!(run.status.code(), Some(code & 0xff));
+        assert_eq!(run.stdout, output);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn runs_like_the_interpreter() {
+        run_like_the_interpreter(include_str!("../tests/golden/program.sila"));
+        run_like_the_interpreter(
+            "def extern puts(s: str): i32 {\n}\n\nstruct Pair {\n    a: i64,\n    b: i64\n}\n\n\
+            def swap(x: &mut i32, y: &mut i32): void {\n    let t = x;\n    x = y;\n    y = t;\n}\n\n\
+            def total(values: [i32; 3]): i32 {\n    let mut sum = 0;\n    for v in values {\n        sum = sum + v;\n    }\n    return sum;\n}\n\n\
+            def @unsafe main(): i32 {\n    let mut x = 1;\n    let mut y = 2;\n    swap(x, y);\n    let pair = Pair { a: 5, b: 6 };\n    \
+            let p = alloc<Pair>(2);\n    free(p);\n    let small: i8 = 127;\n    puts(\"done\");\n    \
+            return x * 10 + y + total([1, 2, 3]) + ((small + 1) -> i32) + (3.9 -> i32);\n}",
+        );
+    }
+
+    /// Each comparison guards a loop or a branch, a wrong operator changes the result or never ends.
+    #[test]
+    fn compares_like_the_interpreter() {
+        run_like_the_interpreter(
+            "def main(): i32 {\n    let mut i = 0;\n    let mut hits = 0;\n    while i != 8 {\n        \
+            if i == 3 {\n            hits = hits + 1;\n        }\n        if i < 2 {\n            hits = hits + 10;\n        }\n        \
+            if i <= 2 {\n            hits = hits + 100;\n        }\n        if i > 6 {\n            hits = hits + 1000;\n        }\n        \
+            if i >= 6 {\n            hits = hits + 10000;\n        } else {\n            hits = hits + 2;\n        }\n        \
+            i = i + 1;\n    }\n    return hits;\n}",
+        );
+    }
+
+    /// An inclusive range ending at the largest value of its type stops after that value.
+    #[test]
+    fn ends_inclusive_ranges_at_the_bound() {
+        run_like_the_interpreter(
+            "def main(): i32 {\n    let lo: u8 = 250;\n    let hi: u8 = 255;\n    let mut sum = 0;\n    \
+            for i in lo..=hi {\n        if i == 252 {\n            continue;\n        }\n        sum = sum + (i -> i32) - 245;\n    }\n    \
+            for _j in hi..=lo {\n        sum = sum + 100;\n    }\n    return sum;\n}",
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use crate::codeviz::print_code_warn;
+use crate::comp_errors::{CodeError, CodeResult, CodeWarning};
+use crate::filemanager::FileManager;
+use crate::lexer::{Token, TokenType};
+use crate::parser::{ASTNode, FunctionMode};
+use crate::types::Type;
+
+pub type BlockId = usize;
+
+/// A step of a basic block, in execution order.
+#[derive(Debug, Clone, Copy)]
+pub enum Step<'a, 'b> {
+    // Statement or expression without control flow of its own
+    Statement(&'b ASTNode<'a>),
+    // Expression deciding which successor runs
+    Condition(&'b ASTNode<'a>),
+    // Loop variable, bound at the start of every iteration
+    Bind(&'a Token),
+}
+
+#[derive(Debug, Default)]
+pub struct BasicBlock<'a, 'b> {
+    pub steps: Vec<Step<'a, 'b>>,
+    pub successors: Vec<BlockId>,
+}
+
+/// Control-flow graph of a function body.
+/// Block 0 is the entry, every `return` jumps to `exit` and `end` is where the body
+/// runs out of statements. Blocks following a `return`, `break` or `continue` have no
+/// predecessors, which is how unreachable code shows up.
+#[derive(Debug)]
+pub struct ControlFlowGraph<'a, 'b> {
+    pub blocks: Vec<BasicBlock<'a, 'b>>,
+    pub exit: BlockId,
+    pub end: BlockId,
+    // Statement lists of the body, with the block each statement starts in
+    pub sequences: Vec<Vec<(&'b ASTNode<'a>, BlockId)>>,
+}
+
+/// Conditions that are known to hold, making a `while` loop infinite.
+fn is_always_true(condition: &ASTNode) -> bool {
+    matches!(condition, ASTNode::Literal(t) if t.token_type == TokenType::Boolean && t.content == "true")
+}
+
+struct Builder<'a, 'b> {
+    graph: ControlFlowGraph<'a, 'b>,
+    current: BlockId,
+    // Continue and break target of each enclosing loop
+    loops: Vec<(BlockId, BlockId)>,
+}
+
+impl<'a, 'b> Builder<'a, 'b> {
+    fn new_block(&mut self) -> BlockId {
+        self.graph.blocks.push(BasicBlock::default());
+        self.graph.blocks.len() - 1
+    }
+
+    fn edge(&mut self, from: BlockId, to: BlockId) {
+        self.graph.blocks[from].successors.push(to);
+    }
+
+    fn step(&mut self, step: Step<'a, 'b>) {
+        self.graph.blocks[self.current].steps.push(step);
+    }
+
+    /// Leaves the current block for `target`, anything after starts unreachable.
+    fn jump(&mut self, target: BlockId) {
+        self.edge(self.current, target);
+        self.current = self.new_block();
+    }
+
+    /// Builds a loop body entered from `header`, `continue` jumps back to it and `break` to `after`.
+    fn build_loop(
+        &mut self,
+        header: BlockId,
+        after: BlockId,
+        body_start: BlockId,
+        body: &'b [Box<ASTNode<'a>>],
+    ) -> CodeResult<()> {
+        self.edge(header, body_start);
+        self.current = body_start;
+        self.loops.push((header, after));
+        self.build_block(body)?;
+        self.loops.pop();
+        self.edge(self.current, header);
+        self.current = after;
+        Ok(())
+    }
+
+    fn build_block(&mut self, body: &'b [Box<ASTNode<'a>>]) -> CodeResult<()> {
+        let mut sequence = vec![];
+        for statement in body {
+            sequence.push((&**statement, self.current));
+            match &**statement {
+                ASTNode::WhileLoop(condition, body) => {
+                    let header = self.new_block();
+                    let after = self.new_block();
+                    self.edge(self.current, header);
+                    self.current = header;
+                    self.step(Step::Condition(condition));
+                    if !is_always_true(condition) {
+                        self.edge(header, after);
+                    }
+                    let body_start = self.new_block();
+                    self.build_loop(header, after, body_start, body)?;
+                }
+                ASTNode::ForLoop(var, iterable, body) => {
+                    self.step(Step::Statement(iterable));
+                    let header = self.new_block();
+                    let after = self.new_block();
+                    self.edge(self.current, header);
+                    self.edge(header, after);
+                    let body_start = self.new_block();
+                    self.graph.blocks[body_start].steps.push(Step::Bind(var));
+                    self.build_loop(header, after, body_start, body)?;
+                }
+                ASTNode::If(condition, then_body, else_body) => {
+                    self.step(Step::Condition(condition));
+                    let branch = self.current;
+                    let mut ends = vec![];
+                    for body in [then_body, else_body] {
+                        self.current = self.new_block();
+                        self.edge(branch, self.current);
+                        self.build_block(body)?;
+                        ends.push(self.current);
+                    }
+                    let after = self.new_block();
+                    for end in ends {
+                        self.edge(end, after);
+                    }
+                    self.current = after;
+                }
+                ASTNode::Return(_) => {
+                    self.step(Step::Statement(statement));
+                    self.jump(self.graph.exit);
+                }
+                ASTNode::Break(keyword) | ASTNode::Continue(keyword) => {
+                    let (header, after) = *self
+                        .loops
+                        .last()
+                        .ok_or_else(|| CodeError::outside_of_loop_error(keyword))?;
+                    let is_break = matches!(**statement, ASTNode::Break(_));
+                    self.jump(if is_break { after } else { header });
+                }
+                other => self.step(Step::Statement(other)),
+            }
+        }
+        self.graph.sequences.push(sequence);
+        Ok(())
+    }
+}
+
+impl<'a, 'b> ControlFlowGraph<'a, 'b> {
+    pub fn build(body: &'b [Box<ASTNode<'a>>]) -> CodeResult<Self> {
+        let mut builder = Builder {
+            graph: ControlFlowGraph {
+                blocks: vec![BasicBlock::default(), BasicBlock::default()],
+                exit: 1,
+                end: 0,
+                sequences: vec![],
+            },
+            current: 0,
+            loops: vec![],
+        };
+        builder.build_block(body)?;
+
+        let end = builder.current;
+        builder.edge(end, builder.graph.exit);
+        builder.graph.end = end;
+        Ok(builder.graph)
+    }
+
+    /// Whether each block can be reached from the entry.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut pending = vec![0];
+        while let Some(block) = pending.pop() {
+            if !reachable[block] {
+                reachable[block] = true;
+                pending.extend(&self.blocks[block].successors);
+            }
+        }
+        reachable
+    }
+}
+
+/// Warns once per statement list, spanning every statement that can not run.
+fn dead_code_warnings(graph: &ControlFlowGraph, reachable: &[bool]) -> Vec<CodeWarning> {
+    let mut warnings = vec![];
+    for sequence in &graph.sequences {
+        // A list starting out unreachable is part of a dead statement in an outer list
+        let Some(first) = sequence.iter().position(|(_, block)| !reachable[*block]) else {
+            continue;
+        };
+        if first == 0 {
+            continue;
+        }
+
+        let (cause, _) = sequence[first - 1];
+        let label = match cause {
+            ASTNode::WhileLoop(..) => "This loop never ends",
+            ASTNode::If(..) => "Every branch of this leaves the block",
+            _ => "Any code following this is unreachable",
+        };
+        let (last, _) = sequence.last().unwrap();
+        warnings.push(CodeWarning::new_dead_code(
+            sequence[first].0.position().merge(last.position()),
+            cause.position(),
+            label,
+        ));
+    }
+    warnings.sort_by_key(|t| t.position.idx_start);
+    warnings
+}
+
+/// Builds the control-flow graph of every function, rejects functions that can end
+/// without returning a value and warns about unreachable code.
+pub fn check_control_flow(ast: &[ASTNode], file_manager: &FileManager) -> CodeResult<()> {
+    for item in ast {
+        let ASTNode::FunctionDef(name, mode, ret, _, body, _) = item else {
+            continue;
+        };
+        // Extern functions only declare a signature
+        if matches!(mode, FunctionMode::Extern) {
+            continue;
+        }
+
+        let graph = ControlFlowGraph::build(body)?;
+        let reachable = graph.reachable();
+        for warning in dead_code_warnings(&graph, &reachable) {
+            print_code_warn(warning, file_manager);
+        }
+
+        let ty = Type::from_node(ret)?;
+        if ty != Type::Void && reachable[graph.end] {
+            return Err(CodeError::missing_return_error(name, ret.position(), &ty.to_string()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comp_errors::CodeErrorType;
+    use crate::testing::{parse, source_file};
+
+    fn check_source(source: &str) -> CodeResult<()> {
+        check_control_flow(&parse(source), source_file(source))
+    }
+
+    /// Lines and causes of the dead code warnings of the first function.
+    fn dead_code(source: &str) -> Vec<(usize, usize, String)> {
+        let ast = parse(source);
+        let ASTNode::FunctionDef(_, _, _, _, body, _) = &ast[0] else {
+            panic!("Expected a function");
+        };
+        let graph = ControlFlowGraph::build(body).unwrap();
+        dead_code_warnings(&graph, &graph.reachable())
+            .into_iter()
+            .map(|t| (t.position.line_start, t.position.line_end, t.labels[0].1.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn requires_a_return_on_every_path() {
+        let error = check_source("def f(x: bool): i32 {\n    if x {\n        return 1;\n    }\n}").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::MissingReturn));
+
+        check_source("def f(x: bool): i32 {\n    if x {\n        return 1;\n    } else {\n        return 2;\n    }\n}")
+            .unwrap();
+        check_source("def f(): i32 {\n    while true {\n    }\n}").unwrap();
+        check_source("def f(): void {\n}").unwrap();
+    }
+
+    #[test]
+    fn loops_that_can_stop_need_a_return_after_them() {
+        let error = check_source("def f(): i32 {\n    while true {\n        break;\n    }\n}").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::MissingReturn));
+    }
+
+    #[test]
+    fn warns_once_about_statements_after_a_return() {
+        let source = "def f(): i32 {\n    return 1;\n    let x = 2;\n    let y = 3;\n}";
+        assert_eq!(dead_code(source), vec![(2, 3, "Any code following this is unreachable".to_string())]);
+    }
+
+    #[test]
+    fn warns_about_code_after_branches_that_all_leave() {
+        let source = "def f(x: bool): i32 {\n    if x {\n        return 1;\n    } else {\n        return 2;\n    }\n    return 3;\n}";
+        assert_eq!(dead_code(source), vec![(6, 6, "Every branch of this leaves the block".to_string())]);
+
+        let source = "def f(): i32 {\n    while true {\n        continue;\n        let x = 1;\n    }\n    return 3;\n}";
+        assert_eq!(
+            dead_code(source),
+            vec![
+                (3, 3, "Any code following this is unreachable".to_string()),
+                (5, 5, "This loop never ends".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_break_outside_of_loops() {
+        let error = check_source("def f(): void {\n    break;\n}").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::OutsideOfLoop));
+    }
+}
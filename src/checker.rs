@@ -0,0 +1,176 @@
+use crate::comp_errors::{CodeError, CodeResult};
+use crate::lexer::{CodePosition, Token};
+use crate::parser::{ASTNode, Parameter};
+use crate::resolver::Resolution;
+use std::collections::HashMap;
+
+/// Matches the arguments of a call against the parameters of the called function.
+/// Returns one expression per parameter, using the default where nothing was passed.
+pub fn bind_arguments<'a, 'b>(
+    call: &'a Token,
+    args: &'b [Box<ASTNode<'a>>],
+    function: &'a Token,
+    params: &'b [Parameter<'a>],
+) -> CodeResult<Vec<&'b ASTNode<'a>>> {
+    let mut slots: Vec<Option<(&'b ASTNode<'a>, CodePosition)>> = vec![None; params.len()];
+
+    for (i, arg) in args.iter().enumerate() {
+        match &**arg {
+            ASTNode::NamedArgument(name, value) => {
+                let index = params
+                    .iter()
+                    .position(|(param, _, _)| param.content == name.content)
+                    .ok_or_else(|| CodeError::unknown_argument_error(name, function))?;
+                if let Some((_, first)) = slots[index] {
+                    return Err(CodeError::duplicate_argument_error(
+                        arg.position(),
+                        first,
+                        params[index].0,
+                    ));
+                }
+                slots[index] = Some((value, arg.position()));
+            }
+            _ => {
+                if i >= params.len() {
+                    return Err(CodeError::too_many_arguments_error(
+                        arg.position(),
+                        function,
+                        params.len(),
+                    ));
+                }
+                slots[i] = Some((arg, arg.position()));
+            }
+        }
+    }
+
+    slots
+        .into_iter()
+        .zip(params)
+        .map(|(slot, (param, _, default))| match (slot, default) {
+            (Some((value, _)), _) => Ok(value),
+            (None, Some(default)) => Ok(&**default),
+            (None, None) => Err(CodeError::missing_argument_error(call, param)),
+        })
+        .collect()
+}
+
+fn check_node<'a>(
+    node: &ASTNode<'a>,
+    resolution: &Resolution<'a>,
+    functions: &HashMap<*const Token, (&'a Token, &[Parameter<'a>])>,
+) -> CodeResult<()> {
+    if let ASTNode::FunctionCall(name, args, _) = node {
+        let declaration = resolution.symbol_of(name).unwrap().declaration;
+        if let Some((function, params)) = functions.get(&(declaration as *const Token)) {
+            bind_arguments(name, args, function, params)?;
+        }
+    }
+    for child in node.children() {
+        check_node(child, resolution, functions)?;
+    }
+    Ok(())
+}
+
+/// Checks every call against the definition of the function it calls.
+pub fn check_calls<'a>(ast: &[ASTNode<'a>], resolution: &Resolution<'a>) -> CodeResult<()> {
+    let mut functions = HashMap::new();
+    for item in ast {
+        if let ASTNode::FunctionDef(name, _, _, params, ..) = item {
+            functions.insert(*name as *const Token, (*name, params.as_slice()));
+        }
+    }
+
+    for item in ast {
+        check_node(item, resolution, &functions)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comp_errors::CodeErrorType;
+    use crate::resolver::resolve;
+    use crate::testing::{parse, render, try_parse};
+
+    // Line 0 declares the function, line 4 calls it
+    const CONNECT: &str = "def connect(host: str, port: i32 = 80, retries: i32 = 3): i32 {\n    return port;\n}\n\n";
+
+    fn check_source(main: &str) -> CodeResult<()> {
+        let ast = parse(&format!("{}def main(): i32 {{\n{}\n}}", CONNECT, main));
+        let resolution = resolve(&ast, &[])?;
+        check_calls(&ast, &resolution)
+    }
+
+    /// Arguments bound to the parameters of `connect` by the call in `main`.
+    fn bound(call: &str) -> Vec<String> {
+        let ast = parse(&format!("{}def main(): i32 {{\n    return {};\n}}", CONNECT, call));
+        let ASTNode::FunctionDef(function, _, _, params, ..) = &ast[0] else {
+            unreachable!()
+        };
+        let ASTNode::FunctionDef(_, _, _, _, body, _) = &ast[1] else {
+            unreachable!()
+        };
+        let ASTNode::Return(value) = &*body[0] else {
+            unreachable!()
+        };
+        let ASTNode::FunctionCall(name, args, _) = &**value else {
+            unreachable!()
+        };
+        bind_arguments(name, args, function, params).unwrap().into_iter().map(render).collect()
+    }
+
+    #[test]
+    fn fills_slots_from_positions_names_and_defaults() {
+        assert_eq!(bound("connect(\"a\")"), ["\"a\"", "80", "3"]);
+        assert_eq!(bound("connect(\"a\", 8080)"), ["\"a\"", "8080", "3"]);
+        assert_eq!(bound("connect(\"a\", retries = 5)"), ["\"a\"", "80", "5"]);
+        assert_eq!(bound("connect(retries = 1, host = \"b\", port = 2)"), ["\"b\"", "2", "1"]);
+    }
+
+    #[test]
+    fn rejects_missing_arguments() {
+        let error = check_source("    return connect(port = 1);").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::MissingArgument));
+        assert_eq!(error.pointer.as_deref(), Some("`host` is missing here"));
+        assert_eq!(error.labels[0].1, "Declared here without a default");
+        assert_eq!(error.labels[0].0.line_start, 0);
+    }
+
+    #[test]
+    fn rejects_duplicate_arguments() {
+        let error = check_source("    return connect(\"a\", host = \"b\");").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::DuplicateArgument));
+        assert_eq!(error.labels[0].1, "First given here");
+        assert_eq!(error.labels[0].0.line_start, 5);
+        assert_eq!(error.labels[1].1, "Parameter declared here");
+        assert_eq!(error.labels[1].0.line_start, 0);
+    }
+
+    #[test]
+    fn rejects_unknown_arguments() {
+        let error = check_source("    return connect(\"a\", timeout = 5);").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::UnknownArgument));
+        assert_eq!(error.footer, "Function `connect` has no parameter named `timeout`");
+        assert_eq!(error.labels[0].1, "Function declared here");
+        assert_eq!(error.labels[0].0.line_start, 0);
+    }
+
+    #[test]
+    fn rejects_too_many_arguments() {
+        let error = check_source("    return connect(\"a\", 1, 2, 3);").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::TooManyArguments));
+        assert_eq!(error.footer, "Function `connect` takes 3 argument(s)");
+        assert_eq!(error.labels[0].1, "Function declared here");
+        assert_eq!(error.labels[0].0.line_start, 0);
+    }
+
+    #[test]
+    fn positional_arguments_come_before_named_ones() {
+        let source = format!("{}def main(): i32 {{\n    return connect(port = 1, \"a\");\n}}", CONNECT);
+        let error = try_parse(&source).unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::ParserUnexpectedToken));
+        assert_eq!(error.notes, ["Positional arguments must come before named arguments"]);
+        assert_eq!(error.position.line_start, 5);
+    }
+}
@@ -16,7 +16,9 @@ pub enum ClParserError {
 
 pub type ClParserResult<T> = Result<T, ClParserError>;
 pub type ClParserResultCallQueue = ClParserResult<Vec<PendingCall>>;
-pub type CallFunction = Box<dyn Fn(&ArgumentParser, &Vec<String>) -> bool>;
+pub type CallFunction = Box<dyn Fn(&ArgumentParser, &[String]) -> bool>;
+/// Flag name -> value, `None` for flags without a value or that were not given.
+pub type FlagMap = HashMap<String, Option<String>>;
 
 #[macro_export]
 macro_rules! mk_clfn {
@@ -167,7 +169,7 @@ impl Argument {
         }
     }
 
-    fn call(&self, argument_parser: &ArgumentParser, args: &Vec<String>) -> bool {
+    fn call(&self, argument_parser: &ArgumentParser, args: &[String]) -> bool {
         (self.triggers)(argument_parser, args)
     }
 }
@@ -233,16 +235,16 @@ impl Flag {
         }
     }
 
-    fn call(&self, argument_parser: &ArgumentParser, value: &Vec<String>) -> bool {
+    fn call(&self, argument_parser: &ArgumentParser, value: &[String]) -> bool {
         (self.triggers)(argument_parser, value)
     }
 }
 
 #[derive(Debug)]
 pub enum CallType {
-    ARGUMENT,
-    POSITIONAL,
-    FLAG,
+    Argument,
+    Positional,
+    Flag,
 }
 
 #[derive(Debug)]
@@ -267,11 +269,6 @@ impl PendingCall {
         self.name == name
     }
 
-    // Just in case
-    pub fn get_args(&self) -> &Vec<String> {
-        &self.args
-    }
-
     pub fn merge_args(&self, mut other: Vec<String>) -> Vec<String> {
         let mut cla = self.args.clone();
         cla.append(&mut other);
@@ -281,13 +278,13 @@ impl PendingCall {
     pub fn call(&self, argument_parser: &ArgumentParser, spec_args: Option<&Vec<String>>) -> bool {
         let args = spec_args.or(Some(&self.args));
         match self.call_type {
-            CallType::ARGUMENT => {
+            CallType::Argument => {
                 argument_parser.arguments[self.index].call(argument_parser, args.unwrap())
             }
-            CallType::POSITIONAL => {
+            CallType::Positional => {
                 argument_parser.positionals[self.index].call(argument_parser, args.unwrap())
             }
-            CallType::FLAG => {
+            CallType::Flag => {
                 argument_parser.flags[self.index].call(argument_parser, args.unwrap())
             }
         }
@@ -332,7 +329,7 @@ impl ArgumentParser {
     fn parse_argument(
         &self,
         argument: &Argument,
-        args: &Vec<String>,
+        args: &[String],
     ) -> ClParserResult<Vec<String>> {
         if args.len() < (argument.nargs + 1) {
             Err(TooFewArguments(argument.nargs))
@@ -349,7 +346,7 @@ impl ArgumentParser {
                 pos.name.clone(),
                 i,
                 self.parse_argument(pos, args)?,
-                CallType::POSITIONAL,
+                CallType::Positional,
             ));
             remove_list.append(&mut (i..pos.args.len() + 1).collect());
         }
@@ -365,13 +362,13 @@ impl ArgumentParser {
         let mut remove_list = vec![];
         let mut pending_calls = vec![];
         for (i, arg) in args.iter().enumerate() {
-            for (n, darg) in (&self.arguments).iter().enumerate() {
+            for (n, darg) in self.arguments.iter().enumerate() {
                 if darg.name == *arg {
                     pending_calls.push(PendingCall::new(
                         darg.name.clone(),
                         n,
                         self.parse_argument(darg, args)?,
-                        CallType::ARGUMENT,
+                        CallType::Argument,
                     ));
                     remove_list.append(&mut (i..darg.args.len() + 1).collect());
                 }
@@ -389,7 +386,7 @@ impl ArgumentParser {
         &self,
         flag: &Flag,
         item: &str,
-        args: &Vec<String>,
+        args: &[String],
         i: usize,
     ) -> ClParserResult<Vec<String>> {
         let val = if flag.value {
@@ -419,22 +416,24 @@ impl ArgumentParser {
 
         for i in 0..args.len() {
             let item = &args[i];
-            for (n, flag) in (&self.flags).iter().enumerate() {
-                if item.starts_with(&flag.name) && (!flag.value || (&flag.name == item))
-                    || item.starts_with(&flag.mini) && (!flag.value || (&flag.mini == item))
+            for (n, flag) in self.flags.iter().enumerate() {
+                // Values follow the flag, either as the next argument or after `=`
+                let given = |name: &String| item == name || flag.value && item.starts_with(&format!("{}=", name));
+                if item.starts_with(&flag.name) && (!flag.value || given(&flag.name))
+                    || item.starts_with(&flag.mini) && (!flag.value || given(&flag.mini))
                 {
                     let rgs = self.parse_flag(flag, item, args, i)?;
                     flag_map.insert(
                         flag.name.clone(),
                         if flag.value {
-                            Some((&rgs[0]).clone())
+                            Some(rgs[0].clone())
                         } else {
                             None
                         },
                     );
-                    pending_calls.push(PendingCall::new(flag.name.clone(), n, rgs, CallType::FLAG));
+                    pending_calls.push(PendingCall::new(flag.name.clone(), n, rgs, CallType::Flag));
                     remove_list.push(i);
-                    if flag.value {
+                    if flag.value && !item.contains('=') {
                         remove_list.push(i + 1);
                     }
                 }
@@ -452,7 +451,7 @@ impl ArgumentParser {
         &self,
         mut args: Vec<String>,
         auto_help_nargs: bool,
-    ) -> ClParserResult<(Vec<PendingCall>, HashMap<String, Option<String>>)> {
+    ) -> ClParserResult<(Vec<PendingCall>, FlagMap)> {
         let mut pending_calls = vec![];
         let mut flag_map = HashMap::new();
 
@@ -460,8 +459,8 @@ impl ArgumentParser {
             flag_map.insert(flag.name.clone(), None);
         }
 
-        if auto_help_nargs && args.len() == 0 {
-            _print_help(&self);
+        if auto_help_nargs && args.is_empty() {
+            _print_help(self);
             return Ok((pending_calls, flag_map));
         }
 
@@ -469,7 +468,7 @@ impl ArgumentParser {
         pending_calls.append(&mut self.parse_arguments(&mut args)?);
         pending_calls.append(&mut self.parse_positionals(&mut args)?);
 
-        if args.len() > 0 {
+        if !args.is_empty() {
             Err(TooManyArguments(args[0].clone()))
         } else {
             Ok((pending_calls, flag_map))
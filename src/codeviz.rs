@@ -1,16 +1,51 @@
 use crate::comp_errors::{CodeError, CodeWarning};
 use crate::filemanager::FileManager;
-use annotate_snippets::{Level, Renderer};
+use crate::lexer::CodePosition;
+use annotate_snippets::{Level, Renderer, Snippet};
 
-pub fn print_code_error(code_error: CodeError, file_manager: &FileManager) {
-    let (mut snip, offset) = file_manager.get_code_snippet(&code_error.position);
-    snip = snip.annotation(match code_error.pointer {
-        None => Level::Error.span(code_error.position.range(offset)),
-        Some(_) => Level::Error
-            .span(code_error.position.range(offset))
-            .label(code_error.pointer.unwrap().leak()),
+/// Snippets for the primary annotation and every secondary label.
+/// Labels close to the primary position share its snippet, others get their own.
+fn build_snippets<'a>(
+    level: Level,
+    position: &CodePosition,
+    pointer: Option<String>,
+    labels: &[(CodePosition, String)],
+    file_manager: &'a FileManager,
+) -> Vec<Snippet<'a>> {
+    let mut snip = file_manager.get_code_snippet(position);
+    let range = file_manager.window_range(position, position).unwrap();
+    snip = snip.annotation(match pointer {
+        None => level.span(range),
+        Some(pointer) => level.span(range).label(pointer.leak()),
     });
 
+    let mut others = vec![];
+    for (label_position, label) in labels {
+        let label = label.clone().leak();
+        match file_manager.window_range(position, label_position) {
+            Some(range) => snip = snip.annotation(Level::Info.span(range).label(label)),
+            None => {
+                let other = file_manager.get_code_snippet(label_position);
+                let range = file_manager.window_range(label_position, label_position).unwrap();
+                others.push(other.annotation(Level::Info.span(range).label(label)));
+            }
+        }
+    }
+
+    let mut snippets = vec![snip];
+    snippets.append(&mut others);
+    snippets
+}
+
+pub fn print_code_error(code_error: CodeError, file_manager: &FileManager) {
+    let snippets = build_snippets(
+        Level::Error,
+        &code_error.position,
+        code_error.pointer,
+        &code_error.labels,
+        file_manager,
+    );
+
     let mut footers = vec![Level::Error.title(code_error.footer.as_str())];
 
     for note in &code_error.notes {
@@ -20,8 +55,8 @@ pub fn print_code_error(code_error: CodeError, file_manager: &FileManager) {
     let id_fmt = format!("{:#04x}", code_error.code_error_type as usize);
     let msg = Level::Error
         .title(code_error.title.as_str())
-        .id(&*id_fmt)
-        .snippet(snip)
+        .id(&id_fmt)
+        .snippets(snippets)
         .footers(footers);
 
     let renderer = Renderer::styled();
@@ -29,13 +64,13 @@ pub fn print_code_error(code_error: CodeError, file_manager: &FileManager) {
 }
 
 pub fn print_code_warn(code_warn: CodeWarning, file_manager: &FileManager) {
-    let (mut snip, offset) = file_manager.get_code_snippet(&code_warn.position);
-    snip = snip.annotation(match code_warn.pointer {
-        None => Level::Warning.span(code_warn.position.range(offset)),
-        Some(_) => Level::Warning
-            .span(code_warn.position.range(offset))
-            .label(code_warn.pointer.unwrap().leak()),
-    });
+    let snippets = build_snippets(
+        Level::Warning,
+        &code_warn.position,
+        code_warn.pointer,
+        &code_warn.labels,
+        file_manager,
+    );
 
     let mut footers = vec![Level::Warning.title(code_warn.footer.as_str())];
 
@@ -46,8 +81,8 @@ pub fn print_code_warn(code_warn: CodeWarning, file_manager: &FileManager) {
     let id_fmt = format!("{:#04x}", code_warn.code_warn_type as usize);
     let msg = Level::Warning
         .title(code_warn.title.as_str())
-        .id(&*id_fmt)
-        .snippet(snip)
+        .id(&id_fmt)
+        .snippets(snippets)
         .footers(footers);
 
     let renderer = Renderer::styled();
@@ -1,9 +1,8 @@
 use crate::codeviz::print_code_error;
 use crate::filemanager::FileManager;
 use crate::lexer::{CodePosition, Token, TokenType};
-use colorize_rs::{AnsiColor, Color};
+use colorize_rs::AnsiColor;
 use std::fmt;
-use std::fmt::format;
 
 #[derive(Debug)]
 pub enum CompilerError {
@@ -36,18 +35,60 @@ impl CompilerError {
 #[derive(Debug)]
 pub enum CodeErrorType {
     LexerUnknownChar,
+    // The code of a kind is its position, kinds nothing reports yet keep theirs
+    #[allow(dead_code)]
     LexerUnexpectedChar,
     LexerEndOfFile,
     ParserUnexpectedToken,
     MissingTokenError,
-    FunctionOverloaded,
+    MultipleModes,
+    InvalidAttribute,
+    MissingArgument,
+    DuplicateArgument,
+    UnknownArgument,
+    TooManyArguments,
+    InvalidBuiltinCall,
+    UseAfterMove,
+    InvalidDropHook,
+    UndefinedName,
+    DuplicateName,
+    TypeMismatch,
+    InvalidOperand,
+    UnknownField,
+    MissingField,
+    NotIterable,
+    CannotInfer,
+    NotConstant,
+    InvalidCast,
+    MissingReturn,
+    OutsideOfLoop,
+    ImmutableAssignment,
+    Uninitialized,
+    Overflow,
+    DivisionByZero,
+    OutOfRange,
+    EvaluationLimit,
+    UnknownModule,
+    PrivateFunction,
+    InvalidEntry,
+    DuplicateDefinition,
+    NoMatchingOverload,
+    AmbiguousCall,
+    RuntimeError,
 }
 
 #[derive(Debug)]
 pub enum CodeWarningType {
     DeadCode,
     UnnecessaryCode,
+    #[allow(dead_code)]
     DiscouragedPractice,
+    UnknownAttribute,
+    LossyCast,
+    UnusedVariable,
+    UnusedFunction,
+    UnusedImport,
+    UnusedMut,
 }
 
 #[derive(Debug)]
@@ -58,6 +99,7 @@ pub struct CodeError {
     pub footer: String,
     pub pointer: Option<String>,
     pub notes: Vec<String>,
+    pub labels: Vec<(CodePosition, String)>,
 }
 
 impl CodeError {
@@ -76,9 +118,22 @@ impl CodeError {
             footer,
             pointer,
             notes,
+            labels: vec![],
         }
     }
 
+    /// Adds a secondary label, e.g. pointing at a declaration.
+    pub fn with_label(mut self, position: CodePosition, label: String) -> Self {
+        self.labels.push((position, label));
+        self
+    }
+
+    /// Appends a note, e.g. a step of a compile-time evaluation backtrace.
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
     pub fn placeholder() -> Self {
         panic!("Please remove this placeholder!");
     }
@@ -97,11 +152,7 @@ impl CodeError {
                 "Expected another token `{}`, but got `{}`",
                 expected, token.token_type
             ),
-            if extra.is_some() {
-                vec![extra.unwrap()]
-            } else {
-                vec![]
-            },
+            extra.into_iter().collect(),
         )
     }
 
@@ -138,17 +189,652 @@ impl CodeError {
         )
     }
 
-    pub fn function_overloaded(already_token: &Token) -> Self {
+    pub fn multiple_modes_error(already_token: &Token) -> Self {
         Self::new(
             already_token.code_position,
-            CodeErrorType::FunctionOverloaded,
-            "Function was overloaded".to_string(),
+            CodeErrorType::MultipleModes,
+            "Multiple function modes".to_string(),
             Some("Function mode set here".to_string()),
             "Can not have multiple function modes".to_string(),
             vec!["Remove one of the modifiers".to_string()],
         )
     }
 
+    pub fn invalid_attribute_error(position: CodePosition, name: &str, expected: &str) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::InvalidAttribute,
+            "Invalid attribute arguments".to_string(),
+            Some(format!("`@{}` is used wrong here", name)),
+            format!("Attribute `@{}` expects {}", name, expected),
+            vec![],
+        )
+    }
+
+    pub fn missing_argument_error(call: &Token, parameter: &Token) -> Self {
+        Self::new(
+            call.code_position,
+            CodeErrorType::MissingArgument,
+            "Missing argument".to_string(),
+            Some(format!("`{}` is missing here", parameter.content)),
+            format!(
+                "Function `{}` needs a value for `{}`",
+                call.content, parameter.content
+            ),
+            vec![format!(
+                "Pass it by position or by name (`{} = ...`)",
+                parameter.content
+            )],
+        )
+        .with_label(
+            parameter.code_position,
+            "Declared here without a default".to_string(),
+        )
+    }
+
+    pub fn duplicate_argument_error(
+        position: CodePosition,
+        first: CodePosition,
+        parameter: &Token,
+    ) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::DuplicateArgument,
+            "Duplicate argument".to_string(),
+            Some(format!("`{}` is given again here", parameter.content)),
+            format!("Argument `{}` was passed more than once", parameter.content),
+            vec![],
+        )
+        .with_label(first, "First given here".to_string())
+        .with_label(parameter.code_position, "Parameter declared here".to_string())
+    }
+
+    pub fn unknown_argument_error(argument: &Token, function: &Token) -> Self {
+        Self::new(
+            argument.code_position,
+            CodeErrorType::UnknownArgument,
+            "Unknown argument".to_string(),
+            Some("No parameter with this name".to_string()),
+            format!(
+                "Function `{}` has no parameter named `{}`",
+                function.content, argument.content
+            ),
+            vec![],
+        )
+        .with_label(function.code_position, "Function declared here".to_string())
+    }
+
+    pub fn too_many_arguments_error(
+        position: CodePosition,
+        function: &Token,
+        expected: usize,
+    ) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::TooManyArguments,
+            "Too many arguments".to_string(),
+            Some("This argument is too many".to_string()),
+            format!(
+                "Function `{}` takes {} argument(s)",
+                function.content, expected
+            ),
+            vec![],
+        )
+        .with_label(function.code_position, "Function declared here".to_string())
+    }
+
+    pub fn invalid_builtin_call_error(position: CodePosition, name: &str, usage: &str) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::InvalidBuiltinCall,
+            "Invalid builtin call".to_string(),
+            Some(format!("`{}` is called wrong here", name)),
+            format!("Builtin `{}` is used like `{}`", name, usage),
+            vec![],
+        )
+    }
+
+    pub fn use_after_move_error(usage: &Token, moved: CodePosition) -> Self {
+        Self::new(
+            usage.code_position,
+            CodeErrorType::UseAfterMove,
+            "Use of moved value".to_string(),
+            Some("Value used here after move".to_string()),
+            format!("`{}` was moved and can not be used anymore", usage.content),
+            vec!["Values of types with a drop hook move when they are used".to_string()],
+        )
+        .with_label(moved, "Value moved here".to_string())
+    }
+
+    pub fn move_out_of_reference_error(usage: &Token, declaration: &Token) -> Self {
+        Self::new(
+            usage.code_position,
+            CodeErrorType::UseAfterMove,
+            "Move out of a reference".to_string(),
+            Some("Moved here".to_string()),
+            format!("`{}` is borrowed from the caller and can not be moved", usage.content),
+            vec![],
+        )
+        .with_label(declaration.code_position, "Passed by reference".to_string())
+    }
+
+    pub fn move_in_loop_error(usage: &Token, declaration: &Token) -> Self {
+        Self::new(
+            usage.code_position,
+            CodeErrorType::UseAfterMove,
+            "Value moved inside of a loop".to_string(),
+            Some("Value moved here, in a previous iteration of the loop".to_string()),
+            format!(
+                "`{}` is declared outside of the loop, but moved in every iteration",
+                usage.content
+            ),
+            vec!["Declare the value inside of the loop instead".to_string()],
+        )
+        .with_label(declaration.code_position, "Declared here".to_string())
+    }
+
+    pub fn invalid_drop_hook_error(position: CodePosition, function: &Token) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::InvalidDropHook,
+            "Invalid drop hook".to_string(),
+            Some("Used here".to_string()),
+            format!(
+                "`{}` must take exactly one parameter, which is a struct",
+                function.content
+            ),
+            vec!["Drop hooks look like `def @drop close(self: File): void { }`".to_string()],
+        )
+    }
+
+    pub fn duplicate_drop_hook_error(function: &Token, first: &Token, type_name: &str) -> Self {
+        Self::new(
+            function.code_position,
+            CodeErrorType::InvalidDropHook,
+            "Duplicate drop hook".to_string(),
+            Some("Second drop hook".to_string()),
+            format!("Struct `{}` already has a drop hook", type_name),
+            vec![],
+        )
+        .with_label(first.code_position, "First defined here".to_string())
+    }
+
+    pub fn undefined_name_error(usage: &Token, expected: &str) -> Self {
+        Self::new(
+            usage.code_position,
+            CodeErrorType::UndefinedName,
+            format!("Undefined {}", expected),
+            Some("Not found in this scope".to_string()),
+            format!("There is no {} named `{}`", expected, usage.content),
+            vec![],
+        )
+    }
+
+    pub fn wrong_symbol_kind_error(
+        usage: &Token,
+        declaration: &Token,
+        expected: &str,
+        found: &str,
+    ) -> Self {
+        Self::new(
+            usage.code_position,
+            CodeErrorType::UndefinedName,
+            format!("Expected a {}", expected),
+            Some(format!("Expected a {}, found a {}", expected, found)),
+            format!("`{}` is a {}, not a {}", usage.content, found, expected),
+            vec![],
+        )
+        .with_label(declaration.code_position, format!("The {} is declared here", found))
+    }
+
+    pub fn duplicate_name_error(name: &Token, first: &Token, kind: &str) -> Self {
+        Self::new(
+            name.code_position,
+            CodeErrorType::DuplicateName,
+            "Duplicate name".to_string(),
+            Some("Declared again here".to_string()),
+            format!("A {} named `{}` already exists in this scope", kind, name.content),
+            vec!["Rename one of them".to_string()],
+        )
+        .with_label(first.code_position, "First declared here".to_string())
+    }
+
+    pub fn type_mismatch_error(position: CodePosition, expected: &str, found: &str) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::TypeMismatch,
+            "Mismatched types".to_string(),
+            Some(format!("Expected `{}`, found `{}`", expected, found)),
+            format!("This expression should be `{}`", expected),
+            vec![],
+        )
+    }
+
+    pub fn invalid_operand_error(position: CodePosition, op: &Token, found: &str) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::InvalidOperand,
+            "Invalid operand".to_string(),
+            Some(format!("This is `{}`", found)),
+            format!("Operator `{}` can not be used on `{}`", op.token_type, found),
+            vec![],
+        )
+        .with_label(op.code_position, "Operator used here".to_string())
+    }
+
+    pub fn unknown_field_error(field: &Token, declaration: &Token) -> Self {
+        Self::new(
+            field.code_position,
+            CodeErrorType::UnknownField,
+            "Unknown field".to_string(),
+            Some("No field with this name".to_string()),
+            format!(
+                "Struct `{}` has no field named `{}`",
+                declaration.content, field.content
+            ),
+            vec![],
+        )
+        .with_label(declaration.code_position, "Struct declared here".to_string())
+    }
+
+    pub fn missing_field_error(literal: &Token, field: &Token) -> Self {
+        Self::new(
+            literal.code_position,
+            CodeErrorType::MissingField,
+            "Missing field".to_string(),
+            Some(format!("`{}` is missing here", field.content)),
+            format!("Struct `{}` needs a value for `{}`", literal.content, field.content),
+            vec![],
+        )
+        .with_label(field.code_position, "Field declared here".to_string())
+    }
+
+    pub fn not_iterable_error(position: CodePosition, found: &str) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::NotIterable,
+            "Not iterable".to_string(),
+            Some(format!("This is `{}`", found)),
+            format!("Can not iterate over `{}`", found),
+            vec!["Loop over a range (`0..n`) or an array instead".to_string()],
+        )
+    }
+
+    pub fn cannot_infer_error(position: CodePosition, suggestion: Option<String>) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::CannotInfer,
+            "Cannot infer type".to_string(),
+            Some("Type must be known here".to_string()),
+            "The type of this expression can not be inferred".to_string(),
+            suggestion.into_iter().collect(),
+        )
+    }
+
+    pub fn not_constant_error(position: CodePosition) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::NotConstant,
+            "Not a constant".to_string(),
+            Some("Must be known at compile time".to_string()),
+            "Expected a constant here".to_string(),
+            vec![],
+        )
+    }
+
+    pub fn invalid_cast_error(
+        position: CodePosition,
+        target: CodePosition,
+        from: &str,
+        to: &str,
+    ) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::InvalidCast,
+            "Invalid cast".to_string(),
+            Some(format!("This is `{}`", from)),
+            format!("`{}` can not be cast to `{}`", from, to),
+            vec![],
+        )
+        .with_label(target, "Target type".to_string())
+    }
+
+    pub fn unsafe_cast_error(
+        position: CodePosition,
+        target: CodePosition,
+        from: &str,
+        to: &str,
+    ) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::InvalidCast,
+            "Unsafe cast".to_string(),
+            Some(format!("This is `{}`", from)),
+            format!("Casting `{}` to `{}` is only allowed in unsafe functions", from, to),
+            vec!["Mark the function with `@unsafe`".to_string()],
+        )
+        .with_label(target, "Target type".to_string())
+    }
+
+    pub fn missing_return_error(name: &Token, ret: CodePosition, ty: &str) -> Self {
+        Self::new(
+            name.code_position,
+            CodeErrorType::MissingReturn,
+            "Missing return".to_string(),
+            Some("This function".to_string()),
+            format!("`{}` may reach its end without returning a value", name.content),
+            vec!["Add a `return` at the end of the function".to_string()],
+        )
+        .with_label(ret, format!("Expected to return `{}`", ty))
+    }
+
+    pub fn outside_of_loop_error(keyword: &Token) -> Self {
+        Self::new(
+            keyword.code_position,
+            CodeErrorType::OutsideOfLoop,
+            format!("`{}` outside of a loop", keyword.content),
+            Some("Not inside of a loop".to_string()),
+            format!("`{}` can only be used inside of `while` and `for` loops", keyword.content),
+            vec![],
+        )
+    }
+
+    pub fn immutable_assignment_error(position: CodePosition, declaration: &Token, kind: &str) -> Self {
+        let hint = match kind {
+            "variable" => format!("Declare it as `let mut {}`", declaration.content),
+            "parameter" => "Take the parameter as `&mut` reference".to_string(),
+            _ => format!("A {} can not be changed", kind),
+        };
+        Self::new(
+            position,
+            CodeErrorType::ImmutableAssignment,
+            format!("Immutable {}", kind),
+            Some("Changed here".to_string()),
+            format!("`{}` is not mutable", declaration.content),
+            vec![hint],
+        )
+        .with_label(declaration.code_position, "Declared here".to_string())
+    }
+
+    pub fn immutable_argument_error(position: CodePosition, param: &Token) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::ImmutableAssignment,
+            "Expected a mutable variable".to_string(),
+            Some("Not a mutable variable".to_string()),
+            format!("Parameter `{}` takes a `&mut` reference", param.content),
+            vec!["Pass a variable declared with `let mut`".to_string()],
+        )
+        .with_label(param.code_position, "Parameter declared here".to_string())
+    }
+
+    pub fn uninitialized_read_error(usage: &Token, declaration: &Token) -> Self {
+        Self::new(
+            usage.code_position,
+            CodeErrorType::Uninitialized,
+            "Possibly uninitialized variable".to_string(),
+            Some("Read here".to_string()),
+            format!("`{}` is not assigned on every path leading here", usage.content),
+            vec!["Assign it on every path before, or give it a value where it is declared".to_string()],
+        )
+        .with_label(declaration.code_position, "Declared here without a value".to_string())
+    }
+
+    pub fn assigned_twice_error(position: CodePosition, declaration: &Token) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::ImmutableAssignment,
+            "Immutable variable assigned twice".to_string(),
+            Some("Assigned again here".to_string()),
+            format!("`{}` may already be initialized", declaration.content),
+            vec![format!("Declare it as `let mut {}`", declaration.content)],
+        )
+        .with_label(declaration.code_position, "Declared here without a value".to_string())
+    }
+
+    pub fn cyclic_constant_error(usage: &Token, declaration: &Token) -> Self {
+        Self::new(
+            usage.code_position,
+            CodeErrorType::NotConstant,
+            "Cyclic constant".to_string(),
+            Some("Used while it is evaluated".to_string()),
+            format!("The value of `{}` depends on itself", declaration.content),
+            vec![],
+        )
+        .with_label(declaration.code_position, "Declared here".to_string())
+    }
+
+    pub fn overflow_error(position: CodePosition, op: &Token, ty: &str) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::Overflow,
+            "Arithmetic overflow".to_string(),
+            Some("Overflows".to_string()),
+            format!("The result of `{}` does not fit into `{}`", op.content, ty),
+            vec![],
+        )
+    }
+
+    pub fn division_by_zero_error(position: CodePosition) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::DivisionByZero,
+            "Division by zero".to_string(),
+            Some("This is zero".to_string()),
+            "Can not divide by zero".to_string(),
+            vec![],
+        )
+    }
+
+    pub fn out_of_range_error(position: CodePosition, value: &str, ty: &str) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::OutOfRange,
+            "Value out of range".to_string(),
+            Some(format!("This is `{}`", value)),
+            format!("`{}` does not fit into `{}`", value, ty),
+            vec![],
+        )
+    }
+
+    pub fn evaluation_limit_error(position: CodePosition) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::EvaluationLimit,
+            "Evaluation limit reached".to_string(),
+            Some("While evaluating this".to_string()),
+            "Evaluating this at compile time takes too long".to_string(),
+            vec!["Check the loops and recursion of the `const def` functions involved".to_string()],
+        )
+    }
+
+    pub fn unknown_module_error(import: &Token, path: &str) -> Self {
+        Self::new(
+            import.code_position,
+            CodeErrorType::UnknownModule,
+            "Unknown module".to_string(),
+            Some("Imported here".to_string()),
+            format!("Can not read `{}`", path),
+            vec!["Modules are imported from the directory of the importing file".to_string()],
+        )
+    }
+
+    pub fn private_function_error(usage: &Token, module: &str) -> Self {
+        Self::new(
+            usage.code_position,
+            CodeErrorType::PrivateFunction,
+            "Private function".to_string(),
+            Some(format!("Private to module `{}`", module)),
+            format!("`{}` is private to module `{}`", usage.content, module),
+            vec![format!("Remove `private` from its definition in `{}` to use it here", module)],
+        )
+    }
+
+    pub fn ambiguous_import_error(usage: &Token, first: &str, second: &str) -> Self {
+        Self::new(
+            usage.code_position,
+            CodeErrorType::DuplicateName,
+            "Ambiguous name".to_string(),
+            Some("Could refer to either".to_string()),
+            format!("`{}` is defined by both `{}` and `{}`", usage.content, first, second),
+            vec!["Define it in this module instead, or import only one of them".to_string()],
+        )
+    }
+
+    pub fn unsupported_import_error(import: &Token, name: &str, module: &str) -> Self {
+        Self::new(
+            import.code_position,
+            CodeErrorType::NotConstant,
+            "Unsupported import".to_string(),
+            Some("Imported here".to_string()),
+            format!("The signature of `{}` in module `{}` can not be imported", name, module),
+            vec!["Default values and array lengths of imported signatures must be literals".to_string()],
+        )
+    }
+
+    pub fn duplicate_entry_error(position: CodePosition, first: CodePosition) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::InvalidEntry,
+            "Duplicate entry point".to_string(),
+            Some("Marked again here".to_string()),
+            "A program can only have one `@entry` function".to_string(),
+            vec!["Remove `@entry` from one of them".to_string()],
+        )
+        .with_label(first, "First marked here".to_string())
+    }
+
+    pub fn missing_entry_error(name: &str) -> Self {
+        Self::new(
+            CodePosition::eof(),
+            CodeErrorType::InvalidEntry,
+            "Missing entry point".to_string(),
+            None,
+            format!("There is no function `{}` to start the program with", name),
+            vec!["Define `def main(): i32`, mark a function with `@entry` or choose one with `--entry`".to_string()],
+        )
+    }
+
+    pub fn conflicting_entry_error(position: CodePosition, chosen: &str) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::InvalidEntry,
+            "Conflicting entry points".to_string(),
+            Some("Marked here".to_string()),
+            format!("`--entry` chose `{}` instead", chosen),
+            vec!["Remove `@entry` or the `--entry` flag".to_string()],
+        )
+    }
+
+    pub fn entry_signature_error(name: &Token, signature: CodePosition) -> Self {
+        Self::new(
+            signature,
+            CodeErrorType::InvalidEntry,
+            "Invalid entry point signature".to_string(),
+            Some("This signature".to_string()),
+            format!("Entry point `{}` has to be `(): i32` or `(argc: i32, argv: **u8): i32`", name.content),
+            vec![],
+        )
+        .with_label(name.code_position, "Entry point".to_string())
+    }
+
+    pub fn extern_entry_error(name: &Token) -> Self {
+        Self::new(
+            name.code_position,
+            CodeErrorType::InvalidEntry,
+            "Extern entry point".to_string(),
+            Some("Declared without a body".to_string()),
+            format!("Entry point `{}` has to be defined in the program", name.content),
+            vec![],
+        )
+    }
+
+    /// `overloads` tells whether both are marked with `@overload`.
+    pub fn duplicate_definition_error(name: &Token, first: &Token, overloads: bool) -> Self {
+        let note = if overloads {
+            "Overloads need different numbers of parameters"
+        } else {
+            "Rename one of them, or mark all of them with `@overload`"
+        };
+        Self::new(
+            name.code_position,
+            CodeErrorType::DuplicateDefinition,
+            "Duplicate definition".to_string(),
+            Some("Defined again here".to_string()),
+            format!("Function `{}` is defined more than once", name.content),
+            vec![note.to_string()],
+        )
+        .with_label(first.code_position, "First defined here".to_string())
+    }
+
+    /// Lists `candidates` (name, number of parameters) as labels.
+    fn with_candidates(mut self, candidates: &[(&Token, usize)]) -> Self {
+        for (candidate, params) in candidates {
+            self = self.with_label(
+                candidate.code_position,
+                format!("Candidate taking {} parameter{}", params, if *params == 1 { "" } else { "s" }),
+            );
+        }
+        self
+    }
+
+    pub fn no_matching_overload_error(position: CodePosition, name: &Token, candidates: &[(&Token, usize)]) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::NoMatchingOverload,
+            "No matching overload".to_string(),
+            Some("Called here".to_string()),
+            format!("No overload of `{}` takes these arguments", name.content),
+            vec![],
+        )
+        .with_candidates(candidates)
+    }
+
+    pub fn ambiguous_call_error(position: CodePosition, name: &Token, candidates: &[(&Token, usize)]) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::AmbiguousCall,
+            "Ambiguous call".to_string(),
+            Some("Called here".to_string()),
+            format!("More than one overload of `{}` takes these arguments", name.content),
+            vec!["Pass the defaulted arguments, or name them".to_string()],
+        )
+        .with_candidates(candidates)
+    }
+
+    pub fn stack_overflow_error(position: CodePosition, limit: usize) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::RuntimeError,
+            "Stack overflow".to_string(),
+            Some("Called here".to_string()),
+            format!("The program nested more than {} calls", limit),
+            vec!["Compiled programs are only limited by the native stack".to_string()],
+        )
+    }
+
+    pub fn invalid_pointer_error(position: CodePosition, action: &str) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::RuntimeError,
+            "Invalid pointer".to_string(),
+            Some(format!("{} here", action)),
+            "This pointer does not point to a live allocation".to_string(),
+            vec![],
+        )
+    }
+
+    pub fn unavailable_extern_error(position: CodePosition, name: &str, available: &[&str]) -> Self {
+        Self::new(
+            position,
+            CodeErrorType::RuntimeError,
+            "Unavailable extern function".to_string(),
+            Some("Called here".to_string()),
+            format!("`{}` can not be called by the interpreter", name),
+            vec![format!("Available are `{}`", available.join("`, `"))],
+        )
+    }
+
     pub fn visualize_error(self, file_manager: &FileManager) {
         print_code_error(self, file_manager)
     }
@@ -170,6 +856,7 @@ pub struct CodeWarning {
     pub footer: String,
     pub pointer: Option<String>,
     pub notes: Vec<String>,
+    pub labels: Vec<(CodePosition, String)>,
 }
 
 impl CodeWarning {
@@ -188,9 +875,16 @@ impl CodeWarning {
             footer,
             pointer,
             notes,
+            labels: vec![],
         }
     }
 
+    /// Adds a secondary label, e.g. pointing at a declaration.
+    pub fn with_label(mut self, position: CodePosition, label: String) -> Self {
+        self.labels.push((position, label));
+        self
+    }
+
     pub fn new_unnecessary_code(position: CodePosition, extra: Option<String>) -> Self {
         Self::new(
             position,
@@ -198,11 +892,102 @@ impl CodeWarning {
             "Unnecessary code".to_string(),
             "This code does not change the outcome".to_string(),
             None,
-            if extra.is_some() {
-                vec![extra.unwrap()]
+            vec![extra.unwrap_or("You should remove it".to_string())],
+        )
+    }
+
+    pub fn new_dead_code(position: CodePosition, cause: CodePosition, cause_label: &str) -> Self {
+        Self::new(
+            position,
+            CodeWarningType::DeadCode,
+            "Unreachable code".to_string(),
+            "This code will never run".to_string(),
+            None,
+            vec!["You should remove it".to_string()],
+        )
+        .with_label(cause, cause_label.to_string())
+    }
+
+    pub fn new_unused_variable(name: &Token, kind: &str) -> Self {
+        Self::new(
+            name.code_position,
+            CodeWarningType::UnusedVariable,
+            format!("Unused {}", kind),
+            format!("`{}` is never used", name.content),
+            None,
+            vec![format!("Prefix it with an underscore (`_{}`) if this is intended", name.content)],
+        )
+    }
+
+    pub fn new_unused_function(name: &Token) -> Self {
+        Self::new(
+            name.code_position,
+            CodeWarningType::UnusedFunction,
+            "Unused function".to_string(),
+            format!("Private function `{}` is never called", name.content),
+            None,
+            vec![format!("Prefix it with an underscore (`_{}`) if this is intended", name.content)],
+        )
+    }
+
+    pub fn new_unused_import(name: &Token) -> Self {
+        Self::new(
+            name.code_position,
+            CodeWarningType::UnusedImport,
+            "Unused import".to_string(),
+            format!("Nothing from `{}` is used", name.content),
+            None,
+            vec!["You should remove it".to_string()],
+        )
+    }
+
+    pub fn new_unused_mut(name: &Token, is_parameter: bool) -> Self {
+        Self::new(
+            name.code_position,
+            CodeWarningType::UnusedMut,
+            "Unnecessary `mut`".to_string(),
+            format!("`{}` is never changed", name.content),
+            None,
+            vec![if is_parameter {
+                "Take the parameter by value instead".to_string()
             } else {
-                vec!["You should remove it".to_string()]
-            },
+                "You should remove the `mut`".to_string()
+            }],
+        )
+    }
+
+    pub fn new_unknown_attribute(position: CodePosition, name: &str) -> Self {
+        Self::new(
+            position,
+            CodeWarningType::UnknownAttribute,
+            "Unknown attribute".to_string(),
+            format!("Attribute `@{}` does not exist and will be ignored", name),
+            Some("This one".to_string()),
+            vec!["Known attributes are `@inline`, `@noreturn`, `@entry`, `@export_name(\"..\")`, `@cold`, `@drop`, `@unsafe` and `@overload`".to_string()],
+        )
+    }
+
+    pub fn new_lossy_cast(position: CodePosition, target: CodePosition, from: &str, to: &str) -> Self {
+        Self::new(
+            position,
+            CodeWarningType::LossyCast,
+            "Lossy cast".to_string(),
+            format!("Casting `{}` to `{}` may change the value", from, to),
+            Some(format!("This is `{}`", from)),
+            vec![],
+        )
+        .with_label(target, "Target type".to_string())
+    }
+
+    pub fn new_no_op_cast(position: CodePosition, target: CodePosition, ty: &str) -> Self {
+        Self::new(
+            position,
+            CodeWarningType::UnnecessaryCode,
+            "Unnecessary cast".to_string(),
+            format!("This already is `{}`", ty),
+            None,
+            vec!["You should remove the cast".to_string()],
         )
+        .with_label(target, "Target type".to_string())
     }
 }
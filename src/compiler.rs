@@ -0,0 +1,1121 @@
+use crate::c_backend::string_literal;
+use crate::checker::bind_arguments;
+use crate::entry::EntryPoint;
+use crate::lexer::{CodePosition, Token, TokenType};
+use crate::modules::Module;
+use crate::parser::{ASTNode, Builtin, FunctionMode};
+use crate::runtime::builtin_symbol;
+use crate::types::Type;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+/// Registers for integer arguments in the System V ABI, in order.
+const INTEGER_REGISTERS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+/// `%xmm0` to `%xmm7` take float arguments.
+const SSE_REGISTERS: usize = 8;
+
+/// Register class of an eightbyte of a value in the System V ABI.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum Class {
+    Integer,
+    Sse,
+}
+
+/// Where a variable lives: in the frame at an offset from `%rbp`, or behind the
+/// pointer stored there (parameters passed by reference).
+#[derive(Debug, Clone, Copy)]
+enum Place {
+    Frame(i64),
+    Reference(i64),
+}
+
+struct Loop {
+    next: usize,
+    end: usize,
+}
+
+pub(crate) fn is_aggregate(ty: &Type) -> bool {
+    matches!(ty, Type::Array(..) | Type::Struct(_))
+}
+
+/// Frame slots are whole eightbytes, so registers can always be stored in full.
+fn slot_size(size: u64) -> i64 {
+    size.next_multiple_of(8) as i64
+}
+
+/// Rounds a size on the stack up to the 16 bytes `%rsp` is aligned to at calls.
+fn align_stack(size: i64) -> i64 {
+    (size + 15) & !15
+}
+
+/// Condition code of an integer comparison.
+fn condition_code(op: TokenType, signed: bool) -> &'static str {
+    match (op, signed) {
+        (TokenType::DoubleEquals, _) => "e",
+        (TokenType::NotEquals, _) => "ne",
+        (TokenType::Greater, true) => "g",
+        (TokenType::GreaterEquals, true) => "ge",
+        (TokenType::Lesser, true) => "l",
+        (TokenType::LesserEquals, true) => "le",
+        (TokenType::Greater, false) => "a",
+        (TokenType::GreaterEquals, false) => "ae",
+        (TokenType::Lesser, false) => "b",
+        (TokenType::LesserEquals, false) => "be",
+        _ => unreachable!(),
+    }
+}
+
+/// `movq` for immediates that fit a sign extended 32 bit field, `movabsq` otherwise.
+fn move_immediate(value: i64, register: &str) -> String {
+    match i32::try_from(value) {
+        Ok(_) => format!("movq ${}, {}", value, register),
+        Err(_) => format!("movabsq ${}, {}", value, register),
+    }
+}
+
+pub(crate) struct Compiler<'a, 'm> {
+    modules: &'m [Module<'a>],
+    // Linker symbols of the functions with a body
+    defined: HashSet<String>,
+    // Struct name -> fields
+    structs: HashMap<String, Vec<(String, Type)>>,
+    // String literals, their index is their label
+    strings: Vec<String>,
+    // Float to integer conversions, by label
+    helpers: HashMap<String, String>,
+    labels: usize,
+    // State of the function being generated
+    code: String,
+    module: usize,
+    places: HashMap<*const Token, (Place, Type)>,
+    // Bytes of the frame taken by parameters and variables
+    frame: i64,
+    // Bytes of temporaries in use below them, and the most used at once
+    temporaries: i64,
+    deepest: i64,
+    loops: Vec<Loop>,
+    return_type: Type,
+    return_label: usize,
+    // Slot holding the address for results returned in memory
+    result_pointer: i64,
+}
+
+impl<'a, 'm> Compiler<'a, 'm> {
+    pub(crate) fn new(modules: &'m [Module<'a>]) -> Self {
+        let mut defined = HashSet::new();
+        let mut structs = HashMap::new();
+        for module in modules {
+            for item in &module.ast {
+                match item {
+                    ASTNode::FunctionDef(_, mode, ..) if *mode != FunctionMode::Extern => {
+                        defined.insert(item.symbol_name(&module.name).unwrap());
+                    }
+                    ASTNode::StructDef(name, fields) => {
+                        let fields = fields
+                            .iter()
+                            .map(|(name, ty)| (name.content.clone(), Type::from_node(ty).unwrap()))
+                            .collect();
+                        structs.insert(name.content.clone(), fields);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Self {
+            modules,
+            defined,
+            structs,
+            strings: vec![],
+            helpers: HashMap::new(),
+            labels: 0,
+            code: String::new(),
+            module: 0,
+            places: HashMap::new(),
+            frame: 0,
+            temporaries: 0,
+            deepest: 0,
+            loops: vec![],
+            return_type: Type::Void,
+            return_label: 0,
+            result_pointer: 0,
+        }
+    }
+
+    /// Size and alignment of `ty`, laid out like C does so values can be shared with C code.
+    pub(crate) fn layout(&self, ty: &Type) -> (u64, u64) {
+        match ty {
+            Type::Array(element, length) => {
+                let (size, align) = self.layout(element);
+                (size * *length as u64, align)
+            }
+            Type::Struct(name) => {
+                let (mut size, mut align) = (0u64, 1);
+                for (_, field) in &self.structs[name] {
+                    let (field_size, field_align) = self.layout(field);
+                    size = size.next_multiple_of(field_align) + field_size;
+                    align = align.max(field_align);
+                }
+                (size.next_multiple_of(align), align)
+            }
+            ty => {
+                let size = ty.size().unwrap() as u64;
+                (size, size.max(1))
+            }
+        }
+    }
+
+    pub(crate) fn size_of(&self, ty: &Type) -> u64 {
+        self.layout(ty).0
+    }
+
+    /// Offsets of the fields of a struct.
+    fn fields(&self, name: &str) -> Vec<(String, u64, Type)> {
+        let mut offset = 0u64;
+        let mut fields = vec![];
+        for (field, ty) in &self.structs[name] {
+            let (size, align) = self.layout(ty);
+            offset = offset.next_multiple_of(align);
+            fields.push((field.clone(), offset, ty.clone()));
+            offset += size;
+        }
+        fields
+    }
+
+    /// Scalars `ty` is made of, with their offset.
+    fn scalars(&self, ty: &Type, offset: u64, scalars: &mut Vec<(u64, Type)>) {
+        match ty {
+            Type::Array(element, length) => {
+                let size = self.size_of(element);
+                for index in 0..*length as u64 {
+                    self.scalars(element, offset + index * size, scalars);
+                }
+            }
+            Type::Struct(name) => {
+                for (_, field_offset, field) in self.fields(name) {
+                    self.scalars(&field, offset + field_offset, scalars);
+                }
+            }
+            ty => scalars.push((offset, ty.clone())),
+        }
+    }
+
+    /// Classes of the eightbytes of `ty` when it is passed in registers, `None` if it is passed in memory.
+    pub(crate) fn classify(&self, ty: &Type) -> Option<Vec<Class>> {
+        if !is_aggregate(ty) {
+            return Some(vec![if ty.is_float() { Class::Sse } else { Class::Integer }]);
+        }
+        let size = self.size_of(ty);
+        if size > 16 {
+            return None;
+        }
+        // An eightbyte is passed in a vector register if it only holds floats
+        let mut classes = vec![Class::Sse; size.div_ceil(8) as usize];
+        let mut scalars = vec![];
+        self.scalars(ty, 0, &mut scalars);
+        for (offset, scalar) in scalars {
+            if !scalar.is_float() {
+                classes[offset as usize / 8] = Class::Integer;
+            }
+        }
+        Some(classes)
+    }
+
+    fn type_of(&self, node: &ASTNode) -> Type {
+        let module = &self.modules[self.module];
+        let ty = module.types.type_of(node);
+        ty.or_else(|| self.modules.iter().find_map(|t| t.types.type_of(node)))
+            .cloned()
+            .unwrap_or(Type::Void)
+    }
+
+    fn type_of_declaration(&self, name: &Token) -> Type {
+        self.modules[self.module].types.type_of_declaration(name).cloned().unwrap_or(Type::Void)
+    }
+
+    fn label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels
+    }
+
+    fn emit(&mut self, instruction: &str) {
+        writeln!(self.code, "    {}", instruction).unwrap();
+    }
+
+    fn emit_label(&mut self, label: usize) {
+        writeln!(self.code, ".L{}:", label).unwrap();
+    }
+
+    /// Debug line information pointing at the `.sila` file.
+    fn location(&mut self, position: CodePosition) {
+        let (file, line, column) = (self.module + 1, position.line_start + 1, position.line_idx_start + 1);
+        self.emit(&format!(".loc {} {} {}", file, line, column));
+    }
+
+    /// Frame slot for a value of `size` bytes, the slot is free again after the statement.
+    fn temporary(&mut self, size: u64) -> i64 {
+        self.temporaries += slot_size(size);
+        self.deepest = self.deepest.max(self.temporaries);
+        -(self.frame + self.temporaries)
+    }
+
+    /// Frame slot for the variable declared by `name`.
+    fn local(&mut self, name: &Token, ty: Type) {
+        self.frame += slot_size(self.size_of(&ty));
+        self.places.insert(name as *const Token, (Place::Frame(-self.frame), ty));
+    }
+
+    /// Operand for the memory of a variable. Through a reference, the pointer is loaded into `%rdi`.
+    fn operand(&mut self, place: Place) -> String {
+        match place {
+            Place::Frame(offset) => format!("{}(%rbp)", offset),
+            Place::Reference(offset) => {
+                self.emit(&format!("movq {}(%rbp), %rdi", offset));
+                "(%rdi)".to_string()
+            }
+        }
+    }
+
+    /// Loads the address of a variable into `%rax`.
+    fn address(&mut self, place: Place) {
+        match place {
+            Place::Frame(offset) => self.emit(&format!("leaq {}(%rbp), %rax", offset)),
+            Place::Reference(offset) => self.emit(&format!("movq {}(%rbp), %rax", offset)),
+        }
+    }
+
+    /// Loads a scalar of `ty` into `%rax`, extended to 64 bits.
+    fn load(&mut self, ty: &Type, operand: &str) {
+        let instruction = match ty {
+            Type::Int(8, true) => format!("movsbq {}, %rax", operand),
+            Type::Int(8, false) | Type::Bool => format!("movzbq {}, %rax", operand),
+            Type::Int(16, true) => format!("movswq {}, %rax", operand),
+            Type::Int(16, false) => format!("movzwq {}, %rax", operand),
+            Type::Int(32, true) => format!("movslq {}, %rax", operand),
+            Type::Int(32, false) | Type::Float(32) => format!("movl {}, %eax", operand),
+            _ => format!("movq {}, %rax", operand),
+        };
+        self.emit(&instruction);
+    }
+
+    /// Stores the scalar in `%rax`, or copies the aggregate `%rax` points to.
+    fn store(&mut self, ty: &Type, operand: &str) {
+        let instruction = match self.size_of(ty) {
+            _ if is_aggregate(ty) => {
+                self.emit(&format!("leaq {}, %rdi", operand));
+                self.emit("movq %rax, %rsi");
+                self.emit(&format!("movq ${}, %rcx", self.size_of(ty)));
+                "rep movsb".to_string()
+            }
+            0 => return,
+            1 => format!("movb %al, {}", operand),
+            2 => format!("movw %ax, {}", operand),
+            4 => format!("movl %eax, {}", operand),
+            _ => format!("movq %rax, {}", operand),
+        };
+        self.emit(&instruction);
+    }
+
+    /// Wraps the integer in `%rax` around to the range of `ty`, like the other backends.
+    fn normalize(&mut self, ty: &Type) {
+        let instruction = match ty {
+            Type::Int(8, true) => "movsbq %al, %rax",
+            Type::Int(8, false) | Type::Bool => "movzbl %al, %eax",
+            Type::Int(16, true) => "movswq %ax, %rax",
+            Type::Int(16, false) => "movzwl %ax, %eax",
+            Type::Int(32, true) => "movslq %eax, %rax",
+            Type::Int(32, false) | Type::Float(32) => "movl %eax, %eax",
+            _ => return,
+        };
+        self.emit(instruction);
+    }
+
+    fn string(&mut self, text: &str) -> usize {
+        match self.strings.iter().position(|t| t == text) {
+            Some(index) => index,
+            None => {
+                self.strings.push(text.to_string());
+                self.strings.len() - 1
+            }
+        }
+    }
+
+    fn function(&mut self, module: usize, function: &ASTNode<'a>) -> String {
+        let ASTNode::FunctionDef(_, _, ret, params, body, _) = function else {
+            unreachable!()
+        };
+        self.module = module;
+        self.places.clear();
+        self.frame = 0;
+        self.temporaries = 0;
+        self.deepest = 0;
+        self.return_type = Type::from_node(ret).unwrap();
+        self.return_label = self.label();
+        self.code.clear();
+
+        // Incoming arguments are stored into the frame, the ones passed on the stack stay there
+        let mut prologue = String::new();
+        let (mut integers, mut vectors, mut stack) = (0, 0, 16);
+        if self.classify(&self.return_type).is_none() {
+            self.frame += 8;
+            self.result_pointer = -self.frame;
+            writeln!(prologue, "    movq %rdi, {}(%rbp)", self.result_pointer).unwrap();
+            integers += 1;
+        }
+        for (name, ty, _) in params {
+            if matches!(**ty, ASTNode::ReferenceType(..)) {
+                let place = match integers < INTEGER_REGISTERS.len() {
+                    true => {
+                        self.frame += 8;
+                        writeln!(prologue, "    movq {}, {}(%rbp)", INTEGER_REGISTERS[integers], -self.frame).unwrap();
+                        integers += 1;
+                        Place::Reference(-self.frame)
+                    }
+                    false => {
+                        stack += 8;
+                        Place::Reference(stack - 8)
+                    }
+                };
+                self.places.insert(*name as *const Token, (place, Type::from_node(ty).unwrap()));
+                continue;
+            }
+
+            let ty = Type::from_node(ty).unwrap();
+            match self.classify(&ty) {
+                Some(classes) if self.fits(&classes, integers, vectors) => {
+                    self.local(name, ty);
+                    let offset = -self.frame;
+                    for (index, class) in classes.iter().enumerate() {
+                        let register = match class {
+                            Class::Integer => INTEGER_REGISTERS[integers].to_string(),
+                            Class::Sse => format!("%xmm{}", vectors),
+                        };
+                        writeln!(prologue, "    movq {}, {}(%rbp)", register, offset + 8 * index as i64).unwrap();
+                        match class {
+                            Class::Integer => integers += 1,
+                            Class::Sse => vectors += 1,
+                        }
+                    }
+                }
+                _ => {
+                    let size = slot_size(self.size_of(&ty));
+                    self.places.insert(*name as *const Token, (Place::Frame(stack), ty));
+                    stack += size;
+                }
+            }
+        }
+        self.allocate(body);
+
+        for statement in body {
+            self.statement(statement);
+        }
+        let frame = align_stack(self.frame + self.deepest);
+        let mut code = String::new();
+        writeln!(code, "    pushq %rbp\n    movq %rsp, %rbp").unwrap();
+        if frame > 0 {
+            writeln!(code, "    subq ${}, %rsp", frame).unwrap();
+        }
+        code.push_str(&prologue);
+        code.push_str(&self.code);
+        writeln!(code, ".L{}:\n    leave\n    ret", self.return_label).unwrap();
+        code
+    }
+
+    /// Whether a value with `classes` still fits the argument registers that are left.
+    pub(crate) fn fits(&self, classes: &[Class], integers: usize, vectors: usize) -> bool {
+        let needed = classes.iter().filter(|t| **t == Class::Integer).count();
+        integers + needed <= INTEGER_REGISTERS.len() && vectors + classes.len() - needed <= SSE_REGISTERS
+    }
+
+    /// Gives every variable of `body` its slot in the frame.
+    fn allocate(&mut self, body: &[Box<ASTNode<'a>>]) {
+        for statement in body {
+            match &**statement {
+                ASTNode::VariableSet(name, ..) => {
+                    let ty = self.type_of_declaration(name);
+                    if ty != Type::Void {
+                        self.local(name, ty);
+                    }
+                }
+                ASTNode::WhileLoop(_, body) => self.allocate(body),
+                ASTNode::If(_, then_body, else_body) => {
+                    self.allocate(then_body);
+                    self.allocate(else_body);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &ASTNode<'a>) {
+        let temporaries = self.temporaries;
+        self.location(statement.position());
+        match statement {
+            ASTNode::VariableSet(name, value, ..) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                if let (Some(_), Some((place, ty))) = (value, self.places.get(&(*name as *const Token)).cloned()) {
+                    let operand = self.operand(place);
+                    self.store(&ty, &operand);
+                }
+            }
+            ASTNode::Assignment(name, value) => {
+                self.expr(value);
+                let (place, ty) = self.places[&self.modules[self.module].key(name)].clone();
+                let operand = self.operand(place);
+                self.store(&ty, &operand);
+            }
+            ASTNode::Return(value) => {
+                self.ret(value);
+                self.emit(&format!("jmp .L{}", self.return_label));
+            }
+            ASTNode::Break(_) => self.emit(&format!("jmp .L{}", self.loops.last().unwrap().end)),
+            ASTNode::Continue(_) => self.emit(&format!("jmp .L{}", self.loops.last().unwrap().next)),
+            ASTNode::If(condition, then_body, else_body) => {
+                let (otherwise, end) = (self.label(), self.label());
+                self.expr(condition);
+                self.emit("testb %al, %al");
+                self.emit(&format!("je .L{}", otherwise));
+                self.block(then_body);
+                self.emit(&format!("jmp .L{}", end));
+                self.emit_label(otherwise);
+                self.block(else_body);
+                self.emit_label(end);
+            }
+            ASTNode::WhileLoop(condition, body) => {
+                let (next, end) = (self.label(), self.label());
+                self.emit_label(next);
+                self.expr(condition);
+                self.emit("testb %al, %al");
+                self.emit(&format!("je .L{}", end));
+                self.loop_body(body, next, end);
+                self.emit(&format!("jmp .L{}", next));
+                self.emit_label(end);
+            }
+            expr => self.expr(expr),
+        }
+        self.temporaries = temporaries;
+    }
+
+    fn block(&mut self, body: &[Box<ASTNode<'a>>]) {
+        for statement in body {
+            self.statement(statement);
+        }
+    }
+
+    fn loop_body(&mut self, body: &[Box<ASTNode<'a>>], next: usize, end: usize) {
+        self.loops.push(Loop { next, end });
+        self.block(body);
+        self.loops.pop();
+    }
+
+    /// Moves the value of `return` to where the ABI returns values of its type.
+    fn ret(&mut self, value: &ASTNode<'a>) {
+        let ty = self.return_type.clone();
+        match (&ty, value) {
+            (Type::Void, ASTNode::Identifier(_)) => return,
+            _ => self.expr(value),
+        }
+        match self.classify(&ty) {
+            _ if ty == Type::Void => {}
+            None => {
+                self.emit("movq %rax, %rsi");
+                self.emit(&format!("movq {}(%rbp), %rdi", self.result_pointer));
+                self.emit(&format!("movq ${}, %rcx", self.size_of(&ty)));
+                self.emit("rep movsb");
+                self.emit(&format!("movq {}(%rbp), %rax", self.result_pointer));
+            }
+            Some(classes) if is_aggregate(&ty) => {
+                self.emit("movq %rax, %rsi");
+                let (mut integers, mut vectors) = (["%rax", "%rdx"].into_iter(), 0..2);
+                for (index, class) in classes.iter().enumerate() {
+                    let register = match class {
+                        Class::Integer => integers.next().unwrap().to_string(),
+                        Class::Sse => format!("%xmm{}", vectors.next().unwrap()),
+                    };
+                    self.emit(&format!("movq {}(%rsi), {}", 8 * index, register));
+                }
+            }
+            Some(_) if ty.is_float() => self.emit("movq %rax, %xmm0"),
+            Some(_) => {}
+        }
+    }
+
+    /// Evaluates `node` into `%rax`: scalars as their value (floats as their bits),
+    /// arrays and structs as the address of their memory.
+    fn expr(&mut self, node: &ASTNode<'a>) {
+        match node {
+            ASTNode::Literal(t) => self.literal(t, &self.type_of(node)),
+            ASTNode::String(t) => {
+                let label = self.string(&t.content);
+                self.emit(&format!("leaq .Lstr{}(%rip), %rax", label));
+            }
+            ASTNode::Identifier(name) => {
+                let (place, ty) = self.places[&self.modules[self.module].key(name)].clone();
+                match is_aggregate(&ty) {
+                    true => self.address(place),
+                    false => {
+                        let operand = self.operand(place);
+                        self.load(&ty, &operand);
+                    }
+                }
+            }
+            ASTNode::BinaryOp(lhs, op, rhs) => self.binary_op(node, lhs, op, rhs),
+            ASTNode::CastExpr(expr, ty) => {
+                let (from, to) = (self.type_of(expr), Type::from_node(ty).unwrap());
+                self.expr(expr);
+                self.cast(&from, &to);
+            }
+            ASTNode::FunctionCall(name, args, _) => self.call(name, args),
+            ASTNode::NamedArgument(_, value) => self.expr(value),
+            ASTNode::BuiltinCall(builtin, _, type_arg, args, _) => {
+                let element = type_arg.as_ref().map_or(0, |t| self.size_of(&Type::from_node(t).unwrap()));
+                let symbol = builtin_symbol(*builtin);
+                match builtin {
+                    Builtin::Alloc => {
+                        self.expr(&args[0]);
+                        self.emit(&format!("imulq ${}, %rax, %rdi", element));
+                    }
+                    Builtin::Realloc => {
+                        self.expr(&args[0]);
+                        let pointer = self.temporary(8);
+                        self.emit(&format!("movq %rax, {}(%rbp)", pointer));
+                        self.expr(&args[1]);
+                        self.emit(&format!("imulq ${}, %rax, %rsi", element));
+                        self.emit(&format!("movq {}(%rbp), %rdi", pointer));
+                    }
+                    Builtin::Free => {
+                        self.expr(&args[0]);
+                        self.emit("movq %rax, %rdi");
+                    }
+                }
+                self.emit(&format!("call {}", symbol));
+            }
+            ASTNode::StructLiteral(name, values) => {
+                let ty = Type::Struct(name.content.clone());
+                let memory = self.temporary(self.size_of(&ty));
+                let fields = self.fields(&name.content);
+                for (field, value) in values {
+                    let (_, offset, ty) = fields.iter().find(|t| t.0 == field.content).unwrap().clone();
+                    self.expr(value);
+                    self.store(&ty, &format!("{}(%rbp)", memory + offset as i64));
+                }
+                self.emit(&format!("leaq {}(%rbp), %rax", memory));
+            }
+            ASTNode::ArrayLiteral(_, elements) => {
+                let ty = self.type_of(node);
+                let Type::Array(element, _) = &ty else {
+                    unreachable!()
+                };
+                let memory = self.temporary(self.size_of(&ty));
+                let size = self.size_of(element) as i64;
+                for (index, value) in elements.iter().enumerate() {
+                    self.expr(value);
+                    self.store(element, &format!("{}(%rbp)", memory + index as i64 * size));
+                }
+                self.emit(&format!("leaq {}(%rbp), %rax", memory));
+            }
+            ASTNode::Index(array, index) => {
+                let element = self.type_of(node);
+                self.expr(array);
+                let base = self.temporary(8);
+                self.emit(&format!("movq %rax, {}(%rbp)", base));
+                self.expr(index);
+                self.emit(&format!("imulq ${}, %rax, %rax", self.size_of(&element)));
+                self.emit(&format!("addq {}(%rbp), %rax", base));
+                if !is_aggregate(&element) {
+                    self.load(&element, "(%rax)");
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn literal(&mut self, token: &Token, ty: &Type) {
+        let instruction = match (token.token_type, ty) {
+            (TokenType::Boolean, _) => format!("movl ${}, %eax", (token.content == "true") as u8),
+            (_, Type::Float(32)) => format!("movl ${}, %eax", (token.content.parse::<f64>().unwrap() as f32).to_bits()),
+            (_, Type::Float(_)) => move_immediate(token.content.parse::<f64>().unwrap().to_bits() as i64, "%rax"),
+            _ => move_immediate(token.content.parse::<i128>().unwrap() as i64, "%rax"),
+        };
+        self.emit(&instruction);
+    }
+
+    /// Integers are computed in 64 bits and wrapped to their type. Division by zero
+    /// raises `SIGFPE`, the interpreter reports it as an error instead.
+    fn binary_op(&mut self, node: &ASTNode<'a>, lhs: &ASTNode<'a>, op: &Token, rhs: &ASTNode<'a>) {
+        let operands = self.type_of(lhs);
+        self.expr(lhs);
+        let left = self.temporary(8);
+        self.emit(&format!("movq %rax, {}(%rbp)", left));
+        self.expr(rhs);
+        self.emit("movq %rax, %rcx");
+        self.emit(&format!("movq {}(%rbp), %rax", left));
+
+        let comparison = !matches!(op.token_type, TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash);
+        match (&operands, comparison) {
+            (Type::Int(_, signed), false) => {
+                match (op.token_type, signed) {
+                    (TokenType::Plus, _) => self.emit("addq %rcx, %rax"),
+                    (TokenType::Minus, _) => self.emit("subq %rcx, %rax"),
+                    (TokenType::Star, _) => self.emit("imulq %rcx, %rax"),
+                    // `idiv` traps on the overflow of the smallest value divided by -1, it wraps instead
+                    (_, true) => {
+                        let (divide, done) = (self.label(), self.label());
+                        self.emit("cmpq $-1, %rcx");
+                        self.emit(&format!("jne .L{}", divide));
+                        self.emit("negq %rax");
+                        self.emit(&format!("jmp .L{}", done));
+                        self.emit_label(divide);
+                        self.emit("cqto");
+                        self.emit("idivq %rcx");
+                        self.emit_label(done);
+                    }
+                    (_, false) => {
+                        self.emit("xorl %edx, %edx");
+                        self.emit("divq %rcx");
+                    }
+                }
+                self.normalize(&self.type_of(node));
+            }
+            (Type::Float(bits), false) => {
+                let (suffix, mov) = if *bits == 32 { ("ss", "movd") } else { ("sd", "movq") };
+                let (rax, rcx) = if *bits == 32 { ("%eax", "%ecx") } else { ("%rax", "%rcx") };
+                let instruction = match op.token_type {
+                    TokenType::Plus => "add",
+                    TokenType::Minus => "sub",
+                    TokenType::Star => "mul",
+                    _ => "div",
+                };
+                self.emit(&format!("{} {}, %xmm0", mov, rax));
+                self.emit(&format!("{} {}, %xmm1", mov, rcx));
+                self.emit(&format!("{}{} %xmm1, %xmm0", instruction, suffix));
+                self.emit(&format!("{} %xmm0, {}", mov, rax));
+            }
+            (Type::Float(bits), true) => {
+                let (suffix, mov) = if *bits == 32 { ("ss", "movd") } else { ("sd", "movq") };
+                let (rax, rcx) = if *bits == 32 { ("%eax", "%ecx") } else { ("%rax", "%rcx") };
+                self.emit(&format!("{} {}, %xmm0", mov, rax));
+                self.emit(&format!("{} {}, %xmm1", mov, rcx));
+                // Comparisons with NaN are false, except for `!=`
+                match op.token_type {
+                    TokenType::Lesser | TokenType::LesserEquals => self.emit(&format!("ucomi{} %xmm0, %xmm1", suffix)),
+                    _ => self.emit(&format!("ucomi{} %xmm1, %xmm0", suffix)),
+                }
+                match op.token_type {
+                    TokenType::DoubleEquals => {
+                        self.emit("sete %al");
+                        self.emit("setnp %cl");
+                        self.emit("andb %cl, %al");
+                    }
+                    TokenType::NotEquals => {
+                        self.emit("setne %al");
+                        self.emit("setp %cl");
+                        self.emit("orb %cl, %al");
+                    }
+                    TokenType::Greater | TokenType::Lesser => self.emit("seta %al"),
+                    _ => self.emit("setae %al"),
+                }
+                self.emit("movzbl %al, %eax");
+            }
+            // Booleans, pointers and strings only compare for equality
+            (ty, _) => {
+                self.emit("cmpq %rcx, %rax");
+                self.emit(&format!("set{} %al", condition_code(op.token_type, matches!(ty, Type::Int(_, true)))));
+                self.emit("movzbl %al, %eax");
+            }
+        }
+    }
+
+    fn cast(&mut self, from: &Type, to: &Type) {
+        match (from, to) {
+            (Type::Int(bits, signed), Type::Float(float)) => {
+                let (suffix, mov) = if *float == 32 { ("ss", "movd") } else { ("sd", "movq") };
+                let rax = if *float == 32 { "%eax" } else { "%rax" };
+                // `cvtsi2sd` converts signed values, the largest unsigned ones are halved first
+                if *bits == 64 && !signed {
+                    let (large, done) = (self.label(), self.label());
+                    self.emit("testq %rax, %rax");
+                    self.emit(&format!("js .L{}", large));
+                    self.emit(&format!("cvtsi2{}q %rax, %xmm0", suffix));
+                    self.emit(&format!("jmp .L{}", done));
+                    self.emit_label(large);
+                    self.emit("movq %rax, %rcx");
+                    self.emit("shrq %rcx");
+                    self.emit("andl $1, %eax");
+                    self.emit("orq %rax, %rcx");
+                    self.emit(&format!("cvtsi2{}q %rcx, %xmm0", suffix));
+                    self.emit(&format!("add{} %xmm0, %xmm0", suffix));
+                    self.emit_label(done);
+                } else {
+                    self.emit(&format!("cvtsi2{}q %rax, %xmm0", suffix));
+                }
+                self.emit(&format!("{} %xmm0, {}", mov, rax));
+            }
+            (Type::Float(bits), Type::Int(..)) => {
+                let helper = format!("f{}_to_{}", bits, to);
+                if !self.helpers.contains_key(&helper) {
+                    let definition = Self::float_to_int(&helper, *bits, to);
+                    self.helpers.insert(helper.clone(), definition);
+                }
+                self.emit(&format!("call .L{}", helper));
+            }
+            (Type::Float(32), Type::Float(64)) => {
+                self.emit("movd %eax, %xmm0");
+                self.emit("cvtss2sd %xmm0, %xmm0");
+                self.emit("movq %xmm0, %rax");
+            }
+            (Type::Float(64), Type::Float(32)) => {
+                self.emit("movq %rax, %xmm0");
+                self.emit("cvtsd2ss %xmm0, %xmm0");
+                self.emit("movd %xmm0, %eax");
+            }
+            (_, Type::Int(..)) => self.normalize(to),
+            _ => {}
+        }
+    }
+
+    /// Float to integer conversions truncate and saturate like in the interpreter, NaN becomes 0.
+    /// Takes the bits of the float in `%rax` and returns the integer there.
+    fn float_to_int(name: &str, bits: u8, to: &Type) -> String {
+        let Type::Int(to_bits, signed) = to else {
+            unreachable!()
+        };
+        let (min, max) = match signed {
+            true => (-(1i128 << (to_bits - 1)), (1i128 << (to_bits - 1)) - 1),
+            false => (0, (1i128 << to_bits) - 1),
+        };
+        let mut code = format!(".L{}:\n", name);
+        match bits {
+            32 => code.push_str("    movd %eax, %xmm0\n    cvtss2sd %xmm0, %xmm0\n"),
+            _ => code.push_str("    movq %rax, %xmm0\n"),
+        }
+        code.push_str("    xorl %eax, %eax\n    ucomisd %xmm0, %xmm0\n    jp 1f\n");
+        for (bound, jump) in [(min, "jbe"), (max, "jae")] {
+            writeln!(code, "    {}\n    movq %rcx, %xmm1", move_immediate((bound as f64).to_bits() as i64, "%rcx")).unwrap();
+            writeln!(code, "    {}\n    ucomisd %xmm1, %xmm0\n    {} 1f", move_immediate(bound as i64, "%rax"), jump).unwrap();
+        }
+        // Above the largest signed value, the top bit is set after converting
+        if *to_bits == 64 && !signed {
+            writeln!(code, "    {}\n    movq %rcx, %xmm1", move_immediate((2f64.powi(63)).to_bits() as i64, "%rcx")).unwrap();
+            code.push_str("    ucomisd %xmm1, %xmm0\n    jb 2f\n    subsd %xmm1, %xmm0\n");
+            code.push_str("    cvttsd2siq %xmm0, %rax\n    btcq $63, %rax\n    ret\n2:\n");
+        }
+        code.push_str("    cvttsd2siq %xmm0, %rax\n1:  ret\n");
+        code
+    }
+
+    /// Loads the address a reference parameter receives for `arg` into `%rax`.
+    /// Values that are not variables are stored in a temporary first.
+    fn reference(&mut self, arg: &ASTNode<'a>) {
+        match arg {
+            ASTNode::NamedArgument(_, value) => self.reference(value),
+            ASTNode::Identifier(name) => {
+                let (place, _) = self.places[&self.modules[self.module].key(name)];
+                self.address(place);
+            }
+            arg => {
+                let ty = self.type_of(arg);
+                self.expr(arg);
+                let memory = self.temporary(self.size_of(&ty));
+                self.store(&ty, &format!("{}(%rbp)", memory));
+                self.emit(&format!("leaq {}(%rbp), %rax", memory));
+            }
+        }
+    }
+
+    /// Calls follow the System V ABI, so C code can call Sila functions and the other way around.
+    /// The arguments are evaluated into temporaries first, then moved to registers and the stack.
+    fn call(&mut self, name: &'a Token, args: &[Box<ASTNode<'a>>]) {
+        let module = &self.modules[self.module];
+        let function = module.callee(name);
+        let ASTNode::FunctionDef(function_name, _, ret, params, ..) = function else {
+            unreachable!()
+        };
+        let symbol = function.symbol_name(&module.name).unwrap();
+
+        let bound = bind_arguments(name, args, function_name, params).unwrap();
+        let mut values = vec![];
+        for ((_, ty, _), arg) in params.iter().zip(bound) {
+            let (ty, memory) = match &**ty {
+                ASTNode::ReferenceType(..) => {
+                    self.reference(arg);
+                    (Type::Pointer(Box::new(Type::Void)), self.temporary(8))
+                }
+                ty => {
+                    let ty = Type::from_node(ty).unwrap();
+                    self.expr(arg);
+                    (ty.clone(), self.temporary(self.size_of(&ty)))
+                }
+            };
+            self.store(&ty, &format!("{}(%rbp)", memory));
+            values.push((memory, ty));
+        }
+
+        let ret = Type::from_node(ret).unwrap();
+        let in_memory = self.classify(&ret).is_none();
+        let (mut integers, mut vectors) = (in_memory as usize, 0);
+        let (mut registers, mut stack) = (vec![], vec![]);
+        for (memory, ty) in values {
+            match self.classify(&ty) {
+                Some(classes) if self.fits(&classes, integers, vectors) => {
+                    for (index, class) in classes.into_iter().enumerate() {
+                        let register = match class {
+                            Class::Integer => INTEGER_REGISTERS[integers].to_string(),
+                            Class::Sse => format!("%xmm{}", vectors),
+                        };
+                        registers.push(format!("movq {}(%rbp), {}", memory + 8 * index as i64, register));
+                        match class {
+                            Class::Integer => integers += 1,
+                            Class::Sse => vectors += 1,
+                        }
+                    }
+                }
+                _ => stack.push((memory, ty)),
+            }
+        }
+
+        // Arguments in memory go below the return address, in order
+        let area = stack.iter().map(|(_, ty)| slot_size(self.size_of(ty))).sum::<i64>();
+        let area = align_stack(area);
+        if area > 0 {
+            self.emit(&format!("subq ${}, %rsp", area));
+        }
+        let mut offset = 0;
+        for (memory, ty) in stack {
+            self.emit(&format!("leaq {}(%rbp), %rsi", memory));
+            self.emit(&format!("leaq {}(%rsp), %rdi", offset));
+            self.emit(&format!("movq ${}, %rcx", slot_size(self.size_of(&ty))));
+            self.emit("rep movsb");
+            offset += slot_size(self.size_of(&ty));
+        }
+        let result = self.temporary(self.size_of(&ret));
+        if in_memory {
+            self.emit(&format!("leaq {}(%rbp), %rdi", result));
+        }
+        for register in registers {
+            self.emit(&register);
+        }
+        match self.defined.contains(&symbol) {
+            true => self.emit(&format!("call {}", symbol)),
+            // Variadic C functions take the number of vector registers used in `%al`
+            false => {
+                self.emit(&format!("movl ${}, %eax", vectors));
+                self.emit(&format!("call {}@PLT", symbol));
+            }
+        }
+        if area > 0 {
+            self.emit(&format!("addq ${}, %rsp", area));
+        }
+
+        match self.classify(&ret) {
+            None => {}
+            Some(classes) if is_aggregate(&ret) => {
+                let (mut integers, mut vectors) = (["%rax", "%rdx"].into_iter(), 0..2);
+                for (index, class) in classes.iter().enumerate() {
+                    let register = match class {
+                        Class::Integer => integers.next().unwrap().to_string(),
+                        Class::Sse => format!("%xmm{}", vectors.next().unwrap()),
+                    };
+                    self.emit(&format!("movq {}, {}(%rbp)", register, result + 8 * index as i64));
+                }
+                self.emit(&format!("leaq {}(%rbp), %rax", result));
+            }
+            Some(_) if ret == Type::Float(32) => self.emit("movd %xmm0, %eax"),
+            Some(_) if ret.is_float() => self.emit("movq %xmm0, %rax"),
+            // Only the bits of the type are defined in the register
+            Some(_) => self.normalize(&ret),
+        }
+    }
+}
+
+/// x86-64 GNU assembler source of the checked `modules` for Linux. Export functions and the
+/// entry are global symbols, calls follow the System V ABI and functions without a body are
+/// called through the PLT, so the program links against C libraries. `.loc` directives map
+/// the instructions back to the `.sila` files.
+pub fn generate(modules: &[Module], entry: &EntryPoint) -> String {
+    let mut compiler = Compiler::new(modules);
+    let mut source = String::from("# Generated by the Sila compiler\n    .text\n");
+    for (index, module) in modules.iter().enumerate() {
+        writeln!(source, "    .file {} {}", index + 1, string_literal(&module.file_manager.input_file)).unwrap();
+    }
+
+    for (index, module) in modules.iter().enumerate() {
+        for item in &module.ast {
+            let ASTNode::FunctionDef(_, mode, ..) = item else {
+                continue;
+            };
+            if *mode == FunctionMode::Extern {
+                continue;
+            }
+            let symbol = item.symbol_name(&module.name).unwrap();
+            source.push('\n');
+            if *mode == FunctionMode::Export || symbol == entry.symbol {
+                writeln!(source, "    .globl {}", symbol).unwrap();
+            }
+            writeln!(source, "    .type {}, @function\n{}:", symbol, symbol).unwrap();
+            compiler.module = index;
+            let position = item.position();
+            writeln!(source, "    .loc {} {} {}", index + 1, position.line_start + 1, position.line_idx_start + 1).unwrap();
+            source.push_str(&compiler.function(index, item));
+            writeln!(source, "    .size {}, .-{}", symbol, symbol).unwrap();
+        }
+    }
+
+    let mut helpers: Vec<&String> = compiler.helpers.values().collect();
+    helpers.sort();
+    helpers.into_iter().for_each(|t| write!(source, "\n{}", t).unwrap());
+
+    if !compiler.strings.is_empty() {
+        source.push_str("\n    .section .rodata\n");
+        for (index, text) in compiler.strings.iter().enumerate() {
+            writeln!(source, ".Lstr{}:\n    .asciz {}", index, string_literal(text)).unwrap();
+        }
+    }
+    source.push_str("\n    .section .note.GNU-stack,\"\",@progbits\n");
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::entry_point;
+    use crate::interpreter::interpret;
+    use crate::runtime::{runtime_asm, start_asm, AllocatorKind};
+    use crate::testing::check_module_of;
+    use std::path::Path;
+    use std::process::Command;
+
+    fn generate_asm(source: &str) -> String {
+        let modules = [check_module_of(source).unwrap_or_else(|e| panic!("{}: {}", e.title, e.footer))];
+        let entry = entry_point(&modules[0].ast, &modules[0].name, None).unwrap();
+        generate(&modules, &entry)
+    }
+
+    /// Builds the files in `dir` with the system C compiler, `false` if there is none.
+    fn build(dir: &Path, args: &[&str]) -> bool {
+        if Command::new("cc").arg("--version").output().is_err() {
+            return false;
+        }
+        let built = Command::new("cc").args(args).current_dir(dir).status().unwrap();
+        assert!(built.success());
+        true
+    }
+
+    #[test]
+    fn declares_functions_by_mode() {
+        let source = generate_asm(
+            "def extern puts(s: str): i32 {\n}\n\ndef private helper(): i32 {\n    return 1;\n}\n\n\
+             def export api(x: &mut i32): void {\n    x = helper();\n    puts(\"api\");\n}\n\n\
+             def main(): i32 {\n    return 0;\n}",
+        );
+        assert!(source.contains("\n    .globl api\n    .type api, @function\napi:\n"));
+        assert!(source.contains("\n    .type test__helper, @function\ntest__helper:\n"));
+        assert!(!source.contains(".globl test__helper"));
+        // The entry is called by the start stub
+        assert!(source.contains(".globl test__main"));
+        assert!(source.contains("    call test__helper\n"));
+        assert!(source.contains("    movl $0, %eax\n    call puts@PLT\n"));
+        assert!(source.contains(".Lstr0:\n    .asciz \"api\"\n"));
+    }
+
+    #[test]
+    fn maps_instructions_to_sila_lines() {
+        let source = generate_asm("def main(): i32 {\n    let x = 1;\n\n    return x;\n}");
+        assert!(source.contains("    .file 1 \"test.sila\"\n"));
+        assert!(source.contains("test__main:\n    .loc 1 1 5\n    pushq %rbp\n"));
+        assert!(source.contains("    .loc 1 2 9\n    movq $1, %rax\n    movl %eax, -8(%rbp)\n"));
+        assert!(source.contains("    .loc 1 4 12\n    movslq -8(%rbp), %rax\n"));
+    }
+
+    #[test]
+    fn classifies_arguments_like_the_abi() {
+        let source = "struct Mixed {\n    a: i32,\n    b: f32,\n    c: f64\n}\n\nstruct Big {\n    a: [i64; 3]\n}\n\n\
+            def main(): i32 {\n    return 0;\n}";
+        let modules = [check_module_of(source).unwrap()];
+        let compiler = Compiler::new(&modules);
+        let mixed = Type::Struct("Mixed".to_string());
+        assert_eq!(compiler.layout(&mixed), (16, 8));
+        assert_eq!(compiler.classify(&mixed), Some(vec![Class::Integer, Class::Sse]));
+        assert_eq!(compiler.classify(&Type::Array(Box::new(Type::Float(32)), 2)), Some(vec![Class::Sse]));
+        assert_eq!(compiler.classify(&Type::Struct("Big".to_string())), None);
+        assert_eq!(compiler.layout(&Type::Array(Box::new(Type::Int(8, false)), 3)), (3, 1));
+    }
+
+    /// Links the program with the libc runtime and the start stub, it has to behave like in the interpreter.
+    #[test]
+    fn runs_like_the_interpreter() {
+        let source = "def extern puts(s: str): i32 {\n}\n\nstruct Pair {\n    a: i64,\n    b: f64\n}\n\n\
+            def swap(x: &mut i32, y: &mut i32): void {\n    let t = x;\n    x = y;\n    y = t;\n}\n\n\
+            def total(values: [i32; 5], pair: Pair): [i32; 2] {\n    let mut sum = 0;\n    for v in values {\n        sum = sum + v;\n    }\n    \
+            return [sum, 7];\n}\n\n\
+            def many(a: i8, b: u16, c: i32, d: i64, e: f32, f: f64, g: u8, h: i32, i: i64): i64 {\n    \
+            return (a -> i64) + (b -> i64) + (c -> i64) + d + (e -> i64) + (f -> i64) + (g -> i64) + (h -> i64) + i;\n}\n\n\
+            def @unsafe main(): i32 {\n    let mut x = 1;\n    let mut y = 2;\n    swap(x, y);\n    \
+            let p = alloc<Pair>(4);\n    let q = realloc<Pair>(p, 8);\n    free(q);\n    let small: i8 = 127;\n    \
+            let mut count: u8 = 0;\n    for i in 250..=255 {\n        count = count + 1;\n    }\n    \
+            let mut t = 0;\n    for v in total([1, 2, 3, 4, 5], Pair { a: 1, b: 2.75 }) {\n        t = t + v;\n    }\n    \
+            let mut w = 0;\n    while w < 10 {\n        w = w + 3;\n        if w == 6 {\n            continue;\n        }\n    }\n    \
+            puts(\"done\");\n    let wide = many(0 - 1, 65535, 3, 4, 5.5, 0.0 - 6.5, 200, 8, 9);\n    \
+            let f: f32 = 7.0;\n    \
+            return x * 10 + y + ((small + 1) -> i32) + (3.9 -> i32) + (count -> i32) + t + w + ((wide - 65000) -> i32) + \
+            ((1000000000000.0 -> i32) / 1000000000) + ((f / 2.0 > 3.0) -> i32) + ((0 - 7) / 2) * 10;\n}";
+        let dir = std::env::temp_dir().join(format!("sila-asm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let modules = [check_module_of(source).unwrap()];
+        let entry = entry_point(&modules[0].ast, &modules[0].name, None).unwrap();
+        std::fs::write(dir.join("program.s"), generate(&modules, &entry)).unwrap();
+        std::fs::write(dir.join("runtime.s"), runtime_asm(AllocatorKind::Libc)).unwrap();
+        std::fs::write(dir.join("start.s"), start_asm(&entry, AllocatorKind::Libc)).unwrap();
+        if !build(&dir, &["-nostartfiles", "-o", "program", "program.s", "runtime.s", "start.s"]) {
+            return;
+        }
+        let run = Command::new(dir.join("program")).output().unwrap();
+
+        let mut output = vec![];
+        let code = interpret(&modules, &entry, "program", &mut output).unwrap();
+        assert_eq!(run.status.code(), Some(code & 0xff));
+        assert_eq!(run.stdout, output);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// C code calls exported functions and is called back with structs passed in registers and in memory.
+    #[test]
+    fn shares_values_with_c() {
+        let source = "struct Mixed {\n    a: i32,\n    b: f32,\n    c: f64\n}\n\nstruct Big {\n    values: [i64; 3]\n}\n\n\
+            def extern weigh(m: Mixed, big: Big, x: f32, a: i64, b: i64, c: i64, d: i64, e: i64, f: i64): i64 {\n}\n\n\
+            def export relay(m: Mixed, big: Big, x: f32): i64 {\n    return weigh(m, big, x, 1, 2, 3, 4, 5, 6);\n}\n\n\
+            def export echo(m: Mixed): Mixed {\n    return m;\n}\n\n\
+            def export echo_big(big: Big): Big {\n    return big;\n}\n\n\
+            def export increment(value: &mut i16): void {\n    value = value + 1;\n}\n\n\
+            def main(): i32 {\n    return 0;\n}";
+        let c_source = "#include <stdint.h>\n\
+            struct Mixed { int32_t a; float b; double c; };\n\
+            struct Big { int64_t values[3]; };\n\
+            int64_t relay(struct Mixed m, struct Big big, float x);\n\
+            struct Mixed echo(struct Mixed m);\n\
+            struct Big echo_big(struct Big big);\n\
+            void increment(int16_t *value);\n\
+            int64_t weigh(struct Mixed m, struct Big big, float x, int64_t a, int64_t b, int64_t c, int64_t d, int64_t e, int64_t f) {\n\
+            \x20   return m.a + (int64_t)m.b + (int64_t)m.c + big.values[0] + big.values[2] + (int64_t)x + a + f;\n}\n\
+            int main(void) {\n\
+            \x20   struct Mixed m = {1, 2.5f, 40.0};\n\
+            \x20   struct Big big = {{100, 0, 300}};\n\
+            \x20   struct Mixed e = echo(m);\n\
+            \x20   struct Big b = echo_big(big);\n\
+            \x20   int16_t value = -1;\n\
+            \x20   increment(&value);\n\
+            \x20   if (e.a != 1 || e.b != 2.5f || e.c != 40.0 || b.values[2] != 300 || value != 0) return 1;\n\
+            \x20   return relay(m, big, 8.0f) == 1 + 2 + 40 + 100 + 300 + 8 + 1 + 6 ? 0 : 2;\n}\n";
+        let dir = std::env::temp_dir().join(format!("sila-abi-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("program.s"), generate_asm(source)).unwrap();
+        std::fs::write(dir.join("main.c"), c_source).unwrap();
+        if !build(&dir, &["-o", "program", "main.c", "program.s"]) {
+            return;
+        }
+        let run = Command::new(dir.join("program")).status().unwrap();
+        assert_eq!(run.code(), Some(0));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,777 @@
+use crate::checker::bind_arguments;
+use crate::comp_errors::{CodeError, CodeResult};
+use crate::lexer::{CodePosition, Token, TokenType};
+use crate::parser::{ASTNode, AttributeKind};
+use crate::resolver::{Resolution, SymbolKind};
+use crate::types::Type;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+// Loop iterations and calls a single constant or array length may take
+const STEP_LIMIT: usize = 1_000_000;
+const CALL_DEPTH_LIMIT: usize = 256;
+// Calls shown at each end of a backtrace, the ones in between are summarized
+const BACKTRACE_EDGE: usize = 5;
+
+/// A value known at compile time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    // Value, Type (`None` for literals that did not settle on a type yet)
+    Int(i128, Option<Type>),
+    Float(f64, Option<Type>),
+    Bool(bool),
+}
+
+impl ConstValue {
+    pub fn ty(&self) -> Option<Type> {
+        match self {
+            ConstValue::Int(_, ty) | ConstValue::Float(_, ty) => ty.clone(),
+            ConstValue::Bool(_) => Some(Type::Bool),
+        }
+    }
+
+    fn describe_type(&self) -> String {
+        match (self, self.ty()) {
+            (_, Some(ty)) => ty.to_string(),
+            (ConstValue::Int(..), None) => "{integer}".to_string(),
+            _ => "{float}".to_string(),
+        }
+    }
+}
+
+impl Display for ConstValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstValue::Int(value, _) => write!(f, "{}", value),
+            ConstValue::Float(value, _) => write!(f, "{:?}", value),
+            ConstValue::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+fn float_value(value: f64, bits: u8, position: CodePosition) -> CodeResult<ConstValue> {
+    let rounded = if bits == 32 { value as f32 as f64 } else { value };
+    if value.is_finite() && !rounded.is_finite() {
+        return Err(CodeError::out_of_range_error(
+            position,
+            &format!("{:?}", value),
+            &Type::Float(bits).to_string(),
+        ));
+    }
+    Ok(ConstValue::Float(rounded, Some(Type::Float(bits))))
+}
+
+/// Outcome of a comparison operator, `None` stands for unordered floats (NaN).
+pub fn compare(op: TokenType, ordering: Option<Ordering>) -> bool {
+    match op {
+        TokenType::DoubleEquals => ordering == Some(Ordering::Equal),
+        TokenType::NotEquals => ordering != Some(Ordering::Equal),
+        TokenType::Greater => ordering == Some(Ordering::Greater),
+        TokenType::Lesser => ordering == Some(Ordering::Less),
+        TokenType::GreaterEquals => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        TokenType::LesserEquals => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        _ => false,
+    }
+}
+
+/// Literal replacing a constant. Typed numbers become `literal -> type`,
+/// which gives the literal its type without a cast.
+fn literal_node(value: &ConstValue, position: CodePosition) -> ASTNode<'static> {
+    let (token_type, ty) = match value {
+        ConstValue::Int(_, ty) => (TokenType::NumberInt, ty),
+        ConstValue::Float(_, ty) => (TokenType::NumberFloat, ty),
+        ConstValue::Bool(_) => {
+            return ASTNode::Literal(Token::synthetic(value.to_string(), TokenType::Boolean, position))
+        }
+    };
+    let literal = ASTNode::Literal(Token::synthetic(value.to_string(), token_type, position));
+    match ty {
+        Some(ty) => ASTNode::CastExpr(
+            Box::new(literal),
+            Box::new(ASTNode::Type(Token::synthetic(
+                ty.to_string(),
+                TokenType::Identifier,
+                position,
+            ))),
+        ),
+        None => literal,
+    }
+}
+
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(ConstValue),
+}
+
+/// Locals of a `const def` call, by declaration.
+#[derive(Default)]
+struct Frame {
+    values: HashMap<*const Token, ConstValue>,
+    // Annotated types of variables declared without a value
+    annotations: HashMap<*const Token, Type>,
+}
+
+/// Evaluates constants, array lengths and the `const def` functions they call.
+/// Integers are computed exactly and checked against their type after every step,
+/// so overflow, division by zero and lossy casts are reported instead of wrapping.
+pub struct ConstEvaluator<'a, 'b> {
+    resolution: &'b Resolution<'a>,
+    // Function declaration -> definition
+    functions: HashMap<*const Token, &'b ASTNode<'a>>,
+    // Constant declaration -> (type, value)
+    constants: HashMap<*const Token, (&'b ASTNode<'a>, &'b ASTNode<'a>)>,
+    values: HashMap<*const Token, ConstValue>,
+    // Array length expression -> length
+    lengths: HashMap<*const ASTNode<'a>, u64>,
+    // Constants currently being evaluated
+    evaluating: Vec<*const Token>,
+    frames: Vec<Frame>,
+    steps: usize,
+    // Calls of the failed evaluation that are shown / left out of the backtrace
+    noted: usize,
+    elided: usize,
+}
+
+impl<'a, 'b> ConstEvaluator<'a, 'b> {
+    pub fn new(ast: &'b [ASTNode<'a>], resolution: &'b Resolution<'a>) -> Self {
+        let mut functions = HashMap::new();
+        let mut constants = HashMap::new();
+        for item in ast {
+            match item {
+                ASTNode::FunctionDef(name, ..) => {
+                    functions.insert(*name as *const Token, item);
+                }
+                ASTNode::ConstDef(name, ty, value) => {
+                    constants.insert(*name as *const Token, (&**ty, &**value));
+                }
+                _ => {}
+            }
+        }
+        Self {
+            resolution,
+            functions,
+            constants,
+            values: HashMap::new(),
+            lengths: HashMap::new(),
+            evaluating: vec![],
+            frames: vec![],
+            steps: 0,
+            noted: 0,
+            elided: 0,
+        }
+    }
+
+    fn step(&mut self, position: CodePosition) -> CodeResult<()> {
+        self.steps += 1;
+        if self.steps > STEP_LIMIT {
+            return Err(CodeError::evaluation_limit_error(position));
+        }
+        Ok(())
+    }
+
+    /// Gives a literal the type `ty`, or checks that a typed value already has it.
+    fn adopt(&self, value: ConstValue, ty: &Type, position: CodePosition) -> CodeResult<ConstValue> {
+        match (value, ty) {
+            (ConstValue::Int(value, None), Type::Int(..)) => {
+                if !ty.fits(value) {
+                    return Err(CodeError::out_of_range_error(
+                        position,
+                        &value.to_string(),
+                        &ty.to_string(),
+                    ));
+                }
+                Ok(ConstValue::Int(value, Some(ty.clone())))
+            }
+            (ConstValue::Int(value, None), Type::Float(bits)) => {
+                float_value(value as f64, *bits, position)
+            }
+            (ConstValue::Float(value, None), Type::Float(bits)) => float_value(value, *bits, position),
+            (value, _) if value.ty().as_ref() == Some(ty) => Ok(value),
+            (value, _) => Err(CodeError::type_mismatch_error(
+                position,
+                &ty.to_string(),
+                &value.describe_type(),
+            )),
+        }
+    }
+
+    fn constant(&mut self, usage: &'a Token, declaration: &'a Token) -> CodeResult<ConstValue> {
+        let key = declaration as *const Token;
+        if let Some(value) = self.values.get(&key) {
+            return Ok(value.clone());
+        }
+        if self.evaluating.contains(&key) {
+            return Err(CodeError::cyclic_constant_error(usage, declaration));
+        }
+
+        let (ty, value) = self.constants[&key];
+        // Constants never see the locals of a call that uses them
+        let frames = std::mem::take(&mut self.frames);
+        self.evaluating.push(key);
+        let result = self.evaluate_constant(ty, value);
+        self.evaluating.pop();
+        self.frames = frames;
+
+        let value = result.map_err(|e| {
+            e.with_note(format!("While evaluating constant `{}`", declaration.content))
+        })?;
+        self.values.insert(key, value.clone());
+        Ok(value)
+    }
+
+    fn evaluate_constant(&mut self, ty: &ASTNode<'a>, value: &ASTNode<'a>) -> CodeResult<ConstValue> {
+        let ty = Type::from_node(ty)?;
+        let result = self.eval_expr(value)?;
+        self.adopt(result, &ty, value.position())
+    }
+
+    fn variable(&self, usage: &'a Token) -> CodeResult<ConstValue> {
+        let symbol = self.resolution.symbol_of(usage).unwrap();
+        self.frames
+            .last()
+            .and_then(|t| t.values.get(&(symbol.declaration as *const Token)))
+            .cloned()
+            .ok_or_else(|| CodeError::not_constant_error(usage.code_position))
+    }
+
+    /// Evaluates both operands, literals take the type of the other side.
+    fn operands(
+        &mut self,
+        lhs_node: &ASTNode<'a>,
+        rhs_node: &ASTNode<'a>,
+    ) -> CodeResult<(ConstValue, ConstValue)> {
+        let lhs = self.eval_expr(lhs_node)?;
+        let rhs = self.eval_expr(rhs_node)?;
+
+        Ok(match (lhs.ty(), rhs.ty()) {
+            (Some(ty), None) => {
+                let rhs = self.adopt(rhs, &ty, rhs_node.position())?;
+                (lhs, rhs)
+            }
+            (None, Some(ty)) => (self.adopt(lhs, &ty, lhs_node.position())?, rhs),
+            (None, None) => match (lhs, rhs) {
+                (ConstValue::Int(a, None), rhs @ ConstValue::Float(..)) => {
+                    (ConstValue::Float(a as f64, None), rhs)
+                }
+                (lhs @ ConstValue::Float(..), ConstValue::Int(b, None)) => {
+                    (lhs, ConstValue::Float(b as f64, None))
+                }
+                other => other,
+            },
+            (Some(a), Some(b)) if a != b => {
+                return Err(CodeError::type_mismatch_error(
+                    rhs_node.position(),
+                    &a.to_string(),
+                    &b.to_string(),
+                )
+                .with_label(lhs_node.position(), format!("This is `{}`", a)))
+            }
+            _ => (lhs, rhs),
+        })
+    }
+
+    fn binary_op(
+        &mut self,
+        lhs_node: &ASTNode<'a>,
+        op: &'a Token,
+        rhs_node: &ASTNode<'a>,
+    ) -> CodeResult<ConstValue> {
+        let (lhs, rhs) = self.operands(lhs_node, rhs_node)?;
+        let position = lhs_node.position().merge(rhs_node.position());
+        match (lhs, rhs) {
+            (ConstValue::Int(a, ty), ConstValue::Int(b, _)) => {
+                let result = match op.token_type {
+                    TokenType::Plus => a.checked_add(b),
+                    TokenType::Minus => a.checked_sub(b),
+                    TokenType::Star => a.checked_mul(b),
+                    TokenType::Slash if b == 0 => {
+                        return Err(CodeError::division_by_zero_error(rhs_node.position()))
+                    }
+                    TokenType::Slash => a.checked_div(b),
+                    _ => return Ok(ConstValue::Bool(compare(op.token_type, a.partial_cmp(&b)))),
+                };
+                match (result, &ty) {
+                    (Some(value), Some(ty)) if ty.fits(value) => Ok(ConstValue::Int(value, Some(ty.clone()))),
+                    (Some(value), None) => Ok(ConstValue::Int(value, None)),
+                    (_, ty) => Err(CodeError::overflow_error(
+                        position,
+                        op,
+                        &ty.as_ref().map_or("i128".to_string(), |t| t.to_string()),
+                    )),
+                }
+            }
+            (ConstValue::Float(a, ty), ConstValue::Float(b, _)) => {
+                let result = match op.token_type {
+                    TokenType::Plus => a + b,
+                    TokenType::Minus => a - b,
+                    TokenType::Star => a * b,
+                    TokenType::Slash if b == 0.0 => {
+                        return Err(CodeError::division_by_zero_error(rhs_node.position()))
+                    }
+                    TokenType::Slash => a / b,
+                    _ => return Ok(ConstValue::Bool(compare(op.token_type, a.partial_cmp(&b)))),
+                };
+                let bits = match ty {
+                    Some(Type::Float(bits)) => bits,
+                    _ => 64,
+                };
+                let rounded = if bits == 32 { result as f32 as f64 } else { result };
+                if !rounded.is_finite() {
+                    return Err(CodeError::overflow_error(position, op, &Type::Float(bits).to_string()));
+                }
+                Ok(ConstValue::Float(rounded, ty))
+            }
+            (ConstValue::Bool(a), ConstValue::Bool(b))
+                if matches!(op.token_type, TokenType::DoubleEquals | TokenType::NotEquals) =>
+            {
+                Ok(ConstValue::Bool(compare(op.token_type, a.partial_cmp(&b))))
+            }
+            (lhs, _) => Err(CodeError::invalid_operand_error(
+                lhs_node.position(),
+                op,
+                &lhs.describe_type(),
+            )),
+        }
+    }
+
+    fn cast(&mut self, expr: &ASTNode<'a>, target: &ASTNode<'a>) -> CodeResult<ConstValue> {
+        let to = Type::from_node(target)?;
+        let value = self.eval_expr(expr)?;
+        let position = expr.position();
+        let out_of_range = |value: &dyn Display| {
+            CodeError::out_of_range_error(position, &value.to_string(), &to.to_string())
+        };
+        match (value, &to) {
+            (ConstValue::Int(value, _), Type::Int(..)) if to.fits(value) => {
+                Ok(ConstValue::Int(value, Some(to.clone())))
+            }
+            (ConstValue::Int(value, _), Type::Int(..)) => Err(out_of_range(&value)),
+            (ConstValue::Int(value, _), Type::Float(bits)) => float_value(value as f64, *bits, position),
+            (ConstValue::Float(value, _), Type::Int(..)) => {
+                if !value.is_finite() || !to.fits(value.trunc() as i128) {
+                    return Err(out_of_range(&format!("{:?}", value)));
+                }
+                Ok(ConstValue::Int(value.trunc() as i128, Some(to.clone())))
+            }
+            (ConstValue::Float(value, _), Type::Float(bits)) => float_value(value, *bits, position),
+            (ConstValue::Bool(value), Type::Int(..)) => Ok(ConstValue::Int(value as i128, Some(to.clone()))),
+            (ConstValue::Bool(value), Type::Bool) => Ok(ConstValue::Bool(value)),
+            (value, _) => Err(CodeError::invalid_cast_error(
+                position,
+                target.position(),
+                &value.describe_type(),
+                &to.to_string(),
+            )),
+        }
+    }
+
+    /// Calls a `const def` function, `None` if it returns nothing.
+    fn call(&mut self, name: &'a Token, args: &[Box<ASTNode<'a>>], position: CodePosition) -> CodeResult<Option<ConstValue>> {
+        let declaration = self.resolution.symbol_of(name).unwrap().declaration;
+        let function = self.functions[&(declaration as *const Token)];
+        let ASTNode::FunctionDef(function_name, _, ret, params, body, _) = function else {
+            unreachable!()
+        };
+        if function.attribute(AttributeKind::Const).is_none() {
+            return Err(CodeError::not_constant_error(position)
+                .with_label(function_name.code_position, "Not a `const def` function".to_string())
+                .with_note("Only `const def` functions can be called at compile time".to_string()));
+        }
+
+        let bound = bind_arguments(name, args, function_name, params)?;
+        let mut frame = Frame::default();
+        let mut shown = vec![];
+        for ((param, ty, _), arg) in params.iter().zip(bound) {
+            if matches!(**ty, ASTNode::ReferenceType(_, true, _)) {
+                return Err(CodeError::not_constant_error(arg.position())
+                    .with_note("`&mut` parameters can not be used at compile time".to_string()));
+            }
+            let value = self.eval_expr(arg)?;
+            let value = self.adopt(value, &Type::from_node(ty)?, arg.position())?;
+            shown.push(value.to_string());
+            frame.values.insert(*param as *const Token, value);
+        }
+
+        if self.frames.len() >= CALL_DEPTH_LIMIT {
+            return Err(CodeError::evaluation_limit_error(position));
+        }
+        self.frames.push(frame);
+        let result = self.exec_block(body, ret);
+        self.frames.pop();
+
+        let note = format!(
+            "In `{}({})`, called on line {}",
+            name.content,
+            shown.join(", "),
+            position.line_start + 1
+        );
+        let ret_type = Type::from_node(ret)?;
+        match result.map_err(|e| self.backtrace(e, note))? {
+            Flow::Return(value) => Ok(Some(value)),
+            _ if ret_type == Type::Void => Ok(None),
+            _ => Err(CodeError::missing_return_error(
+                function_name,
+                ret.position(),
+                &ret_type.to_string(),
+            )),
+        }
+    }
+
+    /// Adds a call to the backtrace of `error`, which is built from the innermost call outwards.
+    fn backtrace(&mut self, mut error: CodeError, note: String) -> CodeError {
+        if self.noted >= BACKTRACE_EDGE && self.frames.len() >= BACKTRACE_EDGE {
+            self.elided += 1;
+            return error;
+        }
+        if self.elided > 0 {
+            error = error.with_note(format!("... {} more calls", self.elided));
+            self.elided = 0;
+        }
+        self.noted += 1;
+        error.with_note(note)
+    }
+
+    fn eval_expr(&mut self, node: &ASTNode<'a>) -> CodeResult<ConstValue> {
+        match node {
+            ASTNode::Literal(t) => match t.token_type {
+                TokenType::Boolean => Ok(ConstValue::Bool(t.content == "true")),
+                TokenType::NumberFloat => Ok(ConstValue::Float(t.content.parse().unwrap(), None)),
+                _ => t
+                    .content
+                    .parse()
+                    .map(|value| ConstValue::Int(value, None))
+                    .map_err(|_| CodeError::out_of_range_error(t.code_position, &t.content, "i128")),
+            },
+            ASTNode::Identifier(name) => {
+                let symbol = *self.resolution.symbol_of(name).unwrap();
+                if symbol.kind == SymbolKind::Constant {
+                    self.constant(name, symbol.declaration)
+                } else {
+                    self.variable(name)
+                }
+            }
+            ASTNode::BinaryOp(lhs, op, rhs) => self.binary_op(lhs, op, rhs),
+            ASTNode::CastExpr(expr, ty) => self.cast(expr, ty),
+            ASTNode::FunctionCall(name, args, _) => self
+                .call(name, args, node.position())?
+                .ok_or_else(|| CodeError::not_constant_error(node.position())),
+            other => Err(CodeError::not_constant_error(other.position())),
+        }
+    }
+
+    fn condition(&mut self, condition: &ASTNode<'a>) -> CodeResult<bool> {
+        match self.eval_expr(condition)? {
+            ConstValue::Bool(value) => Ok(value),
+            other => Err(CodeError::type_mismatch_error(
+                condition.position(),
+                "bool",
+                &other.describe_type(),
+            )),
+        }
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn exec_block(&mut self, body: &[Box<ASTNode<'a>>], ret: &ASTNode<'a>) -> CodeResult<Flow> {
+        for statement in body {
+            let flow = self.exec_statement(statement, ret)?;
+            if !matches!(flow, Flow::Next) {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn exec_loop_body(&mut self, body: &[Box<ASTNode<'a>>], ret: &ASTNode<'a>) -> CodeResult<Option<Flow>> {
+        Ok(match self.exec_block(body, ret)? {
+            Flow::Break => Some(Flow::Next),
+            flow @ Flow::Return(_) => Some(flow),
+            Flow::Next | Flow::Continue => None,
+        })
+    }
+
+    fn exec_statement(&mut self, statement: &ASTNode<'a>, ret: &ASTNode<'a>) -> CodeResult<Flow> {
+        match statement {
+            ASTNode::VariableSet(name, value, annotation, _) => {
+                let key = *name as *const Token;
+                let ty = annotation.as_deref().map(Type::from_node).transpose()?;
+                match (value, &ty) {
+                    (Some(value), Some(ty)) => {
+                        let result = self.eval_expr(value)?;
+                        let result = self.adopt(result, ty, value.position())?;
+                        self.frame().values.insert(key, result);
+                    }
+                    (Some(value), None) => {
+                        let result = self.eval_expr(value)?;
+                        self.frame().values.insert(key, result);
+                    }
+                    (None, ty) => {
+                        self.frame().values.remove(&key);
+                        if let Some(ty) = ty {
+                            self.frame().annotations.insert(key, ty.clone());
+                        }
+                    }
+                }
+            }
+            ASTNode::Assignment(name, value) => {
+                let key = self.resolution.symbol_of(name).unwrap().declaration as *const Token;
+                let mut result = self.eval_expr(value)?;
+                let frame = self.frame();
+                let ty = frame
+                    .annotations
+                    .get(&key)
+                    .cloned()
+                    .or_else(|| frame.values.get(&key).and_then(|t| t.ty()));
+                if let Some(ty) = ty {
+                    result = self.adopt(result, &ty, value.position())?;
+                }
+                self.frame().values.insert(key, result);
+            }
+            ASTNode::If(condition, then_body, else_body) => {
+                let body = if self.condition(condition)? { then_body } else { else_body };
+                return self.exec_block(body, ret);
+            }
+            ASTNode::WhileLoop(condition, body) => loop {
+                self.step(condition.position())?;
+                if !self.condition(condition)? {
+                    break;
+                }
+                if let Some(flow) = self.exec_loop_body(body, ret)? {
+                    return Ok(flow);
+                }
+            },
+            ASTNode::ForLoop(var, iterable, body) => {
+                let ASTNode::Range(start, end, inclusive) = &**iterable else {
+                    return Err(CodeError::not_constant_error(iterable.position()));
+                };
+                let (first, last) = match self.operands(start, end)? {
+                    (ConstValue::Int(first, ty), ConstValue::Int(last, _)) => ((first, ty), last),
+                    (other, _) => {
+                        return Err(CodeError::not_iterable_error(
+                            iterable.position(),
+                            &other.describe_type(),
+                        ))
+                    }
+                };
+                let ((first, ty), last) = (first, if *inclusive { last } else { last - 1 });
+                let key = *var as *const Token;
+                for value in first..=last {
+                    self.step(iterable.position())?;
+                    self.frame().values.insert(key, ConstValue::Int(value, ty.clone()));
+                    if let Some(flow) = self.exec_loop_body(body, ret)? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            ASTNode::Return(value) => {
+                let result = self.eval_expr(value)?;
+                let result = self.adopt(result, &Type::from_node(ret)?, value.position())?;
+                return Ok(Flow::Return(result));
+            }
+            ASTNode::Break(_) => return Ok(Flow::Break),
+            ASTNode::Continue(_) => return Ok(Flow::Continue),
+            ASTNode::FunctionCall(name, args, _) => {
+                self.call(name, args, statement.position())?;
+            }
+            other => {
+                self.eval_expr(other)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Evaluates every constant and every array length of the module.
+    pub fn evaluate(&mut self, ast: &'b [ASTNode<'a>]) -> CodeResult<()> {
+        for item in ast {
+            if let ASTNode::ConstDef(name, ..) = item {
+                self.steps = 0;
+                self.constant(name, name)?;
+            }
+        }
+        for item in ast {
+            self.evaluate_lengths(item)?;
+        }
+        Ok(())
+    }
+
+    fn array_length(&mut self, length: &ASTNode<'a>) -> CodeResult<u64> {
+        let length_type = Type::Int(64, false);
+        match self.eval_expr(length)? {
+            ConstValue::Int(value, _) if length_type.fits(value) => Ok(value as u64),
+            ConstValue::Int(value, _) => Err(CodeError::out_of_range_error(
+                length.position(),
+                &value.to_string(),
+                &length_type.to_string(),
+            )),
+            other => Err(CodeError::type_mismatch_error(
+                length.position(),
+                &length_type.to_string(),
+                &other.describe_type(),
+            )),
+        }
+    }
+
+    fn evaluate_lengths(&mut self, node: &ASTNode<'a>) -> CodeResult<()> {
+        if let ASTNode::ArrayType(_, length) = node {
+            self.steps = 0;
+            let value = self
+                .array_length(length)
+                .map_err(|e| e.with_note("While evaluating the length of an array type".to_string()))?;
+            self.lengths
+                .insert(&**length as *const ASTNode, value);
+        }
+        for child in node.children() {
+            self.evaluate_lengths(child)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces uses of constants and array lengths with the evaluated literals.
+struct Folder<'a, 'b> {
+    resolution: &'b Resolution<'a>,
+    values: HashMap<*const Token, ConstValue>,
+    lengths: HashMap<*const ASTNode<'a>, u64>,
+}
+
+impl<'a, 'b> Folder<'a, 'b> {
+    fn value_of(&self, usage: &Token) -> Option<&ConstValue> {
+        self.resolution
+            .symbol_of(usage)
+            .and_then(|t| self.values.get(&(t.declaration as *const Token)))
+    }
+
+    fn fold(&self, node: &mut ASTNode<'a>) {
+        match node {
+            ASTNode::Identifier(name) => {
+                if let Some(value) = self.value_of(name) {
+                    *node = literal_node(value, name.code_position);
+                }
+            }
+            ASTNode::ConstDef(name, _, value) => {
+                **value = literal_node(&self.values[&(*name as *const Token)], value.position());
+            }
+            ASTNode::ArrayType(_, length) => {
+                let key = &**length as *const ASTNode;
+                if let Some(value) = self.lengths.get(&key) {
+                    let position = length.position();
+                    **length = ASTNode::Literal(Token::synthetic(
+                        value.to_string(),
+                        TokenType::NumberInt,
+                        position,
+                    ));
+                }
+            }
+            _ => {}
+        }
+        for child in node.children_mut() {
+            self.fold(child);
+        }
+    }
+}
+
+/// Evaluates constants and array lengths and folds them into literals, so every later
+/// pass only sees plain literals. Types of folded constants are kept with `->`.
+pub fn fold_constants<'a>(ast: &mut [ASTNode<'a>], resolution: &Resolution<'a>) -> CodeResult<()> {
+    let mut evaluator = ConstEvaluator::new(ast, resolution);
+    evaluator.evaluate(ast)?;
+    let folder = Folder {
+        resolution,
+        values: evaluator.values,
+        lengths: evaluator.lengths,
+    };
+    for item in ast.iter_mut() {
+        folder.fold(item);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comp_errors::CodeErrorType;
+    use crate::resolver::resolve;
+    use crate::testing::{body, parse};
+
+    fn fold_source(source: &str) -> CodeResult<Vec<ASTNode<'static>>> {
+        let mut ast = parse(source);
+        let resolution = resolve(&ast, &[])?;
+        fold_constants(&mut ast, &resolution)?;
+        Ok(ast)
+    }
+
+    fn fold_err(source: &str) -> CodeError {
+        match fold_source(source) {
+            Ok(_) => panic!("Expected an error"),
+            Err(error) => error,
+        }
+    }
+
+    const DOUBLE: &str = "const def double(n: u8): u8 {\n    return n * 2;\n}\n\n";
+
+    #[test]
+    fn folds_constants_into_literals() {
+        let source = "const N: i32 = 2 * 3 + 1;\nconst M: i32 = N * N;\n\ndef f(): i32 {\n    return M;\n}";
+        assert_eq!(body(&fold_source(source).unwrap(), "f"), ["return 49 -> i32"]);
+    }
+
+    #[test]
+    fn calls_const_functions() {
+        let source = format!(
+            "{}const def sum(n: u64): u64 {{\n    let mut total: u64 = 0;\n    for i in 0..=n {{\n        total = total + i;\n    }}\n    return total;\n}}\n\nconst N: u8 = double(100);\nconst S: u64 = sum(10);\n\ndef f(): u64 {{\n    return S + (N -> u64);\n}}",
+            DOUBLE
+        );
+        assert_eq!(body(&fold_source(&source).unwrap(), "f"), ["return 55 -> u64 + 200 -> u8 -> u64"]);
+    }
+
+    #[test]
+    fn reports_overflow_with_the_calls_leading_to_it() {
+        let error = fold_err(&format!("{}const N: u8 = double(200);", DOUBLE));
+        assert!(matches!(error.code_error_type, CodeErrorType::Overflow));
+        assert!(error.notes.contains(&"In `double(200)`, called on line 5".to_string()));
+    }
+
+    #[test]
+    fn elides_the_middle_of_deep_backtraces() {
+        let source = "const def down(n: i32): i32 {\n    if n == 0 {\n        return 1 / n;\n    }\n    return down(n - 1);\n}\n\nconst N: i32 = down(20);";
+        let error = fold_err(source);
+        assert!(matches!(error.code_error_type, CodeErrorType::DivisionByZero));
+        let calls: Vec<_> = error.notes.iter().filter(|t| t.starts_with("In `down")).collect();
+        assert_eq!(calls.len(), 2 * BACKTRACE_EDGE);
+        assert_eq!(calls.first().unwrap().as_str(), "In `down(0)`, called on line 5");
+        assert_eq!(calls.last().unwrap().as_str(), "In `down(20)`, called on line 8");
+        assert!(error.notes.iter().any(|t| t == "... 11 more calls"));
+    }
+
+    #[test]
+    fn rejects_cyclic_constants() {
+        let error = fold_err("const A: i32 = B;\nconst B: i32 = A + 1;");
+        assert!(matches!(error.code_error_type, CodeErrorType::NotConstant));
+    }
+
+    #[test]
+    fn rejects_calls_of_regular_functions() {
+        let error = fold_err("def one(): i32 {\n    return 1;\n}\n\nconst N: i32 = one();");
+        assert!(matches!(error.code_error_type, CodeErrorType::NotConstant));
+    }
+
+    #[test]
+    fn stops_endless_evaluations() {
+        let error = fold_err("const def spin(): i32 {\n    while true {\n    }\n}\n\nconst N: i32 = spin();");
+        assert!(matches!(error.code_error_type, CodeErrorType::EvaluationLimit));
+        // Reaching the call depth limit needs the stack of a main thread, not of a test thread
+        let error = std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(|| fold_err("const def deep(n: i32): i32 {\n    return deep(n);\n}\n\nconst N: i32 = deep(1);"))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(matches!(error.code_error_type, CodeErrorType::EvaluationLimit));
+    }
+}
@@ -0,0 +1,317 @@
+/// Relocation types of the x86-64 psABI.
+pub const R_X86_64_64: u32 = 1;
+pub const R_X86_64_PC32: u32 = 2;
+pub const R_X86_64_PLT32: u32 = 4;
+pub const R_X86_64_32: u32 = 10;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+
+const HEADER_SIZE: usize = 64;
+const SECTION_HEADER_SIZE: usize = 64;
+const SYMBOL_SIZE: usize = 24;
+const RELOCATION_SIZE: usize = 24;
+
+/// What a section holds, which decides its type and flags.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SectionKind {
+    Code,
+    Data,
+    ReadOnly,
+    // Zeroed memory that takes no space in the file, like `.bss`
+    Zeroed,
+    // Not loaded, like DWARF sections and `.note.GNU-stack`
+    Info,
+}
+
+pub struct Section {
+    pub name: String,
+    pub kind: SectionKind,
+    // For `Zeroed` sections only the length counts
+    pub data: Vec<u8>,
+    pub align: u64,
+    pub relocations: Vec<Relocation>,
+}
+
+impl Section {
+    pub fn new(name: &str, kind: SectionKind, align: u64) -> Self {
+        Self { name: name.to_string(), kind, data: vec![], align, relocations: vec![] }
+    }
+}
+
+/// What a relocation refers to: a symbol, or the start of a section for assembler-local labels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Symbol(usize),
+    Section(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relocation {
+    pub offset: u64,
+    pub target: Target,
+    pub kind: u32,
+    pub addend: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    // Index into `Object::sections`, `None` for symbols another object defines
+    pub section: Option<usize>,
+    pub value: u64,
+    pub size: u64,
+    pub global: bool,
+    pub function: bool,
+}
+
+/// An ELF64 relocatable object for x86-64 Linux, the input of `ld` and `ld.lld`.
+pub struct Object {
+    pub sections: Vec<Section>,
+    pub symbols: Vec<Symbol>,
+}
+
+/// Appends `name` to a string table, returns its offset.
+fn add_string(table: &mut Vec<u8>, name: &str) -> u32 {
+    let offset = table.len() as u32;
+    table.extend(name.bytes());
+    table.push(0);
+    offset
+}
+
+fn pad(out: &mut Vec<u8>, align: usize) {
+    out.resize(out.len().next_multiple_of(align.max(1)), 0);
+}
+
+fn symbol_entry(out: &mut Vec<u8>, name: u32, info: u8, section: u16, value: u64, size: u64) {
+    out.extend(name.to_le_bytes());
+    out.push(info);
+    out.push(0);
+    out.extend(section.to_le_bytes());
+    out.extend(value.to_le_bytes());
+    out.extend(size.to_le_bytes());
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entry_size: u64,
+}
+
+impl Object {
+    /// Bytes of the object file. Section headers follow the sections of `self` in order, then
+    /// the relocation sections, the symbol table and the string tables. Local symbols come
+    /// first in the symbol table, starting with one per section.
+    pub fn write(&self) -> Vec<u8> {
+        let mut strings = vec![0];
+        let mut section_names = vec![0];
+        let mut headers = vec![];
+        let mut out = vec![0; HEADER_SIZE];
+
+        for section in &self.sections {
+            let (kind, flags) = match section.kind {
+                SectionKind::Code => (SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR),
+                SectionKind::Data => (SHT_PROGBITS, SHF_ALLOC | SHF_WRITE),
+                SectionKind::ReadOnly => (SHT_PROGBITS, SHF_ALLOC),
+                SectionKind::Zeroed => (SHT_NOBITS, SHF_ALLOC | SHF_WRITE),
+                SectionKind::Info => (SHT_PROGBITS, 0),
+            };
+            pad(&mut out, section.align as usize);
+            let offset = out.len() as u64;
+            if section.kind != SectionKind::Zeroed {
+                out.extend(&section.data);
+            }
+            headers.push(SectionHeader {
+                name: add_string(&mut section_names, &section.name),
+                kind,
+                flags,
+                offset,
+                size: section.data.len() as u64,
+                link: 0,
+                info: 0,
+                align: section.align,
+                entry_size: 0,
+            });
+        }
+
+        // Section symbols, then the other locals, then the globals
+        let mut symbols = vec![0; SYMBOL_SIZE];
+        for index in 0..self.sections.len() {
+            symbol_entry(&mut symbols, 0, STT_SECTION, index as u16 + 1, 0, 0);
+        }
+        let mut indices = vec![0; self.symbols.len()];
+        let mut next = self.sections.len() + 1;
+        let mut first_global = 0;
+        for global in [false, true] {
+            if global {
+                first_global = next;
+            }
+            for (index, symbol) in self.symbols.iter().enumerate().filter(|t| t.1.global == global) {
+                let bind = if global { STB_GLOBAL } else { STB_LOCAL };
+                let kind = if symbol.function { STT_FUNC } else { STT_NOTYPE };
+                let section = symbol.section.map_or(0, |t| t as u16 + 1);
+                let name = add_string(&mut strings, &symbol.name);
+                symbol_entry(&mut symbols, name, bind << 4 | kind, section, symbol.value, symbol.size);
+                indices[index] = next;
+                next += 1;
+            }
+        }
+
+        let relocated: Vec<usize> = (0..self.sections.len()).filter(|t| !self.sections[*t].relocations.is_empty()).collect();
+        let symbol_table = self.sections.len() + relocated.len() + 1;
+        for index in relocated {
+            let section = &self.sections[index];
+            pad(&mut out, 8);
+            let offset = out.len() as u64;
+            for relocation in &section.relocations {
+                let symbol = match relocation.target {
+                    Target::Symbol(symbol) => indices[symbol],
+                    Target::Section(section) => section + 1,
+                };
+                out.extend(relocation.offset.to_le_bytes());
+                out.extend(((symbol as u64) << 32 | relocation.kind as u64).to_le_bytes());
+                out.extend(relocation.addend.to_le_bytes());
+            }
+            headers.push(SectionHeader {
+                name: add_string(&mut section_names, &format!(".rela{}", section.name)),
+                kind: SHT_RELA,
+                flags: SHF_INFO_LINK,
+                offset,
+                size: (section.relocations.len() * RELOCATION_SIZE) as u64,
+                link: symbol_table as u32,
+                info: index as u32 + 1,
+                align: 8,
+                entry_size: RELOCATION_SIZE as u64,
+            });
+        }
+
+        pad(&mut out, 8);
+        headers.push(SectionHeader {
+            name: add_string(&mut section_names, ".symtab"),
+            kind: SHT_SYMTAB,
+            flags: 0,
+            offset: out.len() as u64,
+            size: symbols.len() as u64,
+            link: symbol_table as u32 + 1,
+            info: first_global as u32,
+            align: 8,
+            entry_size: SYMBOL_SIZE as u64,
+        });
+        out.extend(symbols);
+        headers.push(SectionHeader {
+            name: add_string(&mut section_names, ".strtab"),
+            kind: SHT_STRTAB,
+            flags: 0,
+            offset: out.len() as u64,
+            size: strings.len() as u64,
+            link: 0,
+            info: 0,
+            align: 1,
+            entry_size: 0,
+        });
+        out.extend(strings);
+        let name = add_string(&mut section_names, ".shstrtab");
+        headers.push(SectionHeader {
+            name,
+            kind: SHT_STRTAB,
+            flags: 0,
+            offset: out.len() as u64,
+            size: section_names.len() as u64,
+            link: 0,
+            info: 0,
+            align: 1,
+            entry_size: 0,
+        });
+        out.extend(section_names);
+
+        pad(&mut out, 8);
+        let header_offset = out.len() as u64;
+        out.extend([0; SECTION_HEADER_SIZE]);
+        for header in &headers {
+            out.extend(header.name.to_le_bytes());
+            out.extend(header.kind.to_le_bytes());
+            out.extend(header.flags.to_le_bytes());
+            out.extend(0u64.to_le_bytes());
+            out.extend(header.offset.to_le_bytes());
+            out.extend(header.size.to_le_bytes());
+            out.extend(header.link.to_le_bytes());
+            out.extend(header.info.to_le_bytes());
+            out.extend(header.align.to_le_bytes());
+            out.extend(header.entry_size.to_le_bytes());
+        }
+
+        // 64-bit little-endian relocatable file for x86-64 (62)
+        let mut header = vec![0x7F, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        header.extend(1u16.to_le_bytes());
+        header.extend(62u16.to_le_bytes());
+        header.extend(1u32.to_le_bytes());
+        header.extend(0u64.to_le_bytes());
+        header.extend(0u64.to_le_bytes());
+        header.extend(header_offset.to_le_bytes());
+        header.extend(0u32.to_le_bytes());
+        header.extend((HEADER_SIZE as u16).to_le_bytes());
+        header.extend(0u16.to_le_bytes());
+        header.extend(0u16.to_le_bytes());
+        header.extend((SECTION_HEADER_SIZE as u16).to_le_bytes());
+        header.extend((headers.len() as u16 + 1).to_le_bytes());
+        header.extend((headers.len() as u16).to_le_bytes());
+        out[..HEADER_SIZE].copy_from_slice(&header);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Locals come before globals even when they are declared after them.
+    #[test]
+    fn writes_locals_before_globals() {
+        let mut text = Section::new(".text", SectionKind::Code, 16);
+        text.data = vec![0xE8, 0, 0, 0, 0, 0xC3];
+        text.relocations.push(Relocation { offset: 1, target: Target::Symbol(2), kind: R_X86_64_PLT32, addend: -4 });
+        let symbol = |name: &str, section, global| Symbol { name: name.to_string(), section, value: 0, size: 0, global, function: true };
+        let object = Object {
+            sections: vec![text],
+            symbols: vec![symbol("main", Some(0), true), symbol("helper", Some(0), false), symbol("puts", None, true)],
+        };
+        let bytes = object.write();
+        assert_eq!(bytes[..4], [0x7F, b'E', b'L', b'F']);
+        // Null, `.text`, `.rela.text`, `.symtab`, `.strtab` and `.shstrtab`
+        assert_eq!(u16::from_le_bytes([bytes[60], bytes[61]]), 6);
+        let headers = u64::from_le_bytes(bytes[40..48].try_into().unwrap()) as usize;
+        let rela = headers + 2 * SECTION_HEADER_SIZE;
+        let symtab = headers + 3 * SECTION_HEADER_SIZE;
+        assert_eq!(u32_at(&bytes, symtab + 4), SHT_SYMTAB);
+        // Null, the section symbol and `helper` are local
+        assert_eq!(u32_at(&bytes, symtab + 44), 3);
+        // `puts` comes after `main`
+        let relocations = u64::from_le_bytes(bytes[rela + 24..rela + 32].try_into().unwrap()) as usize;
+        assert_eq!(u32_at(&bytes, relocations + 12), 4);
+        assert_eq!(u32_at(&bytes, relocations + 8), R_X86_64_PLT32);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::comp_errors::{CodeError, CodeResult};
+use crate::parser::{ASTNode, AttributeKind, FunctionMode};
+use crate::types::Type;
+
+/// Name of the entry function when neither `@entry` nor `--entry` choose one.
+pub const DEFAULT_ENTRY: &str = "main";
+
+/// Symbol of the generated stub the program starts at, it calls the entry function
+/// and exits with its return value.
+pub const START_SYMBOL: &str = "_start";
+
+/// The function a program starts with.
+#[derive(Debug, PartialEq)]
+pub struct EntryPoint {
+    pub symbol: String,
+    // Whether it takes `argc` and `argv`
+    pub takes_args: bool,
+}
+
+fn is_type(node: &ASTNode, ty: &Type) -> bool {
+    // References would read their value through the argument
+    !matches!(node, ASTNode::ReferenceType(..)) && Type::from_node(node).is_ok_and(|t| t == *ty)
+}
+
+/// Checks that `function` is `(): i32` or `(argc: i32, argv: **u8): i32`.
+fn check_signature(function: &ASTNode) -> CodeResult<bool> {
+    let ASTNode::FunctionDef(name, mode, ret, params, ..) = function else {
+        unreachable!()
+    };
+    if *mode == FunctionMode::Extern {
+        return Err(CodeError::extern_entry_error(name));
+    }
+
+    let int = Type::Int(32, true);
+    let argv = Type::Pointer(Box::new(Type::Pointer(Box::new(Type::Int(8, false)))));
+    let takes_args = match params.as_slice() {
+        [] => false,
+        [(_, argc_type, _), (_, argv_type, _)] if is_type(argc_type, &int) && is_type(argv_type, &argv) => true,
+        _ => false,
+    };
+    if !is_type(ret, &int) || (!params.is_empty() && !takes_args) {
+        let signature = params
+            .first()
+            .map_or(ret.position(), |(t, ..)| t.code_position.merge(ret.position()));
+        return Err(CodeError::entry_signature_error(name, signature));
+    }
+    Ok(takes_args)
+}
+
+/// Finds the entry function of the main module: the one `--entry` names, the one
+/// marked with `@entry` or `main`, in that order.
+pub fn entry_point(ast: &[ASTNode], module: &str, requested: Option<&str>) -> CodeResult<EntryPoint> {
+    let mut marked = ast
+        .iter()
+        .filter_map(|t| Some((t, t.attribute(AttributeKind::Entry)?)));
+    let marked = match (marked.next(), marked.next()) {
+        (Some((_, first)), Some((_, second))) => {
+            return Err(CodeError::duplicate_entry_error(second.position, first.position))
+        }
+        (marked, _) => marked,
+    };
+
+    let function = match (requested, marked) {
+        (Some(requested), Some((ASTNode::FunctionDef(name, ..), attribute))) if name.content != requested => {
+            return Err(CodeError::conflicting_entry_error(attribute.position, requested))
+        }
+        (None, Some((function, _))) => Some(function),
+        (requested, _) => {
+            let name = requested.unwrap_or(DEFAULT_ENTRY);
+            ast.iter()
+                .find(|t| matches!(t, ASTNode::FunctionDef(function, ..) if function.content == name))
+        }
+    };
+    let function =
+        function.ok_or_else(|| CodeError::missing_entry_error(requested.unwrap_or(DEFAULT_ENTRY)))?;
+
+    Ok(EntryPoint {
+        takes_args: check_signature(function)?,
+        symbol: function.symbol_name(module).unwrap(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::parse;
+
+    fn entry(source: &str, requested: Option<&str>) -> CodeResult<EntryPoint> {
+        entry_point(&parse(source), "app", requested)
+    }
+
+    fn entry_error(source: &str, requested: Option<&str>) -> String {
+        entry(source, requested).unwrap_err().title
+    }
+
+    const MAIN: &str = "def main(): i32 {\n    return 0;\n}\n\n";
+
+    #[test]
+    fn defaults_to_main() {
+        let expected = EntryPoint { symbol: "app__main".to_string(), takes_args: false };
+        assert_eq!(entry(MAIN, None).unwrap(), expected);
+        assert_eq!(entry_error("def start(): i32 {\n    return 0;\n}", None), "Missing entry point");
+    }
+
+    #[test]
+    fn takes_the_function_chosen_by_flag_or_attribute() {
+        let source = format!("{}def export start(argc: i32, argv: **u8): i32 {{\n    return argc;\n}}", MAIN);
+        let expected = EntryPoint { symbol: "start".to_string(), takes_args: true };
+        assert_eq!(entry(&source, Some("start")).unwrap(), expected);
+        assert_eq!(entry(&source.replace("def export", "def @entry export"), None).unwrap(), expected);
+        assert_eq!(entry_error(MAIN, Some("start")), "Missing entry point");
+    }
+
+    #[test]
+    fn rejects_several_entry_points() {
+        let source = "def @entry a(): i32 {\n    return 0;\n}\n\ndef @entry b(): i32 {\n    return 0;\n}";
+        assert_eq!(entry_error(source, None), "Duplicate entry point");
+        let source = "def @entry a(): i32 {\n    return 0;\n}";
+        assert_eq!(entry_error(source, Some("main")), "Conflicting entry points");
+        assert!(entry(source, Some("a")).is_ok());
+    }
+
+    #[test]
+    fn rejects_other_signatures() {
+        for signature in ["(): void", "(): u32", "(argc: i32): i32", "(argc: i32, argv: *u8): i32", "(argc: &i32, argv: **u8): i32"] {
+            let source = format!("def main{} {{\n}}", signature);
+            assert_eq!(entry_error(&source, None), "Invalid entry point signature", "{}", signature);
+        }
+        assert_eq!(entry_error("def extern main(): i32 {\n}", None), "Extern entry point");
+    }
+}
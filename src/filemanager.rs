@@ -1,6 +1,7 @@
 use crate::comp_errors::{CompResult, CompilerError};
 use crate::lexer::CodePosition;
 use annotate_snippets::Snippet;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::{fs, io};
 
@@ -47,25 +48,31 @@ impl FileManager {
                 !file_path.parent().is_some_and(|t| t.exists()),
             ))
         } else {
-            let content = fs::read_to_string(&file_path);
-            if content.is_err() {
-                Err(CompilerError::FileCorrupted(input_file))
-            } else {
-                Ok(Self {
+            match fs::read_to_string(&file_path) {
+                Ok(content) => Ok(Self {
                     input_file,
                     file_path,
-                    content: content.unwrap(),
-                })
+                    content,
+                }),
+                Err(_) => Err(CompilerError::FileCorrupted(input_file)),
             }
         }
     }
 
     pub fn new_from(file: String) -> CompResult<Self> {
-        let x = resolve_path(&file);
-        if x.is_err() {
-            Err(CompilerError::FileNotAccessible(file, true))
-        } else {
-            Self::new(x.unwrap(), file)
+        match resolve_path(&file) {
+            Ok(path) => Self::new(path, file),
+            Err(_) => Err(CompilerError::FileNotAccessible(file, true)),
+        }
+    }
+
+    /// Code that does not come from a file, as used by tests.
+    #[cfg(test)]
+    pub fn from_source(content: &str) -> Self {
+        Self {
+            input_file: "test.sila".to_string(),
+            file_path: PathBuf::from("test.sila"),
+            content: content.to_string(),
         }
     }
 
@@ -73,49 +80,53 @@ impl FileManager {
         self.content.clone()
     }
 
-    pub fn get_surrounding_slice(&self, line_index: usize) -> (String, usize) {
+    /// Lines `line_start..=line_end` plus one line of context on either side.
+    pub fn get_surrounding_slice(&self, line_start: usize, line_end: usize) -> String {
         let lines: Vec<&str> = self.content.lines().collect();
-        let total_lines = lines.len();
-
-        if total_lines == 0 {
-            return (String::new(), 0);
-        }
 
         let mut snippet = String::new();
-        let mut offset = 0;
-
-        if line_index > 0 {
-            snippet.push_str(lines[line_index - 1]);
+        if line_start > 0 {
+            snippet.push_str(lines[line_start - 1]);
             snippet.push('\n');
-            offset += lines[line_index - 1].len() + 1;
         }
 
-        if line_index < total_lines {
-            snippet.push_str(lines[line_index]);
+        for line in lines.iter().take(line_end + 2).skip(line_start) {
+            snippet.push_str(line);
             snippet.push('\n');
         }
+        snippet
+    }
 
-        if line_index + 1 < total_lines {
-            snippet.push_str(lines[line_index + 1]);
-            snippet.push('\n');
+    /// Offset of `line` inside the snippet window shown for `window`, if it is part of it.
+    pub fn window_offset(&self, window: &CodePosition, line: usize) -> Option<usize> {
+        if line + 1 < window.line_start || line > window.line_end + 1 {
+            return None;
         }
+        let lines: Vec<&str> = self.content.lines().collect();
+        Some(
+            (window.line_start.saturating_sub(1)..line)
+                .map(|t| lines.get(t).map_or(0, |l| l.len() + 1))
+                .sum(),
+        )
+    }
 
-        (snippet, offset)
+    /// Byte range of `position` inside the snippet window shown for `window`.
+    pub fn window_range(&self, window: &CodePosition, position: &CodePosition) -> Option<Range<usize>> {
+        let start = self.window_offset(window, position.line_start)? + position.line_idx_start;
+        let end = self.window_offset(window, position.line_end)? + position.line_idx_end;
+        Some(start..end)
     }
 
-    pub fn get_code_snippet(&self, code_position: &CodePosition) -> (Snippet, usize) {
+    pub fn get_code_snippet(&self, code_position: &CodePosition) -> Snippet<'_> {
         // TODO: Remove this super evil magic trick
-        let sor_slc = self.get_surrounding_slice(code_position.line_start);
+        let sor_slc = self.get_surrounding_slice(code_position.line_start, code_position.line_end);
         let clean_path = &self.input_file;
-        (
-            Snippet::source(sor_slc.0.leak())
-                .line_start(if code_position.line_start == 0 {
-                    code_position.line_start + 1
-                } else {
-                    code_position.line_start
-                })
-                .origin(relative_path(clean_path).to_string().leak()),
-            sor_slc.1
-        )
+        Snippet::source(sor_slc.leak())
+            .line_start(if code_position.line_start == 0 {
+                code_position.line_start + 1
+            } else {
+                code_position.line_start
+            })
+            .origin(relative_path(clean_path).to_string().leak())
     }
 }
@@ -0,0 +1,213 @@
+use crate::cfg::{ControlFlowGraph, Step};
+use crate::comp_errors::{CodeError, CodeResult};
+use crate::lexer::Token;
+use crate::parser::{ASTNode, FunctionMode};
+use crate::resolver::Resolution;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Which variables are assigned at the start of a block.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct State {
+    // Assigned on every path
+    definite: HashSet<*const Token>,
+    // Assigned on at least one path
+    maybe: HashSet<*const Token>,
+}
+
+impl State {
+    fn join(&mut self, other: &State) {
+        self.definite.retain(|t| other.definite.contains(t));
+        self.maybe.extend(&other.maybe);
+    }
+}
+
+/// Definite assignment analysis for variables declared without a value.
+/// Reading them requires an assignment on every path leading to the read,
+/// assigning them more than once requires `let mut`.
+pub struct InitializationChecker<'a, 'b> {
+    resolution: &'b Resolution<'a>,
+    // Variables of the current function declared without a value -> Mutable
+    deferred: HashMap<*const Token, bool>,
+}
+
+impl<'a, 'b> InitializationChecker<'a, 'b> {
+    pub fn new(resolution: &'b Resolution<'a>) -> Self {
+        Self {
+            resolution,
+            deferred: HashMap::new(),
+        }
+    }
+
+    fn collect_deferred(&mut self, node: &ASTNode<'a>) {
+        if let ASTNode::VariableSet(name, None, _, mutable) = node {
+            self.deferred.insert(*name as *const Token, *mutable);
+        }
+        for child in node.children() {
+            self.collect_deferred(child);
+        }
+    }
+
+    /// Declaration of a tracked variable.
+    fn declaration(&self, usage: &Token) -> Option<&'a Token> {
+        self.resolution
+            .symbol_of(usage)
+            .map(|t| t.declaration)
+            .filter(|t| self.deferred.contains_key(&(*t as *const Token)))
+    }
+
+    fn read(&self, node: &ASTNode<'a>, state: &State) -> CodeResult<()> {
+        if let ASTNode::Identifier(usage) = node {
+            if let Some(declaration) = self.declaration(usage) {
+                if !state.definite.contains(&(declaration as *const Token)) {
+                    return Err(CodeError::uninitialized_read_error(usage, declaration));
+                }
+            }
+        }
+        for child in node.children() {
+            self.read(child, state)?;
+        }
+        Ok(())
+    }
+
+    fn transfer(&self, step: &Step<'a, '_>, state: &mut State) -> CodeResult<()> {
+        match step {
+            Step::Condition(node) => self.read(node, state),
+            Step::Bind(var) => {
+                state.definite.insert(*var as *const Token);
+                Ok(())
+            }
+            Step::Statement(ASTNode::VariableSet(name, value, _, _)) => {
+                if let Some(value) = value {
+                    self.read(value, state)?;
+                }
+                // Declarations inside of loops start out unassigned in every iteration
+                let name = *name as *const Token;
+                state.definite.remove(&name);
+                state.maybe.remove(&name);
+                Ok(())
+            }
+            Step::Statement(node @ ASTNode::Assignment(name, value)) => {
+                self.read(value, state)?;
+                if let Some(declaration) = self.declaration(name) {
+                    let ptr = declaration as *const Token;
+                    if !self.deferred[&ptr] && state.maybe.contains(&ptr) {
+                        return Err(CodeError::assigned_twice_error(node.position(), declaration));
+                    }
+                    state.definite.insert(ptr);
+                    state.maybe.insert(ptr);
+                }
+                Ok(())
+            }
+            Step::Statement(node) => self.read(node, state),
+        }
+    }
+
+    /// Forward data flow over the graph until the block states are stable.
+    /// States only lose definite and gain maybe assignments while iterating,
+    /// so an error found on the way is an error in the final state as well.
+    fn check_graph(&self, graph: &ControlFlowGraph<'a, '_>) -> CodeResult<()> {
+        let mut states: Vec<Option<State>> = vec![None; graph.blocks.len()];
+        states[0] = Some(State::default());
+        // Lowest block first, which follows the source order
+        let mut pending = BTreeSet::from([0]);
+
+        while let Some(block) = pending.pop_first() {
+            let mut state = states[block].clone().unwrap();
+            for step in &graph.blocks[block].steps {
+                self.transfer(step, &mut state)?;
+            }
+
+            for successor in &graph.blocks[block].successors {
+                let next = match &states[*successor] {
+                    Some(old) => {
+                        let mut joined = old.clone();
+                        joined.join(&state);
+                        joined
+                    }
+                    None => state.clone(),
+                };
+                if states[*successor].as_ref() != Some(&next) {
+                    states[*successor] = Some(next);
+                    pending.insert(*successor);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check(mut self, ast: &[ASTNode<'a>]) -> CodeResult<()> {
+        for item in ast {
+            let ASTNode::FunctionDef(_, mode, _, _, body, _) = item else {
+                continue;
+            };
+            if matches!(mode, FunctionMode::Extern) {
+                continue;
+            }
+
+            self.deferred.clear();
+            for statement in body {
+                self.collect_deferred(statement);
+            }
+            if self.deferred.is_empty() {
+                continue;
+            }
+            self.check_graph(&ControlFlowGraph::build(body)?)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn check_initialization<'a>(ast: &[ASTNode<'a>], resolution: &Resolution<'a>) -> CodeResult<()> {
+    InitializationChecker::new(resolution).check(ast)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comp_errors::CodeErrorType;
+    use crate::resolver::resolve;
+    use crate::testing::parse;
+
+    fn check_source(source: &str) -> CodeResult<()> {
+        let ast = parse(source);
+        let resolution = resolve(&ast, &[])?;
+        check_initialization(&ast, &resolution)
+    }
+
+    #[test]
+    fn accepts_variables_assigned_on_every_path() {
+        check_source("def f(c: bool): i32 {\n    let x: i32;\n    if c {\n        x = 1;\n    } else {\n        x = 2;\n    }\n    return x;\n}")
+            .unwrap();
+        check_source("def f(c: bool): i32 {\n    let x: i32;\n    if c {\n        return 0;\n    }\n    x = 1;\n    return x;\n}")
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_reads_assigned_on_one_branch() {
+        let error = check_source("def f(c: bool): i32 {\n    let x: i32;\n    if c {\n        x = 1;\n    }\n    return x;\n}")
+            .unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::Uninitialized));
+        assert_eq!(error.position.line_start, 5);
+    }
+
+    #[test]
+    fn rejects_reads_before_loops_assign() {
+        let error = check_source("def f(): i32 {\n    let x: i32;\n    for i in 0..3 {\n        x = i;\n    }\n    return x;\n}")
+            .unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::Uninitialized));
+    }
+
+    #[test]
+    fn rejects_immutable_variables_assigned_twice() {
+        let error = check_source("def f(c: bool): i32 {\n    let x: i32;\n    if c {\n        x = 1;\n    }\n    x = 2;\n    return x;\n}")
+            .unwrap_err();
+        assert_eq!(error.title, "Immutable variable assigned twice");
+        assert_eq!(error.position.line_start, 5);
+
+        let error = check_source("def f(): i32 {\n    let x: i32;\n    while true {\n        x = 1;\n    }\n}")
+            .unwrap_err();
+        assert_eq!(error.title, "Immutable variable assigned twice");
+
+        check_source("def f(c: bool): i32 {\n    let mut x: i32;\n    x = 1;\n    x = 2;\n    return x;\n}").unwrap();
+    }
+}
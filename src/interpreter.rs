@@ -0,0 +1,650 @@
+use crate::checker::bind_arguments;
+use crate::comp_errors::CodeError;
+use crate::consteval::compare;
+use crate::entry::EntryPoint;
+use crate::lexer::{CodePosition, Token, TokenType};
+use crate::modules::{Module, ModuleResult};
+use crate::parser::{ASTNode, Builtin, FunctionMode};
+use crate::types::Type;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::rc::Rc;
+
+/// Stack size of the thread running the interpreter, calls recurse on the Rust stack.
+pub const INTERPRETER_STACK_SIZE: usize = 1 << 30;
+
+/// Calls nest as deep as the interpreter stack allows, a call takes up to 16 KiB of it in debug
+/// builds. Compiled programs are only limited by the native stack.
+pub const CALL_DEPTH_LIMIT: usize = INTERPRETER_STACK_SIZE / (16 << 10);
+
+/// Extern functions the interpreter provides itself.
+const HOST_FUNCTIONS: [&str; 3] = ["puts", "putchar", "exit"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    // Kept in the range of its type
+    Int(i128),
+    // Rounded to `f32` where that is the type
+    Float(f64),
+    Bool(bool),
+    Str(Rc<str>),
+    // Address in `Memory`, 0 is null
+    Pointer(u64),
+    Array(Vec<Value>),
+    Struct(Vec<Value>),
+    Void,
+}
+
+/// Wraps `value` around to the range of an integer type, like the hardware does.
+pub(crate) fn wrap(value: i128, ty: &Type) -> i128 {
+    let Type::Int(bits, signed) = ty else {
+        return value;
+    };
+    let bits = *bits as u32;
+    let value = value & ((1i128 << bits) - 1);
+    if *signed && value >> (bits - 1) == 1 {
+        value - (1i128 << bits)
+    } else {
+        value
+    }
+}
+
+pub(crate) fn round(value: f64, ty: &Type) -> f64 {
+    match ty {
+        Type::Float(32) => value as f32 as f64,
+        _ => value,
+    }
+}
+
+/// Heap of the running program. Allocations are byte blocks keyed by their address,
+/// string data lives in blocks that are never freed.
+#[derive(Default)]
+pub(crate) struct Memory {
+    blocks: BTreeMap<u64, Vec<u8>>,
+    strings: HashMap<Rc<str>, u64>,
+    next: u64,
+}
+
+impl Memory {
+    pub(crate) fn alloc(&mut self, size: u64) -> u64 {
+        // Leave a gap, so pointers past the end of a block do not point into the next one
+        let address = self.next.max(0x1000);
+        self.next = address + (size + 16).next_multiple_of(16);
+        self.blocks.insert(address, vec![0; size as usize]);
+        address
+    }
+
+    fn free(&mut self, address: u64) -> Option<()> {
+        match address {
+            0 => Some(()),
+            _ => self.blocks.remove(&address).map(|_| ()),
+        }
+    }
+
+    fn realloc(&mut self, address: u64, size: u64) -> Option<u64> {
+        if address == 0 {
+            return Some(self.alloc(size));
+        }
+        let mut data = self.blocks.remove(&address)?;
+        data.resize(size as usize, 0);
+        let moved = self.alloc(size);
+        self.blocks.insert(moved, data);
+        Some(moved)
+    }
+
+    pub(crate) fn intern(&mut self, text: &Rc<str>) -> u64 {
+        if let Some(address) = self.strings.get(text) {
+            return *address;
+        }
+        let address = self.alloc(text.len() as u64 + 1);
+        self.blocks.get_mut(&address).unwrap()[..text.len()].copy_from_slice(text.as_bytes());
+        self.strings.insert(text.clone(), address);
+        address
+    }
+
+    /// Arguments of the entry function, `argv` holds the program name followed by a null pointer.
+    pub(crate) fn entry_args(&mut self, entry: &EntryPoint, program: &str) -> Vec<Value> {
+        if !entry.takes_args {
+            return vec![];
+        }
+        let name = self.intern(&Rc::from(program));
+        let argv = self.alloc(16);
+        self.blocks.get_mut(&argv).unwrap()[..8].copy_from_slice(&name.to_le_bytes());
+        vec![Value::Int(1), Value::Pointer(argv)]
+    }
+
+    /// The nul-terminated string at `address`.
+    fn string(&self, address: u64) -> Option<String> {
+        let (start, data) = self.blocks.range(..=address).next_back()?;
+        let bytes = data.get((address - start) as usize..)?;
+        let end = bytes.iter().position(|t| *t == 0).unwrap_or(bytes.len());
+        Some(String::from_utf8_lossy(&bytes[..end]).to_string())
+    }
+}
+
+/// Value of a literal of type `ty`.
+pub(crate) fn literal(token: &Token, ty: &Type) -> Value {
+    match (token.token_type, ty) {
+        (TokenType::Boolean, _) => Value::Bool(token.content == "true"),
+        (TokenType::NumberFloat, _) | (_, Type::Float(_)) => Value::Float(round(token.content.parse().unwrap(), ty)),
+        _ => Value::Int(token.content.parse().unwrap()),
+    }
+}
+
+/// Applies `op` to operands of the same type, `ty` is the type of the result.
+/// `None` for a division by zero.
+pub(crate) fn binary_op(op: TokenType, ty: &Type, lhs: Value, rhs: Value) -> Option<Value> {
+    Some(match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => match op {
+            TokenType::Plus => Value::Int(wrap(a + b, ty)),
+            TokenType::Minus => Value::Int(wrap(a - b, ty)),
+            TokenType::Star => Value::Int(wrap(a * b, ty)),
+            TokenType::Slash => Value::Int(wrap(a.checked_div(b)?, ty)),
+            _ => Value::Bool(compare(op, a.partial_cmp(&b))),
+        },
+        (Value::Float(a), Value::Float(b)) => match op {
+            TokenType::Plus => Value::Float(round(a + b, ty)),
+            TokenType::Minus => Value::Float(round(a - b, ty)),
+            TokenType::Star => Value::Float(round(a * b, ty)),
+            TokenType::Slash => Value::Float(round(a / b, ty)),
+            _ => Value::Bool(compare(op, a.partial_cmp(&b))),
+        },
+        // Booleans and pointers only compare for equality
+        (a, b) => Value::Bool(compare(op, (a == b).then_some(Ordering::Equal))),
+    })
+}
+
+/// Converts `value` for `expr -> target`. Floats are truncated towards zero and
+/// saturate at the bounds of the target, pointers convert to their address.
+/// `None` if a pointer converted to `str` does not point to a string.
+pub(crate) fn cast(memory: &mut Memory, value: Value, target: &Type) -> Option<Value> {
+    Some(match (value, target) {
+        (Value::Int(value), Type::Int(..)) => Value::Int(wrap(value, target)),
+        (Value::Int(value), Type::Float(_)) => Value::Float(round(value as f64, target)),
+        (Value::Int(value), Type::Pointer(_)) => Value::Pointer(value as u64),
+        (Value::Float(value), Type::Int(bits, signed)) => {
+            let (min, max) = match signed {
+                true => (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1),
+                false => (0, (1i128 << bits) - 1),
+            };
+            Value::Int((value.trunc() as i128).clamp(min, max))
+        }
+        (Value::Float(value), Type::Float(_)) => Value::Float(round(value, target)),
+        (Value::Bool(value), Type::Int(..)) => Value::Int(value as i128),
+        (Value::Pointer(address), Type::Int(..)) => Value::Int(wrap(address as i128, target)),
+        (Value::Pointer(address), Type::Str) => Value::Str(memory.string(address)?.into()),
+        (Value::Str(text), Type::Pointer(_)) => Value::Pointer(memory.intern(&text)),
+        (value, _) => value,
+    })
+}
+
+/// Runs a heap builtin, `element_size` is the size of its type argument.
+/// Fails with the action for the error if the pointer is not an allocation.
+pub(crate) fn builtin(
+    memory: &mut Memory,
+    builtin: Builtin,
+    element_size: u64,
+    args: &[Value],
+) -> Result<Value, &'static str> {
+    match (builtin, args) {
+        (Builtin::Alloc, [Value::Int(count)]) => Ok(Value::Pointer(memory.alloc(element_size * *count as u64))),
+        (Builtin::Free, [Value::Pointer(address)]) => memory.free(*address).map(|_| Value::Void).ok_or("Freed"),
+        (Builtin::Realloc, [Value::Pointer(address), Value::Int(count)]) => memory
+            .realloc(*address, element_size * *count as u64)
+            .map(Value::Pointer)
+            .ok_or("Reallocated"),
+        _ => unreachable!(),
+    }
+}
+
+/// Size of a value of `ty` in the memory of the program. Structs are laid out
+/// field after field, each aligned to its size.
+pub(crate) fn size_of(modules: &[Module], ty: &Type) -> u64 {
+    let name = match ty {
+        Type::Array(element, length) => return size_of(modules, element) * *length as u64,
+        Type::Struct(name) => name,
+        _ => return ty.size().unwrap() as u64,
+    };
+    let fields = modules.iter().find_map(|module| {
+        module.ast.iter().find_map(|t| match t {
+            ASTNode::StructDef(item, fields) if item.content == *name => Some(fields),
+            _ => None,
+        })
+    });
+    let mut size = 0u64;
+    for (_, field) in fields.unwrap() {
+        let field = size_of(modules, &Type::from_node(field).unwrap());
+        size = size.next_multiple_of(field.clamp(1, 8)) + field;
+    }
+    size.next_multiple_of(8)
+}
+
+/// Why a host function did not return.
+pub(crate) enum HostError {
+    Exit(i32),
+    // An argument does not point to a string
+    InvalidPointer,
+    // No host function has this name
+    Unavailable,
+}
+
+impl HostError {
+    /// Error to report at the call, `exit` is handled by the caller.
+    pub(crate) fn to_error(&self, position: CodePosition, symbol: &str) -> CodeError {
+        match self {
+            HostError::Exit(_) => unreachable!(),
+            HostError::InvalidPointer => CodeError::invalid_pointer_error(position, "Read"),
+            HostError::Unavailable => CodeError::unavailable_extern_error(position, symbol, &HOST_FUNCTIONS),
+        }
+    }
+}
+
+/// Calls the extern function `symbol` that has no Sila definition.
+pub(crate) fn call_host(
+    memory: &mut Memory,
+    output: &mut dyn Write,
+    symbol: &str,
+    args: &[Value],
+) -> Result<Value, HostError> {
+    match (symbol, args) {
+        ("puts", [value]) => {
+            let text = match value {
+                Value::Str(text) => text.to_string(),
+                Value::Pointer(address) => memory.string(*address).ok_or(HostError::InvalidPointer)?,
+                _ => return Err(HostError::InvalidPointer),
+            };
+            writeln!(output, "{}", text).unwrap();
+            Ok(Value::Int(0))
+        }
+        ("putchar", [Value::Int(c)]) => {
+            output.write_all(&[*c as u8]).unwrap();
+            Ok(Value::Int(*c))
+        }
+        ("exit", [Value::Int(code)]) => Err(HostError::Exit(*code as i32)),
+        _ => Err(HostError::Unavailable),
+    }
+}
+
+/// Exit code of the process for the value the entry function returned.
+pub(crate) fn exit_code(value: &Value) -> i32 {
+    match value {
+        Value::Int(code) => *code as i32,
+        _ => 0,
+    }
+}
+
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+/// Why a program stopped early.
+enum Trap {
+    // Runtime error and the module it happened in
+    Error(Box<CodeError>, usize),
+    // `exit` was called
+    Exit(i32),
+}
+
+impl Trap {
+    fn error(error: CodeError, module: usize) -> Self {
+        Trap::Error(Box::new(error), module)
+    }
+}
+
+type RunResult<T> = Result<T, Trap>;
+
+/// Locals of a call, keyed by declaration.
+struct Frame {
+    module: usize,
+    locals: HashMap<*const Token, Value>,
+}
+
+/// Runs checked modules by walking their trees. This is the reference semantics
+/// the backends are tested against.
+pub struct Interpreter<'a, 'm, 'o> {
+    modules: &'m [Module<'a>],
+    // Linker symbol -> module and definition of every function with a body
+    symbols: HashMap<String, (usize, &'m ASTNode<'a>)>,
+    memory: Memory,
+    depth: usize,
+    output: &'o mut dyn Write,
+}
+
+impl<'a, 'm, 'o> Interpreter<'a, 'm, 'o> {
+    pub fn new(modules: &'m [Module<'a>], output: &'o mut dyn Write) -> Self {
+        let mut symbols = HashMap::new();
+        for (index, module) in modules.iter().enumerate() {
+            for item in &module.ast {
+                if let ASTNode::FunctionDef(_, mode, ..) = item {
+                    if *mode != FunctionMode::Extern {
+                        symbols.insert(item.symbol_name(&module.name).unwrap(), (index, item));
+                    }
+                }
+            }
+        }
+        Self {
+            modules,
+            symbols,
+            memory: Memory::default(),
+            depth: 0,
+            output,
+        }
+    }
+
+    fn type_of(&self, frame: &Frame, node: &ASTNode) -> Type {
+        self.modules[frame.module].types.type_of(node).cloned().unwrap_or(Type::Void)
+    }
+
+    fn eval_binary_op(&mut self, frame: &mut Frame, node: &ASTNode<'a>) -> RunResult<Value> {
+        let ASTNode::BinaryOp(lhs, op, rhs) = node else {
+            unreachable!()
+        };
+        let (lhs_value, rhs_value) = (self.eval_expr(frame, lhs)?, self.eval_expr(frame, rhs)?);
+        binary_op(op.token_type, &self.type_of(frame, node), lhs_value, rhs_value)
+            .ok_or_else(|| Trap::error(CodeError::division_by_zero_error(rhs.position()), frame.module))
+    }
+
+    fn eval_builtin(
+        &mut self,
+        frame: &mut Frame,
+        node: &ASTNode<'a>,
+        kind: Builtin,
+        type_arg: &Option<Box<ASTNode<'a>>>,
+        args: &[Box<ASTNode<'a>>],
+    ) -> RunResult<Value> {
+        let element_size = match type_arg {
+            Some(ty) => size_of(self.modules, &Type::from_node(ty).unwrap()),
+            None => 0,
+        };
+        let args = self.eval_args(frame, args)?;
+        builtin(&mut self.memory, kind, element_size, &args)
+            .map_err(|action| Trap::error(CodeError::invalid_pointer_error(node.position(), action), frame.module))
+    }
+
+    fn eval_args(&mut self, frame: &mut Frame, args: &[Box<ASTNode<'a>>]) -> RunResult<Vec<Value>> {
+        args.iter().map(|t| self.eval_expr(frame, t)).collect()
+    }
+
+    fn eval_expr(&mut self, frame: &mut Frame, node: &ASTNode<'a>) -> RunResult<Value> {
+        Ok(match node {
+            ASTNode::Literal(t) => literal(t, &self.type_of(frame, node)),
+            ASTNode::String(t) => Value::Str(t.content.as_str().into()),
+            ASTNode::Identifier(name) => frame.locals[&self.modules[frame.module].key(name)].clone(),
+            ASTNode::BinaryOp(..) => self.eval_binary_op(frame, node)?,
+            ASTNode::CastExpr(expr, ty) => {
+                let value = self.eval_expr(frame, expr)?;
+                cast(&mut self.memory, value, &Type::from_node(ty).unwrap()).ok_or_else(|| {
+                    Trap::error(CodeError::invalid_pointer_error(expr.position(), "Read"), frame.module)
+                })?
+            }
+            ASTNode::FunctionCall(name, args, _) => self.call(frame, node, name, args)?,
+            ASTNode::NamedArgument(_, value) => self.eval_expr(frame, value)?,
+            ASTNode::BuiltinCall(builtin, _, type_arg, args, _) => {
+                self.eval_builtin(frame, node, *builtin, type_arg, args)?
+            }
+            ASTNode::StructLiteral(_, fields) => {
+                let mut values = vec![];
+                for (_, value) in fields {
+                    values.push(self.eval_expr(frame, value)?);
+                }
+                Value::Struct(values)
+            }
+            ASTNode::ArrayLiteral(_, elements) => Value::Array(self.eval_args(frame, elements)?),
+            ASTNode::Index(array, index) => {
+                let (Value::Array(elements), Value::Int(index)) =
+                    (self.eval_expr(frame, array)?, self.eval_expr(frame, index)?)
+                else {
+                    unreachable!()
+                };
+                elements[index as usize].clone()
+            }
+            _ => Value::Void,
+        })
+    }
+
+    fn call(
+        &mut self,
+        frame: &mut Frame,
+        node: &ASTNode<'a>,
+        name: &'a Token,
+        args: &[Box<ASTNode<'a>>],
+    ) -> RunResult<Value> {
+        let module = &self.modules[frame.module];
+        let function = module.callee(name);
+        let ASTNode::FunctionDef(function_name, mode, _, params, ..) = function else {
+            unreachable!()
+        };
+
+        // Arguments are evaluated where they are written, defaults included
+        let bound = bind_arguments(name, args, function_name, params).unwrap();
+        let mut values = vec![];
+        for value in bound {
+            values.push(self.eval_expr(frame, value)?);
+        }
+
+        let (callee_module, callee) = match mode {
+            FunctionMode::Extern => {
+                let symbol = function.symbol_name(&module.name).unwrap();
+                match self.symbols.get(&symbol) {
+                    Some(definition) => *definition,
+                    None => {
+                        return call_host(&mut self.memory, self.output, &symbol, &values).map_err(|e| match e {
+                            HostError::Exit(code) => Trap::Exit(code),
+                            e => Trap::error(e.to_error(node.position(), &symbol), frame.module),
+                        })
+                    }
+                }
+            }
+            _ => (frame.module, function),
+        };
+
+        self.depth += 1;
+        if self.depth > CALL_DEPTH_LIMIT {
+            return Err(Trap::error(CodeError::stack_overflow_error(node.position(), CALL_DEPTH_LIMIT),
+                frame.module,
+            ));
+        }
+        let (result, mut callee_frame) = self.run_function(callee_module, callee, values)?;
+        self.depth -= 1;
+
+        // `&mut` arguments are variables, they take the final value of the parameter
+        let ASTNode::FunctionDef(_, _, _, callee_params, ..) = callee else {
+            unreachable!()
+        };
+        let bound = bind_arguments(name, args, function_name, params).unwrap();
+        for ((param, ty, _), arg) in callee_params.iter().zip(bound) {
+            if let (ASTNode::ReferenceType(_, true, _), ASTNode::Identifier(variable)) = (&**ty, arg) {
+                let value = callee_frame.locals.remove(&(*param as *const Token)).unwrap();
+                frame.locals.insert(self.modules[frame.module].key(variable), value);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Runs the body of `function` in `module`, returns its value and its final locals.
+    fn run_function(&mut self, module: usize, function: &'m ASTNode<'a>, args: Vec<Value>) -> RunResult<(Value, Frame)> {
+        let ASTNode::FunctionDef(_, _, _, params, body, _) = function else {
+            unreachable!()
+        };
+        let mut frame = Frame {
+            module,
+            locals: params.iter().map(|(t, ..)| *t as *const Token).zip(args).collect(),
+        };
+        let result = match self.exec_block(&mut frame, body)? {
+            Flow::Return(value) => value,
+            _ => Value::Void,
+        };
+        Ok((result, frame))
+    }
+
+    fn exec_block(&mut self, frame: &mut Frame, body: &[Box<ASTNode<'a>>]) -> RunResult<Flow> {
+        for statement in body {
+            let flow = self.exec(frame, statement)?;
+            if !matches!(flow, Flow::Next) {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Runs a loop body, `Some` once the loop has to stop.
+    fn exec_iteration(&mut self, frame: &mut Frame, body: &[Box<ASTNode<'a>>]) -> RunResult<Option<Flow>> {
+        Ok(match self.exec_block(frame, body)? {
+            Flow::Break => Some(Flow::Next),
+            flow @ Flow::Return(_) => Some(flow),
+            Flow::Next | Flow::Continue => None,
+        })
+    }
+
+    fn exec(&mut self, frame: &mut Frame, statement: &ASTNode<'a>) -> RunResult<Flow> {
+        match statement {
+            ASTNode::VariableSet(name, value, ..) => {
+                // Variables without a value are assigned before they are read
+                if let Some(value) = value {
+                    let value = self.eval_expr(frame, value)?;
+                    frame.locals.insert(*name as *const Token, value);
+                }
+            }
+            ASTNode::Assignment(name, value) => {
+                let value = self.eval_expr(frame, value)?;
+                frame.locals.insert(self.modules[frame.module].key(name), value);
+            }
+            ASTNode::Return(value) => return Ok(Flow::Return(self.eval_expr(frame, value)?)),
+            ASTNode::Break(_) => return Ok(Flow::Break),
+            ASTNode::Continue(_) => return Ok(Flow::Continue),
+            ASTNode::If(condition, then_body, else_body) => {
+                return match self.eval_expr(frame, condition)? {
+                    Value::Bool(true) => self.exec_block(frame, then_body),
+                    _ => self.exec_block(frame, else_body),
+                };
+            }
+            ASTNode::WhileLoop(condition, body) => {
+                while self.eval_expr(frame, condition)? == Value::Bool(true) {
+                    if let Some(flow) = self.exec_iteration(frame, body)? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            expr => {
+                self.eval_expr(frame, expr)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Runs the program from `entry`, returns its exit code.
+    pub fn run(&mut self, entry: &EntryPoint, program: &str) -> ModuleResult<i32> {
+        let (module, function) = self.symbols[&entry.symbol];
+        let args = self.memory.entry_args(entry, program);
+        let result = self.run_function(module, function, args);
+        self.output.flush().unwrap();
+        match result {
+            Ok((value, _)) => Ok(exit_code(&value)),
+            Err(Trap::Exit(code)) => Ok(code),
+            Err(Trap::Error(error, module)) => Err((*error, module)),
+        }
+    }
+}
+
+/// Runs the checked `modules` starting at `entry`, program output goes to `output`.
+pub fn interpret(modules: &[Module], entry: &EntryPoint, program: &str, output: &mut dyn Write) -> ModuleResult<i32> {
+    Interpreter::new(modules, output).run(entry, program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comp_errors::CodeErrorType;
+    use crate::entry::entry_point;
+    use crate::testing::check_module_of;
+
+    /// Runs `source` as the whole program, returns the exit code and the output.
+    fn run(source: &str) -> Result<(i32, String), CodeError> {
+        let module = check_module_of(source).unwrap_or_else(|e| panic!("{}: {}", e.title, e.footer));
+        let entry = entry_point(&module.ast, &module.name, None).unwrap();
+        let mut output = vec![];
+        let code = interpret(&[module], &entry, "app", &mut output).map_err(|(e, _)| e)?;
+        Ok((code, String::from_utf8(output).unwrap()))
+    }
+
+    fn exit_code(body: &str) -> i32 {
+        run(&format!("def @unsafe main(): i32 {{\n{}\n}}", body)).unwrap().0
+    }
+
+    const PUTS: &str = "def extern puts(s: str): i32 {\n}\n\n";
+
+    #[test]
+    fn evaluates_arithmetic_in_the_type_of_the_expression() {
+        assert_eq!(exit_code("    return 7 / 2 * 3 - 1;"), 8);
+        assert_eq!(exit_code("    let b: u8 = 250;\n    return (b + 10) -> i32;"), 4);
+        assert_eq!(exit_code("    let x: i8 = 127;\n    return (x + 1) -> i32;"), -128);
+        assert_eq!(exit_code("    return 2.9 -> i32;"), 2);
+        assert_eq!(exit_code("    let f: f32 = 0.1;\n    return (f -> f64 == 0.1) -> i32;"), 0);
+    }
+
+    #[test]
+    fn runs_loops_and_calls() {
+        let source = "def fib(n: i32): i32 {\n    if n < 2 {\n        return n;\n    }\n    return fib(n - 1) + fib(n - 2);\n}\n\n\
+            def main(): i32 {\n    let mut total = 0;\n    for i in 0..=10 {\n        if i == 3 {\n            continue;\n        }\n        total = total + fib(i);\n    }\n    \
+            while true {\n        break;\n    }\n    return total;\n}";
+        assert_eq!(run(source).unwrap().0, 141);
+    }
+
+    #[test]
+    fn passes_mutable_references_and_defaults() {
+        let source = "def bump(x: &mut i32, by: i32 = 2): void {\n    x = x + by;\n}\n\n\
+            def main(): i32 {\n    let mut x = 1;\n    bump(x);\n    bump(by = 10, x = x);\n    return x;\n}";
+        assert_eq!(run(source).unwrap().0, 13);
+    }
+
+    #[test]
+    fn writes_output_through_host_functions() {
+        let source = format!(
+            "{}def extern exit(code: i32): void {{\n}}\n\ndef @unsafe main(): i32 {{\n    puts(\"hi\");\n    puts((\"hi\" -> *u8) -> str);\n    exit(3);\n    return 0;\n}}",
+            PUTS
+        );
+        assert_eq!(run(&source).unwrap(), (3, "hi\nhi\n".to_string()));
+    }
+
+    #[test]
+    fn allocates_on_the_heap() {
+        let body = "    let p = alloc<i64>(4);\n    let q = realloc<i64>(p, 8);\n    free(q);\n    return (q -> i64 != 0) -> i32;";
+        assert_eq!(exit_code(body), 1);
+        let error = run("def main(): i32 {\n    let p = alloc<i32>(1);\n    free(p);\n    free(p);\n    return 0;\n}").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::RuntimeError));
+    }
+
+    #[test]
+    fn reports_runtime_errors() {
+        let error = run("def main(): i32 {\n    let zero = 0;\n    return 1 / zero;\n}").unwrap_err();
+        assert_eq!(error.title, CodeError::division_by_zero_error(error.position).title);
+
+        let deep = || run("def deep(n: i32): i32 {\n    return deep(n + 1);\n}\n\ndef main(): i32 {\n    return deep(0);\n}");
+        let error = std::thread::Builder::new().stack_size(INTERPRETER_STACK_SIZE).spawn(deep).unwrap().join().unwrap();
+        assert!(matches!(error.unwrap_err().code_error_type, CodeErrorType::RuntimeError));
+
+        let missing = "def extern abort(): void {\n}\n\ndef main(): i32 {\n    abort();\n    return 0;\n}";
+        assert!(matches!(run(missing).unwrap_err().code_error_type, CodeErrorType::RuntimeError));
+    }
+
+    /// The limit is close to what the stack holds, well past the depth of ordinary recursion.
+    #[test]
+    fn nests_calls_up_to_the_limit() {
+        let depth = CALL_DEPTH_LIMIT - 1;
+        let down = move || {
+            let source = format!(
+                "def down(n: i32): i32 {{\n    if n == 0 {{\n        return 0;\n    }}\n    return down(n - 1) + 1;\n}}\n\n\
+                 def main(): i32 {{\n    return down({}) - {};\n}}",
+                depth,
+                depth - 42
+            );
+            run(&source)
+        };
+        let result = std::thread::Builder::new().stack_size(INTERPRETER_STACK_SIZE).spawn(down).unwrap().join().unwrap();
+        assert_eq!(result.unwrap().0, 42);
+    }
+}
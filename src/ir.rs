@@ -0,0 +1,1435 @@
+use crate::checker::bind_arguments;
+use crate::compiler::is_aggregate;
+use crate::entry::EntryPoint;
+use crate::lexer::{Token, TokenType};
+use crate::modules::Module;
+use crate::parser::{ASTNode, Builtin, FunctionMode, Parameter};
+use crate::types::{CastKind, Type};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// Operand of an instruction. Constants carry their type, so every operand has one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    // Parameter or result of an instruction, numbered per function
+    Temp(usize),
+    Int(i128, Type),
+    Float(f64, Type),
+    Bool(bool),
+    // Index into `Program::strings`
+    String(usize),
+    // Read of a variable on a path that never wrote it, which the initialization check rules out
+    Undef(Type),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    // Traps on zero, the smallest signed value divided by -1 wraps
+    Div,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Signedness of integer operations comes from the operand types.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    // Stack memory for a value of the type, in the entry block
+    Alloca(Type),
+    // Pointer
+    Load(Value),
+    // Value, Pointer
+    Store(Value, Value),
+    // Pointer to a struct, field index
+    Field(Value, usize),
+    // Pointer to an array, element index
+    Element(Value, Value),
+    Binary(BinaryOp, Value, Value),
+    Compare(Comparison, Value, Value),
+    // Converts like the interpreter, floats saturate when they become integers
+    Cast(Value, Type),
+    // Symbol, Arguments. Reference parameters receive a pointer
+    Call(String, Vec<Value>),
+    // Element type, Count
+    Alloc(Type, Value),
+    // Element type, Pointer, Count
+    Realloc(Type, Value, Value),
+    Free(Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    // `None` for instructions without a value
+    pub result: Option<usize>,
+    pub op: Op,
+}
+
+/// Selects the value of the predecessor control came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Phi {
+    pub result: usize,
+    // Predecessor, Value
+    pub incoming: Vec<(usize, Value)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(usize),
+    // Condition, Then, Else
+    Branch(Value, usize, usize),
+    Return(Option<Value>),
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<usize> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch(_, then, otherwise) => vec![*then, *otherwise],
+            Terminator::Return(_) | Terminator::Unreachable => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    // Phis come before the other instructions
+    pub phis: Vec<Phi>,
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Linkage {
+    // Only called from this program
+    Internal,
+    // Export functions and the entry
+    External,
+    // Defined outside of Sila, without blocks
+    Import,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub symbol: String,
+    pub linkage: Linkage,
+    // Parameters are the temps `0..params.len()`, reference parameters are pointers
+    pub params: Vec<Type>,
+    pub ret: Type,
+    // The entry block comes first
+    pub blocks: Vec<Block>,
+    // Type of every temp
+    pub types: Vec<Type>,
+}
+
+/// Typed SSA form of a whole program, what backends and optimizations work on instead of the
+/// syntax tree. Scalar variables are SSA values joined by phis, aggregates and variables passed
+/// by reference stay in memory and are accessed with explicit loads and stores.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    // Name, Fields (name, type), sorted by name
+    pub structs: Vec<(String, Vec<(String, Type)>)>,
+    pub strings: Vec<String>,
+    pub functions: Vec<Function>,
+}
+
+impl Program {
+    fn fields(&self, name: &str) -> Option<&[(String, Type)]> {
+        self.structs.iter().find(|t| t.0 == name).map(|t| t.1.as_slice())
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Temp(temp) => write!(f, "%{}", temp),
+            Value::Int(value, ty) => write!(f, "{} {}", ty, value),
+            Value::Float(value, ty) => write!(f, "{} {:?}", ty, value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::String(index) => write!(f, "@str.{}", index),
+            Value::Undef(ty) => write!(f, "{} undef", ty),
+        }
+    }
+}
+
+fn list(values: &[Value]) -> String {
+    values.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Alloca(ty) => write!(f, "alloca {}", ty),
+            Op::Load(pointer) => write!(f, "load {}", pointer),
+            Op::Store(value, pointer) => write!(f, "store {}, {}", value, pointer),
+            Op::Field(pointer, index) => write!(f, "field {}, {}", pointer, index),
+            Op::Element(pointer, index) => write!(f, "element {}, {}", pointer, index),
+            Op::Binary(op, lhs, rhs) => write!(f, "{} {}, {}", format!("{:?}", op).to_lowercase(), lhs, rhs),
+            Op::Compare(op, lhs, rhs) => write!(f, "{} {}, {}", format!("{:?}", op).to_lowercase(), lhs, rhs),
+            Op::Cast(value, ty) => write!(f, "cast {} to {}", value, ty),
+            Op::Call(symbol, args) => write!(f, "call @{}({})", symbol, list(args)),
+            Op::Alloc(ty, count) => write!(f, "alloc {}, {}", ty, count),
+            Op::Realloc(ty, pointer, count) => write!(f, "realloc {}, {}, {}", ty, pointer, count),
+            Op::Free(pointer) => write!(f, "free {}", pointer),
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jmp b{}", target),
+            Terminator::Branch(condition, then, otherwise) => write!(f, "br {}, b{}, b{}", condition, then, otherwise),
+            Terminator::Return(Some(value)) => write!(f, "ret {}", value),
+            Terminator::Return(None) => write!(f, "ret"),
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let linkage = match self.linkage {
+            Linkage::Internal => "",
+            Linkage::External => "export ",
+            Linkage::Import => "declare ",
+        };
+        let params = match self.linkage {
+            Linkage::Import => self.params.iter().map(|t| t.to_string()).collect::<Vec<_>>(),
+            _ => self.params.iter().enumerate().map(|(index, ty)| format!("%{}: {}", index, ty)).collect(),
+        };
+        write!(f, "{}fn @{}({}) -> {}", linkage, self.symbol, params.join(", "), self.ret)?;
+        if self.linkage == Linkage::Import {
+            return writeln!(f);
+        }
+        writeln!(f, " {{")?;
+        for (index, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "b{}:", index)?;
+            for phi in &block.phis {
+                let incoming: Vec<String> = phi.incoming.iter().map(|(block, value)| format!("[b{}: {}]", block, value)).collect();
+                writeln!(f, "    %{}: {} = phi {}", phi.result, self.types[phi.result], incoming.join(", "))?;
+            }
+            for instruction in &block.instructions {
+                match instruction.result {
+                    Some(result) => writeln!(f, "    %{}: {} = {}", result, self.types[result], instruction.op)?,
+                    None => writeln!(f, "    {}", instruction.op)?,
+                }
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut sections = vec![];
+        if !self.structs.is_empty() {
+            let structs: Vec<String> = self
+                .structs
+                .iter()
+                .map(|(name, fields)| {
+                    let fields: Vec<String> = fields.iter().map(|(name, ty)| format!("{}: {}", name, ty)).collect();
+                    format!("struct {} {{ {} }}\n", name, fields.join(", "))
+                })
+                .collect();
+            sections.push(structs.concat());
+        }
+        if !self.strings.is_empty() {
+            let strings: Vec<String> = self.strings.iter().enumerate().map(|(index, text)| format!("@str.{} = {:?}\n", index, text)).collect();
+            sections.push(strings.concat());
+        }
+        let imports: Vec<String> = self.functions.iter().filter(|t| t.linkage == Linkage::Import).map(|t| t.to_string()).collect();
+        if !imports.is_empty() {
+            sections.push(imports.concat());
+        }
+        sections.extend(self.functions.iter().filter(|t| t.linkage != Linkage::Import).map(|t| t.to_string()));
+        write!(f, "{}", sections.join("\n"))
+    }
+}
+
+/// Where the value of a variable lives.
+#[derive(Debug, Clone)]
+enum Place {
+    // SSA variable, by index into `Lowering::variables`
+    Variable(usize),
+    // Pointer, Type of the value
+    Memory(Value, Type),
+}
+
+struct Loop {
+    next: usize,
+    end: usize,
+}
+
+/// Builds the SSA form of a function while walking its body, following Braun et al., "Simple
+/// and Efficient Construction of Static Single Assignment Form". Reads of a variable look for
+/// the last write in the block and its predecessors. Blocks whose predecessors are not all
+/// known yet are not sealed, reads there get a phi that is completed when the block is sealed.
+struct Lowering<'a, 'm> {
+    modules: &'m [Module<'a>],
+    structs: HashMap<String, Vec<(String, Type)>>,
+    strings: Vec<String>,
+    // State of the function being lowered
+    module: usize,
+    function: Function,
+    current: usize,
+    // Whether the current block already ends in a terminator
+    terminated: bool,
+    // `alloca`s at the start of the entry block
+    allocas: usize,
+    places: HashMap<*const Token, Place>,
+    // Type of each SSA variable
+    variables: Vec<Type>,
+    // Variable, Block -> its value at the end of the block
+    writes: HashMap<(usize, usize), Value>,
+    predecessors: Vec<Vec<usize>>,
+    sealed: Vec<bool>,
+    // Phis waiting for the predecessors of their block: variable, phi
+    incomplete: Vec<Vec<(usize, usize)>>,
+    loops: Vec<Loop>,
+}
+
+impl<'a, 'm> Lowering<'a, 'm> {
+    fn new(modules: &'m [Module<'a>]) -> Self {
+        let mut structs = HashMap::new();
+        for module in modules {
+            for item in &module.ast {
+                if let ASTNode::StructDef(name, fields) = item {
+                    let fields = fields
+                        .iter()
+                        .map(|(name, ty)| (name.content.clone(), Type::from_node(ty).unwrap()))
+                        .collect();
+                    structs.insert(name.content.clone(), fields);
+                }
+            }
+        }
+        Self {
+            modules,
+            structs,
+            strings: vec![],
+            module: 0,
+            function: Function { symbol: String::new(), linkage: Linkage::Internal, params: vec![], ret: Type::Void, blocks: vec![], types: vec![] },
+            current: 0,
+            terminated: false,
+            allocas: 0,
+            places: HashMap::new(),
+            variables: vec![],
+            writes: HashMap::new(),
+            predecessors: vec![],
+            sealed: vec![],
+            incomplete: vec![],
+            loops: vec![],
+        }
+    }
+
+    fn type_of(&self, node: &ASTNode) -> Type {
+        let module = &self.modules[self.module];
+        let ty = module.types.type_of(node);
+        ty.or_else(|| self.modules.iter().find_map(|t| t.types.type_of(node)))
+            .cloned()
+            .unwrap_or(Type::Void)
+    }
+
+    fn type_of_declaration(&self, name: &Token) -> Type {
+        self.modules[self.module].types.type_of_declaration(name).cloned().unwrap_or(Type::Void)
+    }
+
+    fn string(&mut self, text: &str) -> Value {
+        let index = match self.strings.iter().position(|t| t == text) {
+            Some(index) => index,
+            None => {
+                self.strings.push(text.to_string());
+                self.strings.len() - 1
+            }
+        };
+        Value::String(index)
+    }
+
+    fn temp(&mut self, ty: Type) -> usize {
+        self.function.types.push(ty);
+        self.function.types.len() - 1
+    }
+
+    fn block(&mut self) -> usize {
+        self.function.blocks.push(Block { phis: vec![], instructions: vec![], terminator: Terminator::Unreachable });
+        self.predecessors.push(vec![]);
+        self.sealed.push(false);
+        self.incomplete.push(vec![]);
+        self.function.blocks.len() - 1
+    }
+
+    fn switch_to(&mut self, block: usize) {
+        self.current = block;
+        self.terminated = false;
+    }
+
+    /// Code after a `return`, `break` or `continue` goes to a block nothing branches to.
+    fn reachable(&mut self) {
+        if self.terminated {
+            let dead = self.block();
+            self.seal(dead);
+            self.switch_to(dead);
+        }
+    }
+
+    fn push(&mut self, result: Option<usize>, op: Op) {
+        self.reachable();
+        self.function.blocks[self.current].instructions.push(Instruction { result, op });
+    }
+
+    /// Appends an instruction with a value of type `ty`.
+    fn emit(&mut self, op: Op, ty: Type) -> Value {
+        let result = self.temp(ty);
+        self.push(Some(result), op);
+        Value::Temp(result)
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.reachable();
+        for successor in terminator.successors() {
+            self.predecessors[successor].push(self.current);
+        }
+        self.function.blocks[self.current].terminator = terminator;
+        self.terminated = true;
+    }
+
+    fn jump(&mut self, target: usize) {
+        if !self.terminated {
+            self.terminate(Terminator::Jump(target));
+        }
+    }
+
+    /// Stack memory for a value of `ty`, allocated once in the entry block.
+    fn alloca(&mut self, ty: Type) -> Value {
+        let result = self.temp(Type::Pointer(Box::new(ty.clone())));
+        let instruction = Instruction { result: Some(result), op: Op::Alloca(ty) };
+        self.function.blocks[0].instructions.insert(self.allocas, instruction);
+        self.allocas += 1;
+        Value::Temp(result)
+    }
+
+    fn variable(&mut self, ty: Type) -> usize {
+        self.variables.push(ty);
+        self.variables.len() - 1
+    }
+
+    fn write(&mut self, variable: usize, block: usize, value: Value) {
+        self.writes.insert((variable, block), value);
+    }
+
+    fn read(&mut self, variable: usize, block: usize) -> Value {
+        if let Some(value) = self.writes.get(&(variable, block)) {
+            return value.clone();
+        }
+        let ty = self.variables[variable].clone();
+        let value = if !self.sealed[block] {
+            let phi = self.phi(block, ty);
+            self.incomplete[block].push((variable, phi));
+            Value::Temp(phi)
+        } else if let [predecessor] = self.predecessors[block][..] {
+            self.read(variable, predecessor)
+        } else if self.predecessors[block].is_empty() {
+            Value::Undef(ty)
+        } else {
+            // Written first, so loops through this block end at the phi
+            let phi = self.phi(block, ty);
+            self.write(variable, block, Value::Temp(phi));
+            self.fill(variable, block, phi);
+            Value::Temp(phi)
+        };
+        self.write(variable, block, value.clone());
+        value
+    }
+
+    fn phi(&mut self, block: usize, ty: Type) -> usize {
+        let result = self.temp(ty);
+        self.function.blocks[block].phis.push(Phi { result, incoming: vec![] });
+        result
+    }
+
+    fn fill(&mut self, variable: usize, block: usize, phi: usize) {
+        for predecessor in self.predecessors[block].clone() {
+            let value = self.read(variable, predecessor);
+            let phis = &mut self.function.blocks[block].phis;
+            phis.iter_mut().find(|t| t.result == phi).unwrap().incoming.push((predecessor, value));
+        }
+    }
+
+    /// All predecessors of `block` are known, its waiting phis get their operands.
+    fn seal(&mut self, block: usize) {
+        for (variable, phi) in std::mem::take(&mut self.incomplete[block]) {
+            self.fill(variable, block, phi);
+        }
+        self.sealed[block] = true;
+    }
+
+    fn load(&mut self, place: &Place) -> Value {
+        match place {
+            Place::Variable(variable) => self.read(*variable, self.current),
+            Place::Memory(pointer, ty) => self.emit(Op::Load(pointer.clone()), ty.clone()),
+        }
+    }
+
+    fn store(&mut self, place: &Place, value: Value) {
+        match place {
+            Place::Variable(variable) => {
+                self.reachable();
+                self.write(*variable, self.current, value);
+            }
+            Place::Memory(pointer, _) => self.push(None, Op::Store(value, pointer.clone())),
+        }
+    }
+
+    /// Finds the variables passed to reference parameters, they need an address.
+    fn scan(&self, node: &'m ASTNode<'a>, addressed: &mut HashSet<*const Token>) {
+        if let ASTNode::FunctionCall(name, args, _) = node {
+            let ASTNode::FunctionDef(function_name, _, _, params, ..) = self.modules[self.module].callee(name) else {
+                unreachable!()
+            };
+            let bound = bind_arguments(name, args, function_name, params).unwrap();
+            for ((_, ty, _), mut arg) in params.iter().zip(bound) {
+                if let ASTNode::NamedArgument(_, value) = arg {
+                    arg = value;
+                }
+                if let (ASTNode::ReferenceType(..), ASTNode::Identifier(name)) = (&**ty, arg) {
+                    addressed.insert(self.modules[self.module].key(name));
+                }
+            }
+        }
+        for child in node.children() {
+            self.scan(child, addressed);
+        }
+    }
+
+    /// Place of a new variable: aggregates and variables passed by reference live in memory.
+    fn declare(&mut self, name: &Token, ty: Type, addressed: bool) -> Place {
+        let place = match is_aggregate(&ty) || addressed {
+            true => Place::Memory(self.alloca(ty.clone()), ty),
+            false => Place::Variable(self.variable(ty)),
+        };
+        self.places.insert(name as *const Token, place.clone());
+        place
+    }
+
+    fn signature(params: &[Parameter<'a>]) -> Vec<Type> {
+        params
+            .iter()
+            .map(|(_, ty, _)| match &**ty {
+                ASTNode::ReferenceType(..) => Type::Pointer(Box::new(Type::from_node(ty).unwrap())),
+                ty => Type::from_node(ty).unwrap(),
+            })
+            .collect()
+    }
+
+    fn declaration(function: &ASTNode<'a>, symbol: String) -> Function {
+        let ASTNode::FunctionDef(_, _, ret, params, ..) = function else {
+            unreachable!()
+        };
+        let ret = Type::from_node(ret).unwrap();
+        let params = Self::signature(params);
+        Function { symbol, linkage: Linkage::Import, types: params.clone(), params, ret, blocks: vec![] }
+    }
+
+    fn function(&mut self, module: usize, function: &'m ASTNode<'a>, symbol: String, linkage: Linkage) -> Function {
+        let ASTNode::FunctionDef(_, _, _, params, body, _) = function else {
+            unreachable!()
+        };
+        self.module = module;
+        self.function = Self::declaration(function, symbol);
+        self.function.linkage = linkage;
+        self.allocas = 0;
+        self.places.clear();
+        self.variables.clear();
+        self.writes.clear();
+        self.predecessors.clear();
+        self.sealed.clear();
+        self.incomplete.clear();
+        let entry = self.block();
+        self.seal(entry);
+        self.switch_to(entry);
+
+        let mut addressed = HashSet::new();
+        for statement in body {
+            self.scan(statement, &mut addressed);
+        }
+        for (index, (name, ty, _)) in params.iter().enumerate() {
+            let place = match &**ty {
+                ASTNode::ReferenceType(..) => Place::Memory(Value::Temp(index), Type::from_node(ty).unwrap()),
+                _ => {
+                    let ty = self.function.params[index].clone();
+                    let place = self.declare(name, ty, addressed.contains(&(*name as *const Token)));
+                    self.store(&place, Value::Temp(index));
+                    place
+                }
+            };
+            self.places.insert(*name as *const Token, place);
+        }
+
+        let addressed = addressed;
+        for statement in body {
+            self.statement(statement, &addressed);
+        }
+        if !self.terminated {
+            match self.function.ret {
+                Type::Void => self.terminate(Terminator::Return(None)),
+                // The control-flow check makes sure other functions return
+                _ => self.terminate(Terminator::Unreachable),
+            }
+        }
+        let mut function = std::mem::replace(&mut self.function, Self::declaration(function, String::new()));
+        simplify(&mut function);
+        function
+    }
+
+    fn statement(&mut self, statement: &'m ASTNode<'a>, addressed: &HashSet<*const Token>) {
+        match statement {
+            ASTNode::VariableSet(name, value, ..) => {
+                let ty = self.type_of_declaration(name);
+                let value = value.as_ref().map(|t| self.expr(t));
+                // Calls of functions without a value are kept for their effects
+                if ty != Type::Void {
+                    let place = self.declare(name, ty, addressed.contains(&(*name as *const Token)));
+                    if let Some(value) = value {
+                        self.store(&place, value);
+                    }
+                }
+            }
+            ASTNode::Assignment(name, value) => {
+                let value = self.expr(value);
+                let place = self.places[&self.modules[self.module].key(name)].clone();
+                self.store(&place, value);
+            }
+            ASTNode::Return(value) => {
+                if self.function.ret == Type::Void {
+                    if !matches!(**value, ASTNode::Identifier(_)) {
+                        self.expr(value);
+                    }
+                    self.terminate(Terminator::Return(None));
+                    return;
+                }
+                let value = self.expr(value);
+                self.terminate(Terminator::Return(Some(value)));
+            }
+            ASTNode::Break(_) => {
+                let end = self.loops.last().unwrap().end;
+                self.terminate(Terminator::Jump(end));
+            }
+            ASTNode::Continue(_) => {
+                let next = self.loops.last().unwrap().next;
+                self.terminate(Terminator::Jump(next));
+            }
+            ASTNode::If(condition, then_body, else_body) => {
+                let condition = self.expr(condition);
+                let then = self.block();
+                let otherwise = if else_body.is_empty() { None } else { Some(self.block()) };
+                let end = self.block();
+                self.terminate(Terminator::Branch(condition, then, otherwise.unwrap_or(end)));
+                self.seal(then);
+                self.switch_to(then);
+                self.statements(then_body, addressed);
+                self.jump(end);
+                if let Some(otherwise) = otherwise {
+                    self.seal(otherwise);
+                    self.switch_to(otherwise);
+                    self.statements(else_body, addressed);
+                    self.jump(end);
+                }
+                self.seal(end);
+                self.switch_to(end);
+            }
+            ASTNode::WhileLoop(condition, body) => {
+                let top = self.block();
+                self.jump(top);
+                self.switch_to(top);
+                let condition = self.expr(condition);
+                let (then, end) = (self.block(), self.block());
+                self.terminate(Terminator::Branch(condition, then, end));
+                self.seal(then);
+                self.switch_to(then);
+                self.loop_body(body, top, end, addressed);
+                self.jump(top);
+                self.seal(top);
+                self.seal(end);
+                self.switch_to(end);
+            }
+            expr => {
+                self.expr(expr);
+            }
+        }
+    }
+
+    fn statements(&mut self, body: &'m [Box<ASTNode<'a>>], addressed: &HashSet<*const Token>) {
+        for statement in body {
+            self.statement(statement, addressed);
+        }
+    }
+
+    fn loop_body(&mut self, body: &'m [Box<ASTNode<'a>>], next: usize, end: usize, addressed: &HashSet<*const Token>) {
+        self.loops.push(Loop { next, end });
+        self.statements(body, addressed);
+        self.loops.pop();
+    }
+
+    fn expr(&mut self, node: &'m ASTNode<'a>) -> Value {
+        match node {
+            ASTNode::Literal(t) => Self::literal(t, self.type_of(node)),
+            ASTNode::String(t) => self.string(&t.content),
+            ASTNode::Identifier(name) => {
+                let place = self.places[&self.modules[self.module].key(name)].clone();
+                self.load(&place)
+            }
+            ASTNode::BinaryOp(lhs, op, rhs) => {
+                let ty = self.type_of(lhs);
+                let (lhs, rhs) = (self.expr(lhs), self.expr(rhs));
+                let binary = match op.token_type {
+                    TokenType::Plus => BinaryOp::Add,
+                    TokenType::Minus => BinaryOp::Sub,
+                    TokenType::Star => BinaryOp::Mul,
+                    TokenType::Slash => BinaryOp::Div,
+                    _ => return self.emit(Op::Compare(Self::comparison(op.token_type), lhs, rhs), Type::Bool),
+                };
+                self.emit(Op::Binary(binary, lhs, rhs), ty)
+            }
+            ASTNode::CastExpr(expr, ty) => {
+                let (from, to) = (self.type_of(expr), Type::from_node(ty).unwrap());
+                let value = self.expr(expr);
+                match from == to {
+                    true => value,
+                    false => self.emit(Op::Cast(value, to.clone()), to),
+                }
+            }
+            ASTNode::FunctionCall(name, args, _) => self.call(name, args),
+            ASTNode::NamedArgument(_, value) => self.expr(value),
+            ASTNode::BuiltinCall(builtin, _, type_arg, args, _) => {
+                let element = type_arg.as_ref().map_or(Type::Void, |t| Type::from_node(t).unwrap());
+                let mut args: Vec<Value> = args.iter().map(|t| self.expr(t)).collect();
+                let op = match builtin {
+                    Builtin::Alloc => Op::Alloc(element, args.remove(0)),
+                    Builtin::Realloc => Op::Realloc(element, args.remove(0), args.remove(0)),
+                    Builtin::Free => {
+                        self.push(None, Op::Free(args.remove(0)));
+                        return Value::Undef(Type::Void);
+                    }
+                };
+                self.emit(op, self.type_of(node))
+            }
+            ASTNode::StructLiteral(name, values) => {
+                let ty = Type::Struct(name.content.clone());
+                let fields = self.structs[&name.content].clone();
+                let memory = self.alloca(ty.clone());
+                for (field, value) in values {
+                    let index = fields.iter().position(|t| t.0 == field.content).unwrap();
+                    let value = self.expr(value);
+                    let pointer = self.emit(Op::Field(memory.clone(), index), Type::Pointer(Box::new(fields[index].1.clone())));
+                    self.push(None, Op::Store(value, pointer));
+                }
+                self.emit(Op::Load(memory), ty)
+            }
+            ASTNode::ArrayLiteral(_, elements) => {
+                let ty = self.type_of(node);
+                let Type::Array(element, _) = &ty else {
+                    unreachable!()
+                };
+                let memory = self.alloca(ty.clone());
+                for (index, value) in elements.iter().enumerate() {
+                    let value = self.expr(value);
+                    let index = Value::Int(index as i128, Type::Int(64, false));
+                    let pointer = self.emit(Op::Element(memory.clone(), index), Type::Pointer(element.clone()));
+                    self.push(None, Op::Store(value, pointer));
+                }
+                self.emit(Op::Load(memory), ty)
+            }
+            ASTNode::Index(array, index) => {
+                let element = self.type_of(node);
+                // Elements are only addressed through memory, where aggregate variables already are
+                let items = match &**array {
+                    ASTNode::Identifier(name) => match self.places[&self.modules[self.module].key(name)].clone() {
+                        Place::Memory(pointer, _) => pointer,
+                        Place::Variable(_) => unreachable!(),
+                    },
+                    array => {
+                        let value = self.expr(array);
+                        let memory = self.alloca(self.type_of(array));
+                        self.push(None, Op::Store(value, memory.clone()));
+                        memory
+                    }
+                };
+                let index = self.expr(index);
+                let pointer = self.emit(Op::Element(items, index), Type::Pointer(Box::new(element.clone())));
+                self.emit(Op::Load(pointer), element)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn comparison(op: TokenType) -> Comparison {
+        match op {
+            TokenType::DoubleEquals => Comparison::Eq,
+            TokenType::NotEquals => Comparison::Ne,
+            TokenType::Lesser => Comparison::Lt,
+            TokenType::LesserEquals => Comparison::Le,
+            TokenType::Greater => Comparison::Gt,
+            TokenType::GreaterEquals => Comparison::Ge,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Integer constants are wrapped to their type, `f32` constants are rounded to it.
+    fn literal(token: &Token, ty: Type) -> Value {
+        match (token.token_type, &ty) {
+            (TokenType::Boolean, _) => Value::Bool(token.content == "true"),
+            (_, Type::Float(bits)) => {
+                let value = token.content.parse::<f64>().unwrap();
+                Value::Float(if *bits == 32 { value as f32 as f64 } else { value }, ty)
+            }
+            (_, Type::Int(bits, signed)) => {
+                let shift = 128 - *bits as u32;
+                let value = token.content.parse::<i128>().unwrap() << shift;
+                let value = if *signed { value >> shift } else { ((value as u128) >> shift) as i128 };
+                Value::Int(value, ty)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Pointer a reference parameter receives for `arg`. Values that are not variables are
+    /// stored in a temporary first.
+    fn reference(&mut self, arg: &'m ASTNode<'a>) -> Value {
+        match arg {
+            ASTNode::NamedArgument(_, value) => self.reference(value),
+            ASTNode::Identifier(name) => match &self.places[&self.modules[self.module].key(name)] {
+                Place::Memory(pointer, _) => pointer.clone(),
+                Place::Variable(_) => unreachable!("variables passed by reference are in memory"),
+            },
+            arg => {
+                let ty = self.type_of(arg);
+                let value = self.expr(arg);
+                let memory = self.alloca(ty);
+                self.push(None, Op::Store(value, memory.clone()));
+                memory
+            }
+        }
+    }
+
+    fn call(&mut self, name: &'a Token, args: &'m [Box<ASTNode<'a>>]) -> Value {
+        let function = self.modules[self.module].callee(name);
+        let ASTNode::FunctionDef(function_name, _, ret, params, ..) = function else {
+            unreachable!()
+        };
+        let symbol = function.symbol_name(&self.modules[self.module].name).unwrap();
+        let ret = Type::from_node(ret).unwrap();
+        let bound = bind_arguments(name, args, function_name, params).unwrap();
+        let mut values = vec![];
+        for ((_, ty, _), arg) in params.iter().zip(bound) {
+            values.push(match **ty {
+                ASTNode::ReferenceType(..) => self.reference(arg),
+                _ => self.expr(arg),
+            });
+        }
+        if ret == Type::Void {
+            self.push(None, Op::Call(symbol, values));
+            return Value::Undef(Type::Void);
+        }
+        self.emit(Op::Call(symbol, values), ret)
+    }
+}
+
+/// Every operand of `block`, with the instruction position that uses it: phis use their
+/// operands at the end of the predecessor, the terminator comes after the instructions.
+fn operands(block: &Block) -> Vec<(usize, &Value)> {
+    let mut operands = vec![];
+    let position = block.phis.len();
+    for (index, instruction) in block.instructions.iter().enumerate() {
+        let values = match &instruction.op {
+            Op::Alloca(_) => vec![],
+            Op::Load(value) | Op::Field(value, _) | Op::Cast(value, _) | Op::Alloc(_, value) | Op::Free(value) => vec![value],
+            Op::Store(lhs, rhs) | Op::Element(lhs, rhs) | Op::Binary(_, lhs, rhs) | Op::Compare(_, lhs, rhs) | Op::Realloc(_, lhs, rhs) => {
+                vec![lhs, rhs]
+            }
+            Op::Call(_, args) => args.iter().collect(),
+        };
+        operands.extend(values.into_iter().map(|t| (position + index, t)));
+    }
+    match &block.terminator {
+        Terminator::Branch(value, ..) | Terminator::Return(Some(value)) => operands.push((usize::MAX, value)),
+        _ => {}
+    }
+    operands
+}
+
+fn operands_mut(block: &mut Block) -> Vec<&mut Value> {
+    let mut operands: Vec<&mut Value> = vec![];
+    for phi in &mut block.phis {
+        operands.extend(phi.incoming.iter_mut().map(|t| &mut t.1));
+    }
+    for instruction in &mut block.instructions {
+        match &mut instruction.op {
+            Op::Alloca(_) => {}
+            Op::Load(value) | Op::Field(value, _) | Op::Cast(value, _) | Op::Alloc(_, value) | Op::Free(value) => operands.push(value),
+            Op::Store(lhs, rhs) | Op::Element(lhs, rhs) | Op::Binary(_, lhs, rhs) | Op::Compare(_, lhs, rhs) | Op::Realloc(_, lhs, rhs) => {
+                operands.push(lhs);
+                operands.push(rhs);
+            }
+            Op::Call(_, args) => operands.extend(args.iter_mut()),
+        }
+    }
+    match &mut block.terminator {
+        Terminator::Branch(value, ..) | Terminator::Return(Some(value)) => operands.push(value),
+        _ => {}
+    }
+    operands
+}
+
+/// Blocks reachable from the entry in reverse postorder.
+fn reverse_postorder(function: &Function) -> Vec<usize> {
+    let (mut order, mut visited, mut stack) = (vec![], vec![false; function.blocks.len()], vec![(0, 0)]);
+    visited[0] = true;
+    while let Some((block, next)) = stack.pop() {
+        // Visited last, so the first successor comes first in the order
+        let mut successors = function.blocks[block].terminator.successors();
+        successors.reverse();
+        match successors.get(next) {
+            Some(&successor) => {
+                stack.push((block, next + 1));
+                if !visited[successor] {
+                    visited[successor] = true;
+                    stack.push((successor, 0));
+                }
+            }
+            None => order.push(block),
+        }
+    }
+    order.reverse();
+    order
+}
+
+/// Drops the blocks control never reaches and the phis that select a single value, then
+/// numbers blocks in reverse postorder and temps in the order they are defined.
+fn simplify(function: &mut Function) {
+    let order = reverse_postorder(function);
+    let mut numbers = vec![None; function.blocks.len()];
+    for (number, block) in order.iter().enumerate() {
+        numbers[*block] = Some(number);
+    }
+    let mut blocks: Vec<Block> = order.iter().map(|t| function.blocks[*t].clone()).collect();
+    for block in &mut blocks {
+        for phi in &mut block.phis {
+            phi.incoming.retain(|t| numbers[t.0].is_some());
+            for incoming in &mut phi.incoming {
+                incoming.0 = numbers[incoming.0].unwrap();
+            }
+        }
+        block.terminator = match &block.terminator {
+            Terminator::Jump(target) => Terminator::Jump(numbers[*target].unwrap()),
+            Terminator::Branch(condition, then, otherwise) => {
+                Terminator::Branch(condition.clone(), numbers[*then].unwrap(), numbers[*otherwise].unwrap())
+            }
+            terminator => terminator.clone(),
+        };
+    }
+
+    // A phi whose operands are one value and itself is that value
+    let mut replaced: HashMap<usize, Value> = HashMap::new();
+    let resolve = |replaced: &HashMap<usize, Value>, mut value: Value| {
+        while let Value::Temp(temp) = value {
+            match replaced.get(&temp) {
+                Some(replacement) => value = replacement.clone(),
+                None => break,
+            }
+        }
+        value
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in &mut blocks {
+            let mut kept = vec![];
+            for phi in std::mem::take(&mut block.phis) {
+                let mut values = vec![];
+                for (_, value) in &phi.incoming {
+                    let value = resolve(&replaced, value.clone());
+                    if value != Value::Temp(phi.result) && !values.contains(&value) {
+                        values.push(value);
+                    }
+                }
+                match values.len() {
+                    0 => {
+                        replaced.insert(phi.result, Value::Undef(function.types[phi.result].clone()));
+                        changed = true;
+                    }
+                    1 => {
+                        replaced.insert(phi.result, values.remove(0));
+                        changed = true;
+                    }
+                    _ => kept.push(phi),
+                }
+            }
+            block.phis = kept;
+        }
+    }
+
+    let mut temps: HashMap<usize, usize> = (0..function.params.len()).map(|t| (t, t)).collect();
+    let mut types = function.types[..function.params.len()].to_vec();
+    for block in &mut blocks {
+        let results = block.phis.iter_mut().map(|t| &mut t.result).chain(block.instructions.iter_mut().filter_map(|t| t.result.as_mut()));
+        for result in results {
+            temps.insert(*result, types.len());
+            types.push(function.types[*result].clone());
+            *result = types.len() - 1;
+        }
+    }
+    for block in &mut blocks {
+        for operand in operands_mut(block) {
+            *operand = match resolve(&replaced, operand.clone()) {
+                Value::Temp(temp) => Value::Temp(temps[&temp]),
+                value => value,
+            };
+        }
+    }
+    function.blocks = blocks;
+    function.types = types;
+}
+
+/// Checks the rules the lowering has to follow in one function.
+struct Verifier<'p> {
+    program: &'p Program,
+    function: &'p Function,
+}
+
+impl<'p> Verifier<'p> {
+    fn type_of(&self, value: &Value) -> Result<Type, String> {
+        Ok(match value {
+            Value::Temp(temp) => self.function.types.get(*temp).cloned().ok_or_else(|| format!("%{} has no type", temp))?,
+            Value::Int(_, ty @ Type::Int(..)) | Value::Float(_, ty @ Type::Float(_)) | Value::Undef(ty) => ty.clone(),
+            Value::Int(..) | Value::Float(..) => return Err(format!("`{}` has the wrong type", value)),
+            Value::Bool(_) => Type::Bool,
+            Value::String(index) if *index < self.program.strings.len() => Type::Str,
+            Value::String(index) => return Err(format!("There is no string @str.{}", index)),
+        })
+    }
+
+    fn pointee(&self, value: &Value) -> Result<Type, String> {
+        match self.type_of(value)? {
+            Type::Pointer(pointee) => Ok(*pointee),
+            ty => Err(format!("`{}` is a {}, not a pointer", value, ty)),
+        }
+    }
+
+    fn integer(&self, value: &Value) -> Result<(), String> {
+        match self.type_of(value)? {
+            Type::Int(..) => Ok(()),
+            ty => Err(format!("`{}` is a {}, not an integer", value, ty)),
+        }
+    }
+
+    fn same(&self, lhs: &Value, rhs: &Value) -> Result<Type, String> {
+        let (left, right) = (self.type_of(lhs)?, self.type_of(rhs)?);
+        match left == right {
+            true => Ok(left),
+            false => Err(format!("`{}` is a {} but `{}` is a {}", lhs, left, rhs, right)),
+        }
+    }
+
+    /// Type of the value of `op`, void if it has none.
+    fn check(&self, op: &Op) -> Result<Type, String> {
+        Ok(match op {
+            Op::Alloca(ty) => Type::Pointer(Box::new(ty.clone())),
+            Op::Load(pointer) => self.pointee(pointer)?,
+            Op::Store(value, pointer) => {
+                let (ty, pointee) = (self.type_of(value)?, self.pointee(pointer)?);
+                if ty != pointee {
+                    return Err(format!("Stores a {} through a pointer to {}", ty, pointee));
+                }
+                Type::Void
+            }
+            Op::Field(pointer, index) => {
+                let pointee = self.pointee(pointer)?;
+                let fields = match &pointee {
+                    Type::Struct(name) => self.program.fields(name).ok_or_else(|| format!("There is no struct {}", name))?,
+                    ty => return Err(format!("`{}` points to a {}, not a struct", pointer, ty)),
+                };
+                let (_, ty) = fields.get(*index).ok_or_else(|| format!("{} has no field {}", pointee, index))?;
+                Type::Pointer(Box::new(ty.clone()))
+            }
+            Op::Element(pointer, index) => {
+                self.integer(index)?;
+                match self.pointee(pointer)? {
+                    Type::Array(element, _) => Type::Pointer(element),
+                    ty => return Err(format!("`{}` points to a {}, not an array", pointer, ty)),
+                }
+            }
+            Op::Binary(_, lhs, rhs) => match self.same(lhs, rhs)? {
+                ty if ty.is_numeric() => ty,
+                ty => return Err(format!("Arithmetic on {}", ty)),
+            },
+            Op::Compare(op, lhs, rhs) => match self.same(lhs, rhs)? {
+                ty if ty.is_numeric() => Type::Bool,
+                ty if matches!(op, Comparison::Eq | Comparison::Ne) && !is_aggregate(&ty) && ty != Type::Void => Type::Bool,
+                ty => return Err(format!("Compares values of type {}", ty)),
+            },
+            Op::Cast(value, to) => match self.type_of(value)?.cast_kind(to) {
+                CastKind::Invalid => return Err(format!("Casts `{}` to {}", value, to)),
+                _ => to.clone(),
+            },
+            Op::Call(symbol, args) => {
+                let callee = self.program.functions.iter().find(|t| t.symbol == *symbol);
+                let callee = callee.ok_or_else(|| format!("There is no function @{}", symbol))?;
+                if callee.params.len() != args.len() {
+                    return Err(format!("@{} takes {} arguments, not {}", symbol, callee.params.len(), args.len()));
+                }
+                for (param, arg) in callee.params.iter().zip(args) {
+                    if self.type_of(arg)? != *param {
+                        return Err(format!("@{} takes a {}, not `{}`", symbol, param, arg));
+                    }
+                }
+                callee.ret.clone()
+            }
+            Op::Alloc(ty, count) => {
+                self.integer(count)?;
+                Type::Pointer(Box::new(ty.clone()))
+            }
+            Op::Realloc(ty, pointer, count) => {
+                self.integer(count)?;
+                if self.pointee(pointer)? != *ty {
+                    return Err(format!("Reallocates `{}` as {}", pointer, ty));
+                }
+                Type::Pointer(Box::new(ty.clone()))
+            }
+            Op::Free(pointer) => {
+                self.pointee(pointer)?;
+                Type::Void
+            }
+        })
+    }
+
+    /// Immediate dominators of the reachable blocks, by Cooper, Harvey and Kennedy,
+    /// "A Simple, Fast Dominance Algorithm".
+    fn dominators(&self, order: &[usize], predecessors: &[Vec<usize>]) -> Vec<Option<usize>> {
+        let mut position = vec![usize::MAX; self.function.blocks.len()];
+        for (index, block) in order.iter().enumerate() {
+            position[*block] = index;
+        }
+        let mut dominators = vec![None; self.function.blocks.len()];
+        dominators[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &order[1..] {
+                let mut dominator = None;
+                for &predecessor in &predecessors[block] {
+                    if dominators[predecessor].is_none() {
+                        continue;
+                    }
+                    dominator = Some(match dominator {
+                        None => predecessor,
+                        Some(mut other) => {
+                            let mut finger = predecessor;
+                            while finger != other {
+                                while position[finger] > position[other] {
+                                    finger = dominators[finger].unwrap();
+                                }
+                                while position[other] > position[finger] {
+                                    other = dominators[other].unwrap();
+                                }
+                            }
+                            finger
+                        }
+                    });
+                }
+                if dominator.is_some() && dominators[block] != dominator {
+                    dominators[block] = dominator;
+                    changed = true;
+                }
+            }
+        }
+        dominators
+    }
+
+    fn verify(&self) -> Result<(), String> {
+        let function = self.function;
+        if function.linkage == Linkage::Import {
+            return match function.blocks.is_empty() {
+                true => Ok(()),
+                false => Err("Imported functions have no blocks".to_string()),
+            };
+        }
+        if function.blocks.is_empty() {
+            return Err("There is no entry block".to_string());
+        }
+        if function.types.get(..function.params.len()) != Some(&function.params[..]) {
+            return Err("The parameter types don't match the signature".to_string());
+        }
+
+        // Every temp is defined once, at a block and a position in it
+        let mut definitions: HashMap<usize, (usize, usize)> = HashMap::new();
+        for temp in 0..function.params.len() {
+            definitions.insert(temp, (0, 0));
+        }
+        let mut predecessors = vec![vec![]; function.blocks.len()];
+        for (index, block) in function.blocks.iter().enumerate() {
+            let results = block.phis.iter().map(|t| Some(t.result)).chain(block.instructions.iter().map(|t| t.result));
+            for (position, result) in results.enumerate() {
+                let Some(result) = result else {
+                    continue;
+                };
+                if result >= function.types.len() {
+                    return Err(format!("%{} has no type", result));
+                }
+                if definitions.insert(result, (index, position)).is_some() {
+                    return Err(format!("%{} is defined twice", result));
+                }
+            }
+            for successor in block.terminator.successors() {
+                if successor >= function.blocks.len() {
+                    return Err(format!("b{} branches to the missing block b{}", index, successor));
+                }
+                predecessors[successor].push(index);
+            }
+        }
+
+        for (index, block) in function.blocks.iter().enumerate() {
+            let error = |e: String| format!("b{}: {}", index, e);
+            for phi in &block.phis {
+                let mut sources: Vec<usize> = phi.incoming.iter().map(|t| t.0).collect();
+                sources.sort();
+                let mut expected = predecessors[index].clone();
+                expected.sort();
+                if sources != expected {
+                    return Err(error(format!("The phi %{} doesn't have one value per predecessor", phi.result)));
+                }
+                for (_, value) in &phi.incoming {
+                    if self.type_of(value).map_err(error)? != function.types[phi.result] {
+                        return Err(error(format!("The phi %{} selects `{}` of another type", phi.result, value)));
+                    }
+                }
+            }
+            for instruction in &block.instructions {
+                let ty = self.check(&instruction.op).map_err(|e| error(format!("`{}`: {}", instruction.op, e)))?;
+                match (instruction.result, ty) {
+                    (None, Type::Void) => {}
+                    (Some(result), ty) if ty != Type::Void && function.types[result] == ty => {}
+                    (_, ty) => return Err(error(format!("`{}` has a value of type {}", instruction.op, ty))),
+                }
+                if matches!(instruction.op, Op::Alloca(_)) && index != 0 {
+                    return Err(error("`alloca` is only allowed in the entry block".to_string()));
+                }
+            }
+            match &block.terminator {
+                Terminator::Branch(condition, ..) if self.type_of(condition).map_err(error)? != Type::Bool => {
+                    return Err(error(format!("Branches on `{}`, which is not a bool", condition)));
+                }
+                Terminator::Return(value) => {
+                    let ty = match value {
+                        Some(value) => self.type_of(value).map_err(error)?,
+                        None => Type::Void,
+                    };
+                    if ty != function.ret {
+                        return Err(error(format!("Returns {} from a function returning {}", ty, function.ret)));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Definitions dominate their uses, blocks control never reaches are not checked
+        let order = reverse_postorder(function);
+        let dominators = self.dominators(&order, &predecessors);
+        let dominates = |dominator: usize, mut block: usize| loop {
+            if block == dominator {
+                return true;
+            }
+            match dominators[block] {
+                Some(parent) if parent != block => block = parent,
+                _ => return false,
+            }
+        };
+        for &index in &order {
+            let block = &function.blocks[index];
+            let mut uses: Vec<(usize, usize, &Value)> = operands(block).into_iter().map(|(position, value)| (index, position, value)).collect();
+            for phi in &block.phis {
+                uses.extend(phi.incoming.iter().map(|(predecessor, value)| (*predecessor, usize::MAX, value)));
+            }
+            for (user, position, value) in uses {
+                let Value::Temp(temp) = value else {
+                    continue;
+                };
+                let Some(&(block, defined)) = definitions.get(temp) else {
+                    return Err(format!("b{}: %{} is never defined", index, temp));
+                };
+                let before = block != user || defined < position || *temp < function.params.len();
+                if !dominates(block, user) || !before {
+                    return Err(format!("b{}: %{} is used where its definition doesn't dominate", index, temp));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that `program` is well formed: blocks end in a terminator that branches to blocks of
+/// the function, phis have one value per predecessor, definitions dominate their uses and the
+/// operands of every instruction have the types it expects.
+pub fn verify(program: &Program) -> Result<(), String> {
+    for function in &program.functions {
+        let verifier = Verifier { program, function };
+        verifier.verify().map_err(|e| format!("@{}: {}", function.symbol, e))?;
+    }
+    Ok(())
+}
+
+/// SSA form of the checked `modules`. Export functions and the entry are external, functions
+/// without a body are imported.
+pub fn lower(modules: &[Module], entry: &EntryPoint) -> Program {
+    let mut lowering = Lowering::new(modules);
+    let mut defined = HashSet::new();
+    for module in modules {
+        for item in &module.ast {
+            if let ASTNode::FunctionDef(_, mode, ..) = item {
+                if *mode != FunctionMode::Extern {
+                    defined.insert(item.symbol_name(&module.name).unwrap());
+                }
+            }
+        }
+    }
+
+    let (mut functions, mut imports) = (vec![], vec![]);
+    for (index, module) in modules.iter().enumerate() {
+        for item in &module.ast {
+            let ASTNode::FunctionDef(_, mode, ..) = item else {
+                continue;
+            };
+            let symbol = item.symbol_name(&module.name).unwrap();
+            // Externs defined in another module are already there
+            if *mode == FunctionMode::Extern {
+                if !defined.contains(&symbol) && !imports.iter().any(|t: &Function| t.symbol == symbol) {
+                    imports.push(Lowering::declaration(item, symbol));
+                }
+                continue;
+            }
+            let linkage = match mode {
+                FunctionMode::Export => Linkage::External,
+                _ if symbol == entry.symbol => Linkage::External,
+                _ => Linkage::Internal,
+            };
+            functions.push(lowering.function(index, item, symbol, linkage));
+        }
+    }
+
+    let mut structs: Vec<(String, Vec<(String, Type)>)> = lowering.structs.into_iter().collect();
+    structs.sort_by(|a, b| a.0.cmp(&b.0));
+    imports.extend(functions);
+    Program { structs, strings: lowering.strings, functions: imports }
+}
+
+/// Textual IR of the checked `modules`. The IR is verified first, an error there is a bug in
+/// the lowering.
+pub fn generate(modules: &[Module], entry: &EntryPoint) -> String {
+    let program = lower(modules, entry);
+    if let Err(error) = verify(&program) {
+        panic!("Invalid IR: {}", error);
+    }
+    program.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::entry_point;
+    use crate::testing::check_module_of;
+
+    /// Expected output for `tests/golden/program.sila`, `SILA_BLESS=1` rewrites it.
+    const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/program.ir");
+
+    fn lower_source(source: &str) -> Program {
+        let modules = [check_module_of(source).unwrap_or_else(|e| panic!("{}: {}", e.title, e.footer))];
+        let entry = entry_point(&modules[0].ast, &modules[0].name, None).unwrap();
+        let program = lower(&modules, &entry);
+        verify(&program).unwrap();
+        program
+    }
+
+    #[test]
+    fn matches_the_golden_file() {
+        let ir = lower_source(include_str!("../tests/golden/program.sila")).to_string();
+        if std::env::var_os("SILA_BLESS").is_some() {
+            std::fs::write(GOLDEN, &ir).unwrap();
+        }
+        assert_eq!(ir, std::fs::read_to_string(GOLDEN).unwrap());
+    }
+
+    /// Scalar variables become phis at the loop header instead of stack memory.
+    #[test]
+    fn joins_variables_with_phis() {
+        let program = lower_source("def main(): i32 {\n    let mut i = 0;\n    while i < 10 {\n        i = i + 1;\n    }\n    return i;\n}");
+        assert_eq!(
+            program.to_string(),
+            "export fn @test__main() -> i32 {\nb0:\n    jmp b1\nb1:\n    %0: i32 = phi [b0: i32 0], [b2: %2]\n    \
+             %1: bool = lt %0, i32 10\n    br %1, b2, b3\nb2:\n    %2: i32 = add %0, i32 1\n    jmp b1\nb3:\n    ret %0\n}\n"
+        );
+    }
+
+    /// A variable passed to a reference parameter needs an address, so it stays in memory.
+    #[test]
+    fn keeps_referenced_variables_in_memory() {
+        let program = lower_source(
+            "def bump(x: &mut i32): void {\n    x = x + 1;\n}\n\n\
+             def main(): i32 {\n    let mut x = 1;\n    bump(x);\n    return x;\n}",
+        );
+        let main = program.functions.iter().find(|t| t.symbol == "test__main").unwrap();
+        assert_eq!(main.blocks[0].instructions[0].op, Op::Alloca(Type::Int(32, true)));
+        assert_eq!(main.blocks[0].instructions[2].op, Op::Call("test__bump".to_string(), vec![Value::Temp(0)]));
+        let bump = program.functions.iter().find(|t| t.symbol == "test__bump").unwrap();
+        assert_eq!(bump.params, [Type::Pointer(Box::new(Type::Int(32, true)))]);
+    }
+
+    #[test]
+    fn rejects_mismatched_types() {
+        let mut program = lower_source("def main(): i32 {\n    let x = 2;\n    return x + 1;\n}");
+        let Op::Binary(_, _, rhs) = &mut program.functions[0].blocks[0].instructions[0].op else {
+            unreachable!()
+        };
+        *rhs = Value::Int(1, Type::Int(64, true));
+        assert_eq!(verify(&program).unwrap_err(), "@test__main: b0: `add i32 2, i64 1`: `i32 2` is a i32 but `i64 1` is a i64");
+    }
+
+    #[test]
+    fn rejects_uses_before_definitions() {
+        let mut program = lower_source(
+            "def main(): i32 {\n    let mut x = 0;\n    if x < 3 {\n        x = x + 1;\n    }\n    return x;\n}",
+        );
+        // The sum is defined in the `then` block, which doesn't dominate the return
+        let last = program.functions[0].blocks.len() - 1;
+        program.functions[0].blocks[last].terminator = Terminator::Return(Some(Value::Temp(1)));
+        assert_eq!(verify(&program).unwrap_err(), format!("@test__main: b{}: %1 is used where its definition doesn't dominate", last));
+    }
+
+    /// Every construct the backends support lowers to IR that verifies.
+    #[test]
+    fn lowers_every_construct() {
+        lower_source(
+            "def extern puts(s: str): i32 {\n}\n\nstruct Pair {\n    a: i64,\n    b: f64\n}\n\n\
+            def swap(x: &mut i32, y: &mut i32): void {\n    let t = x;\n    x = y;\n    y = t;\n}\n\n\
+            def total(values: [i32; 5], pair: Pair): [i32; 2] {\n    let mut sum = 0;\n    for v in values {\n        sum = sum + v;\n    }\n    \
+            return [sum, 7];\n}\n\n\
+            def @unsafe main(): i32 {\n    let mut x = 1;\n    let mut y = 2;\n    swap(x, y);\n    \
+            let p = alloc<Pair>(4);\n    let q = realloc<Pair>(p, 8);\n    free(q);\n    \
+            let mut count: u8 = 0;\n    for i in 250..=255 {\n        count = count + 1;\n        if i == 252 {\n            break;\n        }\n    }\n    \
+            let mut t = 0;\n    for v in total([1, 2, 3, 4, 5], Pair { a: 1, b: 2.75 }) {\n        t = t + v;\n    }\n    \
+            let mut w = 0;\n    while w < 10 {\n        w = w + 3;\n        if w == 6 {\n            continue;\n        }\n    }\n    \
+            puts(\"done\");\n    let f: f32 = 7.0;\n    \
+            return x * 10 + y + (count -> i32) + t + w + ((f / 2.0 > 3.0) -> i32);\n}",
+        );
+    }
+}
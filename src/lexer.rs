@@ -1,7 +1,6 @@
 use crate::comp_errors::{CodeError, CodeResult};
 use std::fmt;
 use std::fmt::{Display, Formatter};
-use std::ops::Range;
 
 #[derive(PartialEq, Copy, Debug, Clone)]
 pub enum TokenType {
@@ -11,17 +10,30 @@ pub enum TokenType {
     Import,
     Extern,
     Mut,
+    For,
+    In,
+    While,
+    Let,
+    Struct,
+    If,
+    Else,
+    Break,
+    Continue,
+    Const,
 
     Identifier,
 
     String,
     NumberInt,
     NumberFloat,
+    Boolean,
 
     LParen,
     RParen,
     Comma,
     Dot,
+    DoubleDot,
+    DoubleDotEquals,
     Plus,
     Minus,
     Slash,
@@ -40,6 +52,9 @@ pub enum TokenType {
     LesserEquals,
     RBrace,
     LBrace,
+    LBracket,
+    RBracket,
+    At,
     As,
     Ref,
     Private,
@@ -58,14 +73,27 @@ impl TokenType {
             TokenType::Import => "import",
             TokenType::Extern => "extern",
             TokenType::Mut => "mut",
+            TokenType::For => "for",
+            TokenType::In => "in",
+            TokenType::While => "while",
+            TokenType::Let => "let",
+            TokenType::Struct => "struct",
+            TokenType::If => "if",
+            TokenType::Else => "else",
+            TokenType::Break => "break",
+            TokenType::Continue => "continue",
+            TokenType::Const => "const",
             TokenType::Identifier => "Identifier",
             TokenType::String => "String",
             TokenType::NumberInt => "Integer",
             TokenType::NumberFloat => "Floating-point",
+            TokenType::Boolean => "Boolean",
             TokenType::LParen => "(",
             TokenType::RParen => ")",
             TokenType::Comma => ",",
             TokenType::Dot => ".",
+            TokenType::DoubleDot => "..",
+            TokenType::DoubleDotEquals => "..=",
             TokenType::Plus => "+",
             TokenType::Minus => "-",
             TokenType::Slash => "/",
@@ -75,7 +103,7 @@ impl TokenType {
             TokenType::Greater => ">",
             TokenType::Lesser => "<",
             TokenType::Pipe => "|",
-            TokenType::And => "&",
+            TokenType::And => "&&",
             TokenType::Ref => "&",
             TokenType::Exclamation => "!",
             TokenType::Equals => "=",
@@ -85,6 +113,9 @@ impl TokenType {
             TokenType::LesserEquals => "<=",
             TokenType::RBrace => "}",
             TokenType::LBrace => "{",
+            TokenType::LBracket => "[",
+            TokenType::RBracket => "]",
+            TokenType::At => "@",
             TokenType::As => "->",
             TokenType::Private => "private",
             TokenType::Return => "return",
@@ -134,17 +165,6 @@ impl CodePosition {
         }
     }
 
-    pub fn is_eof(&self) -> bool {
-        [
-            self.idx_start,
-            self.line_start,
-            self.line_idx_start,
-            self.line_idx_end,
-        ]
-        .iter()
-        .all(|t| *t == 0)
-    }
-
     pub fn merge(&self, other: Self) -> Self {
         Self {
             idx_start: self.idx_start,
@@ -157,12 +177,6 @@ impl CodePosition {
     }
 }
 
-impl CodePosition {
-    pub fn range(&self, offset: usize) -> Range<usize> {
-        self.line_idx_start + offset..self.line_idx_end + offset
-    }
-}
-
 #[derive(Debug)]
 pub struct Token {
     pub content: String,
@@ -184,6 +198,16 @@ impl Token {
             code_position: CodePosition::one_char(idx, line, line_idx),
         }
     }
+
+    /// Token created by the compiler, e.g. for a folded constant.
+    /// It lives for the rest of the compilation and points at the code it stands for.
+    pub fn synthetic(content: String, token_type: TokenType, code_position: CodePosition) -> &'static Token {
+        Box::leak(Box::new(Token {
+            content,
+            token_type,
+            code_position,
+        }))
+    }
 }
 
 pub struct Scanner {
@@ -209,6 +233,11 @@ impl Scanner {
         self.characters.get(self.cursor)
     }
 
+    /// Returns the character after the next one without advancing the cursor.
+    pub fn peek_next(&self) -> Option<&char> {
+        self.characters.get(self.cursor + 1)
+    }
+
     /// Returns true if further progress is not possible.
     pub fn is_done(&self) -> bool {
         self.cursor == self.characters.len()
@@ -231,13 +260,6 @@ impl Scanner {
         }
     }
 
-    pub fn current(&self) -> Option<&char> {
-        match self.characters.get(self.cursor) {
-            Some(character) => Some(character),
-            None => None,
-        }
-    }
-
     pub fn previous(&self) -> Option<&char> {
         match self.characters.get(self.cursor - 1) {
             Some(character) => Some(character),
@@ -247,17 +269,7 @@ impl Scanner {
 
     pub fn this_as_token(&self, token_type: TokenType) -> Option<Token> {
         let c = self.previous();
-        if c.is_none() {
-            None
-        } else {
-            Some(Token::from_one(
-                self.cursor,
-                self.line,
-                self.line_idx,
-                *c.unwrap(),
-                token_type,
-            ))
-        }
+        c.map(|c| Token::from_one(self.cursor, self.line, self.line_idx, *c, token_type))
     }
 
     pub fn this_as_codepos(&self) -> Option<CodePosition> {
@@ -305,12 +317,38 @@ fn tokenizer(scanner: &mut Scanner) -> CodeResult<Option<Token>> {
                 }
             }
 
-            '(' | ')' | ',' | '.' | '+' | '/' | '*' | ':' | ';' | '{' | '}' => {
+            '.' => {
+                let start_pos = scanner.cursor;
+                scanner.pop();
+                if let Some('.') = scanner.peek() {
+                    scanner.pop();
+                    let token_type = if let Some('=') = scanner.peek() {
+                        scanner.pop();
+                        TokenType::DoubleDotEquals
+                    } else {
+                        TokenType::DoubleDot
+                    };
+                    let width = scanner.cursor - start_pos;
+                    return Ok(Some(Token {
+                        content: token_type.visualize(),
+                        token_type,
+                        code_position: CodePosition {
+                            idx_start: start_pos,
+                            idx_end: scanner.cursor,
+                            line_start: scanner.line,
+                            line_end: scanner.line,
+                            line_idx_start: scanner.line_idx - width,
+                            line_idx_end: scanner.line_idx,
+                        },
+                    }));
+                }
+                return Ok(scanner.this_as_token(TokenType::Dot));
+            }
+            '(' | ')' | ',' | '+' | '/' | '*' | ':' | ';' | '{' | '}' | '[' | ']' | '@' => {
                 let token_type = match current {
                     '(' => TokenType::LParen,
                     ')' => TokenType::RParen,
                     ',' => TokenType::Comma,
-                    '.' => TokenType::Dot,
                     '+' => TokenType::Plus,
                     '/' => TokenType::Slash,
                     '*' => TokenType::Star,
@@ -319,6 +357,9 @@ fn tokenizer(scanner: &mut Scanner) -> CodeResult<Option<Token>> {
                     ';' => TokenType::SemiColon,
                     '{' => TokenType::LBrace,
                     '}' => TokenType::RBrace,
+                    '[' => TokenType::LBracket,
+                    ']' => TokenType::RBracket,
+                    '@' => TokenType::At,
                     _ => unreachable!(),
                 };
                 scanner.pop();
@@ -328,9 +369,9 @@ fn tokenizer(scanner: &mut Scanner) -> CodeResult<Option<Token>> {
                 scanner.pop();
                 if let Some('&') = scanner.peek() {
                     scanner.pop();
-                    return Ok(scanner.this_as_token(TokenType::Ref));
+                    return Ok(scanner.this_as_token(TokenType::And));
                 }
-                return Ok(scanner.this_as_token(TokenType::And));
+                return Ok(scanner.this_as_token(TokenType::Ref));
             }
             '-' => {
                 scanner.pop();
@@ -394,6 +435,17 @@ fn tokenizer(scanner: &mut Scanner) -> CodeResult<Option<Token>> {
                     "mut" => TokenType::Mut,
                     "private" => TokenType::Private,
                     "return" => TokenType::Return,
+                    "for" => TokenType::For,
+                    "in" => TokenType::In,
+                    "while" => TokenType::While,
+                    "let" => TokenType::Let,
+                    "struct" => TokenType::Struct,
+                    "if" => TokenType::If,
+                    "else" => TokenType::Else,
+                    "break" => TokenType::Break,
+                    "continue" => TokenType::Continue,
+                    "const" => TokenType::Const,
+                    "true" | "false" => TokenType::Boolean,
                     _ => TokenType::Identifier,
                 };
                 return Ok(Some(Token {
@@ -411,13 +463,18 @@ fn tokenizer(scanner: &mut Scanner) -> CodeResult<Option<Token>> {
             }
 
             // Numbers
-            c if c.is_digit(10) => {
+            c if c.is_ascii_digit() => {
                 let start_pos = scanner.cursor;
                 let mut is_float = false;
                 while let Some(next) = scanner.peek() {
-                    if next.is_digit(10) {
+                    if next.is_ascii_digit() {
                         scanner.pop();
-                    } else if *next == '.' && !is_float {
+                    } else if *next == '.'
+                        && !is_float
+                        && scanner.peek_next().is_some_and(|t| t.is_ascii_digit())
+                    {
+                        // A dot only continues the number if a digit follows,
+                        // otherwise `0..n` would lex as `0.` `.` `n`
                         is_float = true;
                         scanner.pop();
                     } else {
@@ -489,11 +546,52 @@ pub fn tokenize(content: String) -> CodeResult<Vec<Token>> {
     let mut scanner = Scanner::new(content.as_str());
     let mut tokens: Vec<Token> = vec![];
     loop {
-        let token = tokenizer(&mut scanner)?;
-        if token.is_some() {
-            tokens.push(token.unwrap())
-        } else {
-            return Ok(tokens);
+        match tokenizer(&mut scanner)? {
+            Some(token) => tokens.push(token),
+            None => return Ok(tokens),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(source: &str) -> Vec<(TokenType, String)> {
+        tokenize(source.to_string()).unwrap().into_iter().map(|t| (t.token_type, t.content)).collect()
+    }
+
+    #[test]
+    fn ranges_after_integers_are_not_floats() {
+        assert_eq!(
+            lex("0..n"),
+            [
+                (TokenType::NumberInt, "0".to_string()),
+                (TokenType::DoubleDot, "..".to_string()),
+                (TokenType::Identifier, "n".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn inclusive_ranges_are_one_token() {
+        let tokens = tokenize("1..=10".to_string()).unwrap();
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(types, [TokenType::NumberInt, TokenType::DoubleDotEquals, TokenType::NumberInt]);
+        assert_eq!(tokens[1].content, "..=");
+        assert_eq!((tokens[1].code_position.idx_start, tokens[1].code_position.idx_end), (1, 4));
+    }
+
+    #[test]
+    fn digits_after_a_dot_make_a_float() {
+        assert_eq!(lex("1.5"), [(TokenType::NumberFloat, "1.5".to_string())]);
+        assert_eq!(
+            lex("1.5..2"),
+            [
+                (TokenType::NumberFloat, "1.5".to_string()),
+                (TokenType::DoubleDot, "..".to_string()),
+                (TokenType::NumberInt, "2".to_string())
+            ]
+        );
+    }
+}
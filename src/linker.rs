@@ -1,15 +1,51 @@
-use lld_rx::{link, LldFlavor, LldResult};
+use crate::assembler;
+use std::process::Command;
+#[cfg(not(feature = "lld"))]
+use std::io;
+
+#[cfg(feature = "lld")]
+pub use lld_rx::LldFlavor;
+
+/// Same flavors as `lld_rx::LldFlavor`, for builds without the `lld` feature.
+#[cfg(not(feature = "lld"))]
+#[allow(dead_code)]
+pub enum LldFlavor {
+    Elf,
+    Wasm,
+    MachO,
+    Coff,
+}
+
+#[cfg(feature = "lld")]
+fn link(target: LldFlavor, args: Vec<String>) -> Result<(), String> {
+    lld_rx::link(target, args).ok()
+}
+
+/// Runs the LLD driver of the flavor, for ELF the system linker is tried as well.
+#[cfg(not(feature = "lld"))]
+fn link(target: LldFlavor, args: Vec<String>) -> Result<(), String> {
+    let drivers: &[&str] = match target {
+        LldFlavor::Elf => &["ld.lld", "ld"],
+        LldFlavor::Wasm => &["wasm-ld"],
+        LldFlavor::MachO => &["ld64.lld"],
+        LldFlavor::Coff => &["lld-link"],
+    };
+    for driver in drivers {
+        match Command::new(driver).args(&args).output() {
+            Ok(output) if output.status.success() => return Ok(()),
+            Ok(output) => return Err(String::from_utf8_lossy(&output.stderr).to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Err(format!("No linker found, tried `{}`", drivers.join("`, `")))
+}
 
 fn set_entry(lld_flavor: &LldFlavor, args: &mut Vec<String>, entry: String) {
     match lld_flavor {
-        LldFlavor::Elf => {
-            args.push(format!("-e {}", entry));
-        }
-        LldFlavor::Wasm => {
-            args.push(format!("-e {}", entry));
-        }
-        LldFlavor::MachO => {
-            args.push(format!("-e {}", entry));
+        LldFlavor::Elf | LldFlavor::Wasm | LldFlavor::MachO => {
+            args.push("-e".to_string());
+            args.push(entry);
         }
         LldFlavor::Coff => {
             args.push(format!("/entry:{}", entry));
@@ -19,41 +55,77 @@ fn set_entry(lld_flavor: &LldFlavor, args: &mut Vec<String>, entry: String) {
 
 fn set_output(lld_flavor: &LldFlavor, args: &mut Vec<String>, output: String) {
     match lld_flavor {
-        LldFlavor::Elf => {
-            args.push(format!("-o \"{}\"", output));
-        }
-        LldFlavor::Wasm => {
-            args.push(format!("-o \"{}\"", output));
-        }
-        LldFlavor::MachO => {
-            args.push(format!("-o \"{}\"", output));
+        LldFlavor::Elf | LldFlavor::Wasm | LldFlavor::MachO => {
+            args.push("-o".to_string());
+            args.push(output);
         }
         LldFlavor::Coff => {
-            args.push(format!("/out:\"{}\"", output));
+            args.push(format!("/out:{}", output));
         }
     }
 }
 
-fn lld_link(target: LldFlavor, output_path: String, 
-            is_lib: bool, mut extra_args: Vec<String>, 
-            start_symbol: Option<String>) -> LldResult {
+fn set_lib(lld_flavor: &LldFlavor, args: &mut Vec<String>) {
+    args.push(
+        match lld_flavor {
+            LldFlavor::Elf => "-shared",
+            LldFlavor::Wasm => "--no-entry",
+            LldFlavor::MachO => "-dylib",
+            LldFlavor::Coff => "/dll",
+        }
+        .to_string(),
+    );
+}
+
+pub fn lld_link(target: LldFlavor, output_path: String,
+            is_lib: bool, mut extra_args: Vec<String>,
+            start_symbol: Option<String>) -> Result<(), String> {
     if is_lib && start_symbol.is_some() {
         println!("Start symbol {} will be discarded as you are building a library.", start_symbol.clone().unwrap());
     }
-    
+
     let mut args: Vec<String> = vec![];
-    
+
     if is_lib {
-        args.push("/dll".into())
-    }
-    
-    if start_symbol.is_some() {
+        set_lib(&target, &mut args);
+    } else if start_symbol.is_some() {
         set_entry(&target, &mut args, start_symbol.unwrap());
     }
-    
+
     set_output(&target, &mut args, output_path);
-    
+
     args.append(&mut extra_args);
-    
+
     link(target, args)
-}
\ No newline at end of file
+}
+
+/// Assembles the x86-64 `source`, writing the ELF object file to `object`.
+pub fn assemble(source: &str, object: &str) -> Result<(), String> {
+    let bytes = assembler::assemble(source)?;
+    std::fs::write(object, bytes).map_err(|e| format!("Could not write `{}`: {}", object, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_objects_at_the_start_symbol() {
+        let dir = std::env::temp_dir().join(format!("sila-link-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (object, program) = (dir.join("start.o"), dir.join("program"));
+        let source = "    .text\n    .globl begin\nbegin:\n    movl $42, %edi\n    movl $60, %eax\n    syscall\n";
+        assert!(assemble("    bogus %rax\n", object.to_str().unwrap()).is_err());
+        assemble(source, object.to_str().unwrap()).unwrap();
+
+        let objects = vec![object.to_str().unwrap().to_string()];
+        let linked = lld_link(LldFlavor::Elf, program.to_str().unwrap().to_string(), false, objects, Some("begin".to_string()));
+        // Without any linker installed there is nothing to run
+        if linked.as_ref().is_err_and(|t| t.starts_with("No linker found")) {
+            return;
+        }
+        linked.unwrap();
+        assert_eq!(Command::new(&program).status().unwrap().code(), Some(42));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
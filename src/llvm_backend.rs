@@ -0,0 +1,847 @@
+use crate::compiler::{is_aggregate, Class, Compiler};
+use crate::entry::EntryPoint;
+use crate::ir::{lower, BinaryOp, Block, Comparison, Function, Instruction, Linkage, Op, Program, Terminator, Value};
+use crate::modules::Module;
+use crate::parser::Builtin;
+use crate::runtime::builtin_symbol;
+use crate::types::Type;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+/// Calls are lowered for the System V ABI of this target, like in the assembly backend.
+const TARGET_TRIPLE: &str = "x86_64-pc-linux-gnu";
+
+/// How a parameter or the result crosses a call in the System V ABI.
+#[derive(Debug, Clone)]
+enum Passing {
+    // Scalars as themselves, reference parameters as a pointer
+    Direct,
+    // Aggregates of up to 16 bytes, one `i64` or `double` per eightbyte
+    Split(Vec<Class>),
+    // Aggregates in memory, as `byval` parameters and `sret` results
+    Memory,
+}
+
+/// Text of an LLVM string literal, without the quotes.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b' '..=b'~' if byte != b'"' && byte != b'\\' => escaped.push(byte as char),
+            _ => write!(escaped, "\\{:02X}", byte).unwrap(),
+        }
+    }
+    escaped
+}
+
+fn llvm_type(ty: &Type) -> String {
+    match ty {
+        Type::Int(bits, _) => format!("i{}", bits),
+        Type::Float(32) => "float".to_string(),
+        Type::Float(_) => "double".to_string(),
+        Type::Bool => "i1".to_string(),
+        Type::Str | Type::Pointer(_) => "ptr".to_string(),
+        Type::Void | Type::Infer(..) => "void".to_string(),
+        Type::Array(element, length) => format!("[{} x {}]", length, llvm_type(element)),
+        Type::Struct(name) => format!("%struct.{}", name),
+    }
+}
+
+/// Integers narrower than 32 bits are extended by the caller, like C compilers expect.
+fn extension(ty: &Type) -> &'static str {
+    match ty {
+        Type::Int(8 | 16, true) => " signext",
+        Type::Int(8 | 16, false) | Type::Bool => " zeroext",
+        _ => "",
+    }
+}
+
+fn eightbyte_type(class: Class) -> &'static str {
+    match class {
+        Class::Integer => "i64",
+        Class::Sse => "double",
+    }
+}
+
+/// Type of the eightbytes of an aggregate returned in registers.
+fn split_type(classes: &[Class]) -> String {
+    match classes {
+        [class] => eightbyte_type(*class).to_string(),
+        classes => format!("{{ {} }}", classes.iter().map(|t| eightbyte_type(*t)).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+/// Predicate of an integer comparison, booleans and pointers only compare for equality.
+fn integer_predicate(op: Comparison, signed: bool) -> &'static str {
+    match (op, signed) {
+        (Comparison::Eq, _) => "eq",
+        (Comparison::Ne, _) => "ne",
+        (Comparison::Gt, true) => "sgt",
+        (Comparison::Ge, true) => "sge",
+        (Comparison::Lt, true) => "slt",
+        (Comparison::Le, true) => "sle",
+        (Comparison::Gt, false) => "ugt",
+        (Comparison::Ge, false) => "uge",
+        (Comparison::Lt, false) => "ult",
+        (Comparison::Le, false) => "ule",
+    }
+}
+
+/// Comparisons with NaN are false, except for `!=`.
+fn float_predicate(op: Comparison) -> &'static str {
+    match op {
+        Comparison::Eq => "oeq",
+        Comparison::Ne => "une",
+        Comparison::Gt => "ogt",
+        Comparison::Ge => "oge",
+        Comparison::Lt => "olt",
+        Comparison::Le => "ole",
+    }
+}
+
+struct LlvmBackend<'p, 'a, 'm> {
+    // Symbol -> function, for the signature of calls
+    functions: HashMap<&'p str, &'p Function>,
+    // Layout and argument classes, shared with the assembly backend
+    abi: Compiler<'a, 'm>,
+    // Runtime functions and intrinsics the program calls
+    declarations: BTreeSet<String>,
+    // State of the function being generated
+    code: String,
+    // `alloca`s go to the entry block, so loops do not grow the stack
+    allocas: String,
+    types: &'p [Type],
+    // Temp -> operand holding its value
+    names: Vec<String>,
+    values: usize,
+    blocks: usize,
+    // LLVM block code goes to, divisions split the blocks of the IR
+    label: String,
+    return_type: Type,
+    result: Passing,
+}
+
+impl<'p, 'a, 'm> LlvmBackend<'p, 'a, 'm> {
+    fn new(program: &'p Program, modules: &'m [Module<'a>]) -> Self {
+        Self {
+            functions: program.functions.iter().map(|t| (t.symbol.as_str(), t)).collect(),
+            abi: Compiler::new(modules),
+            declarations: BTreeSet::new(),
+            code: String::new(),
+            allocas: String::new(),
+            types: &[],
+            names: vec![],
+            values: 0,
+            blocks: 0,
+            label: String::new(),
+            return_type: Type::Void,
+            result: Passing::Direct,
+        }
+    }
+
+    fn type_of(&self, value: &Value) -> Type {
+        match value {
+            Value::Temp(temp) => self.types[*temp].clone(),
+            Value::Int(_, ty) | Value::Float(_, ty) | Value::Undef(ty) => ty.clone(),
+            Value::Bool(_) => Type::Bool,
+            Value::String(_) => Type::Str,
+        }
+    }
+
+    /// Integer constants are written signed and wrapped to their type, floats as the bits of a double.
+    fn operand(&self, value: &Value) -> String {
+        match value {
+            Value::Temp(temp) => self.names[*temp].clone(),
+            Value::Int(value, Type::Int(bits, _)) => {
+                let shift = 128 - *bits as u32;
+                ((value << shift) >> shift).to_string()
+            }
+            Value::Int(value, _) => value.to_string(),
+            Value::Float(value, _) => format!("0x{:016X}", value.to_bits()),
+            Value::Bool(value) => value.to_string(),
+            Value::String(index) => format!("@.str.{}", index),
+            Value::Undef(_) => "undef".to_string(),
+        }
+    }
+
+    fn typed(&self, value: &Value) -> String {
+        format!("{} {}", llvm_type(&self.type_of(value)), self.operand(value))
+    }
+
+    fn value(&mut self) -> String {
+        self.values += 1;
+        format!("%t.{}", self.values)
+    }
+
+    fn block(&mut self) -> usize {
+        self.blocks += 1;
+        self.blocks
+    }
+
+    fn emit(&mut self, instruction: &str) {
+        writeln!(self.code, "  {}", instruction).unwrap();
+    }
+
+    fn emit_label(&mut self, label: &str) {
+        writeln!(self.code, "{}:", label).unwrap();
+        self.label = label.to_string();
+    }
+
+    /// Stack memory for a value of the LLVM type `ty`.
+    fn alloca(&mut self, name: String, ty: &str) -> String {
+        writeln!(self.allocas, "  {} = alloca {}", name, ty).unwrap();
+        name
+    }
+
+    fn load(&mut self, ty: &Type, pointer: &str) -> String {
+        let value = self.value();
+        self.emit(&format!("{} = load {}, ptr {}", value, llvm_type(ty), pointer));
+        value
+    }
+
+    fn store(&mut self, ty: &Type, value: &str, pointer: &str) {
+        self.emit(&format!("store {} {}, ptr {}", llvm_type(ty), value, pointer));
+    }
+
+    /// Pointer to the element `index` of the array of `ty` at `pointer`.
+    fn element(&mut self, ty: &str, pointer: &str, index: usize) -> String {
+        if index == 0 {
+            return pointer.to_string();
+        }
+        let element = self.value();
+        self.emit(&format!("{} = getelementptr inbounds {}, ptr {}, i64 0, i64 {}", element, ty, pointer, index));
+        element
+    }
+
+    /// How the parameters and the result of a function cross calls. Aggregates that no longer
+    /// fit the argument registers that are left go to memory as a whole.
+    fn passing(&self, ret: &Type, params: &[Type]) -> (Passing, Vec<Passing>) {
+        let result = match self.abi.classify(ret) {
+            None => Passing::Memory,
+            Some(classes) if is_aggregate(ret) => Passing::Split(classes),
+            Some(_) => Passing::Direct,
+        };
+        let (mut integers, mut vectors) = (matches!(result, Passing::Memory) as usize, 0);
+        let mut passing = vec![];
+        for ty in params {
+            match self.abi.classify(ty) {
+                Some(classes) if self.abi.fits(&classes, integers, vectors) => {
+                    let needed = classes.iter().filter(|t| **t == Class::Integer).count();
+                    integers += needed;
+                    vectors += classes.len() - needed;
+                    passing.push(if is_aggregate(ty) { Passing::Split(classes) } else { Passing::Direct });
+                }
+                Some(_) if !is_aggregate(ty) => passing.push(Passing::Direct),
+                _ => passing.push(Passing::Memory),
+            }
+        }
+        (result, passing)
+    }
+
+    fn result_type(ty: &Type, result: &Passing) -> String {
+        match result {
+            Passing::Direct => match extension(ty) {
+                "" => llvm_type(ty),
+                extension => format!("{} {}", extension.trim_start(), llvm_type(ty)),
+            },
+            Passing::Split(classes) => split_type(classes),
+            Passing::Memory => "void".to_string(),
+        }
+    }
+
+    fn sret(&self, ty: &Type) -> String {
+        format!("ptr sret({}) align {}", llvm_type(ty), self.abi.layout(ty).1)
+    }
+
+    /// Argument types of each parameter, reference parameters are pointers in the IR.
+    fn arguments(params: &[Type], passing: &[Passing]) -> Vec<Vec<String>> {
+        let mut arguments = vec![];
+        for (ty, passing) in params.iter().zip(passing) {
+            arguments.push(match passing {
+                Passing::Direct => vec![format!("{}{}", llvm_type(ty), extension(ty))],
+                Passing::Split(classes) => classes.iter().map(|t| eightbyte_type(*t).to_string()).collect(),
+                Passing::Memory => vec![format!("ptr byval({}) align 8", llvm_type(ty))],
+            });
+        }
+        arguments
+    }
+
+    /// `declare` of a function defined outside of Sila.
+    fn declaration(&self, function: &Function) -> String {
+        let (result, passing) = self.passing(&function.ret, &function.params);
+        let mut list = vec![];
+        if let Passing::Memory = result {
+            list.push(self.sret(&function.ret));
+        }
+        list.extend(Self::arguments(&function.params, &passing).into_iter().flatten());
+        format!("declare {} @{}({})", Self::result_type(&function.ret, &result), function.symbol, list.join(", "))
+    }
+
+    fn function(&mut self, function: &'p Function) -> String {
+        self.code.clear();
+        self.allocas.clear();
+        self.types = &function.types;
+        self.names = vec![String::new(); function.types.len()];
+        self.values = 0;
+        self.blocks = 0;
+        self.label = "b0".to_string();
+        self.return_type = function.ret.clone();
+        let (result, passing) = self.passing(&function.ret, &function.params);
+        self.result = result;
+
+        // Aggregates arrive in eightbytes or in memory and are loaded into their temp
+        let mut list = vec![];
+        if let Passing::Memory = self.result {
+            list.push(format!("{} %agg.result", self.sret(&function.ret)));
+        }
+        let arguments = Self::arguments(&function.params, &passing);
+        for (index, ((ty, passing), types)) in function.params.iter().zip(passing).zip(arguments).enumerate() {
+            let argument = format!("%arg.{}", index);
+            self.names[index] = match passing {
+                Passing::Direct => {
+                    list.push(format!("{} {}", types[0], argument));
+                    argument
+                }
+                Passing::Split(classes) => {
+                    let memory_type = format!("[{} x i64]", classes.len());
+                    let memory = self.alloca(format!("{}.addr", argument), &memory_type);
+                    for (eightbyte, ty) in types.iter().enumerate() {
+                        list.push(format!("{} {}.{}", ty, argument, eightbyte));
+                        let pointer = self.element(&memory_type, &memory, eightbyte);
+                        self.emit(&format!("store {} {}.{}, ptr {}", ty, argument, eightbyte, pointer));
+                    }
+                    self.load(ty, &memory)
+                }
+                Passing::Memory => {
+                    list.push(format!("{} {}", types[0], argument));
+                    self.load(ty, &argument)
+                }
+            };
+        }
+
+        // Phis are named first, loops read them before the blocks defining their values
+        for block in &function.blocks {
+            for phi in &block.phis {
+                self.names[phi.result] = self.value();
+            }
+        }
+        let (mut bodies, mut exits) = (vec![], vec![]);
+        for (index, block) in function.blocks.iter().enumerate() {
+            self.label = format!("b{}", index);
+            self.block_body(block);
+            bodies.push(std::mem::take(&mut self.code));
+            exits.push(self.label.clone());
+        }
+
+        let linkage = if function.linkage == Linkage::Internal { "internal " } else { "" };
+        let result = Self::result_type(&function.ret, &self.result);
+        let mut code = format!("define {}{} @{}({}) {{\n", linkage, result, function.symbol, list.join(", "));
+        for (index, (block, body)) in function.blocks.iter().zip(bodies).enumerate() {
+            writeln!(code, "b{}:", index).unwrap();
+            if index == 0 {
+                code.push_str(&self.allocas);
+            }
+            for phi in &block.phis {
+                let incoming: Vec<String> =
+                    phi.incoming.iter().map(|(from, value)| format!("[ {}, %{} ]", self.operand(value), exits[*from])).collect();
+                let ty = llvm_type(&self.types[phi.result]);
+                writeln!(code, "  {} = phi {} {}", self.names[phi.result], ty, incoming.join(", ")).unwrap();
+            }
+            code.push_str(&body);
+        }
+        code.push_str("}\n");
+        code
+    }
+
+    fn block_body(&mut self, block: &Block) {
+        for instruction in &block.instructions {
+            self.instruction(instruction);
+        }
+        match &block.terminator {
+            Terminator::Jump(target) => self.emit(&format!("br label %b{}", target)),
+            Terminator::Branch(condition, then, otherwise) => {
+                let condition = self.operand(condition);
+                self.emit(&format!("br i1 {}, label %b{}, label %b{}", condition, then, otherwise));
+            }
+            Terminator::Return(None) => self.emit("ret void"),
+            Terminator::Return(Some(value)) => self.ret(value),
+            Terminator::Unreachable => self.emit("unreachable"),
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        let result_type = instruction.result.map_or(Type::Void, |t| self.types[t].clone());
+        let value = match &instruction.op {
+            Op::Alloca(ty) => {
+                let name = self.value();
+                self.alloca(name, &llvm_type(ty))
+            }
+            Op::Load(pointer) => {
+                let pointer = self.operand(pointer);
+                self.load(&result_type, &pointer)
+            }
+            Op::Store(value, pointer) => {
+                let (value, pointer) = (self.typed(value), self.operand(pointer));
+                self.emit(&format!("store {}, ptr {}", value, pointer));
+                String::new()
+            }
+            Op::Field(pointer, index) => {
+                let Type::Pointer(ty) = self.type_of(pointer) else {
+                    unreachable!()
+                };
+                let (pointer, field) = (self.operand(pointer), self.value());
+                self.emit(&format!("{} = getelementptr inbounds {}, ptr {}, i32 0, i32 {}", field, llvm_type(&ty), pointer, index));
+                field
+            }
+            Op::Element(pointer, index) => {
+                let Type::Pointer(ty) = self.type_of(pointer) else {
+                    unreachable!()
+                };
+                let (pointer, index, element) = (self.operand(pointer), self.typed(index), self.value());
+                self.emit(&format!("{} = getelementptr inbounds {}, ptr {}, i64 0, {}", element, llvm_type(&ty), pointer, index));
+                element
+            }
+            Op::Binary(op, lhs, rhs) => self.binary(*op, lhs, rhs),
+            Op::Compare(op, lhs, rhs) => {
+                let instruction = match self.type_of(lhs) {
+                    Type::Float(_) => format!("fcmp {}", float_predicate(*op)),
+                    operands => format!("icmp {}", integer_predicate(*op, matches!(operands, Type::Int(_, true)))),
+                };
+                let (operands, rhs) = (self.typed(lhs), self.operand(rhs));
+                let value = self.value();
+                self.emit(&format!("{} = {} {}, {}", value, instruction, operands, rhs));
+                value
+            }
+            Op::Cast(value, to) => {
+                let from = self.type_of(value);
+                let value = self.operand(value);
+                self.cast(value, &from, to)
+            }
+            Op::Call(symbol, args) => self.call(symbol, args),
+            Op::Alloc(ty, count) => {
+                let bytes = self.bytes(ty, count);
+                self.runtime(Builtin::Alloc, &format!("i64 {}", bytes))
+            }
+            Op::Realloc(ty, pointer, count) => {
+                let bytes = self.bytes(ty, count);
+                let list = format!("ptr {}, i64 {}", self.operand(pointer), bytes);
+                self.runtime(Builtin::Realloc, &list)
+            }
+            Op::Free(pointer) => {
+                let list = format!("ptr {}", self.operand(pointer));
+                self.runtime(Builtin::Free, &list)
+            }
+        };
+        if let Some(result) = instruction.result {
+            self.names[result] = value;
+        }
+    }
+
+    /// Size in bytes of `count` values of `ty`.
+    fn bytes(&mut self, ty: &Type, count: &Value) -> String {
+        let (count, bytes) = (self.operand(count), self.value());
+        self.emit(&format!("{} = mul i64 {}, {}", bytes, count, self.abi.size_of(ty)));
+        bytes
+    }
+
+    /// Calls the runtime function of `builtin` with the typed arguments `list`.
+    fn runtime(&mut self, builtin: Builtin, list: &str) -> String {
+        let symbol = builtin_symbol(builtin);
+        let (ret, parameters) = match builtin {
+            Builtin::Alloc => ("ptr", "i64"),
+            Builtin::Realloc => ("ptr", "ptr, i64"),
+            Builtin::Free => ("void", "ptr"),
+        };
+        self.declarations.insert(format!("declare {} @{}({})", ret, symbol, parameters));
+        if ret == "void" {
+            self.emit(&format!("call void @{}({})", symbol, list));
+            return String::new();
+        }
+        let value = self.value();
+        self.emit(&format!("{} = call {} @{}({})", value, ret, symbol, list));
+        value
+    }
+
+    /// Returns `value` where the ABI returns values of its type.
+    fn ret(&mut self, value: &Value) {
+        let ty = self.return_type.clone();
+        let value = self.operand(value);
+        match self.result.clone() {
+            Passing::Direct => self.emit(&format!("ret {} {}", llvm_type(&ty), value)),
+            Passing::Split(classes) => {
+                let eightbytes = self.split(&ty, &value, &classes);
+                let mut result = "poison".to_string();
+                if let [eightbyte] = eightbytes.as_slice() {
+                    result = eightbyte.clone();
+                } else {
+                    for (index, eightbyte) in eightbytes.iter().enumerate() {
+                        let inserted = self.value();
+                        let ty = split_type(&classes);
+                        self.emit(&format!("{} = insertvalue {} {}, {}, {}", inserted, ty, result, eightbyte, index));
+                        result = inserted;
+                    }
+                    result = format!("{} {}", split_type(&classes), result);
+                }
+                self.emit(&format!("ret {}", result));
+            }
+            Passing::Memory => {
+                self.store(&ty, &value, "%agg.result");
+                self.emit("ret void");
+            }
+        }
+    }
+
+    /// Eightbytes of the aggregate `value` as typed arguments, read through memory.
+    fn split(&mut self, ty: &Type, value: &str, classes: &[Class]) -> Vec<String> {
+        let memory_type = format!("[{} x i64]", classes.len());
+        let name = self.value();
+        let memory = self.alloca(name, &memory_type);
+        self.store(ty, value, &memory);
+        let mut eightbytes = vec![];
+        for (index, class) in classes.iter().enumerate() {
+            let eightbyte = self.element(&memory_type, &memory, index);
+            let loaded = self.value();
+            self.emit(&format!("{} = load {}, ptr {}", loaded, eightbyte_type(*class), eightbyte));
+            eightbytes.push(format!("{} {}", eightbyte_type(*class), loaded));
+        }
+        eightbytes
+    }
+
+    /// Integers wrap around like in the other backends. Division by zero traps, so it is not
+    /// undefined behavior LLVM could optimize on.
+    fn binary(&mut self, op: BinaryOp, lhs: &Value, rhs: &Value) -> String {
+        let operands = self.type_of(lhs);
+        let (lhs, rhs) = (self.operand(lhs), self.operand(rhs));
+        let ty = llvm_type(&operands);
+        let instruction = match (&operands, op) {
+            (Type::Int(..), BinaryOp::Add) => "add",
+            (Type::Int(..), BinaryOp::Sub) => "sub",
+            (Type::Int(..), BinaryOp::Mul) => "mul",
+            (Type::Int(_, signed), BinaryOp::Div) => return self.divide(&ty, *signed, &lhs, &rhs),
+            (_, BinaryOp::Add) => "fadd",
+            (_, BinaryOp::Sub) => "fsub",
+            (_, BinaryOp::Mul) => "fmul",
+            (_, BinaryOp::Div) => "fdiv",
+        };
+        let value = self.value();
+        self.emit(&format!("{} = {} {} {}, {}", value, instruction, ty, lhs, rhs));
+        value
+    }
+
+    /// `sdiv` of the smallest value by -1 is undefined, it wraps to the dividend negated instead.
+    fn divide(&mut self, ty: &str, signed: bool, lhs: &str, rhs: &str) -> String {
+        self.declarations.insert("declare void @llvm.trap()".to_string());
+        let block = self.block();
+        let (trap, divide) = (format!("div.zero.{}", block), format!("div.{}", block));
+        let zero = self.value();
+        self.emit(&format!("{} = icmp eq {} {}, 0", zero, ty, rhs));
+        self.emit(&format!("br i1 {}, label %{}, label %{}", zero, trap, divide));
+        self.emit_label(&trap);
+        self.emit("call void @llvm.trap()");
+        self.emit("unreachable");
+        self.emit_label(&divide);
+        if !signed {
+            let quotient = self.value();
+            self.emit(&format!("{} = udiv {} {}, {}", quotient, ty, lhs, rhs));
+            return quotient;
+        }
+        let (minus_one, divisor, quotient) = (self.value(), self.value(), self.value());
+        let (negated, result) = (self.value(), self.value());
+        self.emit(&format!("{} = icmp eq {} {}, -1", minus_one, ty, rhs));
+        self.emit(&format!("{} = select i1 {}, {} 1, {} {}", divisor, minus_one, ty, ty, rhs));
+        self.emit(&format!("{} = sdiv {} {}, {}", quotient, ty, lhs, divisor));
+        self.emit(&format!("{} = sub {} 0, {}", negated, ty, lhs));
+        self.emit(&format!("{} = select i1 {}, {} {}, {} {}", result, minus_one, ty, negated, ty, quotient));
+        result
+    }
+
+    fn cast(&mut self, value: String, from: &Type, to: &Type) -> String {
+        let instruction = match (from, to) {
+            (from, to) if from == to => return value,
+            (Type::Int(from_bits, signed), Type::Int(to_bits, _)) => match to_bits.cmp(from_bits) {
+                std::cmp::Ordering::Less => "trunc",
+                std::cmp::Ordering::Greater if *signed => "sext",
+                std::cmp::Ordering::Greater => "zext",
+                std::cmp::Ordering::Equal => return value,
+            },
+            (Type::Bool, Type::Int(..)) => "zext",
+            (Type::Int(_, true), Type::Float(_)) => "sitofp",
+            (Type::Int(_, false), Type::Float(_)) => "uitofp",
+            // Truncates and saturates like in the interpreter, NaN becomes 0
+            (Type::Float(bits), Type::Int(to_bits, signed)) => {
+                let intrinsic = format!("llvm.fpto{}i.sat.i{}.f{}", if *signed { "s" } else { "u" }, to_bits, bits);
+                let (from, to) = (llvm_type(from), llvm_type(to));
+                self.declarations.insert(format!("declare {} @{}({})", to, intrinsic, from));
+                let converted = self.value();
+                self.emit(&format!("{} = call {} @{}({} {})", converted, to, intrinsic, from, value));
+                return converted;
+            }
+            (Type::Float(32), Type::Float(64)) => "fpext",
+            (Type::Float(64), Type::Float(32)) => "fptrunc",
+            (Type::Pointer(_), Type::Int(..)) => "ptrtoint",
+            (Type::Int(..), Type::Pointer(_)) => "inttoptr",
+            // Pointers and strings are all `ptr`
+            _ => return value,
+        };
+        let converted = self.value();
+        self.emit(&format!("{} = {} {} {} to {}", converted, instruction, llvm_type(from), value, llvm_type(to)));
+        converted
+    }
+
+    /// Calls follow the System V ABI, so C code can call Sila functions and the other way around.
+    fn call(&mut self, symbol: &str, args: &[Value]) -> String {
+        let function = self.functions[symbol];
+        let ret = function.ret.clone();
+        let (result, passing) = self.passing(&ret, &function.params);
+
+        let mut list = vec![];
+        for ((ty, arg), passing) in function.params.iter().zip(args).zip(passing) {
+            let value = self.operand(arg);
+            match passing {
+                Passing::Direct => list.push(format!("{}{} {}", llvm_type(ty), extension(ty), value)),
+                Passing::Split(classes) => list.extend(self.split(ty, &value, &classes)),
+                Passing::Memory => {
+                    let name = self.value();
+                    let memory = self.alloca(name, &llvm_type(ty));
+                    self.store(ty, &value, &memory);
+                    list.push(format!("ptr byval({}) align 8 {}", llvm_type(ty), memory));
+                }
+            }
+        }
+
+        let result_type = Self::result_type(&ret, &result);
+        let memory = match result {
+            Passing::Memory => {
+                let name = self.value();
+                let memory = self.alloca(name, &llvm_type(&ret));
+                list.insert(0, format!("{} {}", self.sret(&ret), memory));
+                Some(memory)
+            }
+            _ => None,
+        };
+        let call = format!("call {} @{}({})", result_type, symbol, list.join(", "));
+        if result_type == "void" {
+            self.emit(&call);
+            return match memory {
+                Some(memory) => self.load(&ret, &memory),
+                None => String::new(),
+            };
+        }
+        let value = self.value();
+        self.emit(&format!("{} = {}", value, call));
+        let Passing::Split(classes) = result else {
+            return value;
+        };
+        // Aggregates in registers are put back together through memory
+        let memory_type = format!("[{} x i64]", classes.len());
+        let name = self.value();
+        let memory = self.alloca(name, &memory_type);
+        for (index, class) in classes.iter().enumerate() {
+            let eightbyte = match classes.len() {
+                1 => value.clone(),
+                _ => {
+                    let extracted = self.value();
+                    self.emit(&format!("{} = extractvalue {} {}, {}", extracted, result_type, value, index));
+                    extracted
+                }
+            };
+            let pointer = self.element(&memory_type, &memory, index);
+            self.emit(&format!("store {} {}, ptr {}", eightbyte_type(*class), eightbyte, pointer));
+        }
+        self.load(&ret, &memory)
+    }
+}
+
+/// Textual LLVM IR of the checked `modules`, for LLVM 15 and later. It is translated from the
+/// SSA program of `ir::lower`: temps become LLVM values and phis, IR blocks keep their number.
+/// External functions of the IR keep external linkage and imports are declared. Arguments are
+/// lowered for the System V ABI, so the program links against C code and the runtime like the
+/// output of the assembly backend.
+pub fn generate(modules: &[Module], entry: &EntryPoint) -> String {
+    let program = lower(modules, entry);
+    let mut backend = LlvmBackend::new(&program, modules);
+    let mut functions = String::new();
+    let mut declarations = vec![];
+    for function in &program.functions {
+        match function.linkage {
+            Linkage::Import => declarations.push(backend.declaration(function)),
+            _ => write!(functions, "\n{}", backend.function(function)).unwrap(),
+        }
+    }
+
+    let mut source = String::from("; Generated by the Sila compiler\n");
+    writeln!(source, "source_filename = \"{}\"", escape(&modules[0].file_manager.input_file)).unwrap();
+    writeln!(source, "target triple = \"{}\"", TARGET_TRIPLE).unwrap();
+
+    if !program.structs.is_empty() {
+        source.push('\n');
+    }
+    for (name, fields) in &program.structs {
+        let fields: Vec<String> = fields.iter().map(|(_, ty)| llvm_type(ty)).collect();
+        writeln!(source, "%struct.{} = type {{ {} }}", name, fields.join(", ")).unwrap();
+    }
+    if !program.strings.is_empty() {
+        source.push('\n');
+    }
+    for (index, text) in program.strings.iter().enumerate() {
+        writeln!(
+            source,
+            "@.str.{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"",
+            index,
+            text.len() + 1,
+            escape(text)
+        )
+        .unwrap();
+    }
+    source.push_str(&functions);
+
+    declarations.extend(backend.declarations);
+    if !declarations.is_empty() {
+        source.push('\n');
+    }
+    for declaration in declarations {
+        writeln!(source, "{}", declaration).unwrap();
+    }
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::entry_point;
+    use crate::interpreter::interpret;
+    use crate::runtime::{runtime_asm, start_asm, AllocatorKind};
+    use crate::testing::check_module_of;
+    use std::path::Path;
+    use std::process::Command;
+
+    /// Expected output for `tests/golden/program.sila`, `SILA_BLESS=1` rewrites it.
+    const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/program.ll");
+
+    fn generate_ir(source: &str) -> String {
+        let modules = [check_module_of(source).unwrap_or_else(|e| panic!("{}: {}", e.title, e.footer))];
+        let entry = entry_point(&modules[0].ast, &modules[0].name, None).unwrap();
+        generate(&modules, &entry)
+    }
+
+    /// Compiles `ir` in `dir` to `object` with `llc`, `false` if there is none.
+    /// LLVM 14 only reads `ptr` with `-opaque-pointers`, later versions always do.
+    fn compile(dir: &Path, ir: &str, object: &str) -> bool {
+        let Ok(version) = Command::new("llc").arg("--version").output() else {
+            return false;
+        };
+        let version = String::from_utf8_lossy(&version.stdout).to_string();
+        let major = version.split("version ").nth(1).and_then(|t| t.split('.').next()?.parse::<u32>().ok());
+        let mut args = vec!["-relocation-model=pic", "-filetype=obj", "-o", object, ir];
+        if major.is_some_and(|t| t < 15) {
+            args.insert(0, "-opaque-pointers");
+        }
+        let compiled = Command::new("llc").args(args).current_dir(dir).status().unwrap();
+        assert!(compiled.success());
+        true
+    }
+
+    fn link(dir: &Path, args: &[&str]) -> bool {
+        if Command::new("cc").arg("--version").output().is_err() {
+            return false;
+        }
+        let linked = Command::new("cc").args(args).current_dir(dir).status().unwrap();
+        assert!(linked.success());
+        true
+    }
+
+    #[test]
+    fn declares_functions_by_mode() {
+        let source = generate_ir(
+            "def extern puts(s: str): i32 {\n}\n\ndef private helper(): i8 {\n    return 1;\n}\n\n\
+             def export api(x: &mut i32): void {\n    x = (helper() -> i32);\n    puts(\"api\");\n}\n\n\
+             def main(): i32 {\n    return 0;\n}",
+        );
+        assert!(source.contains("\ndefine internal signext i8 @test__helper() {\n"));
+        assert!(source.contains("\ndefine void @api(ptr %arg.0) {\n"));
+        // The entry is called by the start stub
+        assert!(source.contains("\ndefine i32 @test__main() {\n"));
+        assert!(source.contains("  %t.1 = call signext i8 @test__helper()\n"));
+        assert!(source.contains("\n@.str.0 = private unnamed_addr constant [4 x i8] c\"api\\00\"\n"));
+        assert!(source.ends_with("\ndeclare i32 @puts(ptr)\n"));
+    }
+
+    #[test]
+    fn matches_the_golden_file() {
+        let ir = generate_ir(include_str!("../tests/golden/program.sila"));
+        if std::env::var_os("SILA_BLESS").is_some() {
+            std::fs::write(GOLDEN, &ir).unwrap();
+        }
+        assert_eq!(ir, std::fs::read_to_string(GOLDEN).unwrap());
+    }
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(escape("a\"b\\c\n"), "a\\22b\\5Cc\\0A");
+    }
+
+    /// Builds the golden program with `llc` and the libc runtime, it has to behave like in the interpreter.
+    #[test]
+    fn runs_like_the_interpreter() {
+        let source = include_str!("../tests/golden/program.sila");
+        let dir = std::env::temp_dir().join(format!("sila-llvm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let modules = [check_module_of(source).unwrap()];
+        let entry = entry_point(&modules[0].ast, &modules[0].name, None).unwrap();
+        std::fs::write(dir.join("program.ll"), generate(&modules, &entry)).unwrap();
+        std::fs::write(dir.join("runtime.s"), runtime_asm(AllocatorKind::Libc)).unwrap();
+        std::fs::write(dir.join("start.s"), start_asm(&entry, AllocatorKind::Libc)).unwrap();
+        if !compile(&dir, "program.ll", "program.o")
+            || !link(&dir, &["-nostartfiles", "-o", "program", "program.o", "runtime.s", "start.s"])
+        {
+            return;
+        }
+        let run = Command::new(dir.join("program")).output().unwrap();
+
+        let mut output = vec![];
+        let code = interpret(&modules, &entry, "program", &mut output).unwrap();
+        assert_eq!(run.status.code(), Some(code & 0xff));
+        assert_eq!(run.stdout, output);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// C code calls exported functions and is called back with structs passed in registers and in memory.
+    #[test]
+    fn shares_values_with_c() {
+        let source = "struct Mixed {\n    a: i32,\n    b: f32,\n    c: f64\n}\n\nstruct Big {\n    values: [i64; 3]\n}\n\n\
+            def extern weigh(m: Mixed, big: Big, x: f32, a: i64, b: i64, c: i64, d: i64, e: i64, f: i64): i64 {\n}\n\n\
+            def export relay(m: Mixed, big: Big, x: f32): i64 {\n    return weigh(m, big, x, 1, 2, 3, 4, 5, 6);\n}\n\n\
+            def export echo(m: Mixed): Mixed {\n    return m;\n}\n\n\
+            def export echo_big(big: Big): Big {\n    return big;\n}\n\n\
+            def export increment(value: &mut i16): void {\n    value = value + 1;\n}\n\n\
+            def main(): i32 {\n    return 0;\n}";
+        let c_source = "#include <stdint.h>\n\
+            struct Mixed { int32_t a; float b; double c; };\n\
+            struct Big { int64_t values[3]; };\n\
+            int64_t relay(struct Mixed m, struct Big big, float x);\n\
+            struct Mixed echo(struct Mixed m);\n\
+            struct Big echo_big(struct Big big);\n\
+            void increment(int16_t *value);\n\
+            int64_t weigh(struct Mixed m, struct Big big, float x, int64_t a, int64_t b, int64_t c, int64_t d, int64_t e, int64_t f) {\n\
+            \x20   return m.a + (int64_t)m.b + (int64_t)m.c + big.values[0] + big.values[2] + (int64_t)x + a + f;\n}\n\
+            int main(void) {\n\
+            \x20   struct Mixed m = {1, 2.5f, 40.0};\n\
+            \x20   struct Big big = {{100, 0, 300}};\n\
+            \x20   struct Mixed e = echo(m);\n\
+            \x20   struct Big b = echo_big(big);\n\
+            \x20   int16_t value = -1;\n\
+            \x20   increment(&value);\n\
+            \x20   if (e.a != 1 || e.b != 2.5f || e.c != 40.0 || b.values[2] != 300 || value != 0) return 1;\n\
+            \x20   return relay(m, big, 8.0f) == 1 + 2 + 40 + 100 + 300 + 8 + 1 + 6 ? 0 : 2;\n}\n";
+        let dir = std::env::temp_dir().join(format!("sila-llvm-abi-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("program.ll"), generate_ir(source)).unwrap();
+        std::fs::write(dir.join("main.c"), c_source).unwrap();
+        if !compile(&dir, "program.ll", "program.o") || !link(&dir, &["-o", "program", "main.c", "program.o"]) {
+            return;
+        }
+        let run = Command::new(dir.join("program")).status().unwrap();
+        assert_eq!(run.code(), Some(0));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,206 @@
+use crate::lexer::{CodePosition, Token, TokenType};
+use crate::parser::ASTNode;
+use crate::typeck::TypeTable;
+use crate::types::Type;
+
+/// Loop condition and the statements opening each iteration.
+type Loop<'a> = (Box<ASTNode<'a>>, Vec<Box<ASTNode<'a>>>);
+
+/// Rewrites checked `for` loops into `while` loops over a hidden counter, so code generation
+/// only knows one kind of loop. The counter is advanced before the body runs, which keeps
+/// `continue` from skipping it:
+///
+/// ```text
+/// for i in a..b { body }     let mut index = a; let end = b;
+///                            while index < end { let i = index; index = index + 1; body }
+///
+/// for i in a..=b { body }    let mut index = a; let end = b; let mut more = index <= end;
+///                            while more { let i = index;
+///                                if index == end { more = false } else { index = index + 1 }; body }
+///
+/// for x in array { body }    let items = array; let mut index: u64 = 0;
+///                            while index < length { let x = items[index]; index = index + 1; body }
+/// ```
+///
+/// An inclusive range stops before incrementing past the bound, so it ends even if the bound
+/// is the largest value of its type. An array loop walks a copy of the array.
+struct LoopLowering<'b> {
+    types: &'b mut TypeTable,
+    position: CodePosition,
+}
+
+impl<'a, 'b> LoopLowering<'b> {
+    fn token(&self, content: &str, token_type: TokenType) -> &'static Token {
+        Token::synthetic(content.to_string(), token_type, self.position)
+    }
+
+    /// Boxes `node` and records its type, nodes are keyed by their address.
+    fn typed(&mut self, node: ASTNode<'a>, ty: &Type) -> Box<ASTNode<'a>> {
+        let node = Box::new(node);
+        self.types.record(&node, ty.clone());
+        node
+    }
+
+    fn variable(&mut self, content: &str, ty: &Type) -> &'static Token {
+        let name = self.token(content, TokenType::Identifier);
+        self.types.declare(name, ty.clone());
+        name
+    }
+
+    fn read(&mut self, name: &'static Token, ty: &Type) -> Box<ASTNode<'a>> {
+        self.typed(ASTNode::Identifier(name), ty)
+    }
+
+    fn integer(&mut self, value: usize, ty: &Type) -> Box<ASTNode<'a>> {
+        let token = self.token(&value.to_string(), TokenType::NumberInt);
+        self.typed(ASTNode::Literal(token), ty)
+    }
+
+    fn boolean(&mut self, value: bool) -> Box<ASTNode<'a>> {
+        let token = self.token(&value.to_string(), TokenType::Boolean);
+        self.typed(ASTNode::Literal(token), &Type::Bool)
+    }
+
+    fn binary(&mut self, lhs: Box<ASTNode<'a>>, op: TokenType, rhs: Box<ASTNode<'a>>, ty: &Type) -> Box<ASTNode<'a>> {
+        let op = self.token(&op.visualize(), op);
+        self.typed(ASTNode::BinaryOp(lhs, op, rhs), ty)
+    }
+
+    /// `index = index + 1`
+    fn increment(&mut self, index: &'static Token, ty: &Type) -> Box<ASTNode<'a>> {
+        let (current, one) = (self.read(index, ty), self.integer(1, ty));
+        let sum = self.binary(current, TokenType::Plus, one, ty);
+        Box::new(ASTNode::Assignment(index, sum))
+    }
+
+    /// Counts a hidden index from the start to the end of the range, returns the loop
+    /// condition and the statements opening each iteration.
+    fn range_loop(&mut self, var: &'a Token, range: ASTNode<'a>, setup: &mut Vec<Box<ASTNode<'a>>>) -> Loop<'a> {
+        let ASTNode::Range(start, end, inclusive) = range else {
+            unreachable!()
+        };
+        let ty = self.types.type_of_declaration(var).cloned().unwrap();
+        let (index, limit) = (self.variable("index", &ty), self.variable("end", &ty));
+        setup.push(Box::new(ASTNode::VariableSet(index, Some(start), None, true)));
+        setup.push(Box::new(ASTNode::VariableSet(limit, Some(end), None, false)));
+        let mut head = vec![Box::new(ASTNode::VariableSet(var, Some(self.read(index, &ty)), None, false))];
+        let (current, bound) = (self.read(index, &ty), self.read(limit, &ty));
+        if !inclusive {
+            head.push(self.increment(index, &ty));
+            return (self.binary(current, TokenType::Lesser, bound, &Type::Bool), head);
+        }
+        let more = self.variable("more", &Type::Bool);
+        let first = self.binary(current, TokenType::LesserEquals, bound, &Type::Bool);
+        setup.push(Box::new(ASTNode::VariableSet(more, Some(first), None, true)));
+        let (current, bound) = (self.read(index, &ty), self.read(limit, &ty));
+        let last = self.binary(current, TokenType::DoubleEquals, bound, &Type::Bool);
+        let stop = Box::new(ASTNode::Assignment(more, self.boolean(false)));
+        head.push(Box::new(ASTNode::If(last, vec![stop], vec![self.increment(index, &ty)])));
+        (self.read(more, &Type::Bool), head)
+    }
+
+    /// Copies the array and counts a hidden index over its elements, returns the loop
+    /// condition and the statements opening each iteration.
+    fn array_loop(&mut self, var: &'a Token, array: Box<ASTNode<'a>>, setup: &mut Vec<Box<ASTNode<'a>>>) -> Loop<'a> {
+        let ty = self.types.type_of(&array).cloned().unwrap();
+        let Type::Array(element, length) = &ty else {
+            unreachable!()
+        };
+        let index_type = Type::Int(64, false);
+        let (items, index) = (self.variable("items", &ty), self.variable("index", &index_type));
+        setup.push(Box::new(ASTNode::VariableSet(items, Some(array), None, false)));
+        let zero = self.integer(0, &index_type);
+        setup.push(Box::new(ASTNode::VariableSet(index, Some(zero), None, true)));
+        let (current, length) = (self.read(index, &index_type), self.integer(*length, &index_type));
+        let condition = self.binary(current, TokenType::Lesser, length, &Type::Bool);
+        let (array, current) = (self.read(items, &ty), self.read(index, &index_type));
+        let item = self.typed(ASTNode::Index(array, current), element);
+        let head = vec![
+            Box::new(ASTNode::VariableSet(var, Some(item), None, false)),
+            self.increment(index, &index_type),
+        ];
+        (condition, head)
+    }
+
+    /// Statements replacing `for var in iterable { body }`.
+    fn lower(&mut self, var: &'a Token, iterable: Box<ASTNode<'a>>, body: Vec<Box<ASTNode<'a>>>) -> Vec<Box<ASTNode<'a>>> {
+        self.position = var.code_position.merge(iterable.position());
+        let mut statements = vec![];
+        // The array keeps its box, its type is recorded under that address
+        let (condition, mut head) = match *iterable {
+            ASTNode::Range(..) => self.range_loop(var, *iterable, &mut statements),
+            _ => self.array_loop(var, iterable, &mut statements),
+        };
+        head.extend(body);
+        statements.push(Box::new(ASTNode::WhileLoop(condition, head)));
+        statements
+    }
+
+    /// Lowers the loops of `body` in place, statements keep their boxes for the same reason.
+    fn lower_block(&mut self, body: &mut Vec<Box<ASTNode<'a>>>) {
+        for mut statement in std::mem::take(body) {
+            match &mut *statement {
+                ASTNode::WhileLoop(_, inner) | ASTNode::ForLoop(_, _, inner) => self.lower_block(inner),
+                ASTNode::If(_, then_body, else_body) => {
+                    self.lower_block(then_body);
+                    self.lower_block(else_body);
+                }
+                _ => {}
+            }
+            match *statement {
+                ASTNode::ForLoop(var, iterable, inner) => body.extend(self.lower(var, iterable, inner)),
+                _ => body.push(statement),
+            }
+        }
+    }
+}
+/// Lowers the `for` loops of every function of a checked module.
+pub fn lower_for_loops(ast: &mut [ASTNode], types: &mut TypeTable) {
+    for item in ast.iter_mut() {
+        if let ASTNode::FunctionDef(name, _, _, _, body, _) = item {
+            let mut lowering = LoopLowering { types, position: name.code_position };
+            lowering.lower_block(body);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{body, check};
+
+    #[test]
+    fn lowers_ranges_to_while_loops() {
+        let source = "def main(): i32 {\n    let mut total = 0;\n    for i in 1..4 {\n        total = total + i;\n    }\n\
+                      for j in 1..=3 {\n        continue;\n    }\n    return total;\n}\n";
+        assert_eq!(
+            body(&check(source).unwrap(), "main"),
+            [
+                "let mut total = 0",
+                "let mut index = 1",
+                "let end = 4",
+                "while index < end { let i = index; index = index + 1; total = total + i }",
+                "let mut index = 1",
+                "let end = 3",
+                "let mut more = index <= end",
+                "while more { let j = index; if index == end { more = false } else { index = index + 1 }; continue }",
+                "return total",
+            ]
+        );
+    }
+
+    #[test]
+    fn lowers_arrays_to_indexed_reads() {
+        let source = "def main(): i32 {\n    let mut total = 0;\n    for x in [1, 2] {\n        total = total + x;\n    }\n\
+                      return total;\n}\n";
+        assert_eq!(
+            body(&check(source).unwrap(), "main"),
+            [
+                "let mut total = 0",
+                "let items = [1, 2]",
+                "let mut index = 0",
+                "while index < 2 { let x = items[index]; index = index + 1; total = total + x }",
+                "return total",
+            ]
+        );
+    }
+}
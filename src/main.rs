@@ -1,50 +1,256 @@
+// Errors carry their snippets and notes, they are only built on the error path
+#![allow(clippy::result_large_err)]
+// Blocks of the syntax tree are `Vec<Box<ASTNode>>` everywhere
+#![allow(clippy::vec_box)]
+
 extern crate colorize_rs;
 
+use crate::bytecode::disassemble;
+use crate::cfg::check_control_flow;
+use crate::checker::check_calls;
 use crate::clparser::{fetch_args_clean, Argument, ArgumentParser, Flag};
 use crate::comp_errors::CodeResult;
+use crate::consteval::fold_constants;
+use crate::entry::{entry_point, EntryPoint, START_SYMBOL};
 use crate::filemanager::FileManager;
-use crate::lexer::tokenize;
+use crate::initialization::check_initialization;
+use crate::interpreter::{interpret, CALL_DEPTH_LIMIT, INTERPRETER_STACK_SIZE};
+use crate::lexer::Token;
+use crate::linker::{assemble, lld_link, LldFlavor};
+use crate::loops::lower_for_loops;
+use crate::modules::{link_imports, load_modules, Module, ModuleResult};
+use crate::mutability::check_mutability;
+use crate::ownership::insert_drops;
 use crate::parser::Parser;
+use crate::resolver::{resolve, warn_unused};
+use crate::runtime::{link_args, runtime_asm, start_asm, AllocatorKind};
+use crate::typeck::check_types;
+use crate::vm::execute;
+use colorize_rs::AnsiColor;
 use std::string::ToString;
 
+mod assembler;
+mod bytecode;
+mod c_backend;
+mod cfg;
+mod checker;
 mod clparser;
 mod codeviz;
 mod comp_errors;
+mod consteval;
+mod elf;
+mod entry;
 mod filemanager;
+mod initialization;
+mod interpreter;
+mod ir;
 mod lexer;
+mod linker;
+mod loops;
+mod llvm_backend;
+mod modules;
+mod mutability;
+mod ownership;
 mod parser;
+mod resolver;
+mod runtime;
+#[cfg(test)]
+mod testing;
+mod typeck;
+mod types;
+mod vm;
+mod wasm_backend;
 mod compiler;
 
-fn compile_job(file_manager: &FileManager) -> CodeResult<()> {
-    let tokens = tokenize(file_manager.get_content())?;
+fn check_module(module: &mut Module) -> CodeResult<()> {
+    let file_manager = module.file_manager;
+    let ast = &mut module.ast;
+
+    let resolution = resolve(ast, &module.imported)?;
+    check_calls(ast, &resolution)?;
+    warn_unused(ast, &resolution, file_manager);
+    check_mutability(ast, &resolution, file_manager)?;
+    check_initialization(ast, &resolution)?;
+    fold_constants(ast, &resolution)?;
+    let mut types = check_types(ast, &resolution, file_manager)?;
+    check_control_flow(ast, file_manager)?;
+    insert_drops(ast, &mut types)?;
+    lower_for_loops(ast, &mut types);
+    module.resolution = resolution;
+    module.types = types;
+    module.index_definitions();
+    Ok(())
+}
 
-    let parser = Parser::new(tokens, file_manager);
-    let ast = parser.parse(&mut 0)?;
+/// Parses and checks every module, the main module comes first.
+fn check_program<'a>(
+    parsers: &'a [Parser<'a>],
+    files: &'a [FileManager],
+    entry: Option<&str>,
+) -> ModuleResult<(Vec<Module<'a>>, EntryPoint)> {
+    let mut modules = vec![];
+    for (index, parser) in parsers.iter().enumerate() {
+        let ast = parser.parse(&mut 0);
+        parser.print_warnings();
+        modules.push(Module::new(&files[index], ast.map_err(|e| (e, index))?));
+    }
 
-    for item in ast {
-        println!("{:?}", item);
+    link_imports(&mut modules)?;
+    let entry = entry_point(&modules[0].ast, &modules[0].name, entry).map_err(|e| (e, 0))?;
+    for (index, module) in modules.iter_mut().enumerate() {
+        check_module(module).map_err(|e| (e, index))?;
+    }
+    Ok((modules, entry))
+}
+
+/// Forms `compile --emit` writes to the output path instead of an executable.
+const EMIT_KINDS: [&str; 8] = ["asm", "ast", "bytecode", "c", "ir", "llvm", "wasm", "wat"];
+
+/// Assembles the program, the start stub and the runtime and links them into the executable
+/// `output`, the program starts at `START_SYMBOL`.
+fn link_program(modules: &[Module], entry: &EntryPoint, output: &str, runtime: AllocatorKind) -> Result<(), String> {
+    let objects = [
+        (format!("{}.o", output), compiler::generate(modules, entry)),
+        (format!("{}.start.o", output), start_asm(entry, runtime)),
+        (format!("{}.runtime.o", output), runtime_asm(runtime).to_string()),
+    ];
+    let mut linked = Ok(());
+    for (object, source) in &objects {
+        linked = linked.and_then(|_| assemble(source, object));
+    }
+    let mut paths: Vec<String> = objects.iter().map(|(t, _)| t.clone()).collect();
+    paths.extend(link_args(runtime));
+    let linked = linked.and_then(|_| lld_link(LldFlavor::Elf, output.to_string(), false, paths, Some(START_SYMBOL.to_string())));
+    for (object, _) in &objects {
+        let _ = std::fs::remove_file(object);
+    }
+    linked
+}
+
+fn compile_job(
+    parsers: &[Parser],
+    files: &[FileManager],
+    entry: Option<&str>,
+    output: &str,
+    emit: Option<&str>,
+    runtime: AllocatorKind,
+) -> ModuleResult<()> {
+    let (modules, entry) = check_program(parsers, files, entry)?;
+
+    let emitted = match emit {
+        Some("asm") => Some(compiler::generate(&modules, &entry).into_bytes()),
+        Some("bytecode") => Some(disassemble(&bytecode::compile(&modules), &modules).into_bytes()),
+        Some("c") => Some(c_backend::generate(&modules, &entry).into_bytes()),
+        Some("ir") => Some(ir::generate(&modules, &entry).into_bytes()),
+        Some("llvm") => Some(llvm_backend::generate(&modules, &entry).into_bytes()),
+        Some("wasm") => Some(wasm_backend::generate_binary(&modules, &entry)),
+        Some("wat") => Some(wasm_backend::generate(&modules, &entry).into_bytes()),
+        Some(_) => Some(modules.iter().flat_map(|t| &t.ast).map(|t| format!("{:?}\n", t)).collect::<String>().into_bytes()),
+        None => None,
+    };
+    let written = match emitted {
+        Some(emitted) => std::fs::write(output, emitted).map_err(|e| format!("Could not write '{}': {}", output, e)),
+        None => link_program(&modules, &entry, output, runtime).map_err(|e| format!("Could not link '{}': {}", output, e)),
+    };
+    if let Err(error) = written {
+        eprintln!("{}", error.b_red().bold());
     }
 
     Ok(())
 }
 
-fn _compile(_: &ArgumentParser, args: &Vec<String>) -> bool {
-    let file_manager_r = FileManager::new_from(args[0].clone());
-    if file_manager_r.is_err() {
-        file_manager_r.unwrap_err().output();
+/// Reads the file at `path` and the modules it imports, the errors are reported here.
+fn load_program(path: &str) -> Option<(Vec<FileManager>, Vec<Vec<Token>>)> {
+    let file_manager = match FileManager::new_from(path.to_string()) {
+        Ok(file_manager) => file_manager,
+        Err(error) => {
+            error.output();
+            return None;
+        }
+    };
+
+    // The main file comes first, followed by the modules it imports
+    let mut files = vec![file_manager];
+    match load_modules(&mut files) {
+        Ok(sources) => Some((files, sources)),
+        Err((error, index)) => {
+            error.visualize_error(&files[index]);
+            None
+        }
+    }
+}
+
+fn _compile(_: &ArgumentParser, args: &[String]) -> bool {
+    // An empty kind means `--emit` was not given
+    let emit = args.get(3).filter(|t| !t.is_empty());
+    if let Some(kind) = emit.filter(|t| !EMIT_KINDS.contains(&t.as_str())) {
+        let expected = EMIT_KINDS.join(", ");
+        eprintln!("{}", format!("Unknown output kind '{}', expected one of: {}", kind, expected).b_red().bold());
         return true;
     }
+    let Some(runtime) = AllocatorKind::from_name(&args[4]) else {
+        let expected = AllocatorKind::NAMES.join(", ");
+        eprintln!("{}", format!("Unknown runtime '{}', expected one of: {}", args[4], expected).b_red().bold());
+        return true;
+    };
 
-    let file_manager = file_manager_r.unwrap();
+    let Some((files, sources)) = load_program(&args[0]) else {
+        return false;
+    };
+    let parsers: Vec<Parser> = sources
+        .into_iter()
+        .zip(&files)
+        .map(|(tokens, file_manager)| Parser::new(tokens, file_manager))
+        .collect();
 
-    let x = compile_job(&file_manager);
-    if x.is_err() {
-        x.unwrap_err().visualize_error(&file_manager);
+    // An empty entry means `--entry` was not given
+    let entry = args.get(2).filter(|t| !t.is_empty());
+    let x = compile_job(&parsers, &files, entry.map(String::as_str), &args[1], emit.map(String::as_str), runtime);
+    if let Err((error, index)) = x {
+        error.visualize_error(&files[index]);
     }
 
     false
 }
 
+fn run_job(path: &str, entry: Option<&str>, walk_tree: bool) -> Option<i32> {
+    let (files, sources) = load_program(path)?;
+    let parsers: Vec<Parser> = sources
+        .into_iter()
+        .zip(&files)
+        .map(|(tokens, file_manager)| Parser::new(tokens, file_manager))
+        .collect();
+
+    let result = check_program(&parsers, &files, entry).and_then(|(modules, entry)| match walk_tree {
+        true => interpret(&modules, &entry, path, &mut std::io::stdout()),
+        false => execute(&bytecode::compile(&modules), &entry, path, &mut std::io::stdout()),
+    });
+    match result {
+        Ok(code) => Some(code),
+        Err((error, index)) => {
+            error.visualize_error(&files[index]);
+            None
+        }
+    }
+}
+
+fn _run(_: &ArgumentParser, args: &[String]) -> bool {
+    let path = args[0].clone();
+    // An empty entry means `--entry` was not given
+    let entry = args.get(1).filter(|t| !t.is_empty()).cloned();
+    let walk_tree = args.get(2).is_some_and(|t| t == "interpret");
+
+    // Calls of the program recurse in the interpreter
+    let job = std::thread::Builder::new()
+        .stack_size(INTERPRETER_STACK_SIZE)
+        .spawn(move || run_job(&path, entry.as_deref(), walk_tree))
+        .unwrap();
+    match job.join().unwrap() {
+        Some(code) => std::process::exit(code),
+        None => std::process::exit(1),
+    }
+}
+
 fn main() {
     let mut argument_parser = ArgumentParser::new();
     argument_parser.add_help();
@@ -57,6 +263,13 @@ fn main() {
         "Compile a file".to_string(),
         false,
     ));
+    argument_parser.add_argument(Argument::new(
+        "run".to_string(),
+        vec!["file_path".to_string()],
+        mk_clfn!(_run),
+        format!("Run a file on the bytecode VM, calls nest at most {} deep", CALL_DEPTH_LIMIT),
+        false,
+    ));
     argument_parser.add_flag(Flag::new(
         "--output".to_string(),
         "-o".to_string(),
@@ -64,20 +277,64 @@ fn main() {
         empty!(),
         "Set output path".to_string(),
     ));
+    argument_parser.add_flag(Flag::new(
+        "--entry".to_string(),
+        "-e".to_string(),
+        true,
+        empty!(),
+        "Set the function the program starts with (default `main`)".to_string(),
+    ));
+    argument_parser.add_flag(Flag::new(
+        "--emit".to_string(),
+        "-E".to_string(),
+        true,
+        empty!(),
+        format!("Write another form of the program ({})", EMIT_KINDS.join(", ")),
+    ));
+    argument_parser.add_flag(Flag::new(
+        "--runtime".to_string(),
+        "-r".to_string(),
+        true,
+        empty!(),
+        format!("Set the allocation runtime linked into the program ({})", AllocatorKind::NAMES.join(", ")),
+    ));
+    argument_parser.add_flag(Flag::new(
+        "--interpret".to_string(),
+        "-i".to_string(),
+        false,
+        empty!(),
+        "Run by walking the syntax tree instead of on the bytecode VM".to_string(),
+    ));
 
-    let result = argument_parser.parse(fetch_args_clean(), true);
-    if result.is_err() {
-        argument_parser.handle_errors(result.unwrap_err());
-        return;
-    }
-    let (pending_calls, flag_map) = result.unwrap();
+    let (pending_calls, flag_map) = match argument_parser.parse(fetch_args_clean(), true) {
+        Ok(result) => result,
+        Err(error) => {
+            argument_parser.handle_errors(error);
+            return;
+        }
+    };
+    let interpret = pending_calls.iter().any(|t| t.has_name("--interpret".to_string()));
 
     for pending_call in pending_calls {
         if pending_call.has_name("compile".to_string()) {
             pending_call.call(
                 &argument_parser,
-                Some(&pending_call.merge_args(vec![(&flag_map).get("--output")
-                .unwrap().clone().or(Some("output".to_string())).unwrap()])),
+                Some(&pending_call.merge_args(vec![flag_map.get("--output")
+                .unwrap().clone().unwrap_or("output".to_string()),
+                flag_map.get("--entry").unwrap().clone().unwrap_or_default(),
+                flag_map.get("--emit").unwrap().clone().unwrap_or_default(),
+                flag_map.get("--runtime").unwrap().clone().unwrap_or("libc".to_string())])),
+            );
+            break;
+        }
+
+        if pending_call.has_name("run".to_string()) {
+            pending_call.call(
+                &argument_parser,
+                Some(&pending_call.merge_args(vec![
+                    flag_map.get("--entry").unwrap().clone().unwrap_or_default(),
+                    if interpret { "interpret" } else { "" }.to_string(),
+                ])),
             );
             break;
         }
@@ -0,0 +1,527 @@
+use crate::comp_errors::{CodeError, CodeResult};
+use crate::filemanager::FileManager;
+use crate::lexer::{tokenize, CodePosition, Token, TokenType};
+use crate::parser::{ASTNode, Attribute, AttributeKind, FunctionMode};
+use crate::resolver::{Resolution, PRIMITIVE_TYPES};
+use crate::typeck::TypeTable;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Error of one module, with the index of the module it belongs to.
+pub type ModuleResult<T> = Result<T, (CodeError, usize)>;
+
+/// A source file of the program, named after its file stem.
+pub struct Module<'a> {
+    pub name: String,
+    pub file_manager: &'a FileManager,
+    pub ast: Vec<ASTNode<'a>>,
+    // Declarations copied from imported modules -> the import they came through
+    pub imported: Vec<(&'a Token, &'a Token)>,
+    // Names and types, filled in once the module is checked
+    pub resolution: Resolution<'a>,
+    pub types: TypeTable,
+    // Function declaration -> index of its definition in `ast`, filled in once the module is checked
+    definitions: HashMap<*const Token, usize>,
+}
+
+impl<'a> Module<'a> {
+    pub fn new(file_manager: &'a FileManager, ast: Vec<ASTNode<'a>>) -> Self {
+        Self {
+            name: module_name(file_manager),
+            file_manager,
+            ast,
+            imported: vec![],
+            resolution: Resolution::default(),
+            types: TypeTable::default(),
+            definitions: HashMap::new(),
+        }
+    }
+
+    /// Indexes the function definitions, the passes no longer move items afterwards.
+    pub fn index_definitions(&mut self) {
+        self.definitions = self
+            .ast
+            .iter()
+            .enumerate()
+            .filter_map(|(index, t)| match t {
+                ASTNode::FunctionDef(name, ..) => Some((*name as *const Token, index)),
+                _ => None,
+            })
+            .collect();
+    }
+
+    /// Variables are keyed by their declaration, names the compiler inserted by their own token.
+    pub fn key(&self, usage: &Token) -> *const Token {
+        self.resolution.symbol_of(usage).map_or(usage, |t| t.declaration) as *const Token
+    }
+
+    /// Definition of the function `call` refers to, the stub of an imported function.
+    pub fn callee(&self, call: &Token) -> &ASTNode<'a> {
+        let declaration = self.resolution.symbol_of(call).unwrap().declaration;
+        &self.ast[self.definitions[&(declaration as *const Token)]]
+    }
+
+    /// Whether the module itself declares `name`.
+    fn defines(&self, name: &str) -> bool {
+        self.ast.iter().any(|t| match t {
+            ASTNode::FunctionDef(item, ..)
+            | ASTNode::StructDef(item, _)
+            | ASTNode::ConstDef(item, ..)
+            | ASTNode::Import(item) => item.content == name,
+            _ => false,
+        })
+    }
+
+    /// Functions defined in Sila by this module, more than one if overloaded.
+    fn functions(&self, name: &str) -> Vec<&ASTNode<'a>> {
+        self.ast
+            .iter()
+            .filter(|t| {
+                matches!(t, ASTNode::FunctionDef(item, mode, ..)
+                    if item.content == name && *mode != FunctionMode::Extern)
+            })
+            .collect()
+    }
+
+    fn structure(&self, name: &str) -> Option<&ASTNode<'a>> {
+        self.ast
+            .iter()
+            .find(|t| matches!(t, ASTNode::StructDef(item, _) if item.content == name))
+    }
+
+    fn drop_hook(&self, struct_name: &str) -> Option<&ASTNode<'a>> {
+        self.ast.iter().find(|t| match t {
+            ASTNode::FunctionDef(_, _, _, params, ..) => {
+                t.attribute(AttributeKind::Drop).is_some()
+                    && matches!(params.as_slice(), [(_, ty, _)]
+                        if matches!(&**ty, ASTNode::Type(item) if item.content == struct_name))
+            }
+            _ => false,
+        })
+    }
+}
+
+pub fn module_name(file_manager: &FileManager) -> String {
+    file_manager
+        .file_path
+        .file_stem()
+        .map_or(String::new(), |t| t.to_string_lossy().to_string())
+}
+
+/// Reads every module `files[0]` imports, directly or indirectly, into `files`
+/// and returns the tokens of each file. `import math` reads `math.sila` from the
+/// directory of the importing file, every file is read once.
+pub fn load_modules(files: &mut Vec<FileManager>) -> ModuleResult<Vec<Vec<Token>>> {
+    let mut sources = vec![];
+    let mut index = 0;
+    while index < files.len() {
+        let tokens = tokenize(files[index].get_content()).map_err(|e| (e, index))?;
+        for pair in tokens.windows(2) {
+            let [keyword, name] = pair else { unreachable!() };
+            if keyword.token_type != TokenType::Import || name.token_type != TokenType::Identifier {
+                continue;
+            }
+            let file_name = format!("{}.sila", name.content);
+            let path = files[index].file_path.with_file_name(&file_name);
+            if files.iter().any(|t| t.file_path == path) {
+                continue;
+            }
+            let display = Path::new(&files[index].input_file)
+                .with_file_name(&file_name)
+                .to_string_lossy()
+                .to_string();
+            let file = FileManager::new(path, display.clone())
+                .map_err(|_| (CodeError::unknown_module_error(name, &display), index))?;
+            files.push(file);
+        }
+        sources.push(tokens);
+        index += 1;
+    }
+    Ok(sources)
+}
+
+/// Copy of a type or literal expression from another module, pointing at `position`.
+/// Names in anything else would be resolved in the wrong module.
+fn relocate(node: &ASTNode, position: CodePosition) -> Option<ASTNode<'static>> {
+    let token = |t: &Token| Token::synthetic(t.content.clone(), t.token_type, position);
+    let boxed = |t: &ASTNode| relocate(t, position).map(Box::new);
+    Some(match node {
+        ASTNode::Type(t) => ASTNode::Type(token(t)),
+        ASTNode::Literal(t) => ASTNode::Literal(token(t)),
+        ASTNode::String(t) => ASTNode::String(token(t)),
+        ASTNode::PointerType(star, pointee) => ASTNode::PointerType(token(star), boxed(pointee)?),
+        ASTNode::ReferenceType(amp, mutable, referenced) => {
+            ASTNode::ReferenceType(token(amp), *mutable, boxed(referenced)?)
+        }
+        ASTNode::ArrayType(element, length) if matches!(**length, ASTNode::Literal(_)) => {
+            ASTNode::ArrayType(boxed(element)?, boxed(length)?)
+        }
+        ASTNode::CastExpr(expr, ty) => ASTNode::CastExpr(boxed(expr)?, boxed(ty)?),
+        ASTNode::BinaryOp(lhs, op, rhs) => ASTNode::BinaryOp(boxed(lhs)?, token(op), boxed(rhs)?),
+        _ => return None,
+    })
+}
+
+/// Names of the structs a type refers to.
+fn struct_names<'a>(ty: &ASTNode<'a>, names: &mut Vec<&'a str>) {
+    match ty {
+        ASTNode::Type(name) if !PRIMITIVE_TYPES.contains(&name.content.as_str()) => {
+            let name: &'a Token = name;
+            names.push(name.content.as_str());
+        }
+        ASTNode::PointerType(_, inner) | ASTNode::ReferenceType(_, _, inner) | ASTNode::ArrayType(inner, _) => {
+            struct_names(inner, names)
+        }
+        _ => {}
+    }
+}
+
+/// Called functions and used struct names.
+fn collect_uses<'a>(node: &ASTNode<'a>, functions: &mut Vec<&'a Token>, structs: &mut Vec<&'a Token>) {
+    match node {
+        ASTNode::FunctionCall(name, ..) => functions.push(name),
+        ASTNode::Type(name) | ASTNode::StructLiteral(name, _)
+            if !PRIMITIVE_TYPES.contains(&name.content.as_str()) =>
+        {
+            structs.push(name)
+        }
+        _ => {}
+    }
+    for child in node.children() {
+        collect_uses(child, functions, structs);
+    }
+}
+
+/// Copies what one module uses from its imports into declarations of its own.
+/// Functions become extern declarations named after their linker symbol, structs are
+/// copied along with their drop hook. Everything points at the import it came through.
+struct Linker<'a, 'm> {
+    modules: &'m [Module<'a>],
+    module: usize,
+    // Imported modules, by index, and the import naming them
+    imports: Vec<(usize, &'a Token)>,
+    stubs: Vec<ASTNode<'a>>,
+    imported: Vec<(&'a Token, &'a Token)>,
+    linked: HashSet<String>,
+}
+
+impl<'a, 'm> Linker<'a, 'm> {
+    fn new(modules: &'m [Module<'a>], module: usize) -> Self {
+        let mut imports = vec![];
+        for item in &modules[module].ast {
+            if let ASTNode::Import(name) = item {
+                let name: &'a Token = name;
+                if let Some(index) = modules.iter().position(|t| t.name == name.content) {
+                    if index != module {
+                        imports.push((index, name));
+                    }
+                }
+            }
+        }
+        Self {
+            modules,
+            module,
+            imports,
+            stubs: vec![],
+            imported: vec![],
+            linked: HashSet::new(),
+        }
+    }
+
+    /// Whether `name` is already declared in the importing module.
+    fn is_declared(&self, name: &str) -> bool {
+        self.linked.contains(name) || self.modules[self.module].defines(name)
+    }
+
+    fn link_function(&mut self, usage: &'a Token) -> CodeResult<()> {
+        let modules = self.modules;
+        let name = usage.content.as_str();
+        if self.is_declared(name) {
+            return Ok(());
+        }
+
+        let is_private = |t: &&ASTNode| matches!(t, ASTNode::FunctionDef(_, FunctionMode::Private, ..));
+        let candidates: Vec<(usize, &'a Token, Vec<&'m ASTNode<'a>>)> = self
+            .imports
+            .iter()
+            .map(|(index, import)| (*index, *import, modules[*index].functions(name)))
+            .filter(|(_, _, functions)| !functions.is_empty())
+            .collect();
+        let visible: Vec<_> = candidates
+            .iter()
+            .filter(|(_, _, functions)| !functions.iter().all(is_private))
+            .collect();
+        match (visible.as_slice(), candidates.first()) {
+            ([], None) => Ok(()),
+            ([], Some((index, ..))) => Err(CodeError::private_function_error(usage, &modules[*index].name)),
+            // Every public overload comes along
+            ([(index, import, functions)], _) => {
+                for function in functions.iter().filter(|t| !is_private(t)) {
+                    self.stub_function(*index, import, function)?;
+                }
+                Ok(())
+            }
+            ([(first, ..), (second, ..), ..], _) => Err(CodeError::ambiguous_import_error(
+                usage,
+                &modules[*first].name,
+                &modules[*second].name,
+            )),
+        }
+    }
+
+    /// Links the struct `name` from one of `imports`, `usage` is where it is needed.
+    fn link_struct(&mut self, usage: &'a Token, name: &str, imports: &[(usize, &'a Token)]) -> CodeResult<()> {
+        let modules = self.modules;
+        if self.is_declared(name) {
+            return Ok(());
+        }
+
+        let candidates: Vec<(usize, &'a Token, &'m ASTNode<'a>)> = imports
+            .iter()
+            .filter_map(|(index, import)| modules[*index].structure(name).map(|t| (*index, *import, t)))
+            .collect();
+        match candidates.as_slice() {
+            [] => Ok(()),
+            [(index, import, structure)] => self.stub_struct(*index, import, structure),
+            [(first, ..), (second, ..), ..] => Err(CodeError::ambiguous_import_error(
+                usage,
+                &modules[*first].name,
+                &modules[*second].name,
+            )),
+        }
+    }
+
+    fn stub_function(&mut self, index: usize, import: &'a Token, function: &'m ASTNode<'a>) -> CodeResult<()> {
+        let module = &self.modules[index];
+        let ASTNode::FunctionDef(name, _, ret, params, _, attributes) = function else {
+            unreachable!()
+        };
+        let position = import.code_position;
+        let unsupported = || CodeError::unsupported_import_error(import, &name.content, &module.name);
+
+        let mut stub_params = vec![];
+        let mut types = vec![];
+        for (param, ty, default) in params {
+            let default = match default {
+                Some(default) => Some(Box::new(relocate(default, position).ok_or_else(unsupported)?)),
+                None => None,
+            };
+            stub_params.push((
+                Token::synthetic(param.content.clone(), param.token_type, position) as &Token,
+                Box::new(relocate(ty, position).ok_or_else(unsupported)?),
+                default,
+            ));
+            struct_names(ty, &mut types);
+        }
+        struct_names(ret, &mut types);
+
+        // Calls go to the symbol of the definition
+        let symbol = function.symbol_name(&module.name).unwrap();
+        let mut stub_attributes = vec![Attribute {
+            kind: AttributeKind::ExportName,
+            name: Token::synthetic("export_name".to_string(), TokenType::Identifier, position),
+            args: vec![Token::synthetic(symbol, TokenType::String, position)],
+            position,
+        }];
+        for attribute in attributes {
+            if matches!(attribute.kind, AttributeKind::Drop | AttributeKind::NoReturn | AttributeKind::Overload) {
+                stub_attributes.push(Attribute {
+                    kind: attribute.kind,
+                    name: Token::synthetic(attribute.name.content.clone(), TokenType::Identifier, position),
+                    args: vec![],
+                    position,
+                });
+            }
+        }
+
+        let stub_name = Token::synthetic(name.content.clone(), TokenType::Identifier, position);
+        self.stubs.push(ASTNode::FunctionDef(
+            stub_name,
+            FunctionMode::Extern,
+            Box::new(relocate(ret, position).ok_or_else(unsupported)?),
+            stub_params,
+            vec![],
+            stub_attributes,
+        ));
+        self.imported.push((stub_name, import));
+        self.linked.insert(name.content.clone());
+
+        // Structs of the signature are the ones of the defining module
+        for ty in types {
+            self.link_struct(import, ty, &[(index, import)])?;
+        }
+        Ok(())
+    }
+
+    fn stub_struct(&mut self, index: usize, import: &'a Token, structure: &'m ASTNode<'a>) -> CodeResult<()> {
+        let module = &self.modules[index];
+        let ASTNode::StructDef(name, fields) = structure else {
+            unreachable!()
+        };
+        let position = import.code_position;
+
+        let mut stub_fields = vec![];
+        let mut types = vec![];
+        for (field, ty) in fields {
+            let ty = relocate(ty, position)
+                .ok_or_else(|| CodeError::unsupported_import_error(import, &name.content, &module.name))?;
+            stub_fields.push((
+                Token::synthetic(field.content.clone(), field.token_type, position) as &Token,
+                Box::new(ty),
+            ));
+        }
+        for (_, ty) in fields {
+            struct_names(ty, &mut types);
+        }
+
+        let stub_name = Token::synthetic(name.content.clone(), TokenType::Identifier, position);
+        self.stubs.push(ASTNode::StructDef(stub_name, stub_fields));
+        self.imported.push((stub_name, import));
+        self.linked.insert(name.content.clone());
+
+        for ty in types {
+            self.link_struct(import, ty, &[(index, import)])?;
+        }
+        // Values of the struct are dropped in the importing module as well
+        if let Some(hook @ ASTNode::FunctionDef(hook_name, ..)) = module.drop_hook(&name.content) {
+            if !self.is_declared(&hook_name.content) {
+                self.stub_function(index, import, hook)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn link(&mut self) -> CodeResult<()> {
+        let (mut functions, mut structs) = (vec![], vec![]);
+        for item in &self.modules[self.module].ast {
+            collect_uses(item, &mut functions, &mut structs);
+        }
+        let imports = self.imports.clone();
+        for usage in structs {
+            self.link_struct(usage, &usage.content, &imports)?;
+        }
+        for usage in functions {
+            self.link_function(usage)?;
+        }
+        Ok(())
+    }
+}
+
+/// Makes the functions and structs every module uses from its imports available to it.
+/// Private functions stay inside of their module, calling them from another one is an error.
+pub fn link_imports(modules: &mut [Module]) -> ModuleResult<()> {
+    let mut linked = vec![];
+    for index in 0..modules.len() {
+        let mut linker = Linker::new(modules, index);
+        linker.link().map_err(|e| (e, index))?;
+        linked.push((linker.stubs, linker.imported));
+    }
+    for (module, (stubs, imported)) in modules.iter_mut().zip(linked) {
+        module.ast.extend(stubs);
+        module.imported = imported;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check_module;
+    use crate::comp_errors::CodeErrorType;
+    use crate::parser::Parser;
+    use crate::testing::body;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static PROGRAMS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Writes `files` (name, source) to a new directory and checks the program
+    /// starting at the first one, the way `compile` does.
+    fn check_program(files: &[(&str, &str)]) -> ModuleResult<Vec<Module<'static>>> {
+        let id = PROGRAMS.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("sila-modules-{}-{}", std::process::id(), id));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, source) in files {
+            fs::write(dir.join(format!("{}.sila", name)), source).unwrap();
+        }
+
+        let main = format!("{}.sila", files[0].0);
+        let mut managers = vec![FileManager::new(dir.join(&main), main).unwrap()];
+        let loaded = load_modules(&mut managers);
+        fs::remove_dir_all(&dir).unwrap();
+        let managers: &'static [FileManager] = managers.leak();
+
+        let mut modules = vec![];
+        for (index, tokens) in loaded?.into_iter().enumerate() {
+            let parser: &'static Parser = Box::leak(Box::new(Parser::new(tokens, &managers[index])));
+            let ast = parser.parse(&mut 0).map_err(|e| (e, index))?;
+            modules.push(Module::new(&managers[index], ast));
+        }
+        link_imports(&mut modules)?;
+        for (index, module) in modules.iter_mut().enumerate() {
+            check_module(module).map_err(|e| (e, index))?;
+        }
+        Ok(modules)
+    }
+
+    fn check_program_err(files: &[(&str, &str)]) -> (CodeError, usize) {
+        match check_program(files) {
+            Ok(_) => panic!("Expected an error"),
+            Err(error) => error,
+        }
+    }
+
+    const MATH: &str = "def add(a: i32, b: i32): i32 {\n    return a + b;\n}\n\ndef private secret(): i32 {\n    return 1;\n}";
+
+    #[test]
+    fn calls_go_to_the_symbol_of_the_imported_function() {
+        let main = "import math\n\ndef @entry main(): i32 {\n    return add(1, b = 2);\n}";
+        let modules = check_program(&[("main", main), ("math", MATH)]).unwrap();
+        assert_eq!(modules.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["main", "math"]);
+        assert_eq!(body(&modules[0].ast, "main"), ["return add(1, b = 2)"]);
+
+        let stub = modules[0].ast.iter().find(|t| matches!(t, ASTNode::FunctionDef(name, ..) if name.content == "add"));
+        let stub = stub.unwrap();
+        assert!(matches!(stub, ASTNode::FunctionDef(_, FunctionMode::Extern, ..)));
+        assert_eq!(stub.symbol_name(&modules[0].name), modules[1].ast[0].symbol_name("math"));
+    }
+
+    #[test]
+    fn rejects_calls_of_private_functions() {
+        let main = "import math\n\ndef @entry main(): i32 {\n    return secret();\n}";
+        let (error, module) = check_program_err(&[("main", main), ("math", MATH)]);
+        assert!(matches!(error.code_error_type, CodeErrorType::PrivateFunction));
+        assert_eq!(module, 0);
+    }
+
+    #[test]
+    fn rejects_unknown_modules() {
+        let (error, module) = check_program_err(&[("main", "import nothing\n\ndef @entry main(): i32 {\n    return 0;\n}")]);
+        assert!(matches!(error.code_error_type, CodeErrorType::UnknownModule));
+        assert_eq!(module, 0);
+    }
+
+    #[test]
+    fn rejects_names_found_in_several_imports() {
+        let main = "import math\nimport other\n\ndef @entry main(): i32 {\n    return add(1, 2);\n}";
+        let (error, _) = check_program_err(&[("main", main), ("math", MATH), ("other", MATH)]);
+        assert!(matches!(error.code_error_type, CodeErrorType::DuplicateName));
+    }
+
+    #[test]
+    fn local_definitions_take_precedence_over_imports() {
+        let main = "import math\n\ndef add(a: i32, b: i32): i32 {\n    return a;\n}\n\ndef @entry main(): i32 {\n    return add(1, 2);\n}";
+        let modules = check_program(&[("main", main), ("math", MATH)]).unwrap();
+        let adds = modules[0].ast.iter().filter(|t| matches!(t, ASTNode::FunctionDef(name, ..) if name.content == "add"));
+        assert_eq!(adds.count(), 1);
+    }
+
+    #[test]
+    fn imports_every_public_overload() {
+        let shapes = "def @overload area(s: i32): i32 {\n    return s * s;\n}\n\ndef @overload area(w: i32, h: i32): i32 {\n    return w * h;\n}";
+        let main = "import shapes\n\ndef @entry main(): i32 {\n    return area(2) + area(2, 3);\n}";
+        let modules = check_program(&[("main", main), ("shapes", shapes)]).unwrap();
+        let stubs: Vec<_> = modules[0].ast.iter().filter_map(|t| t.symbol_name("main")).collect();
+        assert_eq!(stubs, ["main__main", "shapes__area__1", "shapes__area__2"]);
+    }
+}
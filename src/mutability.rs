@@ -0,0 +1,211 @@
+use crate::checker::bind_arguments;
+use crate::codeviz::print_code_warn;
+use crate::comp_errors::{CodeError, CodeResult, CodeWarning};
+use crate::filemanager::FileManager;
+use crate::lexer::{CodePosition, Token};
+use crate::parser::{ASTNode, FunctionMode, Parameter};
+use crate::resolver::Resolution;
+use std::collections::{HashMap, HashSet};
+
+fn is_mut_reference(ty: &ASTNode) -> bool {
+    matches!(ty, ASTNode::ReferenceType(_, true, _))
+}
+
+/// Only `let mut` bindings and `&mut` parameters may be assigned to or passed on
+/// to `&mut` parameters. Bindings that are `mut` without ever changing are reported.
+pub struct MutabilityChecker<'a, 'b> {
+    file_manager: &'b FileManager,
+    resolution: &'b Resolution<'a>,
+    // Function declaration -> (name, parameters)
+    functions: HashMap<*const Token, (&'a Token, &'b [Parameter<'a>])>,
+    // Mutable declarations of the current function, in source order
+    mutable: Vec<(&'a Token, bool)>,
+    mutated: HashSet<*const Token>,
+    // Immutable variables declared without a value, assigning them once initializes them
+    deferred: HashSet<*const Token>,
+}
+
+impl<'a, 'b> MutabilityChecker<'a, 'b> {
+    pub fn new(
+        ast: &'b [ASTNode<'a>],
+        resolution: &'b Resolution<'a>,
+        file_manager: &'b FileManager,
+    ) -> Self {
+        let mut functions = HashMap::new();
+        for item in ast {
+            if let ASTNode::FunctionDef(name, _, _, params, _, _) = item {
+                let name: &'a Token = name;
+                functions.insert(name as *const Token, (name, params.as_slice()));
+            }
+        }
+        Self {
+            file_manager,
+            resolution,
+            functions,
+            mutable: vec![],
+            mutated: HashSet::new(),
+            deferred: HashSet::new(),
+        }
+    }
+
+    fn is_mutable(&self, declaration: &Token) -> bool {
+        self.mutable.iter().any(|(t, _)| std::ptr::eq(*t, declaration))
+    }
+
+    fn is_deferred(&self, usage: &Token) -> bool {
+        let declaration = self.resolution.symbol_of(usage).unwrap().declaration;
+        self.deferred.contains(&(declaration as *const Token))
+    }
+
+    /// Marks the binding `usage` refers to as mutated, if it may be.
+    fn mutate(&mut self, usage: &'a Token, position: CodePosition) -> CodeResult<()> {
+        let symbol = self.resolution.symbol_of(usage).unwrap();
+        if !self.is_mutable(symbol.declaration) {
+            return Err(CodeError::immutable_assignment_error(
+                position,
+                symbol.declaration,
+                symbol.kind.describe(),
+            ));
+        }
+        self.mutated.insert(symbol.declaration as *const Token);
+        Ok(())
+    }
+
+    fn check_call(&mut self, call: &'a Token, args: &'b [Box<ASTNode<'a>>]) -> CodeResult<()> {
+        let declaration = self.resolution.symbol_of(call).unwrap().declaration;
+        let Some((function, params)) = self.functions.get(&(declaration as *const Token)).copied() else {
+            return Ok(());
+        };
+        let bound = bind_arguments(call, args, function, params)?;
+        for ((param, ty, _), arg) in params.iter().zip(bound) {
+            if !is_mut_reference(ty) {
+                continue;
+            }
+            match arg {
+                ASTNode::Identifier(name)
+                    if self
+                        .resolution
+                        .symbol_of(name)
+                        .is_some_and(|t| self.is_mutable(t.declaration)) =>
+                {
+                    self.mutate(name, arg.position())?
+                }
+                ASTNode::Identifier(name) => {
+                    let declaration = self.resolution.symbol_of(name).unwrap().declaration;
+                    return Err(CodeError::immutable_argument_error(arg.position(), param)
+                        .with_label(declaration.code_position, "Declared here".to_string()));
+                }
+                _ => return Err(CodeError::immutable_argument_error(arg.position(), param)),
+            }
+        }
+        Ok(())
+    }
+
+    fn check_node(&mut self, node: &'b ASTNode<'a>) -> CodeResult<()> {
+        match node {
+            ASTNode::VariableSet(name, _, _, true) => self.mutable.push((name, false)),
+            ASTNode::VariableSet(name, None, _, false) => {
+                self.deferred.insert(*name as *const Token);
+            }
+            // Initializes it, assigning twice is caught by the definite assignment analysis
+            ASTNode::Assignment(name, _) if self.is_deferred(name) => {}
+            ASTNode::Assignment(name, _) => self.mutate(name, node.position())?,
+            ASTNode::FunctionCall(name, args, _) => self.check_call(name, args)?,
+            _ => {}
+        }
+        for child in node.children() {
+            self.check_node(child)?;
+        }
+        Ok(())
+    }
+
+    fn check_function(&mut self, function: &'b ASTNode<'a>) -> CodeResult<()> {
+        let ASTNode::FunctionDef(_, mode, _, params, body, _) = function else {
+            return Ok(());
+        };
+        if matches!(mode, FunctionMode::Extern) {
+            return Ok(());
+        }
+
+        self.mutable = params
+            .iter()
+            .filter(|(_, ty, _)| is_mut_reference(ty))
+            .map(|(name, _, _)| (*name, true))
+            .collect();
+        for statement in body {
+            self.check_node(statement)?;
+        }
+
+        for (name, is_parameter) in &self.mutable {
+            if !self.mutated.contains(&(*name as *const Token)) {
+                print_code_warn(CodeWarning::new_unused_mut(name, *is_parameter), self.file_manager);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check(mut self, ast: &'b [ASTNode<'a>]) -> CodeResult<()> {
+        for item in ast {
+            self.check_function(item)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn check_mutability<'a>(
+    ast: &[ASTNode<'a>],
+    resolution: &Resolution<'a>,
+    file_manager: &FileManager,
+) -> CodeResult<()> {
+    MutabilityChecker::new(ast, resolution, file_manager).check(ast)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comp_errors::CodeErrorType;
+    use crate::resolver::resolve;
+    use crate::testing::{parse, source_file};
+
+    fn check_source(source: &str) -> CodeResult<()> {
+        let ast = parse(source);
+        let resolution = resolve(&ast, &[])?;
+        check_mutability(&ast, &resolution, source_file(source))
+    }
+
+    const INC: &str = "def inc(x: &mut i32): void {\n    x = x + 1;\n}\n\n";
+
+    #[test]
+    fn mutable_bindings_can_change() {
+        check_source("def f(): i32 {\n    let mut x = 1;\n    x = 2;\n    return x;\n}").unwrap();
+        check_source(&format!("{}def f(): i32 {{\n    let mut x = 1;\n    inc(x);\n    return x;\n}}", INC))
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_assigning_immutable_bindings() {
+        let error = check_source("def f(): i32 {\n    let x = 1;\n    x = 2;\n    return x;\n}").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::ImmutableAssignment));
+        assert_eq!(error.title, "Immutable variable");
+        assert_eq!(error.labels[0].0.line_start, 1);
+
+        let error = check_source("def f(x: i32): i32 {\n    x = 2;\n    return x;\n}").unwrap_err();
+        assert_eq!(error.title, "Immutable parameter");
+    }
+
+    #[test]
+    fn rejects_passing_immutable_bindings_as_mut() {
+        let error = check_source(&format!("{}def f(): i32 {{\n    let x = 1;\n    inc(x);\n    return x;\n}}", INC))
+            .unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::ImmutableAssignment));
+        assert_eq!(error.title, "Expected a mutable variable");
+
+        let error = check_source(&format!("{}def f(): void {{\n    inc(x = 1);\n}}", INC)).unwrap_err();
+        assert_eq!(error.title, "Expected a mutable variable");
+    }
+
+    #[test]
+    fn immutable_bindings_without_a_value_can_be_assigned() {
+        check_source("def f(): i32 {\n    let x: i32;\n    x = 2;\n    return x;\n}").unwrap();
+    }
+}
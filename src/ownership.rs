@@ -0,0 +1,518 @@
+use crate::comp_errors::{CodeError, CodeResult};
+use crate::lexer::{CodePosition, Token, TokenType};
+use crate::parser::{ASTNode, AttributeKind};
+use crate::typeck::TypeTable;
+use std::collections::{HashMap, HashSet};
+
+struct Local<'a> {
+    name: &'a Token,
+    // Drop hook of the local's type, if it has one
+    hook: Option<&'a Token>,
+    moved: Option<CodePosition>,
+    loop_depth: usize,
+    // `&mut` parameters can be assigned to, but the caller owns and drops the value
+    borrowed: bool,
+}
+
+/// Inserts calls to `@drop` hooks where values go out of scope and enforces move semantics.
+/// Every use of a value whose type has a drop hook moves it, so it is dropped exactly once:
+/// at the end of the block declaring it, or before a `return` leaving that block.
+pub struct DropInserter<'a, 'b> {
+    // Types of the checked module, extended by the temporaries this introduces
+    types: &'b mut TypeTable,
+    // Struct name -> drop hook
+    hooks: HashMap<&'a str, &'a Token>,
+    // Function name -> return type name
+    return_types: HashMap<&'a str, &'a str>,
+    // Function name -> parameter names and whether they are passed by reference
+    parameters: HashMap<&'a str, Vec<(&'a str, bool)>>,
+    scopes: Vec<Vec<Local<'a>>>,
+    loop_depth: usize,
+    // Number of scopes outside of each enclosing loop body
+    loop_scopes: Vec<usize>,
+}
+
+fn type_name<'a>(ty: &ASTNode<'a>) -> Option<&'a str> {
+    match ty {
+        ASTNode::Type(t) => {
+            let t: &'a Token = t;
+            Some(t.content.as_str())
+        }
+        _ => None,
+    }
+}
+
+fn drop_call<'a>(hook: &'a Token, name: &'a Token) -> Box<ASTNode<'a>> {
+    Box::new(ASTNode::FunctionCall(
+        hook,
+        vec![Box::new(ASTNode::Identifier(name))],
+        hook,
+    ))
+}
+
+impl<'a, 'b> DropInserter<'a, 'b> {
+    pub fn new(ast: &[ASTNode<'a>], types: &'b mut TypeTable) -> CodeResult<Self> {
+        let mut structs = HashSet::new();
+        for item in ast {
+            if let ASTNode::StructDef(name, _) = item {
+                let name: &'a Token = name;
+                structs.insert(name.content.as_str());
+            }
+        }
+
+        let mut hooks: HashMap<&'a str, &'a Token> = HashMap::new();
+        let mut return_types = HashMap::new();
+        let mut parameters = HashMap::new();
+        for item in ast {
+            if let ASTNode::FunctionDef(name, _, ret, params, _, _) = item {
+                let name: &'a Token = name;
+                if let Some(ret) = type_name(ret) {
+                    return_types.insert(name.content.as_str(), ret);
+                }
+                let by_reference = params
+                    .iter()
+                    .map(|(t, ty, _)| {
+                        let t: &'a Token = t;
+                        (t.content.as_str(), matches!(**ty, ASTNode::ReferenceType(..)))
+                    })
+                    .collect::<Vec<_>>();
+                parameters.insert(name.content.as_str(), by_reference);
+
+                if let Some(attribute) = item.attribute(AttributeKind::Drop) {
+                    let target = match params.as_slice() {
+                        [(_, ty, _)] => type_name(ty).filter(|t| structs.contains(t)),
+                        _ => None,
+                    };
+                    let target = target
+                        .ok_or_else(|| CodeError::invalid_drop_hook_error(attribute.position, name))?;
+                    if let Some(first) = hooks.get(target) {
+                        return Err(CodeError::duplicate_drop_hook_error(name, first, target));
+                    }
+                    hooks.insert(target, name);
+                }
+            }
+        }
+
+        Ok(Self {
+            types,
+            hooks,
+            return_types,
+            parameters,
+            scopes: vec![],
+            loop_depth: 0,
+            loop_scopes: vec![],
+        })
+    }
+
+    fn hook_of_type(&self, ty: &ASTNode<'a>) -> Option<&'a Token> {
+        type_name(ty).and_then(|t| self.hooks.get(t).copied())
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<&mut Local<'a>> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|t| t.iter_mut().rev())
+            .find(|t| t.name.content == name)
+    }
+
+    /// Drop hook of the value an expression produces.
+    fn hook_of_value(&mut self, value: &ASTNode<'a>) -> Option<&'a Token> {
+        match value {
+            ASTNode::StructLiteral(name, _) => self.hooks.get(name.content.as_str()).copied(),
+            ASTNode::Identifier(name) => self.lookup(&name.content).and_then(|t| t.hook),
+            ASTNode::FunctionCall(name, ..) => self
+                .return_types
+                .get(name.content.as_str())
+                .and_then(|t| self.hooks.get(t))
+                .copied(),
+            _ => None,
+        }
+    }
+
+    fn use_local(&mut self, usage: &'a Token) -> CodeResult<()> {
+        let depth = self.loop_depth;
+        if let Some(local) = self.lookup(&usage.content) {
+            if local.hook.is_none() {
+                return Ok(());
+            }
+            if local.borrowed {
+                return Err(CodeError::move_out_of_reference_error(usage, local.name));
+            }
+            if let Some(moved) = local.moved {
+                return Err(CodeError::use_after_move_error(usage, moved));
+            }
+            if local.loop_depth < depth {
+                return Err(CodeError::move_in_loop_error(usage, local.name));
+            }
+            local.moved = Some(usage.code_position);
+        }
+        Ok(())
+    }
+
+    /// Whether an argument is passed to a reference parameter, which borrows instead of moving.
+    fn is_borrowed(&self, function: &str, idx: usize, arg: &ASTNode) -> bool {
+        let Some(params) = self.parameters.get(function) else {
+            return false;
+        };
+        match arg {
+            ASTNode::NamedArgument(name, _) => params.iter().any(|(t, r)| *r && *t == name.content),
+            _ => params.get(idx).is_some_and(|(_, r)| *r),
+        }
+    }
+
+    fn visit_expr(&mut self, node: &ASTNode<'a>) -> CodeResult<()> {
+        match node {
+            ASTNode::Identifier(name) => return self.use_local(name),
+            ASTNode::FunctionCall(name, args, _) => {
+                for (idx, arg) in args.iter().enumerate() {
+                    if !self.is_borrowed(&name.content, idx, arg) {
+                        self.visit_expr(arg)?;
+                    }
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+        for child in node.children() {
+            self.visit_expr(child)?;
+        }
+        Ok(())
+    }
+
+    fn declare(&mut self, name: &'a Token, hook: Option<&'a Token>) {
+        let loop_depth = self.loop_depth;
+        self.scopes.last_mut().unwrap().push(Local {
+            name,
+            hook,
+            moved: None,
+            loop_depth,
+            borrowed: false,
+        });
+    }
+
+    /// Drop calls for the live values of the innermost `depth` scopes, innermost first.
+    fn drops(&self, depth: usize) -> Vec<Box<ASTNode<'a>>> {
+        self.scopes
+            .iter()
+            .rev()
+            .take(depth)
+            .flat_map(|t| t.iter().rev())
+            .filter(|t| t.moved.is_none() && !t.borrowed)
+            .filter_map(|t| t.hook.map(|hook| drop_call(hook, t.name)))
+            .collect()
+    }
+
+    fn moved_state(&self) -> Vec<Vec<Option<CodePosition>>> {
+        self.scopes
+            .iter()
+            .map(|t| t.iter().map(|t| t.moved).collect())
+            .collect()
+    }
+
+    fn restore_moved_state(&mut self, state: &[Vec<Option<CodePosition>>]) {
+        for (scope, moved) in self.scopes.iter_mut().zip(state) {
+            for (local, moved) in scope.iter_mut().zip(moved) {
+                local.moved = *moved;
+            }
+        }
+    }
+
+    /// Processes both branches of an `if` from the same state. A value moved in only one
+    /// branch is dropped at the end of the other one, so it is dead after the `if` either way.
+    fn process_branches(
+        &mut self,
+        then_body: &mut Vec<Box<ASTNode<'a>>>,
+        else_body: &mut Vec<Box<ASTNode<'a>>>,
+    ) -> CodeResult<bool> {
+        let before = self.moved_state();
+        let (processed, then_diverged) = self.process_block(std::mem::take(then_body))?;
+        *then_body = processed;
+        let after_then = self.moved_state();
+
+        self.restore_moved_state(&before);
+        let (processed, else_diverged) = self.process_block(std::mem::take(else_body))?;
+        *else_body = processed;
+        let after_else = self.moved_state();
+
+        for (depth, scope) in self.scopes.iter_mut().enumerate() {
+            for (idx, local) in scope.iter_mut().enumerate() {
+                let (then_moved, else_moved) = (after_then[depth][idx], after_else[depth][idx]);
+                local.moved = match (then_diverged, else_diverged) {
+                    (true, _) => else_moved,
+                    (_, true) => then_moved,
+                    _ => then_moved.or(else_moved),
+                };
+                let (Some(hook), false) = (local.hook, then_diverged || else_diverged) else {
+                    continue;
+                };
+                match (then_moved, else_moved) {
+                    (Some(_), None) => else_body.push(drop_call(hook, local.name)),
+                    (None, Some(_)) => then_body.push(drop_call(hook, local.name)),
+                    _ => {}
+                }
+            }
+        }
+        Ok(then_diverged && else_diverged)
+    }
+
+    /// Statements are updated in place, so nodes keep their addresses for the type table.
+    /// Also returns whether the block always leaves through `return`, `break` or `continue`.
+    fn process_block(
+        &mut self,
+        body: Vec<Box<ASTNode<'a>>>,
+    ) -> CodeResult<(Vec<Box<ASTNode<'a>>>, bool)> {
+        self.scopes.push(vec![]);
+        let mut statements = vec![];
+        let mut diverged = false;
+
+        for mut statement in body {
+            // Unreachable statements never run, so they neither move nor drop
+            if diverged {
+                statements.push(statement);
+                continue;
+            }
+            // Hook and holder of a value the statement produces without storing it
+            let mut unused = None;
+            match &mut *statement {
+                ASTNode::VariableSet(name, value, annotation, _) => {
+                    let name: &'a Token = name;
+                    let hook = match (annotation, &value) {
+                        (Some(ty), _) => self.hook_of_type(ty),
+                        (None, Some(value)) => self.hook_of_value(value),
+                        (None, None) => None,
+                    };
+                    // The initializer still sees an outer binding of the same name
+                    if let Some(value) = value {
+                        self.visit_expr(value)?;
+                    }
+                    self.declare(name, hook);
+                    if value.is_none() {
+                        // Nothing to drop until the first assignment
+                        self.lookup(&name.content).unwrap().moved = Some(name.code_position);
+                    }
+                }
+                ASTNode::Assignment(name, value) => {
+                    // The old value is dropped before it is overwritten
+                    self.visit_expr(value)?;
+                    let hook = self.hook_of_value(value);
+                    if let Some(local) = self.lookup(&name.content) {
+                        // Variables declared without type and value learn it here
+                        local.hook = local.hook.or(hook);
+                        if let (Some(hook), None) = (local.hook, local.moved) {
+                            statements.push(drop_call(hook, local.name));
+                        }
+                        local.moved = None;
+                    }
+                }
+                ASTNode::WhileLoop(condition, body) => {
+                    self.loop_depth += 1;
+                    self.visit_expr(condition)?;
+                    self.loop_scopes.push(self.scopes.len());
+                    *body = self.process_block(std::mem::take(body))?.0;
+                    self.loop_scopes.pop();
+                    self.loop_depth -= 1;
+                }
+                ASTNode::ForLoop(var, iterable, body) => {
+                    let var: &'a Token = var;
+                    self.visit_expr(iterable)?;
+                    self.loop_depth += 1;
+                    self.scopes.push(vec![]);
+                    self.declare(var, None);
+                    self.loop_scopes.push(self.scopes.len());
+                    *body = self.process_block(std::mem::take(body))?.0;
+                    self.loop_scopes.pop();
+                    self.scopes.pop();
+                    self.loop_depth -= 1;
+                }
+                ASTNode::If(condition, then_body, else_body) => {
+                    self.visit_expr(condition)?;
+                    diverged = self.process_branches(then_body, else_body)?;
+                }
+                ASTNode::Return(value) => {
+                    self.visit_expr(value)?;
+                    let mut drops = self.drops(self.scopes.len());
+                    // Values the returned expression borrows are alive while it runs,
+                    // so it is computed into a temporary before anything is dropped
+                    if !drops.is_empty()
+                        && !matches!(**value, ASTNode::Identifier(_) | ASTNode::Literal(_))
+                    {
+                        let result = Token::synthetic(
+                            "result".to_string(),
+                            TokenType::Identifier,
+                            value.position(),
+                        );
+                        let ty = self.types.type_of(value).cloned().unwrap();
+                        let computed = std::mem::replace(value, Box::new(ASTNode::Identifier(result)));
+                        self.types.declare(result, ty.clone());
+                        self.types.record(value, ty);
+                        statements.push(Box::new(ASTNode::VariableSet(result, Some(computed), None, false)));
+                    }
+                    statements.append(&mut drops);
+                    diverged = true;
+                }
+                ASTNode::Break(_) | ASTNode::Continue(_) => {
+                    // Leaves every scope opened inside of the loop body
+                    let outside = self.loop_scopes.last().copied().unwrap_or(self.scopes.len());
+                    statements.append(&mut self.drops(self.scopes.len() - outside));
+                    diverged = true;
+                }
+                other => {
+                    self.visit_expr(other)?;
+                    if let Some(hook) = self.hook_of_value(other) {
+                        let temporary = Token::synthetic(
+                            "temporary".to_string(),
+                            TokenType::Identifier,
+                            other.position(),
+                        );
+                        self.types.declare(temporary, self.types.type_of(other).cloned().unwrap());
+                        unused = Some((hook, temporary));
+                    }
+                }
+            }
+            // A value nobody takes is held in a temporary and dropped right away
+            if let Some((hook, temporary)) = unused {
+                statement = Box::new(ASTNode::VariableSet(temporary, Some(statement), None, false));
+                statements.push(statement);
+                statements.push(drop_call(hook, temporary));
+                continue;
+            }
+            statements.push(statement);
+        }
+
+        if !diverged {
+            statements.append(&mut self.drops(1));
+        }
+        self.scopes.pop();
+        Ok((statements, diverged))
+    }
+
+    fn process_function(&mut self, function: &mut ASTNode<'a>) -> CodeResult<()> {
+        let is_hook = function.attribute(AttributeKind::Drop).is_some();
+        if let ASTNode::FunctionDef(_, _, _, params, body, _) = function {
+            self.scopes.push(vec![]);
+            for (name, ty, _) in params.iter() {
+                // A drop hook must not drop the value it is dropping
+                let (referenced, borrowed) = match &**ty {
+                    ASTNode::ReferenceType(_, _, referenced) => (&**referenced, true),
+                    other => (other, false),
+                };
+                let hook = if is_hook { None } else { self.hook_of_type(referenced) };
+                self.declare(name, hook);
+                self.scopes.last_mut().unwrap().last_mut().unwrap().borrowed = borrowed;
+            }
+
+            let (mut processed, diverged) = self.process_block(std::mem::take(body))?;
+            if !diverged {
+                processed.append(&mut self.drops(1));
+            }
+            *body = processed;
+            self.scopes.pop();
+        }
+        Ok(())
+    }
+}
+
+/// Runs the drop insertion over every function of a module.
+pub fn insert_drops(ast: &mut [ASTNode], types: &mut TypeTable) -> CodeResult<()> {
+    let mut inserter = DropInserter::new(ast, types)?;
+    for item in ast.iter_mut() {
+        inserter.process_function(item)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::comp_errors::CodeErrorType;
+    use crate::testing::{body, check, check_err};
+
+    const FILE: &str = "struct File {
+    fd: i32
+}
+
+def @drop close(f: File): void {
+}
+
+def take(f: File): void {
+}
+
+def size(f: &File): i32 {
+    return 4;
+}
+";
+
+    fn program(main: &str) -> String {
+        format!("{}\ndef main(): i32 {{\n{}\n}}\n", FILE, main)
+    }
+
+    #[test]
+    fn drops_values_before_returning() {
+        let ast = check(&program("let f = File { fd: 1 };\nreturn 0;")).unwrap();
+        assert_eq!(body(&ast, "main"), ["let f = File { fd: 1 }", "close(f)", "return 0"]);
+    }
+
+    #[test]
+    fn drops_values_nobody_takes() {
+        let source = format!(
+            "{}\ndef open(): File {{\n    return File {{ fd: 3 }};\n}}\n\ndef main(): i32 {{\n\
+             open();\nFile {{ fd: 5 }};\nreturn 0;\n}}\n",
+            FILE
+        );
+        let ast = check(&source).unwrap();
+        assert_eq!(
+            body(&ast, "main"),
+            ["let temporary = open()", "close(temporary)", "let temporary = File { fd: 5 }", "close(temporary)", "return 0"]
+        );
+    }
+
+    #[test]
+    fn moved_values_are_not_dropped() {
+        let ast = check(&program("let f = File { fd: 1 };\ntake(f);\nreturn 0;")).unwrap();
+        assert_eq!(body(&ast, "main"), ["let f = File { fd: 1 }", "take(f)", "return 0"]);
+        assert_eq!(body(&ast, "take"), ["close(f)"]);
+    }
+
+    #[test]
+    fn borrowed_values_outlive_the_returned_expression() {
+        let ast = check(&program("let f = File { fd: 1 };\nreturn size(f);")).unwrap();
+        assert_eq!(
+            body(&ast, "main"),
+            ["let f = File { fd: 1 }", "let result = size(f)", "close(f)", "return result"]
+        );
+    }
+
+    #[test]
+    fn drops_values_moved_in_only_one_branch_in_the_other() {
+        let source = program(
+            "let f = File { fd: 1 };\nif true {\ntake(f);\n} else {\nlet x = 1;\n}\nreturn 0;",
+        );
+        let ast = check(&source).unwrap();
+        assert_eq!(
+            body(&ast, "main"),
+            ["let f = File { fd: 1 }", "if true { take(f) } else { let x = 1; close(f) }", "return 0"]
+        );
+    }
+
+    #[test]
+    fn break_drops_the_loop_body() {
+        let source = program("while true {\nlet f = File { fd: 1 };\nbreak;\n}\nreturn 0;");
+        let ast = check(&source).unwrap();
+        assert_eq!(
+            body(&ast, "main"),
+            ["while true { let f = File { fd: 1 }; close(f); break }", "return 0"]
+        );
+    }
+
+    #[test]
+    fn rejects_use_after_move() {
+        let error = check_err(&program("let f = File { fd: 1 };\ntake(f);\ntake(f);\nreturn 0;"));
+        assert!(matches!(error.code_error_type, CodeErrorType::UseAfterMove));
+    }
+
+    #[test]
+    fn rejects_moves_out_of_outer_values_in_loops() {
+        let error = check_err(&program("let f = File { fd: 1 };\nwhile true {\ntake(f);\n}\nreturn 0;"));
+        assert!(matches!(error.code_error_type, CodeErrorType::UseAfterMove));
+    }
+}
@@ -2,11 +2,15 @@ use crate::codeviz::print_code_warn;
 use crate::comp_errors::{CodeError, CodeResult, CodeWarning};
 use crate::filemanager::FileManager;
 use crate::lexer::{CodePosition, Token, TokenType};
-use crate::parser::ASTNode::FunctionCall;
+use std::cell::{Cell, RefCell};
 
 pub struct Parser<'a> {
     tokens: Vec<Token>,
     file_manager: &'a FileManager,
+    // Cleared while parsing conditions and iterables, where `{` opens the body
+    struct_literals: Cell<bool>,
+    // Printed by `print_warnings` once parsing is done
+    warnings: RefCell<Vec<CodeWarning>>,
 }
 
 impl<'a> Parser<'a> {
@@ -14,6 +18,19 @@ impl<'a> Parser<'a> {
         Self {
             tokens,
             file_manager,
+            struct_literals: Cell::new(true),
+            warnings: RefCell::new(vec![]),
+        }
+    }
+
+    /// Warnings found while parsing, in source order.
+    pub fn take_warnings(&self) -> Vec<CodeWarning> {
+        self.warnings.take()
+    }
+
+    pub fn print_warnings(&self) {
+        for warning in self.take_warnings() {
+            print_code_warn(warning, self.file_manager)
         }
     }
 
@@ -88,7 +105,7 @@ impl<'a> Parser<'a> {
     }
 
     fn warning(&self, code_warning: CodeWarning) {
-        print_code_warn(code_warning, self.file_manager)
+        self.warnings.borrow_mut().push(code_warning)
     }
 
     fn codepos_from_space(&self, s: usize, e: &usize, sub_off: usize) -> CodePosition {
@@ -104,7 +121,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn parse(&self, pointer: &mut usize) -> CodeResult<Vec<ASTNode>> {
+    pub fn parse(&self, pointer: &mut usize) -> CodeResult<Vec<ASTNode<'_>>> {
         let mut statements = Vec::new();
 
         while let Some(token) = self.peek(pointer) {
@@ -116,6 +133,32 @@ impl<'a> Parser<'a> {
                     statements.push(func);
                 }
 
+                // Parse struct definitions
+                TokenType::Struct => {
+                    let struct_def = self.parse_struct(pointer)?;
+                    statements.push(struct_def);
+                }
+
+                // Parse constants and `const def` functions
+                TokenType::Const => {
+                    let keyword = self.advance(pointer).unwrap();
+                    if self.match_token(pointer, TokenType::Define)? {
+                        let mut func = self.parse_function(pointer)?;
+                        if let ASTNode::FunctionDef(.., attributes) = &mut func {
+                            attributes.push(Attribute {
+                                kind: AttributeKind::Const,
+                                name: keyword,
+                                args: vec![],
+                                position: keyword.code_position,
+                            });
+                        }
+                        statements.push(func);
+                    } else {
+                        let const_def = self.parse_const(pointer)?;
+                        statements.push(const_def);
+                    }
+                }
+
                 // Parse import statements
                 TokenType::Import => {
                     let import_stmt = self.parse_import(pointer)?;
@@ -132,7 +175,7 @@ impl<'a> Parser<'a> {
     }
 
     // Parse import statement (assuming a simple import structure)
-    fn parse_import(&self, pointer: &mut usize) -> CodeResult<ASTNode> {
+    fn parse_import(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
         // Consume 'import' keyword
         self.consume(pointer, TokenType::Import, None)?;
 
@@ -143,14 +186,157 @@ impl<'a> Parser<'a> {
         Ok(ASTNode::Import(module_name))
     }
 
-    pub fn parse_function(&self, pointer: &mut usize) -> CodeResult<ASTNode> {
+    // Parse a constant after the `const` keyword, like `const SIZE: u64 = 4 * 8;`
+    fn parse_const(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        let name = self.consume(pointer, TokenType::Identifier, None)?;
+        self.consume(
+            pointer,
+            TokenType::Colon,
+            Some("Constants need a type, like `const SIZE: u64 = 8;`".to_string()),
+        )?;
+        let ty = self.parse_type(pointer)?;
+        self.consume(pointer, TokenType::Equals, None)?;
+        let value = self.parse_expression(pointer)?;
+        self.consume(pointer, TokenType::SemiColon, None)?;
+        Ok(ASTNode::ConstDef(name, Box::new(ty), Box::new(value)))
+    }
+
+    fn parse_struct(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        self.consume(pointer, TokenType::Struct, None)?;
+        let name = self.consume(pointer, TokenType::Identifier, None)?;
+        self.consume(pointer, TokenType::LBrace, None)?;
+
+        let mut fields = vec![];
+        while !self.match_token(pointer, TokenType::RBrace)? {
+            let field = self.consume(pointer, TokenType::Identifier, None)?;
+            self.consume(pointer, TokenType::Colon, None)?;
+            fields.push((field, Box::new(self.parse_type(pointer)?)));
+            if self.match_token(pointer, TokenType::RBrace)? {
+                break;
+            }
+            self.consume(pointer, TokenType::Comma, Some("Add a comma".to_string()))?;
+        }
+
+        Ok(ASTNode::StructDef(name, fields))
+    }
+
+    // `Name { field: expr, ... }`, the name was already consumed
+    fn parse_struct_literal(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        let name = self.previous(pointer).unwrap();
+        self.consume(pointer, TokenType::LBrace, None)?;
+
+        let mut fields = vec![];
+        while !self.match_token(pointer, TokenType::RBrace)? {
+            let field = self.consume(pointer, TokenType::Identifier, None)?;
+            self.consume(pointer, TokenType::Colon, None)?;
+            fields.push((field, Box::new(self.parse_expression(pointer)?)));
+            if self.match_token(pointer, TokenType::RBrace)? {
+                break;
+            }
+            self.consume(pointer, TokenType::Comma, Some("Add a comma".to_string()))?;
+        }
+
+        Ok(ASTNode::StructLiteral(name, fields))
+    }
+
+    /// `{ field:` or `{ }` after an identifier starts a struct literal rather than a block.
+    /// Runs `parse` with struct literals allowed or not. Conditions and iterables
+    /// only allow them nested in parentheses, brackets or arguments.
+    fn with_struct_literals<T>(&self, allowed: bool, parse: impl FnOnce() -> T) -> T {
+        let before = self.struct_literals.replace(allowed);
+        let result = parse();
+        self.struct_literals.set(before);
+        result
+    }
+
+    fn is_struct_literal(&self, pointer: &usize) -> bool {
+        if !self.struct_literals.get() {
+            return false;
+        }
+        let kind = |offset: usize| self.tokens.get(*pointer + offset).map(|t| t.token_type);
+        kind(0) == Some(TokenType::LBrace)
+            && (kind(1) == Some(TokenType::RBrace)
+                || (kind(1) == Some(TokenType::Identifier) && kind(2) == Some(TokenType::Colon)))
+    }
+
+    fn parse_attributes(&self, pointer: &mut usize) -> CodeResult<Vec<Attribute<'_>>> {
+        let mut attributes: Vec<Attribute> = vec![];
+
+        while self.match_token(pointer, TokenType::At)? {
+            let start = *pointer - 1;
+            let name = self.consume(
+                pointer,
+                TokenType::Identifier,
+                Some("Attributes look like `@inline`".to_string()),
+            )?;
+
+            let mut args = vec![];
+            if self.match_token(pointer, TokenType::LParen)? {
+                while !self.match_token(pointer, TokenType::RParen)? {
+                    if !self.multi_match_token(
+                        pointer,
+                        vec![TokenType::String, TokenType::NumberInt, TokenType::Identifier],
+                    )? {
+                        return Err(CodeError::new_unexpected_token_error(
+                            self.current(pointer).unwrap(),
+                            TokenType::String,
+                            Some("Attribute arguments must be literals".to_string()),
+                        ));
+                    }
+                    args.push(self.advance(pointer).unwrap());
+                    if self.match_token(pointer, TokenType::RParen)? {
+                        break;
+                    }
+                    self.consume(pointer, TokenType::Comma, Some("Add a comma".to_string()))?;
+                }
+            }
+
+            let position = self.codepos_from_space(start, pointer, 1);
+            let kind = match AttributeKind::from_name(&name.content) {
+                Some(kind) => kind,
+                None => {
+                    self.warning(CodeWarning::new_unknown_attribute(position, &name.content));
+                    continue;
+                }
+            };
+
+            if !kind.accepts(&args) {
+                return Err(CodeError::invalid_attribute_error(
+                    position,
+                    &name.content,
+                    kind.expected_arguments(),
+                ));
+            }
+
+            if attributes.iter().any(|t| t.kind == kind) {
+                self.warning(CodeWarning::new_unnecessary_code(
+                    position,
+                    Some(format!("`@{}` is already set on this function", name.content)),
+                ));
+                continue;
+            }
+
+            attributes.push(Attribute {
+                kind,
+                name,
+                args,
+                position,
+            });
+        }
+
+        Ok(attributes)
+    }
+
+    pub fn parse_function(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        let attributes = self.parse_attributes(pointer)?;
+
         let fmod = if self.match_token(pointer, TokenType::Export)? { FunctionMode::Export }
         else if self.match_token(pointer, TokenType::Private)? { FunctionMode::Private }
         else if self.match_token(pointer, TokenType::Extern)? { FunctionMode::Extern } 
         else { FunctionMode::Default };
         
         if self.multi_match_token(pointer, vec![TokenType::Extern, TokenType::Export, TokenType::Private])? {
-            return Err(CodeError::function_overloaded(self.previous(pointer).unwrap()))
+            return Err(CodeError::multiple_modes_error(self.previous(pointer).unwrap()))
         }
         
         let name = self.consume(pointer, TokenType::Identifier, None)?;
@@ -172,10 +358,11 @@ impl<'a> Parser<'a> {
             Box::new(return_type),
             args,
             body,
+            attributes,
         ))
     }
 
-    fn parse_block(&self, pointer: &mut usize) -> CodeResult<Vec<Box<ASTNode>>> {
+    fn parse_block(&self, pointer: &mut usize) -> CodeResult<Vec<Box<ASTNode<'_>>>> {
         self.consume(pointer, TokenType::LBrace, None)?;
 
         let mut statements = Vec::new();
@@ -186,9 +373,11 @@ impl<'a> Parser<'a> {
             }
 
             let stmt = self.parse_statement(pointer)?;
+            let is_block = stmt.is_block_statement();
             statements.push(Box::new(stmt));
 
-            if !self.match_token(pointer, TokenType::SemiColon)? {
+            // Statements ending in a block (loops) do not need a semi colon
+            if !self.match_token(pointer, TokenType::SemiColon)? && !is_block {
                 break;
             }
         }
@@ -202,32 +391,185 @@ impl<'a> Parser<'a> {
         Ok(statements)
     }
 
-    fn parse_function_call(&self, pointer: &mut usize) -> CodeResult<ASTNode> {
+    fn parse_function_call(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
         let name = self.previous(pointer).unwrap();
         self.consume(pointer, TokenType::LParen, None)?;
         let mut paras = vec![];
-        while let Some(tok) = self.peek(pointer) {
-            paras.push(Box::new(self.parse_expression(pointer)?));
+        let mut named = false;
+        while !self.match_token(pointer, TokenType::RParen)? {
+            // Named argument (`port = 8080`)
+            if self.peek(pointer).is_some_and(|t| t.token_type == TokenType::Identifier)
+                && self.tokens.get(*pointer + 1).is_some_and(|t| t.token_type == TokenType::Equals)
+            {
+                let arg_name = self.advance(pointer).unwrap();
+                self.advance(pointer);
+                let value = self.with_struct_literals(true, || self.parse_expression(pointer))?;
+                paras.push(Box::new(ASTNode::NamedArgument(arg_name, Box::new(value))));
+                named = true;
+            } else if named {
+                return Err(CodeError::new_unexpected_token_error(
+                    self.current(pointer).unwrap(),
+                    TokenType::RParen,
+                    Some("Positional arguments must come before named arguments".to_string()),
+                ));
+            } else {
+                paras.push(Box::new(
+                    self.with_struct_literals(true, || self.parse_expression(pointer))?,
+                ));
+            }
+            if self.match_token(pointer, TokenType::RParen)? {
+                break;
+            }
+            self.consume(pointer, TokenType::Comma, Some("Add a comma".to_string()))?;
+        }
+        let close = self.previous(pointer).unwrap();
+        Ok(ASTNode::FunctionCall(name, paras, close))
+    }
+
+    /// Builtin called by the identifier at `index`. The names are only reserved
+    /// when followed by `<` or `(`, so they remain usable as variable names.
+    fn builtin_at(&self, index: usize) -> Option<Builtin> {
+        let next = self.tokens.get(index + 1)?;
+        if !matches!(next.token_type, TokenType::Lesser | TokenType::LParen) {
+            return None;
+        }
+        Builtin::from_name(&self.tokens[index].content)
+    }
+
+    // `alloc<T>(n)`, `free(p)`, `realloc<T>(p, n)`
+    fn parse_builtin_call(&self, pointer: &mut usize, builtin: Builtin) -> CodeResult<ASTNode<'_>> {
+        let name = self.previous(pointer).unwrap();
+        let start = *pointer - 1;
+
+        let type_arg = if self.match_token(pointer, TokenType::Lesser)? {
+            let ty = self.parse_type(pointer)?;
+            self.consume(pointer, TokenType::Greater, None)?;
+            Some(Box::new(ty))
+        } else {
+            None
+        };
+
+        self.consume(pointer, TokenType::LParen, None)?;
+        let mut args = vec![];
+        while !self.match_token(pointer, TokenType::RParen)? {
+            args.push(Box::new(
+                self.with_struct_literals(true, || self.parse_expression(pointer))?,
+            ));
             if self.match_token(pointer, TokenType::RParen)? {
                 break;
             }
             self.consume(pointer, TokenType::Comma, Some("Add a comma".to_string()))?;
         }
-        Ok(ASTNode::FunctionCall(name, paras))
+
+        if type_arg.is_some() != builtin.takes_type() || args.len() != builtin.arity() {
+            return Err(CodeError::invalid_builtin_call_error(
+                self.codepos_from_space(start, pointer, 1),
+                &name.content,
+                builtin.usage(),
+            ));
+        }
+
+        let close = self.previous(pointer).unwrap();
+        Ok(ASTNode::BuiltinCall(builtin, name, type_arg, args, close))
     }
 
-    fn parse_return(&self, pointer: &mut usize) -> CodeResult<ASTNode> {
+    fn parse_return(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
         self.consume(pointer, TokenType::Return, None)?;
         Ok(ASTNode::Return(Box::new(self.parse_expression(pointer)?)))
     }
 
-    fn parse_statement(&self, pointer: &mut usize) -> CodeResult<ASTNode> {
+    fn parse_let(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        self.consume(pointer, TokenType::Let, None)?;
+        let mutable = self.match_token(pointer, TokenType::Mut)?;
+        let name = self.consume(pointer, TokenType::Identifier, None)?;
+        let annotation = if self.match_token(pointer, TokenType::Colon)? {
+            Some(Box::new(self.parse_type(pointer)?))
+        } else {
+            None
+        };
+        // Without a value the variable has to be assigned before it is read
+        let value = if self.match_token(pointer, TokenType::Equals)? {
+            Some(Box::new(self.parse_expression(pointer)?))
+        } else {
+            None
+        };
+        Ok(ASTNode::VariableSet(name, value, annotation, mutable))
+    }
+
+    fn parse_assignment(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        let name = self.consume(pointer, TokenType::Identifier, None)?;
+        self.consume(pointer, TokenType::Equals, None)?;
+        let value = self.parse_expression(pointer)?;
+        Ok(ASTNode::Assignment(name, Box::new(value)))
+    }
+
+    fn parse_while(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        self.consume(pointer, TokenType::While, None)?;
+        let condition = self.with_struct_literals(false, || self.parse_expression(pointer))?;
+        let body = self.parse_block(pointer)?;
+        Ok(ASTNode::WhileLoop(Box::new(condition), body))
+    }
+
+    fn parse_if(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        self.consume(pointer, TokenType::If, None)?;
+        let condition = self.with_struct_literals(false, || self.parse_expression(pointer))?;
+        let then_body = self.parse_block(pointer)?;
+        let else_body = if self.match_token(pointer, TokenType::Else)? {
+            // `else if` is an `if` nested in the else branch
+            if self.peek(pointer).is_some_and(|t| t.token_type == TokenType::If) {
+                vec![Box::new(self.parse_if(pointer)?)]
+            } else {
+                self.parse_block(pointer)?
+            }
+        } else {
+            vec![]
+        };
+        Ok(ASTNode::If(Box::new(condition), then_body, else_body))
+    }
+
+    fn parse_for(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        self.consume(pointer, TokenType::For, None)?;
+        let var = self.consume(pointer, TokenType::Identifier, None)?;
+        self.consume(
+            pointer,
+            TokenType::In,
+            Some("For loops look like `for x in 0..n { }`".to_string()),
+        )?;
+        let iterable = self.with_struct_literals(false, || self.parse_range(pointer))?;
+        let body = self.parse_block(pointer)?;
+        Ok(ASTNode::ForLoop(var, Box::new(iterable), body))
+    }
+
+    // Either an expression (array) or a range `a..b` / `a..=b`
+    fn parse_range(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        let start = self.parse_expression(pointer)?;
+        if self.match_token(pointer, TokenType::DoubleDot)? {
+            let end = self.parse_expression(pointer)?;
+            Ok(ASTNode::Range(Box::new(start), Box::new(end), false))
+        } else if self.match_token(pointer, TokenType::DoubleDotEquals)? {
+            let end = self.parse_expression(pointer)?;
+            Ok(ASTNode::Range(Box::new(start), Box::new(end), true))
+        } else {
+            Ok(start)
+        }
+    }
+
+    fn parse_statement(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
         let token = self.peek(pointer);
 
         if let Some(token) = token {
             match token.token_type {
                 TokenType::Identifier => {
-                    if self.match_next_token(pointer, TokenType::LParen)? {
+                    if let Some(builtin) = self.builtin_at(*pointer) {
+                        self.advance(pointer);
+                        self.parse_builtin_call(pointer, builtin)
+                    } else if self
+                        .tokens
+                        .get(*pointer + 1)
+                        .is_some_and(|t| t.token_type == TokenType::Equals)
+                    {
+                        self.parse_assignment(pointer)
+                    } else if self.match_next_token(pointer, TokenType::LParen)? {
                         self.parse_function_call(pointer)
                     } else {
                         let a = *pointer;
@@ -249,7 +591,13 @@ impl<'a> Parser<'a> {
                     res
                 }
                 TokenType::Return => self.parse_return(pointer),
-                o => Err(CodeError::new_unexpected_token_error(
+                TokenType::While => self.parse_while(pointer),
+                TokenType::Let => self.parse_let(pointer),
+                TokenType::For => self.parse_for(pointer),
+                TokenType::If => self.parse_if(pointer),
+                TokenType::Break => Ok(ASTNode::Break(self.advance(pointer).unwrap())),
+                TokenType::Continue => Ok(ASTNode::Continue(self.advance(pointer).unwrap())),
+                _o => Err(CodeError::new_unexpected_token_error(
                     token,
                     TokenType::Statement,
                     Some("Expected some sort of statement".to_string()),
@@ -263,7 +611,7 @@ impl<'a> Parser<'a> {
     }
 
     fn is_done(&self, pointer: &usize) -> bool {
-        (*pointer - 1) == self.tokens.len()
+        *pointer == self.tokens.len() + 1
     }
 
     fn is_done_err(&self, pointer: &usize) -> CodeResult<()> {
@@ -276,7 +624,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_arguments(&self, pointer: &mut usize) -> CodeResult<Vec<(&Token, Box<ASTNode>)>> {
+    fn parse_arguments(&self, pointer: &mut usize) -> CodeResult<Vec<Parameter<'_>>> {
         let mut arguments = Vec::new();
 
         while let Some(token) = self.peek(pointer) {
@@ -286,9 +634,21 @@ impl<'a> Parser<'a> {
 
             let name = self.consume(pointer, TokenType::Identifier, None)?;
             self.consume(pointer, TokenType::Colon, None)?;
-            let arg_type = self.parse_type(pointer)?;
+            // References are only allowed as parameter types
+            let arg_type = if self.match_token(pointer, TokenType::Ref)? {
+                let amp = self.previous(pointer).unwrap();
+                let mutable = self.match_token(pointer, TokenType::Mut)?;
+                ASTNode::ReferenceType(amp, mutable, Box::new(self.parse_type(pointer)?))
+            } else {
+                self.parse_type(pointer)?
+            };
+            let default = if self.match_token(pointer, TokenType::Equals)? {
+                Some(Box::new(self.parse_expression(pointer)?))
+            } else {
+                None
+            };
 
-            arguments.push((name, Box::new(arg_type)));
+            arguments.push((name, Box::new(arg_type), default));
 
             if !self.match_token(pointer, TokenType::Comma)? {
                 break;
@@ -298,7 +658,32 @@ impl<'a> Parser<'a> {
         Ok(arguments)
     }
 
-    fn parse_expression(&self, pointer: &mut usize) -> CodeResult<ASTNode> {
+    fn parse_expression(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        self.parse_comparison(pointer)
+    }
+
+    fn parse_comparison(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        let node = self.parse_cast(pointer)?;
+
+        if let Some(token) = self.peek(pointer) {
+            match token.token_type {
+                TokenType::DoubleEquals
+                | TokenType::NotEquals
+                | TokenType::Greater
+                | TokenType::Lesser
+                | TokenType::GreaterEquals
+                | TokenType::LesserEquals => {
+                    let op = self.advance(pointer).unwrap();
+                    let right = self.parse_cast(pointer)?;
+                    return Ok(ASTNode::BinaryOp(Box::new(node), op, Box::new(right)));
+                }
+                _ => {}
+            }
+        }
+        Ok(node)
+    }
+
+    fn parse_cast(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
         let term = self.parse_term(pointer)?;
         if self.match_token(pointer, TokenType::As)? {
             Ok(ASTNode::CastExpr(
@@ -310,7 +695,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_term(&self, pointer: &mut usize) -> CodeResult<ASTNode> {
+    fn parse_term(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
         let mut node = self.parse_factor(pointer)?;
 
         while let Some(token) = self.peek(pointer) {
@@ -326,7 +711,7 @@ impl<'a> Parser<'a> {
         Ok(node)
     }
 
-    fn parse_factor(&self, pointer: &mut usize) -> CodeResult<ASTNode> {
+    fn parse_factor(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
         let mut node = self.parse_primary(pointer)?;
 
         while let Some(token) = self.peek(pointer) {
@@ -342,20 +727,39 @@ impl<'a> Parser<'a> {
         Ok(node)
     }
 
-    fn parse_primary(&self, pointer: &mut usize) -> CodeResult<ASTNode> {
+    fn parse_primary(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
         if let Some(token) = self.advance(pointer) {
             match token.token_type {
-                TokenType::NumberInt | TokenType::NumberFloat => Ok(ASTNode::Literal(token)),
+                TokenType::NumberInt | TokenType::NumberFloat | TokenType::Boolean => {
+                    Ok(ASTNode::Literal(token))
+                }
                 TokenType::Identifier => {
-                    if self.match_next_token(pointer, TokenType::LParen)? {
+                    if let Some(builtin) = self.builtin_at(*pointer - 1) {
+                        self.parse_builtin_call(pointer, builtin)
+                    } else if self.peek(pointer).is_some_and(|t| t.token_type == TokenType::LParen) {
                         self.parse_function_call(pointer)
+                    } else if self.is_struct_literal(pointer) {
+                        self.parse_struct_literal(pointer)
                     } else {
                         Ok(ASTNode::Identifier(token))
                     }
                 }
                 TokenType::String => Ok(ASTNode::String(token)),
+                TokenType::LBracket => {
+                    let mut elements = vec![];
+                    while !self.match_token(pointer, TokenType::RBracket)? {
+                        elements.push(Box::new(
+                            self.with_struct_literals(true, || self.parse_expression(pointer))?,
+                        ));
+                        if self.match_token(pointer, TokenType::RBracket)? {
+                            break;
+                        }
+                        self.consume(pointer, TokenType::Comma, Some("Add a comma".to_string()))?;
+                    }
+                    Ok(ASTNode::ArrayLiteral(token, elements))
+                }
                 TokenType::LParen => {
-                    let expr = self.parse_expression(pointer)?;
+                    let expr = self.with_struct_literals(true, || self.parse_expression(pointer))?;
                     if self.match_token(pointer, TokenType::RParen)? {
                         Ok(expr)
                     } else {
@@ -379,7 +783,23 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_type(&self, pointer: &mut usize) -> CodeResult<ASTNode> {
+    fn parse_type(&self, pointer: &mut usize) -> CodeResult<ASTNode<'_>> {
+        if self.match_token(pointer, TokenType::Star)? {
+            let star = self.previous(pointer).unwrap();
+            let pointee = self.parse_type(pointer)?;
+            return Ok(ASTNode::PointerType(star, Box::new(pointee)));
+        }
+        if self.match_token(pointer, TokenType::LBracket)? {
+            let element = self.parse_type(pointer)?;
+            self.consume(
+                pointer,
+                TokenType::SemiColon,
+                Some("Array types look like `[i32; 4]`".to_string()),
+            )?;
+            let length = self.parse_expression(pointer)?;
+            self.consume(pointer, TokenType::RBracket, None)?;
+            return Ok(ASTNode::ArrayType(Box::new(element), Box::new(length)));
+        }
         Ok(ASTNode::Type(self.consume(
             pointer,
             TokenType::Identifier,
@@ -388,17 +808,118 @@ impl<'a> Parser<'a> {
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AttributeKind {
+    Inline,
+    NoReturn,
+    Entry,
+    ExportName,
+    Cold,
+    Drop,
+    Unsafe,
+    // Allows other functions of the same name with a different number of parameters
+    Overload,
+    // Set by `const def`, the function can be evaluated at compile time
+    Const,
+}
+
+impl AttributeKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "inline" => Some(AttributeKind::Inline),
+            "noreturn" => Some(AttributeKind::NoReturn),
+            "entry" => Some(AttributeKind::Entry),
+            "export_name" => Some(AttributeKind::ExportName),
+            "cold" => Some(AttributeKind::Cold),
+            "drop" => Some(AttributeKind::Drop),
+            "unsafe" => Some(AttributeKind::Unsafe),
+            "overload" => Some(AttributeKind::Overload),
+            _ => None,
+        }
+    }
+
+    /// Checks the arguments given in parentheses after the attribute name.
+    pub fn accepts(&self, args: &[&Token]) -> bool {
+        match self {
+            AttributeKind::ExportName => {
+                args.len() == 1 && args[0].token_type == TokenType::String
+            }
+            _ => args.is_empty(),
+        }
+    }
+
+    pub fn expected_arguments(&self) -> &'static str {
+        match self {
+            AttributeKind::ExportName => "exactly one string, like `@export_name(\"foo\")`",
+            _ => "no arguments",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Builtin {
+    Alloc,
+    Free,
+    Realloc,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "alloc" => Some(Builtin::Alloc),
+            "free" => Some(Builtin::Free),
+            "realloc" => Some(Builtin::Realloc),
+            _ => None,
+        }
+    }
+
+    /// Whether the builtin takes a type in angle brackets.
+    pub fn takes_type(&self) -> bool {
+        !matches!(self, Builtin::Free)
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Builtin::Alloc | Builtin::Free => 1,
+            Builtin::Realloc => 2,
+        }
+    }
+
+    pub fn usage(&self) -> &'static str {
+        match self {
+            Builtin::Alloc => "alloc<T>(count)",
+            Builtin::Free => "free(pointer)",
+            Builtin::Realloc => "realloc<T>(pointer, count)",
+        }
+    }
+}
+
 #[derive(Debug)]
+pub struct Attribute<'a> {
+    pub kind: AttributeKind,
+    pub name: &'a Token,
+    pub args: Vec<&'a Token>,
+    pub position: CodePosition,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum FunctionMode {
+    // Only visible inside of its module
     Private,
+    // Visible to importing modules and a global linker symbol named like the function
     Export,
+    // Declared here, defined outside of Sila
     Extern,
+    // Visible to importing modules
     Default,
 }
 
+// Name, Type, Default value (opt)
+pub type Parameter<'a> = (&'a Token, Box<ASTNode<'a>>, Option<Box<ASTNode<'a>>>);
+
 #[derive(Debug)]
 pub enum ASTNode<'a> {
-    // Literal (a number)
+    // Literal (a number or boolean)
     Literal(&'a Token),
     // Name
     Identifier(&'a Token),
@@ -410,20 +931,346 @@ pub enum ASTNode<'a> {
     BinaryOp(Box<ASTNode<'a>>, &'a Token, Box<ASTNode<'a>>),
     // Expr, Type
     CastExpr(Box<ASTNode<'a>>, Box<ASTNode<'a>>),
-    // Name, Function mode (private / export / extern), Return-type, Arguments (name, type), Content (Node)
+    // Name, Function mode (private / export / extern), Return-type, Arguments (name, type, default), Content (Node), Attributes
     FunctionDef(
         &'a Token,
         FunctionMode,
         Box<ASTNode<'a>>,
-        Vec<(&'a Token, Box<ASTNode<'a>>)>,
+        Vec<Parameter<'a>>,
         Vec<Box<ASTNode<'a>>>,
+        Vec<Attribute<'a>>,
     ),
-    // Name, Expr, Type annotation (opt)
-    VariableSet(&'a Token, Box<ASTNode<'a>>, Option<Box<ASTNode<'a>>>),
+    // Name, Expr (opt), Type annotation (opt), Mutable (`let mut`)
+    VariableSet(&'a Token, Option<Box<ASTNode<'a>>>, Option<Box<ASTNode<'a>>>, bool),
+    // Name, Expr
+    Assignment(&'a Token, Box<ASTNode<'a>>),
+    // Name, Type, Value (folded to a literal before type checking)
+    ConstDef(&'a Token, Box<ASTNode<'a>>, Box<ASTNode<'a>>),
     // Lib name
     Import(&'a Token),
-    // Name, Arguments (expr)
-    FunctionCall(&'a Token, Vec<Box<ASTNode<'a>>>),
+    // Name, Arguments (expr or named argument), Closing parenthesis
+    FunctionCall(&'a Token, Vec<Box<ASTNode<'a>>>, &'a Token),
+    // Name, Expr (only inside of function calls)
+    NamedArgument(&'a Token, Box<ASTNode<'a>>),
     // Expr
     Return(Box<ASTNode<'a>>),
+    // Condition, Content (Node)
+    WhileLoop(Box<ASTNode<'a>>, Vec<Box<ASTNode<'a>>>),
+    // Loop variable, Iterable (range or array), Content (Node), lowered to a while loop after checking
+    ForLoop(&'a Token, Box<ASTNode<'a>>, Vec<Box<ASTNode<'a>>>),
+    // Condition, Then (Node), Else (Node, empty if missing)
+    If(Box<ASTNode<'a>>, Vec<Box<ASTNode<'a>>>, Vec<Box<ASTNode<'a>>>),
+    // Keyword
+    Break(&'a Token),
+    // Keyword
+    Continue(&'a Token),
+    // Array, Index (inserted when for loops are lowered)
+    Index(Box<ASTNode<'a>>, Box<ASTNode<'a>>),
+    // Start, End, Inclusive (`..=`)
+    Range(Box<ASTNode<'a>>, Box<ASTNode<'a>>, bool),
+    // Opening bracket, Elements (expr)
+    ArrayLiteral(&'a Token, Vec<Box<ASTNode<'a>>>),
+    // Element type, Length (expr)
+    ArrayType(Box<ASTNode<'a>>, Box<ASTNode<'a>>),
+    // Name, Fields (name, type)
+    StructDef(&'a Token, Vec<(&'a Token, Box<ASTNode<'a>>)>),
+    // Struct name, Fields (name, expr)
+    StructLiteral(&'a Token, Vec<(&'a Token, Box<ASTNode<'a>>)>),
+    // Star, Pointee type
+    PointerType(&'a Token, Box<ASTNode<'a>>),
+    // Ampersand, Mutable (`&mut`), Referenced type
+    // Parameters only, the argument is passed by reference and otherwise
+    // behaves like a value of the referenced type
+    ReferenceType(&'a Token, bool, Box<ASTNode<'a>>),
+    // Builtin, Name, Type argument (opt), Arguments (expr), Closing parenthesis
+    BuiltinCall(
+        Builtin,
+        &'a Token,
+        Option<Box<ASTNode<'a>>>,
+        Vec<Box<ASTNode<'a>>>,
+        &'a Token,
+    ),
+}
+
+impl<'a> ASTNode<'a> {
+    /// Statements that end in a block and therefore need no semi colon.
+    pub fn is_block_statement(&self) -> bool {
+        matches!(self, ASTNode::WhileLoop(..) | ASTNode::ForLoop(..) | ASTNode::If(..))
+    }
+
+    /// Source span covered by this node.
+    pub fn position(&self) -> CodePosition {
+        match self {
+            ASTNode::Literal(t)
+            | ASTNode::Identifier(t)
+            | ASTNode::String(t)
+            | ASTNode::Type(t)
+            | ASTNode::Import(t)
+            | ASTNode::Break(t)
+            | ASTNode::Continue(t) => t.code_position,
+            ASTNode::BinaryOp(lhs, _, rhs) => lhs.position().merge(rhs.position()),
+            ASTNode::CastExpr(expr, ty) => expr.position().merge(ty.position()),
+            ASTNode::FunctionDef(name, ..) => name.code_position,
+            ASTNode::VariableSet(name, expr, ty, _) => match (expr, ty) {
+                (Some(expr), _) => name.code_position.merge(expr.position()),
+                (None, Some(ty)) => name.code_position.merge(ty.position()),
+                (None, None) => name.code_position,
+            },
+            ASTNode::Assignment(name, expr) => name.code_position.merge(expr.position()),
+            ASTNode::FunctionCall(name, _, close) => name.code_position.merge(close.code_position),
+            ASTNode::NamedArgument(name, expr) => name.code_position.merge(expr.position()),
+            ASTNode::Return(expr) => expr.position(),
+            ASTNode::WhileLoop(cond, _) | ASTNode::If(cond, ..) => cond.position(),
+            ASTNode::ForLoop(var, iterable, _) => var.code_position.merge(iterable.position()),
+            ASTNode::Range(start, end, _) | ASTNode::Index(start, end) => start.position().merge(end.position()),
+            ASTNode::ArrayLiteral(bracket, elements) => match elements.last() {
+                Some(last) => bracket.code_position.merge(last.position()),
+                None => bracket.code_position,
+            },
+            ASTNode::ArrayType(element, length) => element.position().merge(length.position()),
+            ASTNode::PointerType(star, pointee) | ASTNode::ReferenceType(star, _, pointee) => {
+                star.code_position.merge(pointee.position())
+            }
+            ASTNode::StructDef(name, _) => name.code_position,
+            ASTNode::ConstDef(name, _, value) => name.code_position.merge(value.position()),
+            ASTNode::StructLiteral(name, fields) => match fields.last() {
+                Some((_, last)) => name.code_position.merge(last.position()),
+                None => name.code_position,
+            },
+            ASTNode::BuiltinCall(_, name, .., close) => name.code_position.merge(close.code_position),
+        }
+    }
+
+    /// Direct child nodes, in source order.
+    pub fn children(&self) -> Vec<&ASTNode<'a>> {
+        let mut children: Vec<&ASTNode<'a>> = vec![];
+        match self {
+            ASTNode::Literal(_)
+            | ASTNode::Identifier(_)
+            | ASTNode::String(_)
+            | ASTNode::Type(_)
+            | ASTNode::Import(_)
+            | ASTNode::Break(_)
+            | ASTNode::Continue(_) => {}
+            ASTNode::BinaryOp(lhs, _, rhs) => children.extend([&**lhs, &**rhs]),
+            ASTNode::CastExpr(expr, ty) => children.extend([&**expr, &**ty]),
+            ASTNode::FunctionDef(_, _, ret, params, body, _) => {
+                for (_, ty, default) in params {
+                    children.push(ty);
+                    if let Some(default) = default {
+                        children.push(default);
+                    }
+                }
+                children.push(ret);
+                children.extend(body.iter().map(|t| &**t));
+            }
+            ASTNode::VariableSet(_, expr, ty, _) => {
+                if let Some(ty) = ty {
+                    children.push(ty);
+                }
+                if let Some(expr) = expr {
+                    children.push(expr);
+                }
+            }
+            ASTNode::Assignment(_, expr) => children.push(expr),
+            ASTNode::FunctionCall(_, args, _) => children.extend(args.iter().map(|t| &**t)),
+            ASTNode::NamedArgument(_, expr) | ASTNode::Return(expr) => children.push(expr),
+            ASTNode::WhileLoop(cond, body) => {
+                children.push(cond);
+                children.extend(body.iter().map(|t| &**t));
+            }
+            ASTNode::ForLoop(_, iterable, body) => {
+                children.push(iterable);
+                children.extend(body.iter().map(|t| &**t));
+            }
+            ASTNode::If(cond, then_body, else_body) => {
+                children.push(cond);
+                children.extend(then_body.iter().chain(else_body).map(|t| &**t));
+            }
+            ASTNode::Range(start, end, _) | ASTNode::Index(start, end) => children.extend([&**start, &**end]),
+            ASTNode::ArrayLiteral(_, elements) => children.extend(elements.iter().map(|t| &**t)),
+            ASTNode::ArrayType(element, length) => children.extend([&**element, &**length]),
+            ASTNode::ConstDef(_, ty, value) => children.extend([&**ty, &**value]),
+            ASTNode::PointerType(_, pointee) | ASTNode::ReferenceType(_, _, pointee) => {
+                children.push(pointee)
+            }
+            ASTNode::StructDef(_, fields) | ASTNode::StructLiteral(_, fields) => {
+                children.extend(fields.iter().map(|(_, t)| &**t))
+            }
+            ASTNode::BuiltinCall(_, _, ty, args, _) => {
+                if let Some(ty) = ty {
+                    children.push(ty);
+                }
+                children.extend(args.iter().map(|t| &**t));
+            }
+        }
+        children
+    }
+
+    /// Direct child nodes, in the same order as `children`.
+    pub fn children_mut(&mut self) -> Vec<&mut ASTNode<'a>> {
+        let mut children: Vec<&mut ASTNode<'a>> = vec![];
+        match self {
+            ASTNode::Literal(_)
+            | ASTNode::Identifier(_)
+            | ASTNode::String(_)
+            | ASTNode::Type(_)
+            | ASTNode::Import(_)
+            | ASTNode::Break(_)
+            | ASTNode::Continue(_) => {}
+            ASTNode::BinaryOp(lhs, _, rhs) => children.extend([&mut **lhs, &mut **rhs]),
+            ASTNode::CastExpr(expr, ty) => children.extend([&mut **expr, &mut **ty]),
+            ASTNode::FunctionDef(_, _, ret, params, body, _) => {
+                for (_, ty, default) in params {
+                    children.push(ty);
+                    if let Some(default) = default {
+                        children.push(default);
+                    }
+                }
+                children.push(ret);
+                children.extend(body.iter_mut().map(|t| &mut **t));
+            }
+            ASTNode::VariableSet(_, expr, ty, _) => {
+                if let Some(ty) = ty {
+                    children.push(ty);
+                }
+                if let Some(expr) = expr {
+                    children.push(expr);
+                }
+            }
+            ASTNode::Assignment(_, expr) => children.push(expr),
+            ASTNode::FunctionCall(_, args, _) => children.extend(args.iter_mut().map(|t| &mut **t)),
+            ASTNode::NamedArgument(_, expr) | ASTNode::Return(expr) => children.push(expr),
+            ASTNode::WhileLoop(cond, body) => {
+                children.push(cond);
+                children.extend(body.iter_mut().map(|t| &mut **t));
+            }
+            ASTNode::ForLoop(_, iterable, body) => {
+                children.push(iterable);
+                children.extend(body.iter_mut().map(|t| &mut **t));
+            }
+            ASTNode::If(cond, then_body, else_body) => {
+                children.push(cond);
+                children.extend(then_body.iter_mut().chain(else_body).map(|t| &mut **t));
+            }
+            ASTNode::Range(start, end, _) | ASTNode::Index(start, end) => {
+                children.extend([&mut **start, &mut **end])
+            }
+            ASTNode::ArrayLiteral(_, elements) => {
+                children.extend(elements.iter_mut().map(|t| &mut **t))
+            }
+            ASTNode::ArrayType(element, length) => children.extend([&mut **element, &mut **length]),
+            ASTNode::ConstDef(_, ty, value) => children.extend([&mut **ty, &mut **value]),
+            ASTNode::PointerType(_, pointee) | ASTNode::ReferenceType(_, _, pointee) => {
+                children.push(pointee)
+            }
+            ASTNode::StructDef(_, fields) | ASTNode::StructLiteral(_, fields) => {
+                children.extend(fields.iter_mut().map(|(_, t)| &mut **t))
+            }
+            ASTNode::BuiltinCall(_, _, ty, args, _) => {
+                if let Some(ty) = ty {
+                    children.push(ty);
+                }
+                children.extend(args.iter_mut().map(|t| &mut **t));
+            }
+        }
+        children
+    }
+
+    pub fn attribute(&self, kind: AttributeKind) -> Option<&Attribute<'a>> {
+        match self {
+            ASTNode::FunctionDef(.., attributes) => attributes.iter().find(|t| t.kind == kind),
+            _ => None,
+        }
+    }
+
+    /// The linker symbol of a function in `module`. `@export_name` takes precedence,
+    /// export and extern functions keep their name and everything else is prefixed
+    /// with the module, so modules may use the same names. Overloads end in their
+    /// number of parameters.
+    pub fn symbol_name(&self, module: &str) -> Option<String> {
+        match self {
+            ASTNode::FunctionDef(name, mode, _, params, ..) => {
+                let symbol = match (self.attribute(AttributeKind::ExportName), mode) {
+                    (Some(attribute), _) => return Some(attribute.args[0].content.clone()),
+                    (None, FunctionMode::Export | FunctionMode::Extern) => name.content.clone(),
+                    (None, _) => format!("{}__{}", module, name.content),
+                };
+                Some(match self.attribute(AttributeKind::Overload) {
+                    Some(_) => format!("{}__{}", symbol, params.len()),
+                    None => symbol,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comp_errors::{CodeErrorType, CodeWarningType};
+    use crate::testing::{body, parse, parse_with_warnings, try_parse};
+
+    fn attributes(ast: &[ASTNode<'static>]) -> Vec<AttributeKind> {
+        let ASTNode::FunctionDef(.., attributes) = &ast[0] else {
+            unreachable!()
+        };
+        attributes.iter().map(|t| t.kind).collect()
+    }
+
+    #[test]
+    fn blocks_after_conditions_are_not_struct_literals() {
+        let ast = parse(
+            "def main(): i32 {
+    for x in arr { }
+    while flag { }
+    if c { } else { }
+    if f(P { x: 1 }) { }
+    return 0;
+}",
+        );
+        assert_eq!(
+            body(&ast, "main"),
+            [
+                "for x in arr {  }",
+                "while flag {  }",
+                "if c {  } else {  }",
+                "if f(P { x: 1 }) {  } else {  }",
+                "return 0"
+            ]
+        );
+    }
+
+    #[test]
+    fn builtin_names_are_variables_unless_called() {
+        let ast = parse("def main(): i32 {\n    let free = 1;\n    free(p);\n    return free;\n}");
+        assert_eq!(body(&ast, "main"), ["let free = 1", "free(p)", "return free"]);
+    }
+
+    #[test]
+    fn unknown_attributes_are_ignored_with_a_warning() {
+        let (ast, warnings) = parse_with_warnings("def @fast @inline main(): i32 {\n    return 0;\n}");
+        assert_eq!(attributes(&ast), [AttributeKind::Inline]);
+        assert!(matches!(warnings[..], [CodeWarning { code_warn_type: CodeWarningType::UnknownAttribute, .. }]));
+        assert_eq!(warnings[0].footer, "Attribute `@fast` does not exist and will be ignored");
+    }
+
+    #[test]
+    fn rejects_attributes_with_the_wrong_arguments() {
+        for attribute in ["@export_name", "@export_name(1)", "@export_name(\"a\", \"b\")", "@inline(\"a\")"] {
+            let error = try_parse(&format!("def {} main(): i32 {{\n    return 0;\n}}", attribute)).unwrap_err();
+            assert!(matches!(error.code_error_type, CodeErrorType::InvalidAttribute), "{}", attribute);
+        }
+        let (ast, _) = parse_with_warnings("def @export_name(\"start\") main(): i32 {\n    return 0;\n}");
+        assert_eq!(attributes(&ast), [AttributeKind::ExportName]);
+    }
+
+    #[test]
+    fn repeated_attributes_are_kept_once_with_a_warning() {
+        let (ast, warnings) = parse_with_warnings("def @cold @inline @cold main(): i32 {\n    return 0;\n}");
+        assert_eq!(attributes(&ast), [AttributeKind::Cold, AttributeKind::Inline]);
+        assert!(matches!(warnings[..], [CodeWarning { code_warn_type: CodeWarningType::UnnecessaryCode, .. }]));
+        assert_eq!(warnings[0].notes, ["`@cold` is already set on this function"]);
+    }
 }
@@ -0,0 +1,602 @@
+use crate::checker::bind_arguments;
+use crate::codeviz::print_code_warn;
+use crate::comp_errors::{CodeError, CodeResult, CodeWarning};
+use crate::entry::DEFAULT_ENTRY;
+use crate::filemanager::FileManager;
+use crate::lexer::{CodePosition, Token};
+use crate::parser::{ASTNode, AttributeKind, FunctionMode};
+use std::collections::{HashMap, HashSet};
+
+pub const PRIMITIVE_TYPES: [&str; 13] = [
+    "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64", "f32", "f64", "bool", "str", "void",
+];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SymbolKind {
+    Function,
+    Struct,
+    Import,
+    Parameter,
+    Variable,
+    LoopVariable,
+    Constant,
+}
+
+impl SymbolKind {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            SymbolKind::Function => "function",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Import => "import",
+            SymbolKind::Parameter => "parameter",
+            SymbolKind::Variable => "variable",
+            SymbolKind::LoopVariable => "loop variable",
+            SymbolKind::Constant => "constant",
+        }
+    }
+
+    fn is_value(&self) -> bool {
+        matches!(
+            self,
+            SymbolKind::Parameter
+                | SymbolKind::Variable
+                | SymbolKind::LoopVariable
+                | SymbolKind::Constant
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Symbol<'a> {
+    pub kind: SymbolKind,
+    pub declaration: &'a Token,
+}
+
+/// Binds every used name to the token that declared it.
+/// Tokens are compared by identity, since every token lives exactly once in the token stream.
+#[derive(Debug, Default)]
+pub struct Resolution<'a> {
+    bindings: HashMap<*const Token, Symbol<'a>>,
+    // Every declaration, in source order
+    declarations: Vec<Symbol<'a>>,
+    // Declarations that are referred to at least once
+    used: HashSet<*const Token>,
+    // Declarations copied from imported modules -> the import they came through
+    origins: HashMap<*const Token, *const Token>,
+}
+
+impl<'a> Resolution<'a> {
+    pub fn symbol_of(&self, usage: &Token) -> Option<&Symbol<'a>> {
+        self.bindings.get(&(usage as *const Token))
+    }
+
+    pub fn declarations(&self) -> &[Symbol<'a>] {
+        &self.declarations
+    }
+
+    pub fn is_used(&self, declaration: &Token) -> bool {
+        self.used.contains(&(declaration as *const Token))
+    }
+
+    fn bind(&mut self, usage: &'a Token, symbol: Symbol<'a>) {
+        if !std::ptr::eq(usage, symbol.declaration) {
+            let declaration = symbol.declaration as *const Token;
+            self.used.insert(declaration);
+            if let Some(import) = self.origins.get(&declaration) {
+                self.used.insert(*import);
+            }
+        }
+        self.bindings.insert(usage as *const Token, symbol);
+    }
+}
+
+/// Module, function and block scopes, innermost last.
+pub struct Resolver<'a, 'b> {
+    scopes: Vec<HashMap<&'a str, Symbol<'a>>>,
+    resolution: Resolution<'a>,
+    // Function name -> every function of that name, more than one if overloaded
+    functions: HashMap<&'a str, Vec<&'b ASTNode<'a>>>,
+}
+
+impl<'a, 'b> Resolver<'a, 'b> {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![],
+            resolution: Resolution::default(),
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Declares a name in the innermost scope.
+    /// Variables may shadow earlier ones, everything else must be unique in its scope.
+    fn declare(&mut self, name: &'a Token, kind: SymbolKind) -> CodeResult<()> {
+        let scope = self.scopes.last_mut().unwrap();
+        if let Some(first) = scope.get(name.content.as_str()) {
+            if kind != SymbolKind::Variable || first.kind != SymbolKind::Variable {
+                return Err(CodeError::duplicate_name_error(
+                    name,
+                    first.declaration,
+                    first.kind.describe(),
+                ));
+            }
+        }
+        let symbol = Symbol {
+            kind,
+            declaration: name,
+        };
+        scope.insert(name.content.as_str(), symbol);
+        self.resolution.bind(name, symbol);
+        self.resolution.declarations.push(symbol);
+        Ok(())
+    }
+
+    /// Declares a function in the module scope. Functions of the same name have to be
+    /// marked with `@overload` and take different numbers of parameters.
+    fn declare_function(&mut self, function: &'b ASTNode<'a>) -> CodeResult<()> {
+        let ASTNode::FunctionDef(name, _, _, params, ..) = function else {
+            unreachable!()
+        };
+        let Some(overloads) = self.functions.get_mut(name.content.as_str()) else {
+            self.functions.insert(name.content.as_str(), vec![function]);
+            return self.declare(name, SymbolKind::Function);
+        };
+
+        let is_overload = |t: &ASTNode| t.attribute(AttributeKind::Overload).is_some();
+        let same_arity = overloads
+            .iter()
+            .find(|t| matches!(t, ASTNode::FunctionDef(_, _, _, other, ..) if other.len() == params.len()));
+        match (is_overload(overloads[0]) && is_overload(function), same_arity) {
+            (true, None) => {}
+            (true, Some(ASTNode::FunctionDef(first, ..))) => {
+                return Err(CodeError::duplicate_definition_error(name, first, true))
+            }
+            (_, _) => {
+                let ASTNode::FunctionDef(first, ..) = overloads[0] else {
+                    unreachable!()
+                };
+                return Err(CodeError::duplicate_definition_error(name, first, false));
+            }
+        }
+        overloads.push(function);
+
+        let symbol = Symbol {
+            kind: SymbolKind::Function,
+            declaration: name,
+        };
+        self.resolution.bind(name, symbol);
+        self.resolution.declarations.push(symbol);
+        Ok(())
+    }
+
+    /// Picks the overload of the called function that takes `args`, `None` if the
+    /// function is not overloaded.
+    fn select_overload(
+        &self,
+        call: &'a Token,
+        args: &[Box<ASTNode<'a>>],
+        position: CodePosition,
+    ) -> CodeResult<Option<Symbol<'a>>> {
+        let Some(overloads) = self.functions.get(call.content.as_str()).filter(|t| t.len() > 1) else {
+            return Ok(None);
+        };
+        // Anything else of the same name hides the functions
+        if self.lookup(&call.content).is_some_and(|t| t.kind != SymbolKind::Function) {
+            return Ok(None);
+        }
+        let candidates: Vec<(&'a Token, usize)> = overloads
+            .iter()
+            .map(|t| match t {
+                ASTNode::FunctionDef(name, _, _, params, ..) => (*name, params.len()),
+                _ => unreachable!(),
+            })
+            .collect();
+        let matching: Vec<(&'a Token, usize)> = overloads
+            .iter()
+            .zip(&candidates)
+            .filter(|(t, _)| match t {
+                ASTNode::FunctionDef(name, _, _, params, ..) => bind_arguments(call, args, name, params).is_ok(),
+                _ => unreachable!(),
+            })
+            .map(|(_, candidate)| *candidate)
+            .collect();
+        match matching.as_slice() {
+            [(declaration, _)] => Ok(Some(Symbol {
+                kind: SymbolKind::Function,
+                declaration,
+            })),
+            [] => Err(CodeError::no_matching_overload_error(position, call, &candidates)),
+            _ => Err(CodeError::ambiguous_call_error(position, call, &matching)),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Symbol<'a>> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|t| t.get(name))
+            .copied()
+    }
+
+    fn use_name(
+        &mut self,
+        usage: &'a Token,
+        accepts: impl Fn(SymbolKind) -> bool,
+        expected: &str,
+    ) -> CodeResult<()> {
+        match self.lookup(&usage.content) {
+            Some(symbol) if accepts(symbol.kind) => {
+                self.resolution.bind(usage, symbol);
+                Ok(())
+            }
+            Some(symbol) => Err(CodeError::wrong_symbol_kind_error(
+                usage,
+                symbol.declaration,
+                expected,
+                symbol.kind.describe(),
+            )),
+            None => Err(CodeError::undefined_name_error(usage, expected)),
+        }
+    }
+
+    fn resolve_type(&mut self, ty: &ASTNode<'a>) -> CodeResult<()> {
+        match ty {
+            ASTNode::Type(name) => {
+                if PRIMITIVE_TYPES.contains(&name.content.as_str()) {
+                    return Ok(());
+                }
+                self.use_name(name, |t| t == SymbolKind::Struct, "type")
+            }
+            ASTNode::ArrayType(element, length) => {
+                self.resolve_type(element)?;
+                self.resolve_expr(length)
+            }
+            ASTNode::PointerType(_, pointee) | ASTNode::ReferenceType(_, _, pointee) => {
+                self.resolve_type(pointee)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn resolve_expr(&mut self, node: &ASTNode<'a>) -> CodeResult<()> {
+        match node {
+            ASTNode::Identifier(name) => self.use_name(name, |t| t.is_value(), "variable"),
+            ASTNode::FunctionCall(name, args, _) => {
+                match self.select_overload(name, args, node.position())? {
+                    Some(symbol) => self.resolution.bind(name, symbol),
+                    None => self.use_name(name, |t| t == SymbolKind::Function, "function")?,
+                }
+                for arg in args {
+                    self.resolve_expr(arg)?;
+                }
+                Ok(())
+            }
+            ASTNode::StructLiteral(name, fields) => {
+                self.use_name(name, |t| t == SymbolKind::Struct, "struct")?;
+                for (_, value) in fields {
+                    self.resolve_expr(value)?;
+                }
+                Ok(())
+            }
+            ASTNode::CastExpr(expr, ty) => {
+                self.resolve_expr(expr)?;
+                self.resolve_type(ty)
+            }
+            ASTNode::BuiltinCall(_, _, ty, args, _) => {
+                if let Some(ty) = ty {
+                    self.resolve_type(ty)?;
+                }
+                for arg in args {
+                    self.resolve_expr(arg)?;
+                }
+                Ok(())
+            }
+            other => {
+                for child in other.children() {
+                    self.resolve_expr(child)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn resolve_block(&mut self, body: &[Box<ASTNode<'a>>]) -> CodeResult<()> {
+        self.scopes.push(HashMap::new());
+        for statement in body {
+            match &**statement {
+                ASTNode::VariableSet(name, value, annotation, _) => {
+                    if let Some(annotation) = annotation {
+                        self.resolve_type(annotation)?;
+                    }
+                    // The initializer still sees an outer binding of the same name
+                    if let Some(value) = value {
+                        self.resolve_expr(value)?;
+                    }
+                    self.declare(name, SymbolKind::Variable)?;
+                }
+                ASTNode::Assignment(name, value) => {
+                    self.resolve_expr(value)?;
+                    self.use_name(name, |t| t.is_value(), "variable")?;
+                }
+                ASTNode::WhileLoop(condition, body) => {
+                    self.resolve_expr(condition)?;
+                    self.resolve_block(body)?;
+                }
+                ASTNode::If(condition, then_body, else_body) => {
+                    self.resolve_expr(condition)?;
+                    self.resolve_block(then_body)?;
+                    self.resolve_block(else_body)?;
+                }
+                ASTNode::ForLoop(var, iterable, body) => {
+                    self.resolve_expr(iterable)?;
+                    self.scopes.push(HashMap::new());
+                    self.declare(var, SymbolKind::LoopVariable)?;
+                    self.resolve_block(body)?;
+                    self.scopes.pop();
+                }
+                other => self.resolve_expr(other)?,
+            }
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn resolve_item(&mut self, item: &ASTNode<'a>) -> CodeResult<()> {
+        match item {
+            ASTNode::FunctionDef(_, _, ret, params, body, _) => {
+                self.resolve_type(ret)?;
+                self.scopes.push(HashMap::new());
+                for (name, ty, default) in params {
+                    self.resolve_type(ty)?;
+                    if let Some(default) = default {
+                        self.resolve_expr(default)?;
+                    }
+                    self.declare(name, SymbolKind::Parameter)?;
+                }
+                self.resolve_block(body)?;
+                self.scopes.pop();
+            }
+            ASTNode::StructDef(_, fields) => {
+                let mut seen: HashMap<&str, &Token> = HashMap::new();
+                for (field, ty) in fields {
+                    if let Some(first) = seen.get(field.content.as_str()) {
+                        return Err(CodeError::duplicate_name_error(field, first, "field"));
+                    }
+                    seen.insert(field.content.as_str(), field);
+                    self.resolve_type(ty)?;
+                }
+            }
+            ASTNode::ConstDef(_, ty, value) => {
+                self.resolve_type(ty)?;
+                self.resolve_expr(value)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn resolve(mut self, ast: &'b [ASTNode<'a>]) -> CodeResult<Resolution<'a>> {
+        self.scopes.push(HashMap::new());
+        for item in ast {
+            match item {
+                ASTNode::FunctionDef(..) => self.declare_function(item)?,
+                ASTNode::StructDef(name, _) => self.declare(name, SymbolKind::Struct)?,
+                ASTNode::Import(name) => self.declare(name, SymbolKind::Import)?,
+                ASTNode::ConstDef(name, ..) => self.declare(name, SymbolKind::Constant)?,
+                _ => {}
+            }
+        }
+
+        for item in ast {
+            self.resolve_item(item)?;
+        }
+        Ok(self.resolution)
+    }
+}
+
+/// Resolves a module, `imported` are the declarations copied from other modules
+/// with the import they came through.
+pub fn resolve<'a>(ast: &[ASTNode<'a>], imported: &[(&'a Token, &'a Token)]) -> CodeResult<Resolution<'a>> {
+    let mut resolver = Resolver::new();
+    for (declaration, import) in imported {
+        resolver
+            .resolution
+            .origins
+            .insert(*declaration as *const Token, *import as *const Token);
+    }
+    resolver.resolve(ast)
+}
+
+/// Warns about locals, private functions and imports that are never used.
+/// Names starting with `_` are unused on purpose, extern and export functions are used
+/// outside of the module and `@entry` / `@drop` functions are called by the compiler.
+fn unused_warnings(ast: &[ASTNode], resolution: &Resolution) -> Vec<CodeWarning> {
+    let mut warnings = vec![];
+    let mut private = HashSet::new();
+    // Parameters of extern functions only describe the signature
+    let mut signature_only = HashSet::new();
+    for item in ast {
+        if let ASTNode::FunctionDef(name, mode, _, params, _, _) = item {
+            let implicit = item.attribute(AttributeKind::Entry).is_some()
+                || item.attribute(AttributeKind::Drop).is_some()
+                || name.content == DEFAULT_ENTRY;
+            match mode {
+                FunctionMode::Private if !implicit => {
+                    private.insert(*name as *const Token);
+                }
+                FunctionMode::Extern => {
+                    signature_only.extend(params.iter().map(|(t, _, _)| *t as *const Token))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for symbol in resolution.declarations() {
+        let name = symbol.declaration;
+        let ptr = name as *const Token;
+        if name.content.starts_with('_') || resolution.is_used(name) || signature_only.contains(&ptr) {
+            continue;
+        }
+        let warning = match symbol.kind {
+            SymbolKind::Function if private.contains(&ptr) => CodeWarning::new_unused_function(name),
+            SymbolKind::Import => CodeWarning::new_unused_import(name),
+            SymbolKind::Parameter | SymbolKind::Variable | SymbolKind::LoopVariable => {
+                CodeWarning::new_unused_variable(name, symbol.kind.describe())
+            }
+            _ => continue,
+        };
+        warnings.push(warning);
+    }
+    warnings.sort_by_key(|t| t.position.idx_start);
+    warnings
+}
+
+pub fn warn_unused(ast: &[ASTNode], resolution: &Resolution, file_manager: &FileManager) {
+    for warning in unused_warnings(ast, resolution) {
+        print_code_warn(warning, file_manager);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comp_errors::CodeErrorType;
+    use crate::testing::parse;
+
+    fn resolve_source(source: &str) -> CodeResult<()> {
+        resolve(&parse(source), &[]).map(|_| ())
+    }
+
+    /// Line of the declaration that the value returned by the only function refers to.
+    fn returned_declaration_line(source: &str) -> usize {
+        let ast = parse(source);
+        let resolution = resolve(&ast, &[]).unwrap();
+        let ASTNode::FunctionDef(_, _, _, _, body, _) = &ast[0] else {
+            panic!("Expected a function");
+        };
+        let ASTNode::Return(value) = &**body.last().unwrap() else {
+            panic!("Expected a return");
+        };
+        let ASTNode::Identifier(usage) = &**value else {
+            panic!("Expected an identifier");
+        };
+        resolution.symbol_of(usage).unwrap().declaration.code_position.line_start
+    }
+
+    #[test]
+    fn variables_shadow_earlier_ones() {
+        let source = "def main(x: i32): i32 {\n    let x = x + 1;\n    let x = x + 1;\n    return x;\n}";
+        assert_eq!(returned_declaration_line(source), 2);
+    }
+
+    #[test]
+    fn blocks_end_the_scope_of_their_variables() {
+        let source = "def main(x: i32): i32 {\n    if true {\n        let x = 2;\n    }\n    return x;\n}";
+        assert_eq!(returned_declaration_line(source), 0);
+
+        let error = resolve_source("def main(): i32 {\n    if true {\n        let y = 2;\n    }\n    return y;\n}")
+            .unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::UndefinedName));
+    }
+
+    #[test]
+    fn functions_can_be_used_before_their_definition() {
+        resolve_source("def main(): i32 {\n    return f();\n}\n\ndef f(): i32 {\n    return 1;\n}").unwrap();
+    }
+
+    #[test]
+    fn rejects_duplicate_functions() {
+        let error = resolve_source("def f(): void {\n}\n\ndef f(): void {\n}").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::DuplicateDefinition));
+        assert_eq!(error.labels[0].1, "First defined here");
+        assert_eq!(error.labels[0].0.line_start, 0);
+    }
+
+    #[test]
+    fn rejects_names_of_the_wrong_kind() {
+        let error = resolve_source("struct P {\n    x: i32\n}\n\ndef main(): i32 {\n    return P;\n}").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::UndefinedName));
+        assert_eq!(error.title, "Expected a variable");
+    }
+
+    #[test]
+    fn rejects_unknown_types() {
+        let error = resolve_source("def main(p: Point): i32 {\n    return 0;\n}").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::UndefinedName));
+    }
+
+    /// Kind and line of every unused-name warning.
+    fn unused(source: &str) -> Vec<(String, usize)> {
+        let ast = parse(source);
+        let resolution = resolve(&ast, &[]).unwrap();
+        unused_warnings(&ast, &resolution)
+            .into_iter()
+            .map(|t| (format!("{:?}", t.code_warn_type), t.position.line_start))
+            .collect()
+    }
+
+    #[test]
+    fn warns_about_unused_names() {
+        let source = "def main(x: i32): i32 {\n    let y = 1;\n    for i in 0..3 {\n    }\n    return 0;\n}\n\ndef private helper(): void {\n}";
+        assert_eq!(
+            unused(source),
+            vec![
+                ("UnusedVariable".to_string(), 0),
+                ("UnusedVariable".to_string(), 1),
+                ("UnusedVariable".to_string(), 2),
+                ("UnusedFunction".to_string(), 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn underscores_and_signatures_are_not_unused() {
+        let source = "def main(_x: i32): i32 {\n    let _y = 1;\n    return 0;\n}\n\ndef extern puts(s: str): i32 {\n}\n\ndef helper(): void {\n}\n\ndef @entry private start(): void {\n}";
+        assert_eq!(unused(source), vec![]);
+    }
+
+    const AREA: &str = "def @overload area(s: i32): i32 {\n    return s * s;\n}\n\ndef @overload area(w: i32, h: i32 = 1): i32 {\n    return w * h;\n}\n\n";
+
+    #[test]
+    fn calls_pick_the_overload_taking_their_arguments() {
+        let source = format!("{}def main(): i32 {{\n    return area(2, 3);\n}}", AREA);
+        let ast = parse(&source);
+        let resolution = resolve(&ast, &[]).unwrap();
+        let ASTNode::FunctionDef(_, _, _, _, body, _) = &ast[2] else {
+            panic!("Expected a function");
+        };
+        let ASTNode::Return(call) = &*body[0] else {
+            panic!("Expected a return");
+        };
+        let ASTNode::FunctionCall(name, ..) = &**call else {
+            panic!("Expected a call");
+        };
+        assert_eq!(resolution.symbol_of(name).unwrap().declaration.code_position.line_start, 4);
+        assert_eq!(ast[0].symbol_name("app").unwrap(), "app__area__1");
+        assert_eq!(ast[1].symbol_name("app").unwrap(), "app__area__2");
+    }
+
+    #[test]
+    fn rejects_calls_that_no_or_several_overloads_take() {
+        let error = resolve_source(&format!("{}def main(): i32 {{\n    return area(1, 2, 3);\n}}", AREA)).unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::NoMatchingOverload));
+        assert_eq!(error.labels.len(), 2);
+
+        let error = resolve_source(&format!("{}def main(): i32 {{\n    return area(1);\n}}", AREA)).unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::AmbiguousCall));
+        let labels: Vec<_> = error.labels.iter().map(|(_, t)| t.as_str()).collect();
+        assert_eq!(labels, ["Candidate taking 1 parameter", "Candidate taking 2 parameters"]);
+
+        resolve_source(&format!("{}def main(): i32 {{\n    return area(w = 1);\n}}", AREA)).unwrap();
+    }
+
+    #[test]
+    fn overloads_need_the_attribute_and_different_arities() {
+        let error = resolve_source("def @overload f(a: i32): void {\n}\n\ndef f(): void {\n}").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::DuplicateDefinition));
+
+        let error = resolve_source("def @overload f(a: i32): void {\n}\n\ndef @overload f(b: u8): void {\n}").unwrap_err();
+        assert!(matches!(error.code_error_type, CodeErrorType::DuplicateDefinition));
+        assert_eq!(error.notes, ["Overloads need different numbers of parameters"]);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::entry::{EntryPoint, START_SYMBOL};
+use crate::parser::Builtin;
+
+/// Which allocator backs the heap builtins.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AllocatorKind {
+    /// Forward to libc `malloc`, `free` and `realloc`
+    Libc,
+    /// Free-list allocator on top of `mmap`, for builds without libc
+    Freestanding,
+}
+
+impl AllocatorKind {
+    /// Names `--runtime` accepts.
+    pub const NAMES: [&'static str; 2] = ["libc", "freestanding"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "libc" => Some(AllocatorKind::Libc),
+            "freestanding" => Some(AllocatorKind::Freestanding),
+            _ => None,
+        }
+    }
+}
+
+/// Where the dynamic linker and the C library are on x86-64 Linux distributions.
+const DYNAMIC_LINKER: &str = "/lib64/ld-linux-x86-64.so.2";
+const LIBRARY_PATHS: [&str; 4] = ["/lib/x86_64-linux-gnu", "/usr/lib/x86_64-linux-gnu", "/lib64", "/usr/lib64"];
+
+/// Runtime symbol a builtin is lowered to. All of them take and return byte sizes / pointers,
+/// scaling `count` by the size of `T` happens at the call site.
+pub fn builtin_symbol(builtin: Builtin) -> &'static str {
+    match builtin {
+        Builtin::Alloc => "sila_alloc",
+        Builtin::Free => "sila_free",
+        Builtin::Realloc => "sila_realloc",
+    }
+}
+
+const LIBC_RUNTIME: &str = r#"    .text
+    .globl sila_alloc
+    .globl sila_free
+    .globl sila_realloc
+sila_alloc:
+    jmp malloc@PLT
+sila_free:
+    jmp free@PLT
+sila_realloc:
+    jmp realloc@PLT
+
+    .section .note.GNU-stack,"",@progbits
+"#;
+
+// Blocks carry a 16 byte header: the block size (including the header) and,
+// while the block is free, the next block of the free list.
+// `sila_alloc` takes the first free block that is large enough and otherwise
+// bumps a pointer through chunks of at least 1 MiB mapped with `mmap`.
+const FREESTANDING_RUNTIME: &str = r#"    .text
+    .globl sila_alloc
+    .globl sila_free
+    .globl sila_realloc
+
+# rdi = size in bytes -> rax = pointer (0 if out of memory)
+sila_alloc:
+    addq $15, %rdi
+    andq $-16, %rdi
+    addq $16, %rdi
+    leaq sila_free_list(%rip), %rcx
+1:  movq (%rcx), %rax
+    testq %rax, %rax
+    jz 2f
+    cmpq (%rax), %rdi
+    jbe 3f
+    leaq 8(%rax), %rcx
+    jmp 1b
+3:  movq 8(%rax), %rdx
+    movq %rdx, (%rcx)
+    addq $16, %rax
+    ret
+2:  movq sila_heap_ptr(%rip), %rax
+    leaq (%rax,%rdi), %rsi
+    cmpq sila_heap_end(%rip), %rsi
+    jbe 4f
+    pushq %rdi
+    movq $0x100000, %rsi
+    cmpq %rsi, %rdi
+    cmova %rdi, %rsi
+    pushq %rsi
+    movl $9, %eax
+    xorl %edi, %edi
+    movl $3, %edx
+    movl $0x22, %r10d
+    movq $-1, %r8
+    xorl %r9d, %r9d
+    syscall
+    popq %rsi
+    popq %rdi
+    cmpq $-4096, %rax
+    ja 5f
+    addq %rax, %rsi
+    movq %rsi, sila_heap_end(%rip)
+    leaq (%rax,%rdi), %rsi
+4:  movq %rsi, sila_heap_ptr(%rip)
+    movq %rdi, (%rax)
+    addq $16, %rax
+    ret
+5:  xorl %eax, %eax
+    ret
+
+# rdi = pointer (may be 0)
+sila_free:
+    testq %rdi, %rdi
+    jz 1f
+    subq $16, %rdi
+    movq sila_free_list(%rip), %rax
+    movq %rax, 8(%rdi)
+    movq %rdi, sila_free_list(%rip)
+1:  ret
+
+# rdi = pointer (may be 0), rsi = new size in bytes -> rax = pointer
+sila_realloc:
+    testq %rdi, %rdi
+    jnz 1f
+    movq %rsi, %rdi
+    jmp sila_alloc
+1:  movq -16(%rdi), %rdx
+    subq $16, %rdx
+    cmpq %rsi, %rdx
+    jae 2f
+    pushq %rdi
+    pushq %rdx
+    movq %rsi, %rdi
+    call sila_alloc
+    popq %rcx
+    popq %rsi
+    testq %rax, %rax
+    jz 3f
+    pushq %rsi
+    pushq %rax
+    movq %rax, %rdi
+    rep movsb
+    popq %rax
+    popq %rdi
+    pushq %rax
+    call sila_free
+    popq %rax
+3:  ret
+2:  movq %rdi, %rax
+    ret
+
+    .bss
+    .p2align 3
+sila_free_list:
+    .zero 8
+sila_heap_ptr:
+    .zero 8
+sila_heap_end:
+    .zero 8
+
+    .section .note.GNU-stack,"",@progbits
+"#;
+
+/// x86-64 GNU assembler source of the allocation runtime, linked into every program.
+pub fn runtime_asm(kind: AllocatorKind) -> &'static str {
+    match kind {
+        AllocatorKind::Libc => LIBC_RUNTIME,
+        AllocatorKind::Freestanding => FREESTANDING_RUNTIME,
+    }
+}
+
+/// Linker arguments for the runtime, programs using libc are linked dynamically against it.
+pub fn link_args(kind: AllocatorKind) -> Vec<String> {
+    match kind {
+        AllocatorKind::Libc => {
+            let mut args = vec!["-dynamic-linker".to_string(), DYNAMIC_LINKER.to_string()];
+            args.extend(LIBRARY_PATHS.iter().map(|t| format!("-L{}", t)));
+            args.push("-lc".to_string());
+            args
+        }
+        AllocatorKind::Freestanding => vec!["-static".to_string()],
+    }
+}
+
+/// x86-64 GNU assembler source of `_start`, which passes `argc` and `argv` to the entry
+/// function and exits with its return value. With libc, `exit` flushes its buffers first.
+pub fn start_asm(entry: &EntryPoint, kind: AllocatorKind) -> String {
+    let exit = match kind {
+        AllocatorKind::Libc => "    call exit@PLT\n",
+        AllocatorKind::Freestanding => "    movl $60, %eax\n    syscall\n",
+    };
+    format!(
+        r#"    .text
+    .globl {start}
+{start}:
+    xorl %ebp, %ebp
+    movl (%rsp), %edi
+    leaq 8(%rsp), %rsi
+    andq $-16, %rsp
+    call {entry}
+    movl %eax, %edi
+{exit}
+    .section .note.GNU-stack,"",@progbits
+"#,
+        start = START_SYMBOL,
+        entry = entry.symbol,
+        exit = exit
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::generate;
+    use crate::entry::entry_point;
+    use crate::linker::{assemble, lld_link, LldFlavor};
+    use crate::testing::check_module_of;
+    use std::process::Command;
+
+    /// Links `source` with the runtime of `kind`, returns the exit code of the program.
+    fn run(source: &str, kind: AllocatorKind) -> Option<i32> {
+        let modules = [check_module_of(source).unwrap()];
+        let entry = entry_point(&modules[0].ast, &modules[0].name, None).unwrap();
+        let name = format!("{:?}", kind).to_lowercase();
+        let dir = std::env::temp_dir().join(format!("sila-runtime-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut objects = vec![];
+        for (file, source) in [
+            ("program.o", generate(&modules, &entry)),
+            ("start.o", start_asm(&entry, kind)),
+            ("runtime.o", runtime_asm(kind).to_string()),
+        ] {
+            let object = dir.join(file).to_str().unwrap().to_string();
+            assemble(&source, &object).unwrap();
+            objects.push(object);
+        }
+        objects.extend(link_args(kind));
+        let program = dir.join("program");
+        let linked = lld_link(LldFlavor::Elf, program.to_str().unwrap().to_string(), false, objects, Some(START_SYMBOL.to_string()));
+        if linked.as_ref().is_err_and(|t| t.starts_with("No linker found")) {
+            return None;
+        }
+        linked.unwrap();
+        let code = Command::new(&program).status().unwrap().code();
+        std::fs::remove_dir_all(dir).unwrap();
+        code
+    }
+
+    #[test]
+    fn parses_runtime_names() {
+        for name in AllocatorKind::NAMES {
+            assert!(AllocatorKind::from_name(name).is_some());
+        }
+        assert_eq!(AllocatorKind::from_name("musl"), None);
+    }
+
+    /// Freed blocks are reused, growing a block moves it and large blocks get their own mapping.
+    #[test]
+    fn allocates_without_libc() {
+        let source = "def @unsafe main(): i32 {\n    let a = alloc<i64>(4);\n    free(a);\n    let b = alloc<i64>(2);\n    \
+            let c = realloc<i64>(b, 64);\n    let big = alloc<u8>(4000000);\n    \
+            let reused = (a -> u64) == (b -> u64);\n    let moved = (c -> u64) != (b -> u64);\n    \
+            let mapped = (big -> u64) != 0;\n    \
+            return (reused -> i32) + (moved -> i32) * 2 + (mapped -> i32) * 4;\n}";
+        if let Some(code) = run(source, AllocatorKind::Freestanding) {
+            assert_eq!(code, 7);
+        }
+    }
+
+    #[test]
+    fn links_against_libc() {
+        let source = "def extern puts(s: str): i32 {\n}\n\ndef @unsafe main(): i32 {\n    let p = alloc<i32>(8);\n    \
+            let q = realloc<i32>(p, 16);\n    free(q);\n    puts(\"linked\");\n    return ((q -> u64) != 0) -> i32;\n}";
+        if let Some(code) = run(source, AllocatorKind::Libc) {
+            assert_eq!(code, 1);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::check_module;
+use crate::comp_errors::{CodeError, CodeResult, CodeWarning};
+use crate::filemanager::FileManager;
+use crate::lexer::tokenize;
+use crate::modules::Module;
+use crate::parser::{ASTNode, Parser};
+
+pub fn source_file(source: &str) -> &'static FileManager {
+    Box::leak(Box::new(FileManager::from_source(source)))
+}
+
+fn parse_in(source: &str, file_manager: &'static FileManager) -> CodeResult<Vec<ASTNode<'static>>> {
+    let tokens = tokenize(source.to_string())?;
+    let parser: &'static Parser = Box::leak(Box::new(Parser::new(tokens, file_manager)));
+    parser.parse(&mut 0)
+}
+
+/// Parses `source`. Tokens and file manager live for the rest of the test.
+pub fn try_parse(source: &str) -> CodeResult<Vec<ASTNode<'static>>> {
+    parse_in(source, source_file(source))
+}
+
+pub fn parse(source: &str) -> Vec<ASTNode<'static>> {
+    try_parse(source).unwrap_or_else(|e| panic!("{}: {}", e.title, e.footer))
+}
+
+/// Parses `source` and returns the warnings of the parser with the tree.
+pub fn parse_with_warnings(source: &str) -> (Vec<ASTNode<'static>>, Vec<CodeWarning>) {
+    let tokens = tokenize(source.to_string()).unwrap();
+    let parser: &'static Parser = Box::leak(Box::new(Parser::new(tokens, source_file(source))));
+    let ast = parser.parse(&mut 0).unwrap_or_else(|e| panic!("{}: {}", e.title, e.footer));
+    (ast, parser.take_warnings())
+}
+
+/// Runs every pass a module goes through before code generation.
+pub fn check_module_of(source: &str) -> CodeResult<Module<'static>> {
+    let file_manager = source_file(source);
+    let mut module = Module::new(file_manager, parse_in(source, file_manager)?);
+    check_module(&mut module)?;
+    Ok(module)
+}
+
+pub fn check(source: &str) -> CodeResult<Vec<ASTNode<'static>>> {
+    check_module_of(source).map(|t| t.ast)
+}
+
+pub fn check_err(source: &str) -> CodeError {
+    match check(source) {
+        Ok(_) => panic!("Expected an error"),
+        Err(error) => error,
+    }
+}
+
+/// Source-like rendering of a node, to compare trees in assertions.
+pub fn render(node: &ASTNode) -> String {
+    let list = |nodes: &[Box<ASTNode>]| nodes.iter().map(|t| render(t)).collect::<Vec<_>>().join(", ");
+    let block = |nodes: &[Box<ASTNode>]| nodes.iter().map(|t| render(t)).collect::<Vec<_>>().join("; ");
+    match node {
+        ASTNode::Literal(t) | ASTNode::Identifier(t) | ASTNode::Type(t) => t.content.clone(),
+        ASTNode::String(t) => format!("\"{}\"", t.content),
+        ASTNode::BinaryOp(lhs, op, rhs) => format!("{} {} {}", render(lhs), op.content, render(rhs)),
+        ASTNode::CastExpr(expr, ty) => format!("{} -> {}", render(expr), render(ty)),
+        ASTNode::FunctionCall(name, args, _) | ASTNode::BuiltinCall(_, name, _, args, _) => {
+            format!("{}({})", name.content, list(args))
+        }
+        ASTNode::NamedArgument(name, value) => format!("{} = {}", name.content, render(value)),
+        ASTNode::VariableSet(name, value, _, mutable) => format!(
+            "let {}{}{}",
+            if *mutable { "mut " } else { "" },
+            name.content,
+            value.as_ref().map_or(String::new(), |t| format!(" = {}", render(t)))
+        ),
+        ASTNode::Assignment(name, value) => format!("{} = {}", name.content, render(value)),
+        ASTNode::Return(value) => format!("return {}", render(value)),
+        ASTNode::Break(_) => "break".to_string(),
+        ASTNode::Continue(_) => "continue".to_string(),
+        ASTNode::If(condition, then_body, else_body) => format!(
+            "if {} {{ {} }} else {{ {} }}",
+            render(condition),
+            block(then_body),
+            block(else_body)
+        ),
+        ASTNode::WhileLoop(condition, body) => format!("while {} {{ {} }}", render(condition), block(body)),
+        ASTNode::ForLoop(var, iterable, body) => {
+            format!("for {} in {} {{ {} }}", var.content, render(iterable), block(body))
+        }
+        ASTNode::Range(start, end, inclusive) => {
+            format!("{}..{}{}", render(start), if *inclusive { "=" } else { "" }, render(end))
+        }
+        ASTNode::Index(array, index) => format!("{}[{}]", render(array), render(index)),
+        ASTNode::ArrayLiteral(_, elements) => format!("[{}]", list(elements)),
+        ASTNode::StructLiteral(name, fields) => format!(
+            "{} {{ {} }}",
+            name.content,
+            fields
+                .iter()
+                .map(|(field, value)| format!("{}: {}", field.content, render(value)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Rendered statements of the function called `name`.
+pub fn body(ast: &[ASTNode], name: &str) -> Vec<String> {
+    ast.iter()
+        .find_map(|t| match t {
+            ASTNode::FunctionDef(function, _, _, _, body, _) if function.content == name => {
+                Some(body.iter().map(|t| render(t)).collect())
+            }
+            _ => None,
+        })
+        .unwrap_or_else(|| panic!("No function named `{}`", name))
+}
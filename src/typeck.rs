@@ -0,0 +1,725 @@
+use crate::checker::bind_arguments;
+use crate::codeviz::print_code_warn;
+use crate::comp_errors::{CodeError, CodeResult, CodeWarning};
+use crate::filemanager::FileManager;
+use crate::lexer::{CodePosition, Token, TokenType};
+use crate::parser::{ASTNode, AttributeKind, Builtin, Parameter};
+use crate::resolver::Resolution;
+use crate::types::{CastKind, InferKind, Type};
+use std::collections::HashMap;
+
+/// Types of all checked expressions and declarations.
+/// Expressions are keyed by node address, declarations by their name token.
+#[derive(Debug, Default)]
+pub struct TypeTable {
+    expressions: HashMap<*const ASTNode<'static>, Type>,
+    declarations: HashMap<*const Token, Type>,
+}
+
+impl TypeTable {
+    pub fn type_of(&self, node: &ASTNode) -> Option<&Type> {
+        self.expressions.get(&(node as *const ASTNode).cast::<ASTNode<'static>>())
+    }
+
+    pub fn type_of_declaration(&self, name: &Token) -> Option<&Type> {
+        self.declarations.get(&(name as *const Token))
+    }
+
+    pub fn record(&mut self, node: &ASTNode, ty: Type) {
+        self.expressions
+            .insert((node as *const ASTNode).cast::<ASTNode<'static>>(), ty);
+    }
+
+    pub fn declare(&mut self, name: &Token, ty: Type) {
+        self.declarations.insert(name as *const Token, ty);
+    }
+}
+
+/// Declaration of a struct and its fields.
+type StructFields<'a> = (&'a Token, Vec<(&'a Token, Type)>);
+
+struct Signature<'a, 'b> {
+    name: &'a Token,
+    params: &'b [Parameter<'a>],
+    ret: Type,
+}
+
+pub struct TypeChecker<'a, 'b> {
+    file_manager: &'b FileManager,
+    resolution: &'b Resolution<'a>,
+    // Function declaration -> signature
+    functions: HashMap<*const Token, Signature<'a, 'b>>,
+    // Struct name -> fields
+    structs: HashMap<&'a str, StructFields<'a>>,
+    table: TypeTable,
+    // Return type of the current function and where it was declared
+    return_type: (Type, CodePosition),
+    // Whether the current function is marked `@unsafe`
+    is_unsafe: bool,
+    // Inference variables: binding, kind, origin and the binding it initializes
+    variables: Vec<(Option<Type>, InferKind, CodePosition, Option<&'a Token>)>,
+    // Integer literals of the current function, checked once their types are known
+    literals: Vec<(&'a Token, Type)>,
+}
+
+fn is_arithmetic(op: &Token) -> bool {
+    matches!(
+        op.token_type,
+        TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash
+    )
+}
+
+/// Literals (and arithmetic on them) have no fixed type and adapt to their context.
+fn is_untyped_literal(node: &ASTNode) -> bool {
+    match node {
+        ASTNode::Literal(t) => t.token_type != TokenType::Boolean,
+        ASTNode::BinaryOp(lhs, op, rhs) => {
+            is_arithmetic(op) && is_untyped_literal(lhs) && is_untyped_literal(rhs)
+        }
+        _ => false,
+    }
+}
+
+/// Value of integer literal arithmetic, unless it overflows.
+fn literal_value(node: &ASTNode) -> Option<i128> {
+    match node {
+        ASTNode::Literal(t) if t.token_type == TokenType::NumberInt => t.content.parse().ok(),
+        ASTNode::BinaryOp(lhs, op, rhs) => {
+            let (lhs, rhs) = (literal_value(lhs)?, literal_value(rhs)?);
+            match op.token_type {
+                TokenType::Plus => lhs.checked_add(rhs),
+                TokenType::Minus => lhs.checked_sub(rhs),
+                TokenType::Star => lhs.checked_mul(rhs),
+                TokenType::Slash => lhs.checked_div(rhs),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+impl<'a, 'b> TypeChecker<'a, 'b> {
+    pub fn new(
+        ast: &'b [ASTNode<'a>],
+        resolution: &'b Resolution<'a>,
+        file_manager: &'b FileManager,
+    ) -> CodeResult<Self> {
+        let mut functions = HashMap::new();
+        let mut structs = HashMap::new();
+        for item in ast {
+            match item {
+                ASTNode::FunctionDef(name, _, ret, params, ..) => {
+                    let name: &'a Token = name;
+                    functions.insert(
+                        name as *const Token,
+                        Signature {
+                            name,
+                            params,
+                            ret: Type::from_node(ret)?,
+                        },
+                    );
+                }
+                ASTNode::StructDef(name, fields) => {
+                    let name: &'a Token = name;
+                    let mut field_types = vec![];
+                    for (field, ty) in fields {
+                        field_types.push((*field, Type::from_node(ty)?));
+                    }
+                    structs.insert(name.content.as_str(), (name, field_types));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            file_manager,
+            resolution,
+            functions,
+            structs,
+            table: TypeTable::default(),
+            return_type: (Type::Void, CodePosition::eof()),
+            is_unsafe: false,
+            variables: vec![],
+            literals: vec![],
+        })
+    }
+
+    fn fresh(&mut self, kind: InferKind, origin: CodePosition) -> Type {
+        self.variables.push((None, kind, origin, None));
+        Type::Infer(self.variables.len() - 1, kind)
+    }
+
+    /// Substitutes all bound inference variables.
+    fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Infer(id, _) => match &self.variables[*id].0 {
+                Some(bound) => self.resolve(bound),
+                None => ty.clone(),
+            },
+            Type::Pointer(pointee) => Type::Pointer(Box::new(self.resolve(pointee))),
+            Type::Array(element, length) => Type::Array(Box::new(self.resolve(element)), *length),
+            other => other.clone(),
+        }
+    }
+
+    /// Makes both types equal by binding inference variables, false if they can not be.
+    fn unify(&mut self, a: &Type, b: &Type) -> bool {
+        let (a, b) = (self.resolve(a), self.resolve(b));
+        match (&a, &b) {
+            (Type::Infer(x, _), Type::Infer(y, _)) if x == y => true,
+            (Type::Infer(x, kind_x), Type::Infer(y, kind_y)) => {
+                if kind_x.rank() <= kind_y.rank() {
+                    self.variables[*x].0 = Some(b.clone());
+                } else {
+                    self.variables[*y].0 = Some(a.clone());
+                }
+                true
+            }
+            (Type::Infer(x, kind), other) | (other, Type::Infer(x, kind)) => {
+                if kind.accepts(other) {
+                    self.variables[*x].0 = Some(other.clone());
+                    true
+                } else {
+                    false
+                }
+            }
+            (Type::Pointer(p), Type::Pointer(q)) => self.unify(p, q),
+            (Type::Array(p, n), Type::Array(q, m)) => n == m && self.unify(p, q),
+            _ => a == b,
+        }
+    }
+
+    /// Numeric, or a literal that will become numeric.
+    fn is_numeric(&self, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Infer(_, kind) => kind != InferKind::Unknown,
+            other => other.is_numeric(),
+        }
+    }
+
+    fn is_integer(&self, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Infer(_, kind) => kind == InferKind::Integer,
+            other => other.is_integer(),
+        }
+    }
+
+    /// Remembers which binding an unresolved type initializes, for suggestions.
+    fn name_variables(&mut self, ty: &Type, name: &'a Token) {
+        match self.resolve(ty) {
+            Type::Infer(id, _) => self.variables[id].3 = Some(name),
+            Type::Pointer(inner) | Type::Array(inner, _) => self.name_variables(&inner, name),
+            _ => {}
+        }
+    }
+
+    /// Gives literals their default type, right away.
+    fn default_variables(&mut self, ty: &Type) -> CodeResult<Type> {
+        match self.resolve(ty) {
+            Type::Infer(id, kind) => {
+                let (_, _, origin, name) = self.variables[id];
+                let ty = kind.default_type().ok_or_else(|| {
+                    CodeError::cannot_infer_error(
+                        origin,
+                        Some(match name {
+                            Some(name) => format!(
+                                "Add a type annotation, like `let {}: <type> = ...`",
+                                name.content
+                            ),
+                            None => "Add a type annotation or a cast".to_string(),
+                        }),
+                    )
+                })?;
+                self.variables[id].0 = Some(ty.clone());
+                Ok(ty)
+            }
+            Type::Pointer(pointee) => Ok(Type::Pointer(Box::new(self.default_variables(&pointee)?))),
+            Type::Array(element, length) => {
+                Ok(Type::Array(Box::new(self.default_variables(&element)?), length))
+            }
+            other => Ok(other),
+        }
+    }
+
+    fn warning(&self, code_warning: CodeWarning) {
+        print_code_warn(code_warning, self.file_manager)
+    }
+
+    fn check_cast(&mut self, expr: &ASTNode<'a>, target: &ASTNode<'a>) -> CodeResult<Type> {
+        let to = Type::from_node(target)?;
+        // `1 -> u8` just gives the literal a type, as long as its value survives
+        let exact = match (&to, literal_value(expr)) {
+            (Type::Int(..), Some(value)) => to.fits(value),
+            (ty, _) => ty.is_float(),
+        };
+        if is_untyped_literal(expr) && exact {
+            self.check_expr(expr, Some(&to))?;
+            return Ok(to);
+        }
+
+        // A literal has to settle on a type before it can be converted
+        let from = self.check_expr(expr, None)?;
+        let from = self.default_variables(&from)?;
+        let (position, target_position) = (expr.position(), target.position());
+        match from.cast_kind(&to) {
+            CastKind::NoOp => self.warning(CodeWarning::new_no_op_cast(
+                position,
+                target_position,
+                &to.to_string(),
+            )),
+            CastKind::Lossy => self.warning(CodeWarning::new_lossy_cast(
+                position,
+                target_position,
+                &from.to_string(),
+                &to.to_string(),
+            )),
+            CastKind::Unsafe if !self.is_unsafe => {
+                return Err(CodeError::unsafe_cast_error(
+                    position,
+                    target_position,
+                    &from.to_string(),
+                    &to.to_string(),
+                ))
+            }
+            CastKind::Invalid => {
+                return Err(CodeError::invalid_cast_error(
+                    position,
+                    target_position,
+                    &from.to_string(),
+                    &to.to_string(),
+                ))
+            }
+            CastKind::Lossless | CastKind::Unsafe => {}
+        }
+        Ok(to)
+    }
+
+    /// Checks `node` against `expected`, pointing at `declared` as the origin of the expectation.
+    fn expect(
+        &mut self,
+        node: &ASTNode<'a>,
+        expected: &Type,
+        declared: Option<(CodePosition, String)>,
+    ) -> CodeResult<Type> {
+        let found = self.check_expr(node, Some(expected))?;
+        if !self.unify(&found, expected) {
+            let error = CodeError::type_mismatch_error(
+                node.position(),
+                &self.resolve(expected).to_string(),
+                &self.resolve(&found).to_string(),
+            );
+            return Err(match declared {
+                Some((position, label)) => error.with_label(position, label),
+                None => error,
+            });
+        }
+        Ok(found)
+    }
+
+    fn check_binary_op(
+        &mut self,
+        lhs: &ASTNode<'a>,
+        op: &'a Token,
+        rhs: &ASTNode<'a>,
+        expected: Option<&Type>,
+    ) -> CodeResult<Type> {
+        let hint = expected.filter(|t| is_arithmetic(op) && self.is_numeric(t)).cloned();
+
+        let lhs_type = self.check_expr(lhs, hint.as_ref())?;
+        let rhs_type = self.check_expr(rhs, Some(&lhs_type))?;
+        if !self.unify(&lhs_type, &rhs_type) {
+            return Err(CodeError::type_mismatch_error(
+                rhs.position(),
+                &self.resolve(&lhs_type).to_string(),
+                &self.resolve(&rhs_type).to_string(),
+            )
+            .with_label(lhs.position(), format!("This is `{}`", self.resolve(&lhs_type))));
+        }
+
+        let valid = match (op.token_type, self.resolve(&lhs_type)) {
+            (TokenType::DoubleEquals | TokenType::NotEquals, Type::Bool | Type::Pointer(_)) => true,
+            (_, ty) => self.is_numeric(&ty),
+        };
+        if !valid {
+            return Err(CodeError::invalid_operand_error(
+                lhs.position(),
+                op,
+                &self.resolve(&lhs_type).to_string(),
+            ));
+        }
+
+        Ok(if is_arithmetic(op) { lhs_type } else { Type::Bool })
+    }
+
+    fn check_call(
+        &mut self,
+        name: &'a Token,
+        args: &[Box<ASTNode<'a>>],
+    ) -> CodeResult<Type> {
+        let declaration = self.resolution.symbol_of(name).unwrap().declaration;
+        let signature = &self.functions[&(declaration as *const Token)];
+        let (function, params, ret) = (signature.name, signature.params, signature.ret.clone());
+        let bound = bind_arguments(name, args, function, params)?;
+        for ((param, ty, _), value) in params.iter().zip(bound) {
+            let expected = Type::from_node(ty)?;
+            self.expect(
+                value,
+                &expected,
+                Some((param.code_position, format!("Parameter declared as `{}`", expected))),
+            )?;
+        }
+        // Named arguments are not expressions of their own, but keep them in the table
+        for arg in args {
+            if let ASTNode::NamedArgument(_, value) = &**arg {
+                let ty = self.table.type_of(value).cloned().unwrap();
+                self.table.record(arg, ty);
+            }
+        }
+        Ok(ret)
+    }
+
+    fn check_builtin(
+        &mut self,
+        builtin: Builtin,
+        type_arg: &Option<Box<ASTNode<'a>>>,
+        args: &[Box<ASTNode<'a>>],
+    ) -> CodeResult<Type> {
+        let count = Type::Int(64, false);
+        match builtin {
+            Builtin::Alloc => {
+                let ty = Type::from_node(type_arg.as_ref().unwrap())?;
+                self.expect(&args[0], &count, None)?;
+                Ok(Type::Pointer(Box::new(ty)))
+            }
+            Builtin::Free => {
+                let found = self.check_expr(&args[0], None)?;
+                let found = self.resolve(&found);
+                if !matches!(found, Type::Pointer(_)) {
+                    return Err(CodeError::type_mismatch_error(
+                        args[0].position(),
+                        "a pointer",
+                        &found.to_string(),
+                    ));
+                }
+                Ok(Type::Void)
+            }
+            Builtin::Realloc => {
+                let ty = Type::Pointer(Box::new(Type::from_node(type_arg.as_ref().unwrap())?));
+                self.expect(&args[0], &ty, None)?;
+                self.expect(&args[1], &count, None)?;
+                Ok(ty)
+            }
+        }
+    }
+
+    fn check_struct_literal(
+        &mut self,
+        name: &'a Token,
+        fields: &[(&'a Token, Box<ASTNode<'a>>)],
+    ) -> CodeResult<Type> {
+        let (declaration, declared) = self.structs[name.content.as_str()].clone();
+        for (field, value) in fields {
+            let (declared_field, ty) = declared
+                .iter()
+                .find(|(t, _)| t.content == field.content)
+                .ok_or_else(|| CodeError::unknown_field_error(field, declaration))?;
+            self.expect(
+                value,
+                ty,
+                Some((
+                    declared_field.code_position,
+                    format!("Field declared as `{}`", ty),
+                )),
+            )?;
+        }
+        if let Some((missing, _)) = declared
+            .iter()
+            .find(|(t, _)| !fields.iter().any(|(f, _)| f.content == t.content))
+        {
+            return Err(CodeError::missing_field_error(name, missing));
+        }
+        Ok(Type::Struct(name.content.clone()))
+    }
+
+    pub fn check_expr(&mut self, node: &ASTNode<'a>, expected: Option<&Type>) -> CodeResult<Type> {
+        let ty = match node {
+            ASTNode::Literal(t) => match t.token_type {
+                TokenType::Boolean => Type::Bool,
+                TokenType::NumberFloat => match expected.map(|t| self.resolve(t)) {
+                    Some(Type::Float(bits)) => Type::Float(bits),
+                    _ => self.fresh(InferKind::Float, t.code_position),
+                },
+                _ => {
+                    let ty = match expected.map(|t| self.resolve(t)) {
+                        Some(ty) if ty.is_numeric() => ty,
+                        _ => self.fresh(InferKind::Integer, t.code_position),
+                    };
+                    self.literals.push((t, ty.clone()));
+                    ty
+                }
+            },
+            ASTNode::String(_) => Type::Str,
+            ASTNode::Identifier(name) => {
+                let declaration = self.resolution.symbol_of(name).unwrap().declaration;
+                self.table.type_of_declaration(declaration).cloned().unwrap()
+            }
+            ASTNode::BinaryOp(lhs, op, rhs) => self.check_binary_op(lhs, op, rhs, expected)?,
+            ASTNode::CastExpr(expr, ty) => self.check_cast(expr, ty)?,
+            ASTNode::FunctionCall(name, args, _) => self.check_call(name, args)?,
+            ASTNode::BuiltinCall(builtin, _, type_arg, args, _) => {
+                self.check_builtin(*builtin, type_arg, args)?
+            }
+            ASTNode::StructLiteral(name, fields) => self.check_struct_literal(name, fields)?,
+            ASTNode::ArrayLiteral(bracket, elements) => {
+                let element_hint = match expected.map(|t| self.resolve(t)) {
+                    Some(Type::Array(element, _)) => Some(*element),
+                    _ => None,
+                };
+                let first = match elements.first() {
+                    Some(first) => self.check_expr(first, element_hint.as_ref())?,
+                    None => self.fresh(InferKind::Unknown, bracket.code_position),
+                };
+                for element in elements.iter().skip(1) {
+                    self.expect(element, &first, None)?;
+                }
+                Type::Array(Box::new(first), elements.len())
+            }
+            ASTNode::Range(start, end, _) => {
+                let ty = self.check_expr(start, None)?;
+                let ty = self.expect(end, &ty, None)?;
+                if !self.is_integer(&ty) {
+                    return Err(CodeError::type_mismatch_error(
+                        start.position(),
+                        "an integer",
+                        &self.resolve(&ty).to_string(),
+                    ));
+                }
+                ty
+            }
+            _ => Type::Void,
+        };
+        self.table.record(node, ty.clone());
+        Ok(ty)
+    }
+
+    fn check_block(&mut self, body: &[Box<ASTNode<'a>>]) -> CodeResult<()> {
+        for statement in body {
+            match &**statement {
+                ASTNode::VariableSet(name, value, annotation, _) => {
+                    let ty = match (annotation, value) {
+                        (Some(annotation), Some(value)) => {
+                            let ty = Type::from_node(annotation)?;
+                            self.expect(
+                                value,
+                                &ty,
+                                Some((annotation.position(), "Expected because of this".to_string())),
+                            )?
+                        }
+                        (Some(annotation), None) => Type::from_node(annotation)?,
+                        (None, Some(value)) => {
+                            let ty = self.check_expr(value, None)?;
+                            self.name_variables(&ty, name);
+                            ty
+                        }
+                        // Decided by the assignments that follow
+                        (None, None) => {
+                            let ty = self.fresh(InferKind::Unknown, name.code_position);
+                            self.name_variables(&ty, name);
+                            ty
+                        }
+                    };
+                    self.table.declare(name, ty);
+                }
+                ASTNode::Assignment(name, value) => {
+                    let declaration = self.resolution.symbol_of(name).unwrap().declaration;
+                    let ty = self.table.type_of_declaration(declaration).cloned().unwrap();
+                    let label = format!("Declared as `{}`", self.resolve(&ty));
+                    self.expect(value, &ty, Some((declaration.code_position, label)))?;
+                }
+                ASTNode::WhileLoop(condition, body) => {
+                    self.expect(condition, &Type::Bool, None)?;
+                    self.check_block(body)?;
+                }
+                ASTNode::If(condition, then_body, else_body) => {
+                    self.expect(condition, &Type::Bool, None)?;
+                    self.check_block(then_body)?;
+                    self.check_block(else_body)?;
+                }
+                ASTNode::Break(_) | ASTNode::Continue(_) => {}
+                ASTNode::ForLoop(var, iterable, body) => {
+                    let ty = self.check_expr(iterable, None)?;
+                    let ty = match self.resolve(&ty) {
+                        _ if matches!(**iterable, ASTNode::Range(..)) => ty,
+                        Type::Array(element, _) => *element,
+                        other => {
+                            return Err(CodeError::not_iterable_error(
+                                iterable.position(),
+                                &other.to_string(),
+                            ))
+                        }
+                    };
+                    self.table.declare(var, ty);
+                    self.check_block(body)?;
+                }
+                ASTNode::Return(value) => {
+                    let (ty, position) = self.return_type.clone();
+                    self.expect(
+                        value,
+                        &ty,
+                        Some((position, "Expected because of the return type".to_string())),
+                    )?;
+                }
+                other => {
+                    self.check_expr(other, None)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_function(&mut self, function: &ASTNode<'a>) -> CodeResult<()> {
+        if let ASTNode::FunctionDef(_, _, ret, params, body, _) = function {
+            for (name, ty, default) in params {
+                let ty = Type::from_node(ty)?;
+                if let Some(default) = default {
+                    self.expect(default, &ty, None)?;
+                }
+                self.table.declare(name, ty);
+            }
+            self.return_type = (Type::from_node(ret)?, ret.position());
+            self.is_unsafe = function.attribute(AttributeKind::Unsafe).is_some();
+
+            // Inference is local to the function
+            let first_variable = self.variables.len();
+            self.check_block(body)?;
+            for id in first_variable..self.variables.len() {
+                let kind = self.variables[id].1;
+                self.default_variables(&Type::Infer(id, kind))?;
+            }
+            self.check_literals()?;
+        }
+        Ok(())
+    }
+
+    /// Integer literals have to fit the type they settled on.
+    fn check_literals(&mut self) -> CodeResult<()> {
+        for (literal, ty) in std::mem::take(&mut self.literals) {
+            let ty = self.resolve(&ty);
+            if !ty.is_integer() {
+                continue;
+            }
+            if !literal.content.parse().is_ok_and(|value| ty.fits(value)) {
+                return Err(CodeError::out_of_range_error(
+                    literal.code_position,
+                    &literal.content,
+                    &ty.to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check(mut self, ast: &[ASTNode<'a>]) -> CodeResult<TypeTable> {
+        for item in ast {
+            self.check_function(item)?;
+        }
+
+        let mut table = std::mem::take(&mut self.table);
+        for ty in table.expressions.values_mut().chain(table.declarations.values_mut()) {
+            *ty = self.resolve(ty);
+        }
+        Ok(table)
+    }
+}
+
+pub fn check_types<'a>(
+    ast: &[ASTNode<'a>],
+    resolution: &Resolution<'a>,
+    file_manager: &FileManager,
+) -> CodeResult<TypeTable> {
+    TypeChecker::new(ast, resolution, file_manager)?.check(ast)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comp_errors::CodeErrorType;
+    use crate::resolver::resolve;
+    use crate::testing::{parse, source_file};
+
+    fn check_source(source: &str) -> CodeResult<(Vec<ASTNode<'static>>, TypeTable)> {
+        let ast = parse(source);
+        let resolution = resolve(&ast, &[])?;
+        let table = check_types(&ast, &resolution, source_file(source))?;
+        Ok((ast, table))
+    }
+
+    /// Type of the value returned at the end of the first function.
+    fn returned_type(ast: &[ASTNode], table: &TypeTable) -> Type {
+        let ASTNode::FunctionDef(_, _, _, _, body, _) = &ast[0] else {
+            panic!("Expected a function");
+        };
+        let ASTNode::Return(value) = &**body.last().unwrap() else {
+            panic!("Expected a return");
+        };
+        let value = match &**value {
+            ASTNode::CastExpr(expr, _) => expr,
+            value => value,
+        };
+        table.type_of(value).unwrap().clone()
+    }
+
+    fn error_type(source: &str) -> CodeErrorType {
+        check_source(source).unwrap_err().code_error_type
+    }
+
+    #[test]
+    fn literals_take_the_expected_type() {
+        let (ast, table) = check_source("def f(): u8 {\n    let x = 1;\n    return x + 2;\n}").unwrap();
+        assert_eq!(returned_type(&ast, &table), Type::Int(8, false));
+    }
+
+    #[test]
+    fn unconstrained_literals_default_to_i32() {
+        let (ast, table) = check_source("def f(): i64 {\n    return 300 -> i64;\n}").unwrap();
+        assert_eq!(returned_type(&ast, &table), Type::Int(64, true));
+
+        // A cast that would lose the value converts an `i32` instead of typing the literal
+        let (ast, table) = check_source("def f(): u8 {\n    return 300 -> u8;\n}").unwrap();
+        assert_eq!(returned_type(&ast, &table), Type::Int(32, true));
+    }
+
+    #[test]
+    fn rejects_mismatched_types() {
+        let source = "def f(): i32 {\n    let x: bool = 1;\n    return 0;\n}";
+        assert!(matches!(error_type(source), CodeErrorType::TypeMismatch));
+        let source = "def f(x: u8, y: i32): i32 {\n    return x + y;\n}";
+        assert!(matches!(error_type(source), CodeErrorType::TypeMismatch));
+        let source = "def f(): bool {\n    return true + false;\n}";
+        assert!(matches!(error_type(source), CodeErrorType::InvalidOperand));
+    }
+
+    #[test]
+    fn rejects_literals_out_of_range() {
+        let source = "def f(): i32 {\n    let y: u8 = 256;\n    return 0;\n}";
+        assert!(matches!(error_type(source), CodeErrorType::OutOfRange));
+        let source = "def f(): i32 {\n    return 3000000000;\n}";
+        assert!(matches!(error_type(source), CodeErrorType::OutOfRange));
+        check_source("def f(): u8 {\n    return 255;\n}").unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_casts() {
+        let source = "def f(): bool {\n    return \"a\" -> bool;\n}";
+        assert!(matches!(error_type(source), CodeErrorType::InvalidCast));
+    }
+
+    #[test]
+    fn classifies_casts() {
+        let (i32_, u8_, f64_) = (Type::Int(32, true), Type::Int(8, false), Type::Float(64));
+        assert!(matches!(i32_.cast_kind(&u8_), CastKind::Lossy));
+        assert!(matches!(u8_.cast_kind(&i32_), CastKind::Lossless));
+        assert!(matches!(i32_.cast_kind(&i32_), CastKind::NoOp));
+        assert!(matches!(f64_.cast_kind(&u8_), CastKind::Lossy));
+    }
+}
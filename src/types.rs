@@ -0,0 +1,206 @@
+use crate::comp_errors::{CodeError, CodeResult};
+use crate::lexer::TokenType;
+use crate::parser::ASTNode;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CastKind {
+    // Source and target are the same
+    NoOp,
+    // Every value survives the conversion
+    Lossless,
+    // Values may be truncated, rounded or change their sign
+    Lossy,
+    // Reinterprets pointers, only allowed in `@unsafe` functions
+    Unsafe,
+    Invalid,
+}
+
+/// What an inference variable may still become.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum InferKind {
+    // Integer literal, becomes any numeric type (default `i32`)
+    Integer,
+    // Float literal, becomes any float type (default `f64`)
+    Float,
+    // Nothing known yet
+    Unknown,
+}
+
+impl InferKind {
+    pub fn accepts(&self, ty: &Type) -> bool {
+        match self {
+            InferKind::Integer => ty.is_numeric(),
+            InferKind::Float => ty.is_float(),
+            InferKind::Unknown => true,
+        }
+    }
+
+    /// The more specific kind wins when two variables are unified.
+    pub fn rank(&self) -> usize {
+        match self {
+            InferKind::Unknown => 0,
+            InferKind::Integer => 1,
+            InferKind::Float => 2,
+        }
+    }
+
+    pub fn default_type(&self) -> Option<Type> {
+        match self {
+            InferKind::Integer => Some(Type::Int(32, true)),
+            InferKind::Float => Some(Type::Float(64)),
+            InferKind::Unknown => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    // Bits, Signed
+    Int(u8, bool),
+    // Bits
+    Float(u8),
+    Bool,
+    Str,
+    Void,
+    Pointer(Box<Type>),
+    // Element, Length
+    Array(Box<Type>, usize),
+    Struct(String),
+    // Inference variable, only exists while type checking
+    Infer(usize, InferKind),
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "i8" => Type::Int(8, true),
+            "i16" => Type::Int(16, true),
+            "i32" => Type::Int(32, true),
+            "i64" => Type::Int(64, true),
+            "u8" => Type::Int(8, false),
+            "u16" => Type::Int(16, false),
+            "u32" => Type::Int(32, false),
+            "u64" => Type::Int(64, false),
+            "f32" => Type::Float(32),
+            "f64" => Type::Float(64),
+            "bool" => Type::Bool,
+            "str" => Type::Str,
+            "void" => Type::Void,
+            _ => return None,
+        })
+    }
+
+    /// Converts a type annotation. Names that are not primitive are structs,
+    /// the resolver already made sure they exist.
+    pub fn from_node(node: &ASTNode) -> CodeResult<Self> {
+        match node {
+            ASTNode::Type(name) => Ok(Type::from_name(&name.content)
+                .unwrap_or_else(|| Type::Struct(name.content.clone()))),
+            ASTNode::PointerType(_, pointee) => {
+                Ok(Type::Pointer(Box::new(Type::from_node(pointee)?)))
+            }
+            // A reference parameter holds a value of the referenced type
+            ASTNode::ReferenceType(_, _, referenced) => Type::from_node(referenced),
+            ASTNode::ArrayType(element, length) => match &**length {
+                ASTNode::Literal(t) if t.token_type == TokenType::NumberInt => Ok(Type::Array(
+                    Box::new(Type::from_node(element)?),
+                    t.content.parse().unwrap(),
+                )),
+                other => Err(CodeError::not_constant_error(other.position())),
+            },
+            other => Err(CodeError::not_constant_error(other.position())),
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Type::Int(..))
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::Float(_))
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// Whether an integer type can hold `value`.
+    pub fn fits(&self, value: i128) -> bool {
+        match self {
+            Type::Int(bits, true) => {
+                let max = (1i128 << (bits - 1)) - 1;
+                value >= -max - 1 && value <= max
+            }
+            Type::Int(bits, false) => value >= 0 && value < 1i128 << bits,
+            _ => false,
+        }
+    }
+
+    pub fn cast_kind(&self, target: &Type) -> CastKind {
+        if self == target {
+            return CastKind::NoOp;
+        }
+        match (self, target) {
+            (Type::Int(from, from_signed), Type::Int(to, to_signed)) => {
+                if (from_signed == to_signed && to >= from) || (!from_signed && *to_signed && to > from) {
+                    CastKind::Lossless
+                } else {
+                    CastKind::Lossy
+                }
+            }
+            // Integers fit into the mantissa (24 / 53 bits)
+            (Type::Int(from, _), Type::Float(to)) => {
+                if (*to == 32 && *from <= 16) || (*to == 64 && *from <= 32) {
+                    CastKind::Lossless
+                } else {
+                    CastKind::Lossy
+                }
+            }
+            (Type::Float(_), Type::Int(..)) => CastKind::Lossy,
+            (Type::Float(from), Type::Float(to)) => {
+                if to > from {
+                    CastKind::Lossless
+                } else {
+                    CastKind::Lossy
+                }
+            }
+            (Type::Bool, Type::Int(..)) => CastKind::Lossless,
+            (Type::Pointer(_), Type::Int(..) | Type::Pointer(_) | Type::Str)
+            | (Type::Int(..) | Type::Str, Type::Pointer(_)) => CastKind::Unsafe,
+            _ => CastKind::Invalid,
+        }
+    }
+
+    /// Size in bytes, structs are laid out by the backends.
+    pub fn size(&self) -> Option<usize> {
+        match self {
+            Type::Int(bits, _) | Type::Float(bits) => Some(*bits as usize / 8),
+            Type::Bool => Some(1),
+            Type::Str | Type::Pointer(_) => Some(8),
+            Type::Void => Some(0),
+            Type::Array(element, length) => element.size().map(|t| t * length),
+            Type::Struct(_) | Type::Infer(..) => None,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int(bits, true) => write!(f, "i{}", bits),
+            Type::Int(bits, false) => write!(f, "u{}", bits),
+            Type::Float(bits) => write!(f, "f{}", bits),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "str"),
+            Type::Void => write!(f, "void"),
+            Type::Pointer(pointee) => write!(f, "*{}", pointee),
+            Type::Array(element, length) => write!(f, "[{}; {}]", element, length),
+            Type::Struct(name) => write!(f, "{}", name),
+            Type::Infer(_, InferKind::Integer) => write!(f, "{{integer}}"),
+            Type::Infer(_, InferKind::Float) => write!(f, "{{float}}"),
+            Type::Infer(_, InferKind::Unknown) => write!(f, "_"),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use crate::bytecode::{Instruction, Program};
+use crate::comp_errors::CodeError;
+use crate::entry::EntryPoint;
+use crate::interpreter::{binary_op, builtin, call_host, cast, exit_code, HostError, Memory, Value, CALL_DEPTH_LIMIT};
+use crate::modules::ModuleResult;
+use std::io::Write;
+
+struct CallFrame {
+    function: usize,
+    // Next instruction
+    pc: usize,
+    // First slot in `Machine::slots`
+    base: usize,
+}
+
+/// Stack machine running a bytecode `Program`. Calls push frames instead of
+/// recursing, so deep recursion in the program only grows the heap.
+pub struct Machine<'p, 'o> {
+    program: &'p Program,
+    stack: Vec<Value>,
+    slots: Vec<Value>,
+    frames: Vec<CallFrame>,
+    memory: Memory,
+    output: &'o mut dyn Write,
+}
+
+impl<'p, 'o> Machine<'p, 'o> {
+    pub fn new(program: &'p Program, output: &'o mut dyn Write) -> Self {
+        Self {
+            program,
+            stack: vec![],
+            slots: vec![],
+            frames: vec![],
+            memory: Memory::default(),
+            output,
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+
+    fn pop_int(&mut self) -> i128 {
+        match self.pop() {
+            Value::Int(value) => value,
+            value => unreachable!("{:?}", value),
+        }
+    }
+
+    /// Moves the arguments on top of the stack into the slots of a new frame.
+    fn enter(&mut self, function: usize) {
+        let callee = &self.program.functions[function];
+        let base = self.slots.len();
+        self.slots.resize(base + callee.slots as usize, Value::Void);
+        let args = self.stack.split_off(self.stack.len() - callee.params as usize);
+        for (slot, arg) in self.slots[base..].iter_mut().zip(args) {
+            *slot = arg;
+        }
+        self.frames.push(CallFrame { function, pc: 0, base });
+    }
+
+    /// Runs `function` with `args` until it returns, returns the exit code of the program.
+    pub fn run(&mut self, function: usize, args: Vec<Value>) -> ModuleResult<i32> {
+        self.stack.extend(args);
+        self.enter(function);
+        let result = self.execute();
+        self.output.flush().unwrap();
+        result
+    }
+
+    fn execute(&mut self) -> ModuleResult<i32> {
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let function = &self.program.functions[frame.function];
+            let (instruction, position) = (function.code[frame.pc], function.positions[frame.pc]);
+            let (base, module) = (frame.base, function.module);
+            frame.pc += 1;
+
+            match instruction {
+                Instruction::Const(index) => self.stack.push(self.program.constants[index as usize].clone()),
+                Instruction::Load(slot) => self.stack.push(self.slots[base + slot as usize].clone()),
+                Instruction::Store(slot) => self.slots[base + slot as usize] = self.pop(),
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::Binary(op, ty) => {
+                    let (rhs, lhs) = (self.pop(), self.pop());
+                    let value = binary_op(op, &self.program.types[ty as usize], lhs, rhs)
+                        .ok_or_else(|| (CodeError::division_by_zero_error(position), module))?;
+                    self.stack.push(value);
+                }
+                Instruction::Cast(ty) => {
+                    let value = self.pop();
+                    let value = cast(&mut self.memory, value, &self.program.types[ty as usize])
+                        .ok_or_else(|| (CodeError::invalid_pointer_error(position, "Read"), module))?;
+                    self.stack.push(value);
+                }
+                Instruction::Jump(target) => self.frames.last_mut().unwrap().pc = target as usize,
+                Instruction::JumpIfFalse(target) => {
+                    if self.pop() != Value::Bool(true) {
+                        self.frames.last_mut().unwrap().pc = target as usize;
+                    }
+                }
+                Instruction::Call(callee) => {
+                    if self.frames.len() >= CALL_DEPTH_LIMIT {
+                        return Err((CodeError::stack_overflow_error(position, CALL_DEPTH_LIMIT), module));
+                    }
+                    self.enter(callee as usize);
+                }
+                Instruction::CallHost(symbol, count) => {
+                    let Value::Str(symbol) = &self.program.constants[symbol as usize] else {
+                        unreachable!()
+                    };
+                    let args = self.stack.split_off(self.stack.len() - count as usize);
+                    match call_host(&mut self.memory, self.output, symbol, &args) {
+                        Ok(value) => self.stack.push(value),
+                        Err(HostError::Exit(code)) => return Ok(code),
+                        Err(error) => return Err((error.to_error(position, symbol), module)),
+                    }
+                }
+                Instruction::Builtin(kind, element_size) => {
+                    let args = self.stack.split_off(self.stack.len() - kind.arity());
+                    let value = builtin(&mut self.memory, kind, element_size as u64, &args)
+                        .map_err(|action| (CodeError::invalid_pointer_error(position, action), module))?;
+                    self.stack.push(value);
+                }
+                Instruction::MakeArray(count) => {
+                    let elements = self.stack.split_off(self.stack.len() - count as usize);
+                    self.stack.push(Value::Array(elements));
+                }
+                Instruction::MakeStruct(count) => {
+                    let fields = self.stack.split_off(self.stack.len() - count as usize);
+                    self.stack.push(Value::Struct(fields));
+                }
+                Instruction::Index => {
+                    let index = self.pop_int();
+                    let Value::Array(elements) = self.pop() else {
+                        unreachable!()
+                    };
+                    self.stack.push(elements[index as usize].clone());
+                }
+                Instruction::Return => {
+                    let frame = self.frames.pop().unwrap();
+                    let result = self.pop();
+                    if self.frames.is_empty() {
+                        return Ok(exit_code(&result));
+                    }
+                    self.stack.push(result);
+                    for slot in &self.program.functions[frame.function].references {
+                        self.stack.push(self.slots[frame.base + *slot as usize].clone());
+                    }
+                    self.slots.truncate(frame.base);
+                }
+            }
+        }
+    }
+}
+
+/// Runs the compiled `program` starting at `entry`, program output goes to `output`.
+pub fn execute(program: &Program, entry: &EntryPoint, name: &str, output: &mut dyn Write) -> ModuleResult<i32> {
+    let mut machine = Machine::new(program, output);
+    let args = machine.memory.entry_args(entry, name);
+    let function = program.function(&entry.symbol).unwrap();
+    machine.run(function as usize, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::compile;
+    use crate::comp_errors::CodeErrorType;
+    use crate::entry::entry_point;
+    use crate::interpreter::{interpret, INTERPRETER_STACK_SIZE};
+    use crate::testing::check_module_of;
+
+    type Outcome = Result<(i32, String), (String, usize)>;
+
+    /// Runs `source` on the VM and on the interpreter, they have to agree.
+    fn run(source: &str) -> Outcome {
+        let source = source.to_string();
+        let job = move || {
+            let modules = [check_module_of(&source).unwrap_or_else(|e| panic!("{}: {}", e.title, e.footer))];
+            let entry = entry_point(&modules[0].ast, &modules[0].name, None).unwrap();
+            let outcome = |result: ModuleResult<i32>, output: Vec<u8>| -> Outcome {
+                match result {
+                    Ok(code) => Ok((code, String::from_utf8(output).unwrap())),
+                    Err((error, _)) => Err((error.title, error.position.idx_start)),
+                }
+            };
+            let mut output = vec![];
+            let vm = outcome(execute(&compile(&modules), &entry, "app", &mut output), output);
+            let mut output = vec![];
+            let tree = outcome(interpret(&modules, &entry, "app", &mut output), output);
+            assert_eq!(vm, tree);
+            vm
+        };
+        // The interpreter recurses on the stack of its thread
+        std::thread::Builder::new().stack_size(INTERPRETER_STACK_SIZE).spawn(job).unwrap().join().unwrap()
+    }
+
+    #[test]
+    fn runs_control_flow() {
+        let source = "def main(): i32 {\n    let mut total = 0;\n    for i in 0..10 {\n        if i == 2 {\n            continue;\n        }\n        \
+            if i == 8 {\n            break;\n        }\n        total = total + i;\n    }\n    for x in [100, 200] {\n        total = total + x;\n    }\n    \
+            let mut n = 0;\n    while n < 5 {\n        n = n + 1;\n    }\n    return total + n;\n}";
+        assert_eq!(run(source), Ok((331, String::new())));
+    }
+
+    #[test]
+    fn runs_calls_with_references_and_defaults() {
+        let source = "def extern puts(s: str): i32 {\n}\n\n\
+            def swap(a: &mut i32, b: &mut i32): void {\n    let t = a;\n    a = b;\n    b = t;\n}\n\n\
+            def scale(x: i32, by: i32 = 3): i32 {\n    return x * by;\n}\n\n\
+            def main(): i32 {\n    let mut a = 1;\n    let mut b = 2;\n    swap(a, b);\n    puts(\"swapped\");\n    \
+            return scale(a) * 10 + scale(by = 1, x = b);\n}";
+        assert_eq!(run(source), Ok((61, "swapped\n".to_string())));
+    }
+
+    #[test]
+    fn wraps_and_converts_like_the_interpreter() {
+        let source = "def @unsafe main(): i32 {\n    let b: u8 = 200;\n    let f: f32 = 1.5;\n    let p = alloc<i32>(2);\n    free(p);\n    \
+            return ((b + b) -> i32) + ((f * 3.0) -> i32) + ((p -> i64 == 0) -> i32);\n}";
+        assert_eq!(run(source), Ok((148, String::new())));
+    }
+
+    #[test]
+    fn reports_the_same_runtime_errors() {
+        let division = run("def main(): i32 {\n    let zero = 0;\n    return 7 / zero;\n}").unwrap_err();
+        assert_eq!(division.0, "Division by zero");
+
+        let deep = run("def deep(n: i32): i32 {\n    return deep(n + 1);\n}\n\ndef main(): i32 {\n    return deep(0);\n}");
+        assert_eq!(deep.unwrap_err().1, 35);
+
+        let source = "def extern abort(): void {\n}\n\ndef main(): i32 {\n    abort();\n    return 0;\n}";
+        let module = check_module_of(source).unwrap();
+        let entry = entry_point(&module.ast, &module.name, None).unwrap();
+        let modules = [module];
+        let error = execute(&compile(&modules), &entry, "app", &mut vec![]).unwrap_err().0;
+        assert!(matches!(error.code_error_type, CodeErrorType::RuntimeError));
+    }
+}
@@ -0,0 +1,1686 @@
+use crate::checker::bind_arguments;
+use crate::compiler::is_aggregate;
+use crate::entry::{EntryPoint, START_SYMBOL};
+use crate::lexer::{Token, TokenType};
+use crate::modules::Module;
+use crate::parser::{ASTNode, Builtin, FunctionMode, Parameter};
+use crate::runtime::builtin_symbol;
+use crate::types::Type;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+/// Module the functions without a Sila body are imported from.
+const IMPORT_MODULE: &str = "env";
+/// `argv` of an entry that takes arguments, the host passes none. The word at this address is 0.
+const ARGV: i32 = 8;
+/// String literals start here, so none of them is at the null address.
+const DATA_START: u32 = 16;
+/// The stack grows down from this many bytes above the strings, the heap starts at its top.
+const STACK_SIZE: u32 = 64 * 1024;
+const PAGE_SIZE: u32 = 64 * 1024;
+// Globals holding the top of the stack and the end of the heap
+const STACK_POINTER: u32 = 0;
+const HEAP_POINTER: u32 = 1;
+const GLOBALS: [&str; 2] = ["__stack_pointer", "__heap_pointer"];
+
+/// Binary encoding of the instructions without immediates, memory accesses are followed by
+/// their alignment and offset.
+const OPCODES: &[(&str, &[u8])] = &[
+    ("unreachable", &[0x00]),
+    ("else", &[0x05]),
+    ("end", &[0x0B]),
+    ("return", &[0x0F]),
+    ("drop", &[0x1A]),
+    ("select", &[0x1B]),
+    ("i32.load", &[0x28]),
+    ("i64.load", &[0x29]),
+    ("f32.load", &[0x2A]),
+    ("f64.load", &[0x2B]),
+    ("i32.load8_s", &[0x2C]),
+    ("i32.load8_u", &[0x2D]),
+    ("i32.load16_s", &[0x2E]),
+    ("i32.load16_u", &[0x2F]),
+    ("i32.store", &[0x36]),
+    ("i64.store", &[0x37]),
+    ("f32.store", &[0x38]),
+    ("f64.store", &[0x39]),
+    ("i32.store8", &[0x3A]),
+    ("i32.store16", &[0x3B]),
+    ("memory.size", &[0x3F, 0x00]),
+    ("memory.grow", &[0x40, 0x00]),
+    ("i32.eqz", &[0x45]),
+    ("i32.eq", &[0x46]),
+    ("i32.ne", &[0x47]),
+    ("i32.lt_s", &[0x48]),
+    ("i32.lt_u", &[0x49]),
+    ("i32.gt_s", &[0x4A]),
+    ("i32.gt_u", &[0x4B]),
+    ("i32.le_s", &[0x4C]),
+    ("i32.le_u", &[0x4D]),
+    ("i32.ge_s", &[0x4E]),
+    ("i32.ge_u", &[0x4F]),
+    ("i64.eq", &[0x51]),
+    ("i64.ne", &[0x52]),
+    ("i64.lt_s", &[0x53]),
+    ("i64.lt_u", &[0x54]),
+    ("i64.gt_s", &[0x55]),
+    ("i64.gt_u", &[0x56]),
+    ("i64.le_s", &[0x57]),
+    ("i64.le_u", &[0x58]),
+    ("i64.ge_s", &[0x59]),
+    ("i64.ge_u", &[0x5A]),
+    ("f32.eq", &[0x5B]),
+    ("f32.ne", &[0x5C]),
+    ("f32.lt", &[0x5D]),
+    ("f32.gt", &[0x5E]),
+    ("f32.le", &[0x5F]),
+    ("f32.ge", &[0x60]),
+    ("f64.eq", &[0x61]),
+    ("f64.ne", &[0x62]),
+    ("f64.lt", &[0x63]),
+    ("f64.gt", &[0x64]),
+    ("f64.le", &[0x65]),
+    ("f64.ge", &[0x66]),
+    ("i32.add", &[0x6A]),
+    ("i32.sub", &[0x6B]),
+    ("i32.mul", &[0x6C]),
+    ("i32.div_s", &[0x6D]),
+    ("i32.div_u", &[0x6E]),
+    ("i32.and", &[0x71]),
+    ("i32.shl", &[0x74]),
+    ("i32.shr_u", &[0x76]),
+    ("i64.add", &[0x7C]),
+    ("i64.sub", &[0x7D]),
+    ("i64.mul", &[0x7E]),
+    ("i64.div_s", &[0x7F]),
+    ("i64.div_u", &[0x80]),
+    ("f32.add", &[0x92]),
+    ("f32.sub", &[0x93]),
+    ("f32.mul", &[0x94]),
+    ("f32.div", &[0x95]),
+    ("f64.add", &[0xA0]),
+    ("f64.sub", &[0xA1]),
+    ("f64.mul", &[0xA2]),
+    ("f64.div", &[0xA3]),
+    ("i32.wrap_i64", &[0xA7]),
+    ("i64.extend_i32_s", &[0xAC]),
+    ("i64.extend_i32_u", &[0xAD]),
+    ("f32.convert_i32_s", &[0xB2]),
+    ("f32.convert_i32_u", &[0xB3]),
+    ("f32.convert_i64_s", &[0xB4]),
+    ("f32.convert_i64_u", &[0xB5]),
+    ("f32.demote_f64", &[0xB6]),
+    ("f64.convert_i32_s", &[0xB7]),
+    ("f64.convert_i32_u", &[0xB8]),
+    ("f64.convert_i64_s", &[0xB9]),
+    ("f64.convert_i64_u", &[0xBA]),
+    ("f64.promote_f32", &[0xBB]),
+    ("i32.extend8_s", &[0xC0]),
+    ("i32.extend16_s", &[0xC1]),
+    ("i32.trunc_sat_f32_s", &[0xFC, 0x00]),
+    ("i32.trunc_sat_f32_u", &[0xFC, 0x01]),
+    ("i32.trunc_sat_f64_s", &[0xFC, 0x02]),
+    ("i32.trunc_sat_f64_u", &[0xFC, 0x03]),
+    ("i64.trunc_sat_f32_s", &[0xFC, 0x04]),
+    ("i64.trunc_sat_f32_u", &[0xFC, 0x05]),
+    ("i64.trunc_sat_f64_s", &[0xFC, 0x06]),
+    ("i64.trunc_sat_f64_u", &[0xFC, 0x07]),
+    ("memory.copy", &[0xFC, 0x0A, 0x00, 0x00]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl ValType {
+    fn code(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7F,
+            ValType::I64 => 0x7E,
+            ValType::F32 => 0x7D,
+            ValType::F64 => 0x7C,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F32 => "f32",
+            ValType::F64 => "f64",
+        }
+    }
+}
+
+/// Integers of up to 32 bits, booleans and addresses are `i32`, aggregates are passed by address.
+fn value_type(ty: &Type) -> ValType {
+    match ty {
+        Type::Int(64, _) => ValType::I64,
+        Type::Float(32) => ValType::F32,
+        Type::Float(_) => ValType::F64,
+        _ => ValType::I32,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Instruction {
+    // Blocks have a label in the text format, branches the label and the relative depth
+    Block(String),
+    Loop(String),
+    If,
+    Br(String, u32),
+    BrIf(String, u32),
+    Call(String),
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(f32),
+    F64Const(f64),
+    // Load or store, alignment as a power of two, offset
+    Memory(&'static str, u32, u32),
+    Op(&'static str),
+    // Frees the frame, replaced once the size of the frame is known
+    Epilogue,
+}
+
+fn op(name: &str) -> Instruction {
+    let (name, _) = OPCODES.iter().find(|t| t.0 == name).unwrap_or_else(|| panic!("unknown instruction {}", name));
+    Instruction::Op(name)
+}
+
+fn constant(ty: ValType, value: i64) -> Instruction {
+    match ty {
+        ValType::I64 => Instruction::I64Const(value),
+        _ => Instruction::I32Const(value as i32),
+    }
+}
+
+/// Load or store of a value of `ty`, with the alignment of its size.
+fn access(ty: &Type, store: bool) -> (&'static str, u32) {
+    match (ty, store) {
+        (Type::Int(8, true), false) => ("i32.load8_s", 0),
+        (Type::Int(8, _) | Type::Bool, false) => ("i32.load8_u", 0),
+        (Type::Int(8, _) | Type::Bool, true) => ("i32.store8", 0),
+        (Type::Int(16, true), false) => ("i32.load16_s", 1),
+        (Type::Int(16, _), false) => ("i32.load16_u", 1),
+        (Type::Int(16, _), true) => ("i32.store16", 1),
+        (Type::Int(64, _), false) => ("i64.load", 3),
+        (Type::Int(64, _), true) => ("i64.store", 3),
+        (Type::Float(32), false) => ("f32.load", 2),
+        (Type::Float(32), true) => ("f32.store", 2),
+        (Type::Float(_), false) => ("f64.load", 3),
+        (Type::Float(_), true) => ("f64.store", 3),
+        (_, false) => ("i32.load", 2),
+        (_, true) => ("i32.store", 2),
+    }
+}
+
+/// Name of a comparison without its type, `suffix` picks the signedness of integers.
+fn comparison(op: TokenType, suffix: &str) -> String {
+    match op {
+        TokenType::DoubleEquals => "eq".to_string(),
+        TokenType::NotEquals => "ne".to_string(),
+        TokenType::Greater => format!("gt{}", suffix),
+        TokenType::GreaterEquals => format!("ge{}", suffix),
+        TokenType::Lesser => format!("lt{}", suffix),
+        TokenType::LesserEquals => format!("le{}", suffix),
+        _ => unreachable!(),
+    }
+}
+
+fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        // The sign bit of the last byte has to match the value
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn name(out: &mut Vec<u8>, text: &str) {
+    unsigned(out, text.len() as u64);
+    out.extend(text.bytes());
+}
+
+fn section(out: &mut Vec<u8>, id: u8, content: Vec<u8>) {
+    out.push(id);
+    unsigned(out, content.len() as u64);
+    out.extend(content);
+}
+
+/// Text of a string in the text format, without the quotes.
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for byte in bytes {
+        match byte {
+            b' '..=b'~' if *byte != b'"' && *byte != b'\\' => escaped.push(*byte as char),
+            _ => write!(escaped, "\\{:02x}", byte).unwrap(),
+        }
+    }
+    escaped
+}
+
+/// A function without a Sila body, the host provides it.
+struct Import {
+    symbol: String,
+    params: Vec<ValType>,
+    results: Vec<ValType>,
+}
+
+struct Function {
+    symbol: String,
+    // Name the host calls it by
+    export: Option<String>,
+    params: Vec<(String, ValType)>,
+    results: Vec<ValType>,
+    locals: Vec<(String, ValType)>,
+    body: Vec<Instruction>,
+}
+
+impl Function {
+    fn local_name(&self, index: u32) -> &str {
+        let index = index as usize;
+        match self.params.get(index) {
+            Some((name, _)) => name,
+            None => &self.locals[index - self.params.len()].0,
+        }
+    }
+
+    fn text(&self, instruction: &Instruction) -> String {
+        match instruction {
+            Instruction::Block(label) => format!("block ${}", label),
+            Instruction::Loop(label) => format!("loop ${}", label),
+            Instruction::If => "if".to_string(),
+            Instruction::Br(label, _) => format!("br ${}", label),
+            Instruction::BrIf(label, _) => format!("br_if ${}", label),
+            Instruction::Call(symbol) => format!("call ${}", symbol),
+            Instruction::LocalGet(index) => format!("local.get ${}", self.local_name(*index)),
+            Instruction::LocalSet(index) => format!("local.set ${}", self.local_name(*index)),
+            Instruction::LocalTee(index) => format!("local.tee ${}", self.local_name(*index)),
+            Instruction::GlobalGet(index) => format!("global.get ${}", GLOBALS[*index as usize]),
+            Instruction::GlobalSet(index) => format!("global.set ${}", GLOBALS[*index as usize]),
+            Instruction::I32Const(value) => format!("i32.const {}", value),
+            Instruction::I64Const(value) => format!("i64.const {}", value),
+            Instruction::F32Const(value) => format!("f32.const {:?}", value),
+            Instruction::F64Const(value) => format!("f64.const {:?}", value),
+            Instruction::Memory(name, _, 0) => name.to_string(),
+            Instruction::Memory(name, _, offset) => format!("{} offset={}", name, offset),
+            Instruction::Op(name) => name.to_string(),
+            Instruction::Epilogue => unreachable!(),
+        }
+    }
+
+    fn wat(&self, out: &mut String) {
+        write!(out, "\n  (func ${}", self.symbol).unwrap();
+        if let Some(export) = &self.export {
+            write!(out, " (export \"{}\")", export).unwrap();
+        }
+        for (name, ty) in &self.params {
+            write!(out, " (param ${} {})", name, ty.name()).unwrap();
+        }
+        for ty in &self.results {
+            write!(out, " (result {})", ty.name()).unwrap();
+        }
+        out.push('\n');
+        for (name, ty) in &self.locals {
+            writeln!(out, "    (local ${} {})", name, ty.name()).unwrap();
+        }
+        let mut depth = 2;
+        for instruction in &self.body {
+            let indent = match instruction {
+                Instruction::Op("end") => {
+                    depth -= 1;
+                    depth
+                }
+                Instruction::Op("else") => depth - 1,
+                _ => depth,
+            };
+            writeln!(out, "{}{}", "  ".repeat(indent), self.text(instruction)).unwrap();
+            if matches!(instruction, Instruction::Block(_) | Instruction::Loop(_) | Instruction::If) {
+                depth += 1;
+            }
+        }
+        out.push_str("  )\n");
+    }
+
+    fn encode(&self, indices: &HashMap<&str, u32>) -> Vec<u8> {
+        let mut code = vec![];
+        // Locals are declared in runs of the same type
+        let mut runs: Vec<(u32, ValType)> = vec![];
+        for (_, ty) in &self.locals {
+            match runs.last_mut() {
+                Some((count, last)) if last == ty => *count += 1,
+                _ => runs.push((1, *ty)),
+            }
+        }
+        unsigned(&mut code, runs.len() as u64);
+        for (count, ty) in runs {
+            unsigned(&mut code, count as u64);
+            code.push(ty.code());
+        }
+        for instruction in &self.body {
+            match instruction {
+                Instruction::Block(_) => code.extend([0x02, 0x40]),
+                Instruction::Loop(_) => code.extend([0x03, 0x40]),
+                Instruction::If => code.extend([0x04, 0x40]),
+                Instruction::Br(_, depth) | Instruction::BrIf(_, depth) => {
+                    code.push(if matches!(instruction, Instruction::Br(..)) { 0x0C } else { 0x0D });
+                    unsigned(&mut code, *depth as u64);
+                }
+                Instruction::Call(symbol) => {
+                    code.push(0x10);
+                    unsigned(&mut code, indices[symbol.as_str()] as u64);
+                }
+                Instruction::LocalGet(index)
+                | Instruction::LocalSet(index)
+                | Instruction::LocalTee(index)
+                | Instruction::GlobalGet(index)
+                | Instruction::GlobalSet(index) => {
+                    code.push(match instruction {
+                        Instruction::LocalGet(_) => 0x20,
+                        Instruction::LocalSet(_) => 0x21,
+                        Instruction::LocalTee(_) => 0x22,
+                        Instruction::GlobalGet(_) => 0x23,
+                        _ => 0x24,
+                    });
+                    unsigned(&mut code, *index as u64);
+                }
+                Instruction::I32Const(value) => {
+                    code.push(0x41);
+                    signed(&mut code, *value as i64);
+                }
+                Instruction::I64Const(value) => {
+                    code.push(0x42);
+                    signed(&mut code, *value);
+                }
+                Instruction::F32Const(value) => {
+                    code.push(0x43);
+                    code.extend(value.to_le_bytes());
+                }
+                Instruction::F64Const(value) => {
+                    code.push(0x44);
+                    code.extend(value.to_le_bytes());
+                }
+                Instruction::Memory(name, align, offset) => {
+                    code.extend(OPCODES.iter().find(|t| t.0 == *name).unwrap().1);
+                    unsigned(&mut code, *align as u64);
+                    unsigned(&mut code, *offset as u64);
+                }
+                Instruction::Op(name) => code.extend(OPCODES.iter().find(|t| t.0 == *name).unwrap().1),
+                Instruction::Epilogue => unreachable!(),
+            }
+        }
+        code.push(0x0B);
+        code
+    }
+}
+
+/// A whole WebAssembly module, written out in the text or the binary format.
+struct WasmModule {
+    imports: Vec<Import>,
+    functions: Vec<Function>,
+    // Bytes of the string literals, at `DATA_START`
+    data: Vec<u8>,
+    pages: u32,
+    // Initial value of each of `GLOBALS`
+    globals: [u32; 2],
+}
+
+impl WasmModule {
+    fn wat(&self) -> String {
+        let mut out = String::from(";; Generated by the Sila compiler\n(module\n");
+        for import in &self.imports {
+            write!(out, "  (import \"{}\" \"{}\" (func ${}", IMPORT_MODULE, import.symbol, import.symbol).unwrap();
+            if !import.params.is_empty() {
+                let params: Vec<&str> = import.params.iter().map(|t| t.name()).collect();
+                write!(out, " (param {})", params.join(" ")).unwrap();
+            }
+            for ty in &import.results {
+                write!(out, " (result {})", ty.name()).unwrap();
+            }
+            out.push_str("))\n");
+        }
+        writeln!(out, "  (memory (export \"memory\") {})", self.pages).unwrap();
+        for (global, value) in GLOBALS.iter().zip(self.globals) {
+            writeln!(out, "  (global ${} (mut i32) (i32.const {}))", global, value).unwrap();
+        }
+        if !self.data.is_empty() {
+            writeln!(out, "  (data (i32.const {}) \"{}\")", DATA_START, escape(&self.data)).unwrap();
+        }
+        for function in &self.functions {
+            function.wat(&mut out);
+        }
+        out.push_str(")\n");
+        out
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut types: Vec<(Vec<ValType>, Vec<ValType>)> = vec![];
+        let mut type_index = |params: Vec<ValType>, results: Vec<ValType>| {
+            let signature = (params, results);
+            match types.iter().position(|t| *t == signature) {
+                Some(index) => index as u64,
+                None => {
+                    types.push(signature);
+                    types.len() as u64 - 1
+                }
+            }
+        };
+        let mut indices = HashMap::new();
+        let mut imports = vec![];
+        unsigned(&mut imports, self.imports.len() as u64);
+        for import in &self.imports {
+            indices.insert(import.symbol.as_str(), indices.len() as u32);
+            name(&mut imports, IMPORT_MODULE);
+            name(&mut imports, &import.symbol);
+            imports.push(0x00);
+            unsigned(&mut imports, type_index(import.params.clone(), import.results.clone()));
+        }
+        let mut functions = vec![];
+        unsigned(&mut functions, self.functions.len() as u64);
+        for function in &self.functions {
+            indices.insert(function.symbol.as_str(), indices.len() as u32);
+            let params = function.params.iter().map(|t| t.1).collect();
+            unsigned(&mut functions, type_index(params, function.results.clone()));
+        }
+
+        let mut out = b"\0asm".to_vec();
+        out.extend(1u32.to_le_bytes());
+        let mut section_types = vec![];
+        unsigned(&mut section_types, types.len() as u64);
+        for (params, results) in &types {
+            section_types.push(0x60);
+            for list in [params, results] {
+                unsigned(&mut section_types, list.len() as u64);
+                section_types.extend(list.iter().map(|t| t.code()));
+            }
+        }
+        section(&mut out, 1, section_types);
+        section(&mut out, 2, imports);
+        section(&mut out, 3, functions);
+
+        let mut memory = vec![1, 0x00];
+        unsigned(&mut memory, self.pages as u64);
+        section(&mut out, 5, memory);
+
+        let mut globals = vec![];
+        unsigned(&mut globals, GLOBALS.len() as u64);
+        for value in self.globals {
+            globals.extend([ValType::I32.code(), 0x01, 0x41]);
+            signed(&mut globals, value as i64);
+            globals.push(0x0B);
+        }
+        section(&mut out, 6, globals);
+
+        let exported: Vec<&Function> = self.functions.iter().filter(|t| t.export.is_some()).collect();
+        let mut exports = vec![];
+        unsigned(&mut exports, exported.len() as u64 + 1);
+        name(&mut exports, "memory");
+        exports.extend([0x02, 0x00]);
+        for function in exported {
+            name(&mut exports, function.export.as_ref().unwrap());
+            exports.push(0x00);
+            unsigned(&mut exports, indices[function.symbol.as_str()] as u64);
+        }
+        section(&mut out, 7, exports);
+
+        let mut code = vec![];
+        unsigned(&mut code, self.functions.len() as u64);
+        for function in &self.functions {
+            let body = function.encode(&indices);
+            unsigned(&mut code, body.len() as u64);
+            code.extend(body);
+        }
+        section(&mut out, 10, code);
+
+        if !self.data.is_empty() {
+            let mut data = vec![1, 0x00, 0x41];
+            signed(&mut data, DATA_START as i64);
+            data.push(0x0B);
+            unsigned(&mut data, self.data.len() as u64);
+            data.extend(&self.data);
+            section(&mut out, 11, data);
+        }
+        out
+    }
+}
+
+/// Where a variable is.
+#[derive(Debug, Clone, Copy)]
+enum Place {
+    // A local of the function, for scalars
+    Local(u32),
+    // Frame memory at an offset, for aggregates and scalars passed by reference
+    Frame(u32),
+    // Memory at the address in a local, for reference and aggregate parameters
+    Pointer(u32),
+}
+
+struct Loop {
+    // Label and depth of the blocks `continue` and `break` leave
+    next: (String, u32),
+    end: (String, u32),
+}
+
+struct WasmBackend<'a, 'm> {
+    modules: &'m [Module<'a>],
+    // Linker symbols of the functions with a body
+    defined: HashSet<String>,
+    // Struct name -> fields
+    structs: HashMap<String, Vec<(String, Type)>>,
+    // String literal -> address
+    strings: HashMap<String, u32>,
+    data: Vec<u8>,
+    // Whether the program calls `alloc`, `realloc` or `free`
+    allocates: bool,
+    // State of the function being generated
+    code: Vec<Instruction>,
+    module: usize,
+    // Parameters come first
+    locals: Vec<(String, ValType)>,
+    taken: HashSet<String>,
+    places: HashMap<*const Token, (Place, Type)>,
+    // Local holding the address of the frame, made for the first frame slot
+    frame_pointer: Option<u32>,
+    // Bytes of the frame taken by variables, temporaries come after them
+    variables: u32,
+    temporaries: u32,
+    deepest: u32,
+    // Scalar parameters passed by reference are copied to these frame slots
+    copies: Vec<(u32, u32, Type)>,
+    values: usize,
+    blocks: usize,
+    // Blocks the current instruction is in
+    depth: u32,
+    loops: Vec<Loop>,
+    return_type: Type,
+    result_pointer: Option<u32>,
+}
+
+impl<'a, 'm> WasmBackend<'a, 'm> {
+    fn new(modules: &'m [Module<'a>]) -> Self {
+        let mut defined = HashSet::new();
+        let mut structs = HashMap::new();
+        for module in modules {
+            for item in &module.ast {
+                match item {
+                    ASTNode::FunctionDef(_, mode, ..) if *mode != FunctionMode::Extern => {
+                        defined.insert(item.symbol_name(&module.name).unwrap());
+                    }
+                    ASTNode::StructDef(name, fields) => {
+                        let fields = fields
+                            .iter()
+                            .map(|(name, ty)| (name.content.clone(), Type::from_node(ty).unwrap()))
+                            .collect();
+                        structs.insert(name.content.clone(), fields);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Self {
+            modules,
+            defined,
+            structs,
+            strings: HashMap::new(),
+            data: vec![],
+            allocates: false,
+            code: vec![],
+            module: 0,
+            locals: vec![],
+            taken: HashSet::new(),
+            places: HashMap::new(),
+            frame_pointer: None,
+            variables: 0,
+            temporaries: 0,
+            deepest: 0,
+            copies: vec![],
+            values: 0,
+            blocks: 0,
+            depth: 0,
+            loops: vec![],
+            return_type: Type::Void,
+            result_pointer: None,
+        }
+    }
+
+    fn type_of(&self, node: &ASTNode) -> Type {
+        let module = &self.modules[self.module];
+        let ty = module.types.type_of(node);
+        ty.or_else(|| self.modules.iter().find_map(|t| t.types.type_of(node)))
+            .cloned()
+            .unwrap_or(Type::Void)
+    }
+
+    fn type_of_declaration(&self, name: &Token) -> Type {
+        self.modules[self.module].types.type_of_declaration(name).cloned().unwrap_or(Type::Void)
+    }
+
+    /// Size and alignment of `ty` in the 32-bit address space, laid out like C does.
+    fn layout(&self, ty: &Type) -> (u32, u32) {
+        match ty {
+            Type::Array(element, length) => {
+                let (size, align) = self.layout(element);
+                (size * *length as u32, align)
+            }
+            Type::Struct(name) => {
+                let (mut size, mut align) = (0u32, 1);
+                for (_, field) in &self.structs[name] {
+                    let (field_size, field_align) = self.layout(field);
+                    size = size.next_multiple_of(field_align) + field_size;
+                    align = align.max(field_align);
+                }
+                (size.next_multiple_of(align), align)
+            }
+            Type::Str | Type::Pointer(_) => (4, 4),
+            ty => {
+                let size = ty.size().unwrap() as u32;
+                (size, size.max(1))
+            }
+        }
+    }
+
+    /// Offset and type of the field `field` of the struct `name`.
+    fn field(&self, name: &str, field: &str) -> (u32, Type) {
+        let mut offset = 0u32;
+        for (name, ty) in &self.structs[name] {
+            let (size, align) = self.layout(ty);
+            offset = offset.next_multiple_of(align);
+            if name == field {
+                return (offset, ty.clone());
+            }
+            offset += size;
+        }
+        unreachable!()
+    }
+
+    /// Parameter and result types of a function. Aggregate results are written to memory at
+    /// the address passed first.
+    fn signature(ret: &Type, params: &[Parameter<'a>]) -> (Vec<ValType>, Vec<ValType>) {
+        let mut types = vec![];
+        if is_aggregate(ret) {
+            types.push(ValType::I32);
+        }
+        for (_, ty, _) in params {
+            types.push(match &**ty {
+                ASTNode::ReferenceType(..) => ValType::I32,
+                ty => value_type(&Type::from_node(ty).unwrap()),
+            });
+        }
+        let results = match ret {
+            Type::Void => vec![],
+            ret if is_aggregate(ret) => vec![],
+            ret => vec![value_type(ret)],
+        };
+        (types, results)
+    }
+
+    fn string(&mut self, text: &str) -> u32 {
+        if let Some(address) = self.strings.get(text) {
+            return *address;
+        }
+        let address = DATA_START + self.data.len() as u32;
+        self.data.extend(text.bytes());
+        self.data.push(0);
+        self.strings.insert(text.to_string(), address);
+        address
+    }
+
+    /// Local name for a Sila name. Generated names contain a dot, so they never clash with these.
+    fn unique(&mut self, name: &str) -> String {
+        let mut local = name.to_string();
+        let mut count = 0;
+        while self.taken.contains(&local) {
+            count += 1;
+            local = format!("{}_{}", name, count);
+        }
+        self.taken.insert(local.clone());
+        local
+    }
+
+    fn local(&mut self, name: &str, ty: ValType) -> u32 {
+        let name = self.unique(name);
+        self.locals.push((name, ty));
+        self.locals.len() as u32 - 1
+    }
+
+    /// A local for intermediate values.
+    fn scratch(&mut self, ty: ValType) -> u32 {
+        self.values += 1;
+        let name = format!("t.{}", self.values);
+        self.local(&name, ty)
+    }
+
+    fn block(&mut self) -> usize {
+        self.blocks += 1;
+        self.blocks
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.code.push(instruction);
+    }
+
+    /// Starts a block, returns the depth branches to it are relative to.
+    fn open(&mut self, block: Instruction) -> u32 {
+        self.emit(block);
+        self.depth += 1;
+        self.depth
+    }
+
+    fn close(&mut self) {
+        self.emit(op("end"));
+        self.depth -= 1;
+    }
+
+    fn branch(&mut self, (label, depth): &(String, u32), conditional: bool) {
+        let relative = self.depth - depth;
+        self.emit(match conditional {
+            true => Instruction::BrIf(label.clone(), relative),
+            false => Instruction::Br(label.clone(), relative),
+        });
+    }
+
+    fn frame_pointer(&mut self) -> u32 {
+        if let Some(frame_pointer) = self.frame_pointer {
+            return frame_pointer;
+        }
+        let frame_pointer = self.local("frame.pointer", ValType::I32);
+        self.frame_pointer = Some(frame_pointer);
+        frame_pointer
+    }
+
+    /// Frame memory for a variable of `ty`, for the whole function.
+    fn variable(&mut self, ty: &Type) -> Place {
+        let (size, align) = self.layout(ty);
+        let offset = self.variables.next_multiple_of(align);
+        self.variables = offset + size;
+        self.frame_pointer();
+        Place::Frame(offset)
+    }
+
+    /// Frame memory for a value of `ty`, until the end of the statement.
+    fn temporary(&mut self, ty: &Type) -> u32 {
+        let (size, align) = self.layout(ty);
+        let offset = (self.variables + self.temporaries).next_multiple_of(align);
+        self.temporaries = offset + size - self.variables;
+        self.deepest = self.deepest.max(self.temporaries);
+        self.frame_pointer();
+        offset
+    }
+
+    /// Place of the variable declared by `name`, scalars that are not in memory get a local.
+    fn place(&mut self, name: &Token, ty: &Type) -> Place {
+        let key = name as *const Token;
+        if let Some((place, _)) = self.places.get(&key) {
+            return *place;
+        }
+        let local = self.local(&name.content, value_type(ty));
+        self.places.insert(key, (Place::Local(local), ty.clone()));
+        Place::Local(local)
+    }
+
+    /// Pushes the address `place` is relative to, returns its offset from it.
+    fn base(&mut self, place: Place) -> u32 {
+        match place {
+            Place::Frame(offset) => {
+                let frame_pointer = self.frame_pointer();
+                self.emit(Instruction::LocalGet(frame_pointer));
+                offset
+            }
+            Place::Pointer(local) => {
+                self.emit(Instruction::LocalGet(local));
+                0
+            }
+            Place::Local(_) => unreachable!(),
+        }
+    }
+
+    fn address(&mut self, place: Place) {
+        let offset = self.base(place);
+        if offset != 0 {
+            self.emit(Instruction::I32Const(offset as i32));
+            self.emit(op("i32.add"));
+        }
+    }
+
+    fn load(&mut self, ty: &Type, offset: u32) {
+        let (name, align) = access(ty, false);
+        self.emit(Instruction::Memory(name, align, offset));
+    }
+
+    fn store(&mut self, ty: &Type, offset: u32) {
+        let (name, align) = access(ty, true);
+        self.emit(Instruction::Memory(name, align, offset));
+    }
+
+    /// Pushes the value at `place`, the address for aggregates.
+    fn read(&mut self, place: Place, ty: &Type) {
+        match place {
+            Place::Local(local) => self.emit(Instruction::LocalGet(local)),
+            place if is_aggregate(ty) => self.address(place),
+            place => {
+                let offset = self.base(place);
+                self.load(ty, offset);
+            }
+        }
+    }
+
+    /// Stores the value `value` pushes at `place`, aggregates are copied.
+    fn write(&mut self, place: Place, ty: &Type, value: impl FnOnce(&mut Self)) {
+        match place {
+            Place::Local(local) => {
+                value(self);
+                self.emit(Instruction::LocalSet(local));
+            }
+            place if is_aggregate(ty) => {
+                self.address(place);
+                value(self);
+                self.emit(Instruction::I32Const(self.layout(ty).0 as i32));
+                self.emit(op("memory.copy"));
+            }
+            place => {
+                let offset = self.base(place);
+                value(self);
+                self.store(ty, offset);
+            }
+        }
+    }
+
+    /// Integers narrower than 32 bits are kept sign or zero extended to their `i32`.
+    fn normalize(&mut self, ty: &Type) {
+        match ty {
+            Type::Int(8, true) => self.emit(op("i32.extend8_s")),
+            Type::Int(16, true) => self.emit(op("i32.extend16_s")),
+            Type::Int(bits @ (8 | 16), false) => {
+                self.emit(Instruction::I32Const((1 << bits) - 1));
+                self.emit(op("i32.and"));
+            }
+            _ => {}
+        }
+    }
+
+    /// Finds the variables declared in `node` and the ones passed to reference parameters.
+    fn scan(&self, node: &'m ASTNode<'a>, addressed: &mut HashSet<*const Token>, declared: &mut Vec<&'a Token>) {
+        match node {
+            ASTNode::VariableSet(name, ..) => declared.push(*name),
+            ASTNode::FunctionCall(name, args, _) => {
+                let ASTNode::FunctionDef(function_name, _, _, params, ..) = self.modules[self.module].callee(name) else {
+                    unreachable!()
+                };
+                let bound = bind_arguments(name, args, function_name, params).unwrap();
+                for ((_, ty, _), mut arg) in params.iter().zip(bound) {
+                    if let ASTNode::NamedArgument(_, value) = arg {
+                        arg = value;
+                    }
+                    if let (ASTNode::ReferenceType(..), ASTNode::Identifier(name)) = (&**ty, arg) {
+                        addressed.insert(self.modules[self.module].key(name));
+                    }
+                }
+            }
+            _ => {}
+        }
+        for child in node.children() {
+            self.scan(child, addressed, declared);
+        }
+    }
+
+    /// Gives frame memory to the aggregate variables of `body` and to the scalars that are
+    /// passed by reference.
+    fn reserve(&mut self, body: &'m [Box<ASTNode<'a>>], params: &[Parameter<'a>]) {
+        let (mut addressed, mut declared) = (HashSet::new(), vec![]);
+        for statement in body {
+            self.scan(statement, &mut addressed, &mut declared);
+        }
+        for (name, _, _) in params {
+            let key = *name as *const Token;
+            let Some((Place::Local(local), ty)) = self.places.get(&key).cloned() else {
+                continue;
+            };
+            if addressed.contains(&key) {
+                let place = self.variable(&ty);
+                let Place::Frame(offset) = place else { unreachable!() };
+                self.copies.push((local, offset, ty.clone()));
+                self.places.insert(key, (place, ty));
+            }
+        }
+        for name in declared {
+            let (key, ty) = (name as *const Token, self.type_of_declaration(name));
+            if ty != Type::Void && (is_aggregate(&ty) || addressed.contains(&key)) {
+                let place = self.variable(&ty);
+                self.places.insert(key, (place, ty));
+            }
+        }
+    }
+
+    fn function(&mut self, module: usize, function: &'m ASTNode<'a>, export: Option<String>) -> Function {
+        let ASTNode::FunctionDef(_, _, ret, params, body, _) = function else {
+            unreachable!()
+        };
+        self.module = module;
+        self.code.clear();
+        self.locals.clear();
+        self.taken.clear();
+        self.places.clear();
+        self.frame_pointer = None;
+        (self.variables, self.temporaries, self.deepest) = (0, 0, 0);
+        self.copies.clear();
+        (self.values, self.blocks, self.depth) = (0, 0, 0);
+        self.return_type = Type::from_node(ret).unwrap();
+        let (_, results) = Self::signature(&self.return_type, params);
+        self.result_pointer = is_aggregate(&self.return_type).then(|| self.local("agg.result", ValType::I32));
+
+        // References and aggregates arrive as addresses, the other parameters as their value
+        for (name, ty, _) in params {
+            let reference = matches!(**ty, ASTNode::ReferenceType(..));
+            let ty = Type::from_node(ty).unwrap();
+            let place = match reference || is_aggregate(&ty) {
+                true => Place::Pointer(self.local(&name.content, ValType::I32)),
+                false => Place::Local(self.local(&name.content, value_type(&ty))),
+            };
+            self.places.insert(*name as *const Token, (place, ty));
+        }
+        let parameters = self.locals.len();
+        self.reserve(body, params);
+
+        self.statements(body);
+        match self.return_type {
+            Type::Void => self.emit(Instruction::Epilogue),
+            // The control-flow check makes sure other functions return
+            _ => self.emit(op("unreachable")),
+        }
+
+        // The stack pointer moves down by the frame, which stays 16-byte aligned
+        let frame = (self.variables + self.deepest).next_multiple_of(16);
+        let (mut code, mut epilogue) = (vec![], vec![]);
+        if let Some(frame_pointer) = self.frame_pointer {
+            code.extend([
+                Instruction::GlobalGet(STACK_POINTER),
+                Instruction::I32Const(frame as i32),
+                op("i32.sub"),
+                Instruction::LocalTee(frame_pointer),
+                Instruction::GlobalSet(STACK_POINTER),
+            ]);
+            for (local, offset, ty) in std::mem::take(&mut self.copies) {
+                code.extend([Instruction::LocalGet(frame_pointer), Instruction::LocalGet(local)]);
+                let (name, align) = access(&ty, true);
+                code.push(Instruction::Memory(name, align, offset));
+            }
+            epilogue = vec![
+                Instruction::LocalGet(frame_pointer),
+                Instruction::I32Const(frame as i32),
+                op("i32.add"),
+                Instruction::GlobalSet(STACK_POINTER),
+            ];
+        }
+        for instruction in self.code.drain(..) {
+            match instruction {
+                Instruction::Epilogue => code.extend(epilogue.iter().cloned()),
+                instruction => code.push(instruction),
+            }
+        }
+
+        let mut locals = std::mem::take(&mut self.locals);
+        let params = locals.drain(..parameters).collect();
+        Function {
+            symbol: function.symbol_name(&self.modules[module].name).unwrap(),
+            export,
+            params,
+            results,
+            locals,
+            body: code,
+        }
+    }
+
+    /// Temporaries are released after each statement.
+    fn statements(&mut self, body: &[Box<ASTNode<'a>>]) {
+        for statement in body {
+            let temporaries = self.temporaries;
+            self.statement(statement);
+            self.temporaries = temporaries;
+        }
+    }
+
+    fn statement(&mut self, statement: &ASTNode<'a>) {
+        match statement {
+            ASTNode::VariableSet(name, value, ..) => {
+                let ty = self.type_of_declaration(name);
+                // Calls of functions without a value are kept for their effects
+                if ty == Type::Void {
+                    if let Some(value) = value {
+                        self.expr(value);
+                    }
+                    return;
+                }
+                let place = self.place(name, &ty);
+                if let Some(value) = value {
+                    self.write(place, &ty, |this| this.expr(value));
+                }
+            }
+            ASTNode::Assignment(name, value) => {
+                let (place, ty) = self.places[&self.modules[self.module].key(name)].clone();
+                self.write(place, &ty, |this| this.expr(value));
+            }
+            ASTNode::Return(value) => self.ret(value),
+            ASTNode::Break(_) => {
+                let end = self.loops.last().unwrap().end.clone();
+                self.branch(&end, false);
+            }
+            ASTNode::Continue(_) => {
+                let next = self.loops.last().unwrap().next.clone();
+                self.branch(&next, false);
+            }
+            ASTNode::If(condition, then_body, else_body) => {
+                self.expr(condition);
+                self.open(Instruction::If);
+                self.statements(then_body);
+                if !else_body.is_empty() {
+                    self.emit(op("else"));
+                    self.statements(else_body);
+                }
+                self.close();
+            }
+            ASTNode::WhileLoop(condition, body) => {
+                let block = self.block();
+                let (label, next) = (format!("while.end.{}", block), format!("while.cond.{}", block));
+                let end = (label.clone(), self.open(Instruction::Block(label)));
+                let next = (next.clone(), self.open(Instruction::Loop(next)));
+                self.expr(condition);
+                self.emit(op("i32.eqz"));
+                self.branch(&end, true);
+                self.loop_body(body, &next, &end);
+                self.branch(&next, false);
+                self.close();
+                self.close();
+            }
+            expr => {
+                self.expr(expr);
+                if self.type_of(expr) != Type::Void {
+                    self.emit(op("drop"));
+                }
+            }
+        }
+    }
+
+    fn loop_body(&mut self, body: &[Box<ASTNode<'a>>], next: &(String, u32), end: &(String, u32)) {
+        self.loops.push(Loop { next: next.clone(), end: end.clone() });
+        self.statements(body);
+        self.loops.pop();
+    }
+
+    /// Aggregates are returned by copying them to the memory the caller passed.
+    fn ret(&mut self, value: &ASTNode<'a>) {
+        let ty = self.return_type.clone();
+        match self.result_pointer {
+            _ if ty == Type::Void => {
+                if !matches!(value, ASTNode::Identifier(_)) {
+                    self.expr(value);
+                }
+            }
+            Some(result) => self.write(Place::Pointer(result), &ty, |this| this.expr(value)),
+            None => self.expr(value),
+        }
+        self.emit(Instruction::Epilogue);
+        self.emit(op("return"));
+    }
+
+    /// Pushes the value of `node`, the address of a copy for aggregates.
+    fn expr(&mut self, node: &ASTNode<'a>) {
+        match node {
+            ASTNode::Literal(t) => {
+                let literal = Self::literal(t, &self.type_of(node));
+                self.emit(literal);
+            }
+            ASTNode::String(t) => {
+                let address = self.string(&t.content);
+                self.emit(Instruction::I32Const(address as i32));
+            }
+            ASTNode::Identifier(name) => {
+                let (place, ty) = self.places[&self.modules[self.module].key(name)].clone();
+                self.read(place, &ty);
+            }
+            ASTNode::BinaryOp(lhs, op, rhs) => self.binary_op(lhs, op, rhs),
+            ASTNode::CastExpr(expr, ty) => {
+                let (from, to) = (self.type_of(expr), Type::from_node(ty).unwrap());
+                self.expr(expr);
+                self.cast(&from, &to);
+            }
+            ASTNode::FunctionCall(name, args, _) => self.call(name, args),
+            ASTNode::NamedArgument(_, value) => self.expr(value),
+            ASTNode::BuiltinCall(builtin, _, type_arg, args, _) => {
+                self.allocates = true;
+                let size = type_arg.as_ref().map_or(0, |t| self.layout(&Type::from_node(t).unwrap()).0);
+                for arg in args {
+                    self.expr(arg);
+                }
+                // The count is a `u64`, the size in bytes an address
+                if *builtin != Builtin::Free {
+                    self.emit(Instruction::I64Const(size as i64));
+                    self.emit(op("i64.mul"));
+                    self.emit(op("i32.wrap_i64"));
+                }
+                self.emit(Instruction::Call(builtin_symbol(*builtin).to_string()));
+            }
+            ASTNode::StructLiteral(name, values) => {
+                let offset = self.temporary(&Type::Struct(name.content.clone()));
+                for (field, value) in values {
+                    let (field_offset, ty) = self.field(&name.content, &field.content);
+                    self.write(Place::Frame(offset + field_offset), &ty, |this| this.expr(value));
+                }
+                self.address(Place::Frame(offset));
+            }
+            ASTNode::ArrayLiteral(_, elements) => {
+                let ty = self.type_of(node);
+                let Type::Array(element, _) = &ty else {
+                    unreachable!()
+                };
+                let offset = self.temporary(&ty);
+                let size = self.layout(element).0;
+                for (index, value) in elements.iter().enumerate() {
+                    let place = Place::Frame(offset + index as u32 * size);
+                    self.write(place, element, |this| this.expr(value));
+                }
+                self.address(Place::Frame(offset));
+            }
+            ASTNode::Index(array, index) => {
+                let element = self.type_of(node);
+                let size = self.layout(&element).0;
+                self.expr(array);
+                self.expr(index);
+                self.emit(op("i32.wrap_i64"));
+                self.emit(Instruction::I32Const(size as i32));
+                self.emit(op("i32.mul"));
+                self.emit(op("i32.add"));
+                if !is_aggregate(&element) {
+                    self.load(&element, 0);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Integer constants are wrapped to their type, narrow ones sign or zero extended.
+    fn literal(token: &Token, ty: &Type) -> Instruction {
+        match (token.token_type, ty) {
+            (TokenType::Boolean, _) => Instruction::I32Const((token.content == "true") as i32),
+            (_, Type::Float(32)) => Instruction::F32Const(token.content.parse::<f64>().unwrap() as f32),
+            (_, Type::Float(_)) => Instruction::F64Const(token.content.parse::<f64>().unwrap()),
+            (_, Type::Int(bits, signed)) => {
+                let (value, shift) = (token.content.parse::<i128>().unwrap(), 128 - *bits as u32);
+                let value = match signed {
+                    true => (value << shift) >> shift,
+                    false => (((value << shift) as u128) >> shift) as i128,
+                };
+                match bits {
+                    64 => Instruction::I64Const(value as i64),
+                    _ => Instruction::I32Const(value as i32),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Integers wrap around like in the other backends, division by zero traps.
+    fn binary_op(&mut self, lhs: &ASTNode<'a>, op_token: &Token, rhs: &ASTNode<'a>) {
+        let operands = self.type_of(lhs);
+        let prefix = value_type(&operands).name();
+        if let (Type::Int(_, true), TokenType::Slash) = (&operands, op_token.token_type) {
+            return self.divide(lhs, rhs, &operands);
+        }
+        self.expr(lhs);
+        self.expr(rhs);
+        let name = match (&operands, op_token.token_type) {
+            (_, TokenType::Plus) => "add".to_string(),
+            (_, TokenType::Minus) => "sub".to_string(),
+            (_, TokenType::Star) => "mul".to_string(),
+            (Type::Float(_), TokenType::Slash) => "div".to_string(),
+            (_, TokenType::Slash) => "div_u".to_string(),
+            (Type::Float(_), op) => comparison(op, ""),
+            (Type::Int(_, true), op) => comparison(op, "_s"),
+            (_, op) => comparison(op, "_u"),
+        };
+        self.emit(op(&format!("{}.{}", prefix, name)));
+        if matches!(op_token.token_type, TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash) {
+            self.normalize(&operands);
+        }
+    }
+
+    /// `div_s` of the smallest value by -1 traps, it wraps to the dividend negated instead.
+    fn divide(&mut self, lhs: &ASTNode<'a>, rhs: &ASTNode<'a>, ty: &Type) {
+        let value_type = value_type(ty);
+        let prefix = value_type.name();
+        let (dividend, divisor) = (self.scratch(value_type), self.scratch(value_type));
+        self.expr(lhs);
+        self.emit(Instruction::LocalSet(dividend));
+        self.expr(rhs);
+        self.emit(Instruction::LocalSet(divisor));
+        self.emit(constant(value_type, 0));
+        self.emit(Instruction::LocalGet(dividend));
+        self.emit(op(&format!("{}.sub", prefix)));
+        self.emit(Instruction::LocalGet(dividend));
+        self.emit(constant(value_type, 1));
+        for instruction in [
+            Instruction::LocalGet(divisor),
+            Instruction::LocalGet(divisor),
+            constant(value_type, -1),
+            op(&format!("{}.eq", prefix)),
+            op("select"),
+            op(&format!("{}.div_s", prefix)),
+            Instruction::LocalGet(divisor),
+            constant(value_type, -1),
+            op(&format!("{}.eq", prefix)),
+            op("select"),
+        ] {
+            self.emit(instruction);
+        }
+        self.normalize(ty);
+    }
+
+    fn cast(&mut self, from: &Type, to: &Type) {
+        let (source, target) = (value_type(from), value_type(to));
+        match (from, to) {
+            (from, to) if from == to => {}
+            (Type::Int(_, signed), Type::Int(..)) => {
+                match (source, target) {
+                    (ValType::I64, ValType::I32) => self.emit(op("i32.wrap_i64")),
+                    (ValType::I32, ValType::I64) if *signed => self.emit(op("i64.extend_i32_s")),
+                    (ValType::I32, ValType::I64) => self.emit(op("i64.extend_i32_u")),
+                    _ => {}
+                }
+                self.normalize(to);
+            }
+            (Type::Bool, Type::Int(..)) if target == ValType::I64 => self.emit(op("i64.extend_i32_u")),
+            (Type::Int(_, signed), Type::Float(_)) => {
+                let suffix = if *signed { "s" } else { "u" };
+                self.emit(op(&format!("{}.convert_{}_{}", target.name(), source.name(), suffix)));
+            }
+            // Truncates and saturates like in the interpreter, NaN becomes 0
+            (Type::Float(_), Type::Int(bits, signed)) if *bits >= 32 => {
+                let suffix = if *signed { "s" } else { "u" };
+                self.emit(op(&format!("{}.trunc_sat_{}_{}", target.name(), source.name(), suffix)));
+            }
+            (Type::Float(_), Type::Int(bits, signed)) => {
+                let (min, max) = match signed {
+                    true => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+                    false => (0, (1 << bits) - 1),
+                };
+                let value = self.scratch(ValType::I32);
+                self.emit(op(&format!("i32.trunc_sat_{}_s", source.name())));
+                self.emit(Instruction::LocalSet(value));
+                for (limit, beyond) in [(max, "i32.gt_s"), (min, "i32.lt_s")] {
+                    self.emit(Instruction::I32Const(limit));
+                    self.emit(Instruction::LocalGet(value));
+                    self.emit(Instruction::LocalGet(value));
+                    self.emit(Instruction::I32Const(limit));
+                    self.emit(op(beyond));
+                    self.emit(op("select"));
+                    self.emit(Instruction::LocalSet(value));
+                }
+                self.emit(Instruction::LocalGet(value));
+            }
+            (Type::Float(32), Type::Float(64)) => self.emit(op("f64.promote_f32")),
+            (Type::Float(64), Type::Float(32)) => self.emit(op("f32.demote_f64")),
+            (Type::Pointer(_) | Type::Str, Type::Int(..)) => {
+                if target == ValType::I64 {
+                    self.emit(op("i64.extend_i32_u"));
+                }
+                self.normalize(to);
+            }
+            (Type::Int(64, _), Type::Pointer(_) | Type::Str) => self.emit(op("i32.wrap_i64")),
+            // Pointers and strings are all addresses
+            _ => {}
+        }
+    }
+
+    /// Pushes the address a reference parameter receives for `arg`. Values that are not
+    /// variables in memory are copied to a temporary first.
+    fn reference(&mut self, arg: &ASTNode<'a>) {
+        match arg {
+            ASTNode::NamedArgument(_, value) => self.reference(value),
+            ASTNode::Identifier(name) if !matches!(self.places[&self.modules[self.module].key(name)].0, Place::Local(_)) => {
+                let place = self.places[&self.modules[self.module].key(name)].0;
+                self.address(place);
+            }
+            arg => {
+                let ty = self.type_of(arg);
+                let offset = self.temporary(&ty);
+                self.write(Place::Frame(offset), &ty, |this| this.expr(arg));
+                self.address(Place::Frame(offset));
+            }
+        }
+    }
+
+    /// Aggregate arguments are copied by the caller and passed by address, like the memory
+    /// for an aggregate result.
+    fn call(&mut self, name: &'a Token, args: &[Box<ASTNode<'a>>]) {
+        let function = self.modules[self.module].callee(name);
+        let ASTNode::FunctionDef(function_name, _, ret, params, ..) = function else {
+            unreachable!()
+        };
+        let symbol = function.symbol_name(&self.modules[self.module].name).unwrap();
+        let ret = Type::from_node(ret).unwrap();
+        let result = is_aggregate(&ret).then(|| self.temporary(&ret));
+        if let Some(result) = result {
+            self.address(Place::Frame(result));
+        }
+
+        let bound = bind_arguments(name, args, function_name, params).unwrap();
+        for ((_, ty, _), arg) in params.iter().zip(bound) {
+            if let ASTNode::ReferenceType(..) = **ty {
+                self.reference(arg);
+                continue;
+            }
+            let ty = Type::from_node(ty).unwrap();
+            if is_aggregate(&ty) {
+                let copy = self.temporary(&ty);
+                self.write(Place::Frame(copy), &ty, |this| this.expr(arg));
+                self.address(Place::Frame(copy));
+            } else {
+                self.expr(arg);
+            }
+        }
+        self.emit(Instruction::Call(symbol));
+        if let Some(result) = result {
+            self.address(Place::Frame(result));
+        }
+    }
+}
+
+/// The allocation runtime: a bump allocator above the stack that grows the memory as needed.
+/// Blocks are never reused, so `sila_free` does nothing.
+fn allocator() -> Vec<Function> {
+    use Instruction::*;
+    let alloc = builtin_symbol(Builtin::Alloc).to_string();
+    let (size, block) = (0, 1);
+    let alloc_body = vec![
+        GlobalGet(HEAP_POINTER),
+        LocalTee(block),
+        // Blocks start 8-byte aligned after a header with their size
+        LocalGet(size),
+        I32Const(7),
+        op("i32.add"),
+        I32Const(-8),
+        op("i32.and"),
+        op("i32.add"),
+        I32Const(8),
+        op("i32.add"),
+        GlobalSet(HEAP_POINTER),
+        GlobalGet(HEAP_POINTER),
+        op("memory.size"),
+        I32Const(16),
+        op("i32.shl"),
+        op("i32.gt_u"),
+        If,
+        GlobalGet(HEAP_POINTER),
+        I32Const(PAGE_SIZE as i32 - 1),
+        op("i32.add"),
+        I32Const(16),
+        op("i32.shr_u"),
+        op("memory.size"),
+        op("i32.sub"),
+        op("memory.grow"),
+        I32Const(-1),
+        op("i32.eq"),
+        If,
+        op("unreachable"),
+        op("end"),
+        op("end"),
+        LocalGet(block),
+        LocalGet(size),
+        Memory("i32.store", 2, 0),
+        LocalGet(block),
+        I32Const(8),
+        op("i32.add"),
+    ];
+    let (pointer, size, moved, old) = (0, 1, 2, 3);
+    let realloc_body = vec![
+        LocalGet(size),
+        Call(alloc.clone()),
+        LocalSet(moved),
+        LocalGet(pointer),
+        If,
+        LocalGet(pointer),
+        I32Const(8),
+        op("i32.sub"),
+        Memory("i32.load", 2, 0),
+        LocalSet(old),
+        // Copies the smaller of the two sizes
+        LocalGet(moved),
+        LocalGet(pointer),
+        LocalGet(old),
+        LocalGet(size),
+        LocalGet(old),
+        LocalGet(size),
+        op("i32.lt_u"),
+        op("select"),
+        op("memory.copy"),
+        op("end"),
+        LocalGet(moved),
+    ];
+    let i32 = |name: &str| (name.to_string(), ValType::I32);
+    vec![
+        Function {
+            symbol: alloc,
+            export: None,
+            params: vec![i32("size")],
+            results: vec![ValType::I32],
+            locals: vec![i32("block")],
+            body: alloc_body,
+        },
+        Function {
+            symbol: builtin_symbol(Builtin::Realloc).to_string(),
+            export: None,
+            params: vec![i32("pointer"), i32("size")],
+            results: vec![ValType::I32],
+            locals: vec![i32("moved"), i32("old")],
+            body: realloc_body,
+        },
+        Function {
+            symbol: builtin_symbol(Builtin::Free).to_string(),
+            export: None,
+            params: vec![i32("pointer")],
+            results: vec![],
+            locals: vec![],
+            body: vec![],
+        },
+    ]
+}
+
+fn build(modules: &[Module], entry: &EntryPoint) -> WasmModule {
+    let mut backend = WasmBackend::new(modules);
+    let mut imports = vec![];
+    let mut functions = vec![];
+    let mut externs = HashSet::new();
+
+    for (index, module) in modules.iter().enumerate() {
+        for item in &module.ast {
+            let ASTNode::FunctionDef(_, mode, ret, params, ..) = item else {
+                continue;
+            };
+            let symbol = item.symbol_name(&module.name).unwrap();
+            // Externs defined in another module are already there
+            if *mode == FunctionMode::Extern {
+                if !backend.defined.contains(&symbol) && externs.insert(symbol.clone()) {
+                    let (params, results) = WasmBackend::signature(&Type::from_node(ret).unwrap(), params);
+                    imports.push(Import { symbol, params, results });
+                }
+                continue;
+            }
+            let export = (*mode == FunctionMode::Export).then_some(symbol);
+            functions.push(backend.function(index, item, export));
+        }
+    }
+    if backend.allocates {
+        functions.extend(allocator());
+    }
+
+    // The host starts the program by calling `_start`, which returns the exit code
+    let mut start = vec![];
+    if entry.takes_args {
+        start.extend([Instruction::I32Const(0), Instruction::I32Const(ARGV)]);
+    }
+    start.push(Instruction::Call(entry.symbol.clone()));
+    functions.push(Function {
+        symbol: START_SYMBOL.to_string(),
+        export: Some(START_SYMBOL.to_string()),
+        params: vec![],
+        results: vec![ValType::I32],
+        locals: vec![],
+        body: start,
+    });
+
+    let heap = (DATA_START + backend.data.len() as u32).next_multiple_of(16) + STACK_SIZE;
+    WasmModule {
+        imports,
+        functions,
+        data: backend.data,
+        pages: heap.div_ceil(PAGE_SIZE),
+        globals: [heap, heap],
+    }
+}
+
+/// WebAssembly text of the checked `modules`. Export functions are exported under their
+/// symbol and functions without a body are imported from the `env` module. The module exports
+/// its memory and a `_start` function that calls the entry and returns its exit code.
+pub fn generate(modules: &[Module], entry: &EntryPoint) -> String {
+    build(modules, entry).wat()
+}
+
+/// The same module as `generate`, in the binary format.
+pub fn generate_binary(modules: &[Module], entry: &EntryPoint) -> Vec<u8> {
+    build(modules, entry).encode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::entry_point;
+    use crate::interpreter::interpret;
+    use crate::testing::check_module_of;
+    use std::process::Command;
+
+    /// Runs the module in Node.js with `puts`, `putchar` and `exit` as host functions and
+    /// exits with the result of `_start`.
+    const HOST: &str = "const fs = require('fs');\n\
+        let memory;\n\
+        class Exit { constructor(code) { this.code = code; } }\n\
+        const env = {\n\
+        \x20   puts: (s) => {\n\
+        \x20       const bytes = new Uint8Array(memory.buffer);\n\
+        \x20       let end = s;\n\
+        \x20       while (bytes[end] !== 0) end++;\n\
+        \x20       fs.writeSync(1, Buffer.concat([Buffer.from(bytes.subarray(s, end)), Buffer.from('\\n')]));\n\
+        \x20       return 0;\n\
+        \x20   },\n\
+        \x20   putchar: (c) => { fs.writeSync(1, Buffer.from([c & 0xff])); return c; },\n\
+        \x20   exit: (code) => { throw new Exit(code); },\n\
+        };\n\
+        WebAssembly.instantiate(fs.readFileSync(process.argv[2]), { env }).then(({ instance }) => {\n\
+        \x20   memory = instance.exports.memory;\n\
+        \x20   try {\n\
+        \x20       process.exitCode = instance.exports._start() & 0xff;\n\
+        \x20   } catch (e) {\n\
+        \x20       if (!(e instanceof Exit)) throw e;\n\
+        \x20       process.exitCode = e.code & 0xff;\n\
+        \x20   }\n\
+        });\n";
+
+    fn generate_wat(source: &str) -> String {
+        let modules = [check_module_of(source).unwrap_or_else(|e| panic!("{}: {}", e.title, e.footer))];
+        let entry = entry_point(&modules[0].ast, &modules[0].name, None).unwrap();
+        generate(&modules, &entry)
+    }
+
+    /// Runs `source` compiled to WebAssembly and in the interpreter, which have to agree.
+    fn run_like_the_interpreter(source: &str) {
+        if Command::new("node").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("sila-wasm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let modules = [check_module_of(source).unwrap()];
+        let entry = entry_point(&modules[0].ast, &modules[0].name, None).unwrap();
+        std::fs::write(dir.join("program.wasm"), generate_binary(&modules, &entry)).unwrap();
+        std::fs::write(dir.join("host.js"), HOST).unwrap();
+        let run = Command::new("node").args(["host.js", "program.wasm"]).current_dir(&dir).output().unwrap();
+        assert!(run.stderr.is_empty(), "{}", String::from_utf8_lossy(&run.stderr));
+
+        let mut output = vec![];
+        let code = interpret(&modules, &entry, "program", &mut output).unwrap();
+        assert_eq!(run.status.code(), Some(code & 0xff));
+        assert_eq!(run.stdout, output);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn declares_functions_by_mode() {
+        let source = generate_wat(
+            "def extern puts(s: str): i32 {\n}\n\ndef private helper(): i8 {\n    return 1;\n}\n\n\
+             def export api(x: &mut i32): void {\n    x = (helper() -> i32);\n    puts(\"api\");\n}\n\n\
+             def main(): i32 {\n    return 0;\n}",
+        );
+        assert!(source.contains("\n  (import \"env\" \"puts\" (func $puts (param i32) (result i32)))\n"));
+        assert!(source.contains("\n  (func $test__helper (result i32)\n    i32.const 1\n    return\n"));
+        assert!(source.contains("\n  (func $api (export \"api\") (param $x i32)\n    local.get $x\n    call $test__helper\n"));
+        assert!(source.contains("\n  (data (i32.const 16) \"api\\00\")\n"));
+        assert!(source.contains("\n  (func $_start (export \"_start\") (result i32)\n    call $test__main\n  )\n"));
+    }
+
+    #[test]
+    fn encodes_integers() {
+        let mut out = vec![];
+        unsigned(&mut out, 624485);
+        assert_eq!(out, [0xE5, 0x8E, 0x26]);
+        out.clear();
+        signed(&mut out, -123456);
+        assert_eq!(out, [0xC0, 0xBB, 0x78]);
+        out.clear();
+        signed(&mut out, 64);
+        assert_eq!(out, [0xC0, 0x00]);
+    }
+
+    #[test]
+    fn runs_like_the_interpreter() {
+        run_like_the_interpreter(include_str!("../tests/golden/program.sila"));
+        run_like_the_interpreter(
+            "def extern puts(s: str): i32 {\n}\n\nstruct Pair {\n    a: i64,\n    b: f64\n}\n\n\
+            def swap(x: &mut i32, y: &mut i32): void {\n    let t = x;\n    x = y;\n    y = t;\n}\n\n\
+            def total(values: [i32; 5], pair: Pair): [i32; 2] {\n    let mut sum = 0;\n    for v in values {\n        sum = sum + v;\n    }\n    \
+            return [sum, 7];\n}\n\n\
+            def many(a: i8, b: u16, c: i32, d: i64, e: f32, f: f64, g: u8, h: i32, i: i64): i64 {\n    \
+            return (a -> i64) + (b -> i64) + (c -> i64) + d + (e -> i64) + (f -> i64) + (g -> i64) + (h -> i64) + i;\n}\n\n\
+            def @unsafe main(): i32 {\n    let mut x = 1;\n    let mut y = 2;\n    swap(x, y);\n    \
+            let p = alloc<Pair>(4);\n    let q = realloc<Pair>(p, 8);\n    free(q);\n    let small: i8 = 127;\n    \
+            let mut count: u8 = 0;\n    for i in 250..=255 {\n        count = count + 1;\n    }\n    \
+            let mut t = 0;\n    for v in total([1, 2, 3, 4, 5], Pair { a: 1, b: 2.75 }) {\n        t = t + v;\n    }\n    \
+            let mut w = 0;\n    while w < 10 {\n        w = w + 3;\n        if w == 6 {\n            continue;\n        }\n    }\n    \
+            puts(\"done\");\n    let wide = many(0 - 1, 65535, 3, 4, 5.5, 0.0 - 6.5, 200, 8, 9);\n    \
+            let f: f32 = 7.0;\n    \
+            return x * 10 + y + ((small + 1) -> i32) + (3.9 -> i32) + (count -> i32) + t + w + ((wide - 65000) -> i32) + \
+            ((1000000000000.0 -> i32) / 1000000000) + ((f / 2.0 > 3.0) -> i32) + ((0 - 7) / 2) * 10 + ((300.5 -> i8) -> i32);\n}",
+        );
+    }
+}
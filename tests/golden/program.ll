@@ -0,0 +1,213 @@
+; Generated by the Sila compiler
+source_filename = "test.sila"
+target triple = "x86_64-pc-linux-gnu"
+
+%struct.Line = type { %struct.Point, %struct.Point, i64 }
+%struct.Point = type { i32, float }
+
+@.str.0 = private unnamed_addr constant [7 x i8] c"golden\00"
+
+define internal i64 @test__point(i32 %arg.0, float %arg.1) {
+b0:
+  %t.1 = alloca %struct.Point
+  %t.5 = alloca [1 x i64]
+  %t.2 = getelementptr inbounds %struct.Point, ptr %t.1, i32 0, i32 1
+  store float %arg.1, ptr %t.2
+  %t.3 = getelementptr inbounds %struct.Point, ptr %t.1, i32 0, i32 0
+  store i32 %arg.0, ptr %t.3
+  %t.4 = load %struct.Point, ptr %t.1
+  store %struct.Point %t.4, ptr %t.5
+  %t.6 = load i64, ptr %t.5
+  ret i64 %t.6
+}
+
+define void @line(ptr sret(%struct.Line) align 8 %agg.result, i64 %arg.0.0, i64 %arg.1.0) {
+b0:
+  %arg.0.addr = alloca [1 x i64]
+  %arg.1.addr = alloca [1 x i64]
+  %t.3 = alloca %struct.Point
+  %t.4 = alloca %struct.Point
+  %t.5 = alloca %struct.Line
+  store i64 %arg.0.0, ptr %arg.0.addr
+  %t.1 = load %struct.Point, ptr %arg.0.addr
+  store i64 %arg.1.0, ptr %arg.1.addr
+  %t.2 = load %struct.Point, ptr %arg.1.addr
+  store %struct.Point %t.1, ptr %t.3
+  store %struct.Point %t.2, ptr %t.4
+  %t.6 = load %struct.Point, ptr %t.3
+  %t.7 = getelementptr inbounds %struct.Line, ptr %t.5, i32 0, i32 0
+  store %struct.Point %t.6, ptr %t.7
+  %t.8 = load %struct.Point, ptr %t.4
+  %t.9 = getelementptr inbounds %struct.Line, ptr %t.5, i32 0, i32 1
+  store %struct.Point %t.8, ptr %t.9
+  %t.10 = getelementptr inbounds %struct.Line, ptr %t.5, i32 0, i32 2
+  store i64 2, ptr %t.10
+  %t.11 = load %struct.Line, ptr %t.5
+  store %struct.Line %t.11, ptr %agg.result
+  ret void
+}
+
+define internal void @test__swap(ptr %arg.0, ptr %arg.1) {
+b0:
+  %t.1 = load i8, ptr %arg.0
+  %t.2 = load i8, ptr %arg.1
+  store i8 %t.2, ptr %arg.0
+  store i8 %t.1, ptr %arg.1
+  ret void
+}
+
+define internal zeroext i16 @test__sum(i64 %arg.0.0) {
+b0:
+  %arg.0.addr = alloca [1 x i64]
+  %t.4 = alloca [4 x i16]
+  %t.5 = alloca [4 x i16]
+  store i64 %arg.0.0, ptr %arg.0.addr
+  %t.1 = load [4 x i16], ptr %arg.0.addr
+  store [4 x i16] %t.1, ptr %t.4
+  %t.6 = load [4 x i16], ptr %t.4
+  store [4 x i16] %t.6, ptr %t.5
+  br label %b1
+b1:
+  %t.2 = phi i64 [ 0, %b0 ], [ %t.10, %b3 ], [ %t.10, %b4 ]
+  %t.3 = phi i16 [ 0, %b0 ], [ %t.3, %b3 ], [ %t.12, %b4 ]
+  %t.7 = icmp ult i64 %t.2, 4
+  br i1 %t.7, label %b2, label %b5
+b2:
+  %t.8 = getelementptr inbounds [4 x i16], ptr %t.5, i64 0, i64 %t.2
+  %t.9 = load i16, ptr %t.8
+  %t.10 = add i64 %t.2, 1
+  %t.11 = icmp eq i16 %t.9, 0
+  br i1 %t.11, label %b3, label %b4
+b3:
+  br label %b1
+b4:
+  %t.12 = add i16 %t.3, %t.9
+  br label %b1
+b5:
+  ret i16 %t.3
+}
+
+define i32 @test__main() {
+b0:
+  %t.8 = alloca i8
+  %t.9 = alloca i8
+  %t.10 = alloca %struct.Line
+  %t.11 = alloca [4 x i16]
+  %t.22 = alloca [1 x i64]
+  %t.25 = alloca [1 x i64]
+  %t.27 = alloca [1 x i64]
+  %t.29 = alloca [1 x i64]
+  %t.31 = alloca %struct.Line
+  %t.55 = alloca [1 x i64]
+  %t.12 = sub i8 0, 3
+  store i8 %t.12, ptr %t.8
+  store i8 100, ptr %t.9
+  call void @test__swap(ptr %t.8, ptr %t.9)
+  br label %b1
+b1:
+  %t.1 = phi i32 [ 0, %b0 ], [ %t.13, %b4 ]
+  br i1 true, label %b2, label %b5
+b2:
+  %t.13 = add i32 %t.1, 1
+  %t.14 = icmp sge i32 %t.13, 10
+  br i1 %t.14, label %b3, label %b4
+b3:
+  br label %b5
+b4:
+  br label %b1
+b5:
+  %t.2 = phi i32 [ %t.1, %b1 ], [ %t.13, %b3 ]
+  %t.15 = icmp sle i32 250, 255
+  br label %b6
+b6:
+  %t.3 = phi i1 [ %t.15, %b5 ], [ %t.6, %b10 ]
+  %t.4 = phi i32 [ 250, %b5 ], [ %t.7, %b10 ]
+  %t.5 = phi i8 [ 0, %b5 ], [ %t.18, %b10 ]
+  br i1 %t.3, label %b7, label %b11
+b7:
+  %t.16 = icmp eq i32 %t.4, 255
+  br i1 %t.16, label %b8, label %b9
+b8:
+  br label %b10
+b9:
+  %t.17 = add i32 %t.4, 1
+  br label %b10
+b10:
+  %t.6 = phi i1 [ false, %b8 ], [ %t.3, %b9 ]
+  %t.7 = phi i32 [ %t.4, %b8 ], [ %t.17, %b9 ]
+  %t.18 = add i8 %t.5, 1
+  br label %b6
+b11:
+  %t.19 = mul i64 4, 8
+  %t.20 = call ptr @sila_alloc(i64 %t.19)
+  call void @sila_free(ptr %t.20)
+  %t.21 = call i64 @test__point(i32 1, float 0x4004000000000000)
+  store i64 %t.21, ptr %t.22
+  %t.23 = load %struct.Point, ptr %t.22
+  %t.24 = call i64 @test__point(i32 3, float 0x4012000000000000)
+  store i64 %t.24, ptr %t.25
+  %t.26 = load %struct.Point, ptr %t.25
+  store %struct.Point %t.23, ptr %t.27
+  %t.28 = load i64, ptr %t.27
+  store %struct.Point %t.26, ptr %t.29
+  %t.30 = load i64, ptr %t.29
+  call void @line(ptr sret(%struct.Line) align 8 %t.31, i64 %t.28, i64 %t.30)
+  %t.32 = load %struct.Line, ptr %t.31
+  store %struct.Line %t.32, ptr %t.10
+  %t.33 = call i32 @puts(ptr @.str.0)
+  %t.34 = sub i32 0, 7
+  %t.35 = icmp eq i32 2, 0
+  br i1 %t.35, label %div.zero.1, label %div.1
+div.zero.1:
+  call void @llvm.trap()
+  unreachable
+div.1:
+  %t.36 = icmp eq i32 2, -1
+  %t.37 = select i1 %t.36, i32 1, i32 2
+  %t.38 = sdiv i32 %t.34, %t.37
+  %t.39 = sub i32 0, %t.34
+  %t.40 = select i1 %t.36, i32 %t.39, i32 %t.38
+  %t.41 = call i32 @llvm.fptosi.sat.i32.f64(double 0x41E65A0BC0100000)
+  %t.42 = load i8, ptr %t.8
+  %t.43 = sext i8 %t.42 to i32
+  %t.44 = load i8, ptr %t.9
+  %t.45 = sext i8 %t.44 to i32
+  %t.46 = add i32 %t.43, %t.45
+  %t.47 = add i32 %t.46, %t.2
+  %t.48 = zext i8 %t.5 to i32
+  %t.49 = add i32 %t.47, %t.48
+  %t.50 = getelementptr inbounds [4 x i16], ptr %t.11, i64 0, i64 0
+  store i16 1, ptr %t.50
+  %t.51 = getelementptr inbounds [4 x i16], ptr %t.11, i64 0, i64 1
+  store i16 0, ptr %t.51
+  %t.52 = getelementptr inbounds [4 x i16], ptr %t.11, i64 0, i64 2
+  store i16 -1, ptr %t.52
+  %t.53 = getelementptr inbounds [4 x i16], ptr %t.11, i64 0, i64 3
+  store i16 3, ptr %t.53
+  %t.54 = load [4 x i16], ptr %t.11
+  store [4 x i16] %t.54, ptr %t.55
+  %t.56 = load i64, ptr %t.55
+  %t.57 = call zeroext i16 @test__sum(i64 %t.56)
+  %t.58 = zext i16 %t.57 to i32
+  %t.59 = add i32 %t.49, %t.58
+  %t.60 = add i32 %t.59, %t.40
+  %t.61 = icmp eq i32 1000000000, 0
+  br i1 %t.61, label %div.zero.2, label %div.2
+div.zero.2:
+  call void @llvm.trap()
+  unreachable
+div.2:
+  %t.62 = icmp eq i32 1000000000, -1
+  %t.63 = select i1 %t.62, i32 1, i32 1000000000
+  %t.64 = sdiv i32 %t.41, %t.63
+  %t.65 = sub i32 0, %t.41
+  %t.66 = select i1 %t.62, i32 %t.65, i32 %t.64
+  %t.67 = add i32 %t.60, %t.66
+  ret i32 %t.67
+}
+
+declare i32 @puts(ptr)
+declare i32 @llvm.fptosi.sat.i32.f64(double)
+declare ptr @sila_alloc(i64)
+declare void @llvm.trap()
+declare void @sila_free(ptr)